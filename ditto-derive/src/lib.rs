@@ -0,0 +1,407 @@
+//! `#[derive(Crdt)]` for structs whose fields are Ditto CRDTs.
+//!
+//! Deriving `Crdt` for a struct like
+//!
+//! ```rust,ignore
+//! #[derive(Clone, Crdt)]
+//! pub struct Issue {
+//!     title: Text,
+//!     tags: Set<String>,
+//!     votes: Counter,
+//!     assignee: Register<u32>,
+//! }
+//! ```
+//!
+//! generates a composite CRDT, `IssueCrdt`, that replicates every
+//! field with a single summary and site id. Alongside it the derive
+//! generates:
+//!
+//! * `IssueOp`: an op enum with one variant per field (`Title`, `Tags`, ...)
+//! * `IssueLocalOp`: the local ops of a field, tagged the same way
+//! * `IssueState`: the replicated state, one field per struct field plus the summary
+//! * `IssueDelta`: the fields that changed since a remote summary
+//! * `IssueValue`: the local value of each field
+//!
+//! `IssueCrdt` implements `ditto::Crdt`, and has one `edit_<field>`
+//! method per field that edits the field in place:
+//!
+//! ```rust,ignore
+//! let mut issue = IssueCrdt::new(Issue{...});
+//! let op = issue.edit_title(|title| title.replace(0, 0, "Bug: ").unwrap())?;
+//! ```
+//!
+//! The struct itself implements `ditto::Nested`, so it can also be
+//! stored in a `Map` or `List` and merged field by field. Every field
+//! must be a CRDT that implements `ditto::NestedCrdt`, and the struct
+//! must implement `Clone`.
+
+extern crate proc_macro;
+extern crate proc_macro2;
+#[macro_use] extern crate quote;
+#[macro_use] extern crate syn;
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use syn::{Data, DeriveInput, Error, Fields, Ident, Type};
+
+#[proc_macro_derive(Crdt)]
+pub fn derive_crdt(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match expand(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+struct Field<'a> {
+    ident: &'a Ident,
+    ty: &'a Type,
+    variant: Ident,
+    edit_fn: Ident,
+}
+
+fn expand(input: &DeriveInput) -> Result<TokenStream2, Error> {
+    if !input.generics.params.is_empty() || input.generics.where_clause.is_some() {
+        return Err(Error::new_spanned(&input.generics, "derive(Crdt) does not support generic structs"))
+    }
+
+    let named = match input.data {
+        Data::Struct(ref data) => match data.fields {
+            Fields::Named(ref fields) => &fields.named,
+            _ => return Err(Error::new_spanned(&input.ident, "derive(Crdt) requires a struct with named fields")),
+        },
+        _ => return Err(Error::new_spanned(&input.ident, "derive(Crdt) only supports structs")),
+    };
+
+    if named.is_empty() {
+        return Err(Error::new_spanned(&input.ident, "derive(Crdt) requires at least one field"))
+    }
+
+    let mut fields = vec![];
+    for field in named {
+        let ident = field.ident.as_ref().expect("Named fields must have an ident");
+        let name = ident.to_string().trim_start_matches("r#").to_owned();
+        if name == "summary" {
+            return Err(Error::new_spanned(ident, "`summary` is reserved for the shared summary"))
+        }
+
+        fields.push(Field{
+            ident,
+            ty: &field.ty,
+            variant: format_ident!("{}", camel_case(&name)),
+            edit_fn: format_ident!("edit_{}", name),
+        });
+    }
+
+    let vis = &input.vis;
+    let name = &input.ident;
+    let crdt = format_ident!("{}Crdt", name);
+    let op = format_ident!("{}Op", name);
+    let local_op = format_ident!("{}LocalOp", name);
+    let state = format_ident!("{}State", name);
+    let delta = format_ident!("{}Delta", name);
+    let value = format_ident!("{}Value", name);
+
+    let idents: &Vec<&Ident> = &fields.iter().map(|f| f.ident).collect();
+    let types: &Vec<&Type> = &fields.iter().map(|f| f.ty).collect();
+    let variants: &Vec<&Ident> = &fields.iter().map(|f| &f.variant).collect();
+    let edit_fns: &Vec<&Ident> = &fields.iter().map(|f| &f.edit_fn).collect();
+
+    let crdt_doc = format!("A replicated `{}`. Every field shares one summary and site id.", name);
+    let op_doc = format!("An op that edits one field of a `{}`.", crdt);
+    let local_op_doc = format!("The local ops that result from editing one field of a `{}`.", crdt);
+    let state_doc = format!("The replicated state of a `{}`.", crdt);
+    let delta_doc = format!("The fields of a `{}` that a remote site has not yet seen.", crdt);
+    let value_doc = format!("The local value of a `{}`.", crdt);
+    let edit_docs: Vec<String> = fields.iter().map(|f| format!(
+        "Edits the `{}` field in place and returns an op that can be sent to \
+         remote sites for replication. `f` makes a single edit through the \
+         field's own methods and returns its op. If the CRDT does not have a \
+         site allocated, it caches the op and returns an `AwaitingSite` error.",
+        f.ident)).collect();
+
+    Ok(quote! {
+        #[doc = #crdt_doc]
+        #[derive(Clone)]
+        #vis struct #crdt {
+            value: #name,
+            summary: ::ditto::dot::Summary,
+            site_id: ::ditto::dot::SiteId,
+            cached_ops: Vec<#op>,
+        }
+
+        #[doc = #op_doc]
+        #[derive(Debug, Clone, PartialEq, ::ditto::__private::Serialize, ::ditto::__private::Deserialize)]
+        #[serde(crate = "::ditto::__private::serde")]
+        #vis enum #op {
+            #( #variants(<#types as ::ditto::Nested>::NestedOp), )*
+        }
+
+        #[doc = #local_op_doc]
+        #[derive(Debug, Clone, PartialEq, ::ditto::__private::Serialize, ::ditto::__private::Deserialize)]
+        #[serde(crate = "::ditto::__private::serde")]
+        #vis enum #local_op {
+            #( #variants(Vec<<#types as ::ditto::Nested>::NestedLocalOp>), )*
+        }
+
+        #[doc = #state_doc]
+        #[derive(Debug, Clone, PartialEq, ::ditto::__private::Serialize, ::ditto::__private::Deserialize)]
+        #[serde(crate = "::ditto::__private::serde")]
+        #vis struct #state {
+            #( pub #idents: #types, )*
+            pub summary: ::ditto::dot::Summary,
+        }
+
+        #[doc = #delta_doc]
+        #[derive(Debug, Clone, PartialEq, ::ditto::__private::Serialize, ::ditto::__private::Deserialize)]
+        #[serde(crate = "::ditto::__private::serde")]
+        #vis struct #delta {
+            #( pub #idents: Option<#types>, )*
+            pub summary: ::ditto::dot::Summary,
+        }
+
+        #[doc = #value_doc]
+        #[derive(Debug, Clone, PartialEq)]
+        #vis struct #value {
+            #( pub #idents: <#types as ::ditto::Crdt>::LocalValue, )*
+        }
+
+        #[allow(dead_code)]
+        impl #crdt {
+            /// Constructs a new CRDT from a value. The site that
+            /// constructs the CRDT is assigned id 1.
+            pub fn new(mut value: #name) -> Self {
+                let mut summary = ::ditto::dot::Summary::default();
+                let dot = summary.get_dot(1);
+                ::ditto::Nested::nested_reset(&mut value, dot);
+                #crdt{value, summary, site_id: 1, cached_ops: vec![]}
+            }
+
+            /// Returns a reference to the CRDT's fields.
+            pub fn value(&self) -> &#name {
+                &self.value
+            }
+
+            #(
+                #[doc = #edit_docs]
+                pub fn #edit_fns<F>(&mut self, f: F) -> Result<#op, ::ditto::Error>
+                    where F: FnOnce(&mut #types) -> Result<<#types as ::ditto::Nested>::NestedOp, ::ditto::Error>
+                {
+                    let op = ::ditto::NestedCrdt::nested_edit(&mut self.value.#idents, &mut self.summary, self.site_id, f)?;
+                    self.after_op(#op::#variants(op))
+                }
+            )*
+
+            fn after_op(&mut self, op: #op) -> Result<#op, ::ditto::Error> {
+                if self.site_id == 0 {
+                    self.cached_ops.push(op);
+                    Err(::ditto::Error::AwaitingSiteId)
+                } else {
+                    Ok(op)
+                }
+            }
+        }
+
+        impl ::ditto::Crdt for #crdt {
+            type State = #state;
+            type Op = #op;
+            type LocalOp = #local_op;
+            type LocalValue = #value;
+            type Delta = #delta;
+
+            fn site_id(&self) -> ::ditto::dot::SiteId {
+                self.site_id
+            }
+
+            fn summary(&self) -> ::std::borrow::Cow<'_, ::ditto::dot::Summary> {
+                ::std::borrow::Cow::Borrowed(&self.summary)
+            }
+
+            fn clone_state(&self) -> #state {
+                #state{
+                    #( #idents: self.value.#idents.clone(), )*
+                    summary: self.summary.clone(),
+                }
+            }
+
+            fn into_state(self) -> #state {
+                let value = self.value;
+                #state{
+                    #( #idents: value.#idents, )*
+                    summary: self.summary,
+                }
+            }
+
+            fn from_state(state: #state, site_id: Option<::ditto::dot::SiteId>) -> Result<Self, ::ditto::Error> {
+                let site_id = match site_id {
+                    None => 0,
+                    Some(0) => return Err(::ditto::Error::InvalidSiteId),
+                    Some(s) => s,
+                };
+
+                let value = #name{ #( #idents: state.#idents, )* };
+                Ok(#crdt{value, summary: state.summary, site_id, cached_ops: vec![]})
+            }
+
+            fn local_value(&self) -> #value {
+                #value{ #( #idents: ::ditto::Crdt::local_value(&self.value.#idents), )* }
+            }
+
+            fn execute_op(&mut self, op: #op) -> #local_op {
+                for dot in ::ditto::CausalOp::inserted_dots(&op) {
+                    self.summary.insert(dot);
+                }
+
+                match op {
+                    #( #op::#variants(op) => #local_op::#variants(::ditto::Nested::nested_execute_op(&mut self.value.#idents, op)), )*
+                }
+            }
+
+            fn validate_and_execute_op(&mut self, op: #op, site_id: ::ditto::dot::SiteId) -> Result<#local_op, ::ditto::Error> {
+                ::ditto::NestedOp::nested_validate(&op, site_id)?;
+                Ok(self.execute_op(op))
+            }
+
+            fn merge(&mut self, other: #state) -> Result<Vec<#local_op>, ::ditto::Error> {
+                #( ::ditto::Nested::nested_validate_no_unassigned_sites(&other.#idents)?; )*
+                other.summary.validate_no_unassigned_sites()?;
+                let other_value = #name{ #( #idents: other.#idents, )* };
+                let local_ops = ::ditto::Nested::nested_merge(&mut self.value, other_value, &self.summary, &other.summary);
+                self.summary.merge(&other.summary);
+                Ok(local_ops)
+            }
+
+            fn delta_since(&self, since: &::ditto::dot::Summary) -> #delta {
+                #delta{
+                    #( #idents: if ::ditto::Nested::nested_unchanged_since(&self.value.#idents, since) {
+                        None
+                    } else {
+                        Some(self.value.#idents.clone())
+                    }, )*
+                    summary: self.summary.clone(),
+                }
+            }
+
+            fn merge_delta(&mut self, delta: #delta) -> Result<(), ::ditto::Error> {
+                #( if let Some(ref value) = delta.#idents {
+                    ::ditto::Nested::nested_validate_no_unassigned_sites(value)?;
+                } )*
+                delta.summary.validate_no_unassigned_sites()?;
+
+                #( if let Some(value) = delta.#idents {
+                    ::ditto::Nested::nested_force_merge(&mut self.value.#idents, value, &self.summary, &delta.summary);
+                } )*
+                self.summary.merge(&delta.summary);
+                Ok(())
+            }
+
+            fn add_site_id(&mut self, site_id: ::ditto::dot::SiteId) -> Result<Vec<#op>, ::ditto::Error> {
+                if self.site_id != 0 {
+                    return Err(::ditto::Error::AlreadyHasSiteId);
+                }
+
+                self.site_id = site_id;
+                ::ditto::Nested::nested_add_site_id(&mut self.value, site_id);
+                self.summary.add_site_id(site_id);
+                Ok(self.cached_ops.drain(..)
+                    .map(|mut op| { ::ditto::NestedOp::nested_add_site_id(&mut op, site_id); op })
+                    .collect())
+            }
+        }
+
+        impl ::ditto::Nested for #name {
+            type NestedOp = #op;
+            type NestedLocalOp = #local_op;
+
+            fn nested_reset(&mut self, dot: ::ditto::dot::Dot) {
+                #( ::ditto::Nested::nested_reset(&mut self.#idents, dot); )*
+            }
+
+            fn nested_execute_op(&mut self, op: #op) -> Vec<#local_op> {
+                match op {
+                    #( #op::#variants(op) => {
+                        let ops = ::ditto::Nested::nested_execute_op(&mut self.#idents, op);
+                        if ops.is_empty() { vec![] } else { vec![#local_op::#variants(ops)] }
+                    } )*
+                }
+            }
+
+            fn nested_merge(&mut self, other: Self, summary: &::ditto::dot::Summary, other_summary: &::ditto::dot::Summary) -> Vec<#local_op> {
+                let mut local_ops = vec![];
+                #(
+                    let ops = ::ditto::Nested::nested_merge(&mut self.#idents, other.#idents, summary, other_summary);
+                    if !ops.is_empty() { local_ops.push(#local_op::#variants(ops)); }
+                )*
+                local_ops
+            }
+
+            fn nested_force_merge(&mut self, other: Self, summary: &::ditto::dot::Summary, other_summary: &::ditto::dot::Summary) {
+                #( ::ditto::Nested::nested_force_merge(&mut self.#idents, other.#idents, summary, other_summary); )*
+            }
+
+            fn nested_unchanged_since(&self, since: &::ditto::dot::Summary) -> bool {
+                #( ::ditto::Nested::nested_unchanged_since(&self.#idents, since) )&&*
+            }
+
+            fn nested_add_site_id(&mut self, site_id: ::ditto::dot::SiteId) {
+                #( ::ditto::Nested::nested_add_site_id(&mut self.#idents, site_id); )*
+            }
+
+            fn nested_validate_all(&self, site_id: ::ditto::dot::SiteId) -> Result<(), ::ditto::Error> {
+                #( ::ditto::Nested::nested_validate_all(&self.#idents, site_id)?; )*
+                Ok(())
+            }
+
+            fn nested_validate_no_unassigned_sites(&self) -> Result<(), ::ditto::Error> {
+                #( ::ditto::Nested::nested_validate_no_unassigned_sites(&self.#idents)?; )*
+                Ok(())
+            }
+
+            fn nested_can_merge(&self, other: &Self) -> bool {
+                #( ::ditto::Nested::nested_can_merge(&self.#idents, &other.#idents) )&&*
+            }
+        }
+
+        impl ::ditto::CausalOp for #op {
+            fn inserted_dots(&self) -> Vec<::ditto::dot::Dot> {
+                match *self {
+                    #( #op::#variants(ref op) => ::ditto::CausalOp::inserted_dots(op), )*
+                }
+            }
+
+            fn referenced_dots(&self) -> Vec<::ditto::dot::Dot> {
+                match *self {
+                    #( #op::#variants(ref op) => ::ditto::CausalOp::referenced_dots(op), )*
+                }
+            }
+        }
+
+        impl ::ditto::NestedOp for #op {
+            fn nested_add_site_id(&mut self, site_id: ::ditto::dot::SiteId) {
+                match *self {
+                    #( #op::#variants(ref mut op) => ::ditto::NestedOp::nested_add_site_id(op, site_id), )*
+                }
+            }
+
+            fn nested_validate(&self, site_id: ::ditto::dot::SiteId) -> Result<(), ::ditto::Error> {
+                match *self {
+                    #( #op::#variants(ref op) => ::ditto::NestedOp::nested_validate(op, site_id), )*
+                }
+            }
+        }
+    })
+}
+
+/// Converts a snake_case field name to a CamelCase variant name.
+fn camel_case(name: &str) -> String {
+    name.split('_')
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            let mut chars = part.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars).collect(),
+                None => String::new(),
+            }
+        })
+        .collect()
+}
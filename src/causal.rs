@@ -0,0 +1,194 @@
+//! A wrapper that executes remote ops in causal order.
+
+use Error;
+use dot::{Dot, SiteId};
+use traits::{Crdt, CausalOp};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+
+/// A CausalReplica wraps a CRDT so that remote ops can be delivered
+/// in any order. This allows op-based replication over transports
+/// that drop, duplicate, or reorder messages, such as UDP, gossip
+/// protocols, or multi-path relays.
+///
+/// Each received op is checked against the CRDT's summary. An op is
+/// held back until the CRDT has seen the preceding op from the same
+/// site and every element that the op removes or edits. Held-back ops
+/// are executed as soon as their gaps are filled, either by other ops
+/// or by merged state. Held-back ops are indexed by the dots they are
+/// waiting for, so executing an op only checks the ops that were
+/// waiting for its dots. Ops that the CRDT has already seen are ignored.
+///
+/// Local edits can be made through [`crdt_mut`](#method.crdt_mut).
+///
+/// ```
+/// use ditto::{CausalReplica, List};
+///
+/// let mut list1 = List::new();
+/// let list2 = List::from_state(list1.clone_state(), Some(2)).unwrap();
+/// let op1 = list1.push(1).unwrap();
+/// let op2 = list1.push(2).unwrap();
+///
+/// let mut replica = CausalReplica::new(list2);
+/// assert!(replica.receive(op2).is_empty());
+/// assert_eq!(replica.missing_dots().len(), 1);
+/// assert_eq!(replica.receive(op1).len(), 2);
+/// assert_eq!(replica.crdt().local_value(), [1, 2]);
+/// ```
+pub struct CausalReplica<C: Crdt> {
+    crdt:    C,
+    pending: BTreeMap<u64, Pending<C::Op>>,
+    waiting: HashMap<Dot, Vec<u64>>,
+    next_id: u64,
+}
+
+/// A held-back op and the number of dots it is still waiting for.
+struct Pending<O> {
+    op:      O,
+    missing: usize,
+}
+
+impl<C: Crdt> CausalReplica<C> where C::Op: CausalOp {
+
+    /// Constructs and returns a new CausalReplica that wraps a CRDT.
+    pub fn new(crdt: C) -> Self {
+        CausalReplica{crdt, pending: BTreeMap::new(), waiting: HashMap::new(), next_id: 0}
+    }
+
+    /// Returns a reference to the wrapped CRDT.
+    pub fn crdt(&self) -> &C {
+        &self.crdt
+    }
+
+    /// Returns a mutable reference to the wrapped CRDT
+    /// for making local edits.
+    pub fn crdt_mut(&mut self) -> &mut C {
+        &mut self.crdt
+    }
+
+    /// Consumes the CausalReplica and returns the wrapped CRDT.
+    /// Any held-back ops are dropped.
+    pub fn into_crdt(self) -> C {
+        self.crdt
+    }
+
+    /// Returns the ops that are waiting for missing dots,
+    /// in the order they were received.
+    pub fn pending_ops(&self) -> Vec<&C::Op> {
+        self.pending.values().map(|pending| &pending.op).collect()
+    }
+
+    /// Receives a remote op. If the CRDT is ready for the op, the op
+    /// is executed along with any held-back ops that it unblocks.
+    /// Otherwise the op is held back. Returns the local ops of every
+    /// executed op, in execution order.
+    pub fn receive(&mut self, op: C::Op) -> Vec<C::LocalOp> {
+        let mut local_ops = vec![];
+        if has_executed(&self.crdt, &op) { return local_ops }
+
+        let missing: Vec<Dot> = {
+            let summary = self.crdt.summary();
+            op.required_dots().into_iter().filter(|dot| !summary.contains(dot)).collect()
+        };
+
+        if missing.is_empty() {
+            self.execute(VecDeque::from(vec![op]), &mut local_ops);
+        } else {
+            let id = self.next_id;
+            self.next_id += 1;
+            for dot in &missing {
+                self.waiting.entry(*dot).or_default().push(id);
+            }
+            let _ = self.pending.insert(id, Pending{op, missing: missing.len()});
+        }
+
+        local_ops
+    }
+
+    /// Validates that an op only inserts elements from the given
+    /// site id, then receives the op.
+    pub fn validate_and_receive(&mut self, op: C::Op, site_id: SiteId) -> Result<Vec<C::LocalOp>, Error> {
+        if op.inserted_dots().iter().any(|dot| dot.site_id != site_id) {
+            return Err(Error::InvalidOp)
+        }
+        Ok(self.receive(op))
+    }
+
+    /// Merges remote state into the CRDT, then executes any
+    /// held-back ops that the state unblocks. Returns the local ops
+    /// of the merge followed by those of every executed op.
+    pub fn merge(&mut self, state: C::State) -> Result<Vec<C::LocalOp>, Error> {
+        let mut local_ops = self.crdt.merge(state)?;
+
+        let executed: Vec<u64> = self.pending.iter()
+            .filter(|&(_, pending)| has_executed(&self.crdt, &pending.op))
+            .map(|(id, _)| *id)
+            .collect();
+        for id in executed {
+            let _ = self.pending.remove(&id);
+        }
+        let pending = &self.pending;
+        self.waiting.retain(|_, ids| {
+            ids.retain(|id| pending.contains_key(id));
+            !ids.is_empty()
+        });
+
+        let seen_dots: Vec<Dot> = {
+            let summary = self.crdt.summary();
+            self.waiting.keys().filter(|dot| summary.contains(dot)).cloned().collect()
+        };
+        let mut ready = VecDeque::new();
+        self.release(seen_dots, &mut ready);
+        self.execute(ready, &mut local_ops);
+        Ok(local_ops)
+    }
+
+    /// Returns the dots that held-back ops are waiting for,
+    /// excluding dots inserted by other held-back ops.
+    pub fn missing_dots(&self) -> Vec<Dot> {
+        let summary = self.crdt.summary();
+        let pending_dots: HashSet<Dot> = self.pending.values()
+            .flat_map(|pending| pending.op.inserted_dots())
+            .collect();
+
+        let mut missing_dots: Vec<Dot> = self.waiting.keys()
+            .filter(|dot| !summary.contains(dot) && !pending_dots.contains(dot))
+            .cloned()
+            .collect();
+
+        missing_dots.sort();
+        missing_dots
+    }
+
+    /// Executes the ready ops, and every held-back op that
+    /// they unblock.
+    fn execute(&mut self, mut ready: VecDeque<C::Op>, local_ops: &mut Vec<C::LocalOp>) {
+        while let Some(op) = ready.pop_front() {
+            if has_executed(&self.crdt, &op) { continue }
+            let inserted_dots = op.inserted_dots();
+            local_ops.push(self.crdt.execute_op(op));
+            self.release(inserted_dots, &mut ready);
+        }
+    }
+
+    /// Marks the dots as seen and moves the held-back ops that
+    /// are no longer waiting for any dot to `ready`.
+    fn release(&mut self, dots: Vec<Dot>, ready: &mut VecDeque<C::Op>) {
+        for dot in dots {
+            for id in self.waiting.remove(&dot).unwrap_or_default() {
+                let is_ready = match self.pending.get_mut(&id) {
+                    Some(pending) => { pending.missing -= 1; pending.missing == 0 }
+                    None => false,
+                };
+                if is_ready {
+                    ready.push_back(self.pending.remove(&id).unwrap().op);
+                }
+            }
+        }
+    }
+}
+
+fn has_executed<C: Crdt>(crdt: &C, op: &C::Op) -> bool where C::Op: CausalOp {
+    let inserted_dots = op.inserted_dots();
+    let summary = crdt.summary();
+    !inserted_dots.is_empty() && inserted_dots.iter().all(|dot| summary.contains(dot))
+}
@@ -1,7 +1,8 @@
 //! A CRDT that stores an `i64` value that increments
 
 use Error;
-use dot::{SiteId, Counter as RCounter};
+use dot::{Dot, SiteId, Summary, Counter as RCounter};
+use traits::{Crdt, CausalOp, Nested, NestedOp, NestedCrdt};
 use std::borrow::Cow;
 use std::collections::HashMap;
 
@@ -24,17 +25,34 @@ use std::collections::HashMap;
 ///     sites that have incremented the `Counter`
 ///
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(from = "CounterFields")]
 pub struct Counter {
     inner:            CounterInner,
     site_id:          SiteId,
     awaiting_site_id: Option<Op>,
+    #[serde(skip_serializing)]
+    summary:          Summary,
+}
+
+/// The serialized fields of a `Counter`. The summary is not
+/// serialized; it is rebuilt from the increments.
+#[derive(Deserialize)]
+struct CounterFields {
+    inner:            CounterInner,
+    site_id:          SiteId,
+    awaiting_site_id: Option<Op>,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct CounterState<'a>(Cow<'a, CounterInner>);
 
+/// The increments of every site. Each site's total is stored with
+/// the counter of its latest increment, so that the counters can
+/// come from the summary of a CRDT that nests the counter, such as
+/// [`Json`](../json/Json.t.html).
+#[doc(hidden)]
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
-pub(crate) struct CounterInner(HashMap<SiteId, SiteInc>);
+pub struct CounterInner(HashMap<SiteId, SiteInc>);
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 struct SiteInc {
@@ -54,8 +72,9 @@ impl Counter {
     /// Constructs and returns a new Counter with site id 1.
     pub fn new(value: i64) -> Self {
         let site_id = 1;
-        let inner = CounterInner::new(value, site_id);
-        Counter{inner, site_id, awaiting_site_id: None}
+        let inner = CounterInner::new(value, Dot::new(site_id, 1));
+        let summary = inner.summary();
+        Counter{inner, site_id, awaiting_site_id: None, summary}
     }
 
     /// Returns the counter value.
@@ -69,6 +88,7 @@ impl Counter {
     /// op and returns an `AwaitingSiteId` error.
     pub fn increment(&mut self, amount: i64) -> Result<Op, Error> {
         let op = self.inner.increment(amount, self.site_id);
+        self.summary.insert_up_to(Dot::new(op.site_id, op.counter));
         if self.site_id == 0 {
             self.awaiting_site_id = Some(op);
             Err(Error::AwaitingSiteId)
@@ -82,6 +102,12 @@ impl Counter {
         self.site_id
     }
 
+    /// Returns a reference to the summary of the increments
+    /// the `Counter` has seen.
+    pub fn summary(&self) -> &Summary {
+        &self.summary
+    }
+
     /// Returns a reference to the `Counter` state.
     pub fn state(&self) -> CounterState {
         CounterState(Cow::Borrowed(&self.inner))
@@ -106,17 +132,16 @@ impl Counter {
             Some(s) => s,
         };
 
-        Ok(Counter{
-            inner: state.0.into_owned(),
-            site_id,
-            awaiting_site_id: None,
-        })
+        let inner = state.0.into_owned();
+        let summary = inner.summary();
+        Ok(Counter{inner, site_id, awaiting_site_id: None, summary})
     }
 
     /// Executes an Op and returns the equivalent increment.
     /// If the op has already been executed or superceded,
     /// nothing is done.
     pub fn execute_op(&mut self, op: &Op) -> Option<i64> {
+        self.summary.insert_up_to(Dot::new(op.site_id, op.counter));
         self.inner.execute_op(op)
     }
 
@@ -129,7 +154,22 @@ impl Counter {
 
     /// Merges remote state into the Counter.
     pub fn merge(&mut self, other: CounterState) {
-        self.inner.merge(other.0.into_owned())
+        let other = other.0.into_owned();
+        for (site_id, site_inc) in &other.0 {
+            self.summary.insert_up_to(Dot::new(*site_id, site_inc.counter));
+        }
+        self.inner.merge(other)
+    }
+
+    /// Returns the state of every site whose increments are not
+    /// yet in the given summary.
+    pub fn delta_since(&self, since: &Summary) -> CounterState<'static> {
+        CounterState(Cow::Owned(self.inner.delta_since(since)))
+    }
+
+    /// Merges a delta from a remote `Counter` into the `Counter`.
+    pub fn merge_delta(&mut self, delta: CounterState) {
+        self.merge(delta)
     }
 
     /// Assigns a site id and returns a cached op if it exists.
@@ -137,6 +177,7 @@ impl Counter {
         if self.site_id != 0 { return Err(Error::AlreadyHasSiteId) }
         self.site_id = site_id;
         self.inner.add_site_id(site_id);
+        self.summary.add_site_id(site_id);
 
         if let Some(mut op) = self.awaiting_site_id.take() {
             op.add_site_id(site_id);
@@ -147,28 +188,168 @@ impl Counter {
     }
 }
 
+impl Crdt for Counter {
+    type State = CounterState<'static>;
+    type Op = Op;
+    type LocalOp = Option<i64>;
+    type LocalValue = i64;
+    type Delta = CounterState<'static>;
+
+    fn site_id(&self) -> SiteId {
+        self.site_id
+    }
+
+    fn summary(&self) -> Cow<'_, Summary> {
+        Cow::Borrowed(&self.summary)
+    }
+
+    fn clone_state(&self) -> CounterState<'static> {
+        Counter::clone_state(self)
+    }
+
+    fn into_state(self) -> CounterState<'static> {
+        Counter::into_state(self)
+    }
+
+    fn from_state(state: CounterState<'static>, site_id: Option<SiteId>) -> Result<Self, Error> {
+        Counter::from_state(state, site_id)
+    }
+
+    fn local_value(&self) -> i64 {
+        self.get()
+    }
+
+    fn execute_op(&mut self, op: Op) -> Option<i64> {
+        Counter::execute_op(self, &op)
+    }
+
+    fn validate_and_execute_op(&mut self, op: Op, site_id: SiteId) -> Result<Option<i64>, Error> {
+        Counter::validate_and_execute_op(self, &op, site_id)
+    }
+
+    fn merge(&mut self, other: CounterState<'static>) -> Result<Vec<Option<i64>>, Error> {
+        let value = self.get();
+        Counter::merge(self, other);
+        let diff = self.get() - value;
+        Ok(if diff == 0 { vec![] } else { vec![Some(diff)] })
+    }
+
+    fn delta_since(&self, since: &Summary) -> CounterState<'static> {
+        Counter::delta_since(self, since)
+    }
+
+    fn merge_delta(&mut self, delta: CounterState<'static>) -> Result<(), Error> {
+        Counter::merge_delta(self, delta);
+        Ok(())
+    }
+
+    fn add_site_id(&mut self, site_id: SiteId) -> Result<Vec<Op>, Error> {
+        Ok(Counter::add_site_id(self, site_id)?.into_iter().collect())
+    }
+}
+
+impl Nested for Counter {
+    type NestedOp = Op;
+    type NestedLocalOp = i64;
+
+    fn nested_reset(&mut self, dot: Dot) {
+        self.inner = CounterInner::new(self.get(), dot);
+        self.summary = self.inner.summary();
+        self.site_id = 0;
+        self.awaiting_site_id = None;
+    }
+
+    fn nested_execute_op(&mut self, op: Op) -> Vec<i64> {
+        self.execute_op(&op).into_iter().collect()
+    }
+
+    fn nested_merge(&mut self, other: Counter, _: &Summary, _: &Summary) -> Vec<i64> {
+        let value = self.get();
+        self.inner.merge(other.inner);
+        self.summary = self.inner.summary();
+        let diff = self.get() - value;
+        if diff == 0 { vec![] } else { vec![diff] }
+    }
+
+    fn nested_unchanged_since(&self, since: &Summary) -> bool {
+        self.inner.0.iter().all(|(site_id, site_inc)| since.contains_pair(*site_id, site_inc.counter))
+    }
+
+    fn nested_add_site_id(&mut self, site_id: SiteId) {
+        self.inner.add_site_id(site_id);
+        self.summary.add_site_id(site_id);
+    }
+
+    fn nested_validate_all(&self, site_id: SiteId) -> Result<(), Error> {
+        self.inner.validate_all(site_id)
+    }
+
+    fn nested_validate_no_unassigned_sites(&self) -> Result<(), Error> {
+        self.inner.validate_no_unassigned_sites()
+    }
+}
+
+impl NestedCrdt for Counter {
+    fn nested_edit<F>(&mut self, summary: &mut Summary, site_id: SiteId, f: F) -> Result<Op, Error>
+        where F: FnOnce(&mut Self) -> Result<Op, Error>
+    {
+        self.site_id = site_id;
+        let result = f(self);
+        self.site_id = 0;
+
+        let mut op = match self.awaiting_site_id.take() {
+            Some(op) => op,
+            None => result?,
+        };
+
+        // the increment's counter comes from the enclosing CRDT's summary
+        let dot = summary.get_dot(site_id);
+        op.counter = dot.counter;
+        if let Some(site_inc) = self.inner.0.get_mut(&site_id) {
+            site_inc.counter = dot.counter;
+        }
+        self.summary = self.inner.summary();
+        Ok(op)
+    }
+}
+
+impl From<CounterFields> for Counter {
+    fn from(fields: CounterFields) -> Self {
+        let CounterFields{inner, site_id, awaiting_site_id} = fields;
+        let summary = inner.summary();
+        Counter{inner, site_id, awaiting_site_id, summary}
+    }
+}
+
 impl CounterInner {
-    fn new(inc: i64, site_id: SiteId) -> Self {
+    pub(crate) fn new(inc: i64, dot: Dot) -> Self {
         let mut map = HashMap::new();
-        map.insert(site_id, SiteInc{inc, counter: 1});
+        map.insert(dot.site_id, SiteInc{inc, counter: dot.counter});
         CounterInner(map)
     }
 
-    fn get(&self) -> i64 {
+    pub(crate) fn get(&self) -> i64 {
         self.0.values().fold(0, |sum, site_count| sum + site_count.inc)
     }
 
     fn increment(&mut self, amount: i64, site_id: SiteId) -> Op {
+        let counter = self.0.get(&site_id).map_or(0, |site_inc| site_inc.counter) + 1;
+        self.increment_at(amount, Dot::new(site_id, counter))
+    }
+
+    /// Increments the counter with an op whose counter is
+    /// `dot.counter`, which must exceed the site's last counter.
+    pub(crate) fn increment_at(&mut self, amount: i64, dot: Dot) -> Op {
         let site_inc = self.0
-            .entry(site_id)
+            .entry(dot.site_id)
             .or_insert_with(|| SiteInc{inc: 0, counter: 0});
 
         site_inc.inc += amount;
-        site_inc.counter += 1;
-        Op{site_id, counter: site_inc.counter, inc: site_inc.inc}
+        site_inc.counter = dot.counter;
+        Op{site_id: dot.site_id, counter: dot.counter, inc: site_inc.inc}
     }
 
-    fn execute_op(&mut self, op: &Op) -> Option<i64> {
+    pub(crate) fn execute_op(&mut self, op: &Op) -> Option<i64> {
         let Op{site_id, counter, inc} = *op;
         let site_inc = self.0
             .entry(site_id)
@@ -184,7 +365,7 @@ impl CounterInner {
         }
     }
 
-    fn merge(&mut self, other: CounterInner) {
+    pub(crate) fn merge(&mut self, other: CounterInner) {
         for (site_id, SiteInc{inc, counter}) in other.0 {
             let site_inc = self.0
                 .entry(site_id)
@@ -197,11 +378,35 @@ impl CounterInner {
         }
     }
 
-    fn add_site_id(&mut self, site_id: SiteId) {
+    fn summary(&self) -> Summary {
+        let mut summary = Summary::default();
+        for (site_id, site_inc) in &self.0 {
+            summary.insert_up_to(Dot::new(*site_id, site_inc.counter));
+        }
+        summary
+    }
+
+    pub(crate) fn delta_since(&self, since: &Summary) -> CounterInner {
+        let map = self.0.iter()
+            .filter(|&(site_id, site_inc)| !since.contains_pair(*site_id, site_inc.counter))
+            .map(|(site_id, site_inc)| (*site_id, site_inc.clone()))
+            .collect();
+        CounterInner(map)
+    }
+
+    pub(crate) fn add_site_id(&mut self, site_id: SiteId) {
         if let Some(site_inc) = self.0.remove(&0) {
             self.0.insert(site_id, site_inc);
         }
     }
+
+    pub(crate) fn validate_no_unassigned_sites(&self) -> Result<(), Error> {
+        if self.0.contains_key(&0) { Err(Error::InvalidSiteId) } else { Ok(()) }
+    }
+
+    pub(crate) fn validate_all(&self, site_id: SiteId) -> Result<(), Error> {
+        if self.0.keys().all(|s| *s == site_id) { Ok(()) } else { Err(Error::InvalidSiteId) }
+    }
 }
 
 impl Op {
@@ -224,3 +429,29 @@ impl Op {
         if self.site_id == site_id { Ok(()) } else { Err(Error::InvalidOp) }
     }
 }
+
+impl NestedOp for Op {
+    fn nested_add_site_id(&mut self, site_id: SiteId) {
+        if self.site_id == 0 { self.site_id = site_id; }
+    }
+
+    fn nested_validate(&self, site_id: SiteId) -> Result<(), Error> {
+        self.validate(site_id)
+    }
+}
+
+impl CausalOp for Op {
+    fn inserted_dots(&self) -> Vec<Dot> {
+        vec![Dot::new(self.site_id, self.counter)]
+    }
+
+    fn referenced_dots(&self) -> Vec<Dot> {
+        vec![]
+    }
+
+    /// Counter ops carry each site's running total, so an op
+    /// never needs to wait for the ops that precede it.
+    fn required_dots(&self) -> Vec<Dot> {
+        vec![]
+    }
+}
@@ -1,28 +1,54 @@
-use map_tuple_vec;
-use std::cmp::max;
-use std::collections::HashMap;
+use serde::{Serialize, Serializer, Deserialize, Deserializer};
+use serde::de::{self, SeqAccess, Visitor};
+use serde::ser::SerializeSeq;
+use std::cmp::{max, min, Ordering};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
 
 pub type SiteId = u32;
 pub type Counter = u32;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub struct Dot {
     pub site_id: SiteId,
     pub counter: Counter,
 }
 
-#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
-pub struct Summary(#[serde(with = "map_tuple_vec")] HashMap<u32,u32>);
+/// A Summary records every dot that a CRDT has seen. For each site
+/// it stores the highest counter seen plus any gaps below that
+/// counter, so dots that are delivered out of order are recorded
+/// exactly. A site without gaps is serialized as a single
+/// `(site_id, counter)` pair.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Summary(HashMap<SiteId, SiteSummary>);
+
+/// The dots seen from a single site: every counter up to and
+/// including `counter` except those in `gaps`. Gaps are sorted,
+/// disjoint, inclusive ranges of counters.
+#[derive(Debug, Clone, Default, PartialEq)]
+struct SiteSummary {
+    counter: Counter,
+    gaps: Vec<(Counter, Counter)>,
+}
 
 impl Dot {
     pub fn new(site_id: SiteId, counter: Counter) -> Self {
         Dot{site_id, counter}
     }
+
+    pub fn predecessor(&self) -> Option<Dot> {
+        if self.counter > 1 {
+            Some(Dot{site_id: self.site_id, counter: self.counter - 1})
+        } else {
+            None
+        }
+    }
 }
 
 impl Summary {
+    /// Returns the highest counter seen from a site.
     pub fn get(&self, site_id: SiteId) -> Counter {
-        *self.0.get(&site_id).unwrap_or(&0)
+        self.0.get(&site_id).map_or(0, |site| site.counter)
     }
 
     pub fn get_dot(&mut self, site_id: SiteId) -> Dot {
@@ -30,48 +56,104 @@ impl Summary {
         Dot{site_id, counter}
     }
 
+    pub fn next_dot(&self, site_id: SiteId) -> Dot {
+        let counter = self.get(site_id) + 1;
+        Dot{site_id, counter}
+    }
+
     pub fn increment(&mut self, site_id: SiteId) -> Counter {
-        let entry = self.0.entry(site_id).or_insert(0);
-        *entry += 1;
-        *entry
+        let entry = self.0.entry(site_id).or_default();
+        entry.counter += 1;
+        entry.counter
     }
 
     pub fn contains(&self, dot: &Dot) -> bool {
-        match self.0.get(&dot.site_id) {
-            Some(counter) => *counter >= dot.counter,
-            None => false,
-        }
+        self.contains_pair(dot.site_id, dot.counter)
     }
 
     pub fn contains_pair(&self, site_id: u32, counter: u32) -> bool {
         match self.0.get(&site_id) {
-            Some(site_counter) => *site_counter >= counter,
+            Some(site) => site.contains(counter),
             None => false,
         }
     }
 
+    /// Returns true if the summary has gaps for any site.
+    pub fn has_gaps(&self) -> bool {
+        self.0.values().any(|site| !site.gaps.is_empty())
+    }
+
+    /// Records a single dot. Dots between the site's previous
+    /// highest counter and the new dot are recorded as gaps.
     pub fn insert(&mut self, dot: Dot) {
-        let entry = self.0.entry(dot.site_id).or_insert(dot.counter);
-        *entry = max(*entry, dot.counter);
+        self.insert_pair(dot.site_id, dot.counter)
     }
 
     pub fn insert_pair(&mut self, site_id: u32, counter: u32) {
-        let entry = self.0.entry(site_id).or_insert(counter);
-        *entry = max(*entry, counter);
+        self.0.entry(site_id).or_default().insert(counter)
+    }
+
+    /// Records a dot along with every preceding dot from its site.
+    pub fn insert_up_to(&mut self, dot: Dot) {
+        self.0.entry(dot.site_id).or_default().insert_up_to(dot.counter)
+    }
+
+    /// Compares the summary to another summary. Returns `Less` or
+    /// `Greater` if one summary has seen a strict subset of the dots
+    /// the other has seen, `Equal` if both have seen the same dots,
+    /// and `None` if each has seen dots the other has not.
+    pub fn compare(&self, other: &Summary) -> Option<Ordering> {
+        let self_ahead = !self.missing_from(other).is_empty();
+        let other_ahead = !other.missing_from(self).is_empty();
+        match (self_ahead, other_ahead) {
+            (false, false) => Some(Ordering::Equal),
+            (false, true) => Some(Ordering::Less),
+            (true, false) => Some(Ordering::Greater),
+            (true, true) => None,
+        }
+    }
+
+    /// Returns, per site, the inclusive ranges of counters that
+    /// this summary has seen and `other` has not. Sites with
+    /// nothing missing are omitted.
+    pub fn missing_from(&self, other: &Summary) -> BTreeMap<SiteId, Vec<(Counter, Counter)>> {
+        let mut missing = BTreeMap::new();
+        let unseen_site = SiteSummary::default();
+        for (site_id, site) in &self.0 {
+            let other_site = other.0.get(site_id).unwrap_or(&unseen_site);
+            let ranges = intersect(&site.seen(), &other_site.missing_up_to(site.counter));
+            if !ranges.is_empty() {
+                missing.insert(*site_id, ranges);
+            }
+        }
+        missing
+    }
+
+    /// Returns a summary of the dots this summary has seen,
+    /// except for `dots`.
+    pub fn without(&self, mut dots: Vec<Dot>) -> Summary {
+        dots.sort();
+        let mut summary = Summary::default();
+        for (site_id, site) in &self.0 {
+            let start = dots.binary_search(&Dot::new(*site_id, 0)).unwrap_or_else(|idx| idx);
+            let counters = dots[start..].iter().take_while(|dot| dot.site_id == *site_id).map(|dot| dot.counter);
+            let seen = subtract(&site.seen(), counters);
+            if !seen.is_empty() {
+                summary.0.insert(*site_id, SiteSummary::from_seen(&seen));
+            }
+        }
+        summary
     }
 
     pub fn merge(&mut self, other: &Summary) {
-        for (site_id, counter) in &other.0 {
-            let site_id = *site_id;
-            let counter = *counter;
-            let entry = self.0.entry(site_id).or_insert(counter);
-            *entry = max(*entry, counter);
+        for (site_id, other_site) in &other.0 {
+            self.0.entry(*site_id).or_default().merge(other_site);
         }
     }
 
     pub fn add_site_id(&mut self, site_id: SiteId) {
-        if let Some(counter) = self.0.remove(&0) {
-            self.0.insert(site_id, counter);
+        if let Some(site) = self.0.remove(&0) {
+            self.0.insert(site_id, site);
         }
     }
 
@@ -79,3 +161,203 @@ impl Summary {
         if self.0.contains_key(&0) { Err(::Error::InvalidSiteId) } else { Ok(()) }
     }
 }
+
+impl SiteSummary {
+    /// Returns the site that has seen exactly the given
+    /// sorted, disjoint ranges of counters.
+    fn from_seen(seen: &[(Counter, Counter)]) -> Self {
+        let mut site = SiteSummary::default();
+        for &(lo, hi) in seen {
+            if lo > site.counter + 1 {
+                site.gaps.push((site.counter + 1, lo - 1));
+            }
+            site.counter = hi;
+        }
+        site
+    }
+
+    fn contains(&self, counter: Counter) -> bool {
+        counter <= self.counter && self.gap_idx(counter).is_err()
+    }
+
+    fn insert(&mut self, counter: Counter) {
+        if counter > self.counter {
+            if counter > self.counter + 1 {
+                self.gaps.push((self.counter + 1, counter - 1));
+            }
+            self.counter = counter;
+        } else if let Ok(idx) = self.gap_idx(counter) {
+            let (lo, hi) = self.gaps[idx];
+            if lo == hi {
+                self.gaps.remove(idx);
+            } else if counter == lo {
+                self.gaps[idx].0 = lo + 1;
+            } else if counter == hi {
+                self.gaps[idx].1 = hi - 1;
+            } else {
+                self.gaps[idx].1 = counter - 1;
+                self.gaps.insert(idx + 1, (counter + 1, hi));
+            }
+        }
+    }
+
+    fn insert_up_to(&mut self, counter: Counter) {
+        self.gaps.retain(|&(_, hi)| hi > counter);
+        if let Some(gap) = self.gaps.first_mut() {
+            gap.0 = max(gap.0, counter + 1);
+        }
+        self.counter = max(self.counter, counter);
+    }
+
+    /// The resulting site has seen every counter that either
+    /// site has seen, so its gaps are the intersection of the
+    /// counters missing from each site.
+    fn merge(&mut self, other: &SiteSummary) {
+        let counter = max(self.counter, other.counter);
+        self.gaps = intersect(&self.missing_up_to(counter), &other.missing_up_to(counter));
+        self.counter = counter;
+    }
+
+    /// Returns the ranges of counters the site has seen.
+    fn seen(&self) -> Vec<(Counter, Counter)> {
+        let mut seen = vec![];
+        let mut lo = 1;
+        for &(gap_lo, gap_hi) in &self.gaps {
+            if lo < gap_lo { seen.push((lo, gap_lo - 1)); }
+            lo = gap_hi + 1;
+        }
+        if lo <= self.counter {
+            seen.push((lo, self.counter));
+        }
+        seen
+    }
+
+    fn missing_up_to(&self, counter: Counter) -> Vec<(Counter, Counter)> {
+        let mut missing = self.gaps.clone();
+        if self.counter < counter {
+            missing.push((self.counter + 1, counter));
+        }
+        missing
+    }
+
+    fn gap_idx(&self, counter: Counter) -> Result<usize, usize> {
+        self.gaps.binary_search_by(|&(lo, hi)| {
+            if hi < counter {
+                Ordering::Less
+            } else if lo > counter {
+                Ordering::Greater
+            } else {
+                Ordering::Equal
+            }
+        })
+    }
+}
+
+/// Returns the intersection of two sorted lists of disjoint,
+/// inclusive ranges.
+fn intersect(ranges1: &[(Counter, Counter)], ranges2: &[(Counter, Counter)]) -> Vec<(Counter, Counter)> {
+    let mut ranges = vec![];
+    let (mut i, mut j) = (0, 0);
+
+    while i < ranges1.len() && j < ranges2.len() {
+        let (lo1, hi1) = ranges1[i];
+        let (lo2, hi2) = ranges2[j];
+        let lo = max(lo1, lo2);
+        let hi = min(hi1, hi2);
+        if lo <= hi { ranges.push((lo, hi)); }
+        if hi1 < hi2 { i += 1 } else { j += 1 }
+    }
+
+    ranges
+}
+
+/// Returns a sorted list of disjoint, inclusive ranges with
+/// the given sorted counters removed.
+fn subtract<I: Iterator<Item = Counter>>(ranges: &[(Counter, Counter)], counters: I) -> Vec<(Counter, Counter)> {
+    let mut counters = counters.peekable();
+    let mut result = vec![];
+
+    for &(lo, hi) in ranges {
+        let mut next = Some(lo);
+        while let Some(&counter) = counters.peek() {
+            if counter > hi { break }
+            let _ = counters.next();
+            let lo = match next {
+                Some(lo) if counter >= lo => lo,
+                _ => continue,
+            };
+            if counter > lo { result.push((lo, counter - 1)); }
+            next = if counter < hi { Some(counter + 1) } else { None };
+        }
+        if let Some(lo) = next {
+            result.push((lo, hi));
+        }
+    }
+
+    result
+}
+
+impl Serialize for Summary {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(Some(self.0.len()))?;
+        for (site_id, site) in &self.0 {
+            if site.gaps.is_empty() {
+                seq.serialize_element(&(site_id, site.counter))?;
+            } else {
+                seq.serialize_element(&(site_id, site.counter, &site.gaps))?;
+            }
+        }
+        seq.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for Summary {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let entries: Vec<SiteEntry> = Deserialize::deserialize(deserializer)?;
+        let map = entries.into_iter()
+            .map(|SiteEntry(site_id, site)| (site_id, site))
+            .collect();
+        Ok(Summary(map))
+    }
+}
+
+/// A serialized site: `(site_id, counter)` or
+/// `(site_id, counter, gaps)` if the site has gaps.
+struct SiteEntry(SiteId, SiteSummary);
+
+impl<'de> Deserialize<'de> for SiteEntry {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_seq(SiteEntryVisitor)
+    }
+}
+
+struct SiteEntryVisitor;
+
+impl<'de> Visitor<'de> for SiteEntryVisitor {
+    type Value = SiteEntry;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a (site_id, counter) or (site_id, counter, gaps) tuple")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<SiteEntry, A::Error> {
+        let site_id = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(0, &self))?;
+        let counter = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(1, &self))?;
+        let gaps: Vec<(Counter, Counter)> = seq.next_element()?.unwrap_or_default();
+        if !valid_gaps(&gaps, counter) {
+            return Err(de::Error::invalid_value(de::Unexpected::Seq, &"sorted, disjoint gaps below the counter"))
+        }
+        Ok(SiteEntry(site_id, SiteSummary{counter, gaps}))
+    }
+}
+
+/// Returns true if the gaps are sorted, disjoint, nonempty
+/// ranges of counters between 1 and `counter` (exclusive).
+fn valid_gaps(gaps: &[(Counter, Counter)], counter: Counter) -> bool {
+    let mut min_lo = 1;
+    for &(lo, hi) in gaps {
+        if lo < min_lo || lo > hi || hi >= counter { return false }
+        min_lo = hi + 1;
+    }
+    true
+}
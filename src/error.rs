@@ -9,15 +9,18 @@ pub enum Error {
     DeserializeSequenceUid,
     DoesNotExist,
     DuplicateUid,
+    InvalidDelta,
     InvalidIndex,
     InvalidJson,
     InvalidLocalOp,
+    InvalidMessage,
     InvalidOp,
     InvalidPointer,
     InvalidSiteId,
     KeyDoesNotExist,
     Noop,
     OutOfBounds,
+    TestFailed,
     UidDoesNotExist,
     VLQNoTerminatingByte,
     WrongJsonType,
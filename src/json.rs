@@ -1,16 +1,22 @@
 //! A CRDT that stores a JSON value.
 
 use Error;
+use counter::{self, Counter, CounterInner};
 use dot::{Dot, Summary, SiteId};
 use list::{self, Inner as ListInner};
 use map::{self, Inner as MapInner};
+use map_tuple_vec;
+use register::{self, Register, Inner as RegisterInner};
 use text::{self, Inner as TextInner};
 use sequence;
 use traits::*;
 
 use serde_json::{self, Value as SJValue};
 use std::borrow::Cow;
-use std::collections::HashMap;
+use std::cmp::{max, min};
+use std::collections::{HashMap, HashSet};
+use std::mem;
+use std::ops::Range;
 use std::hash::Hash;
 use std::str::FromStr;
 
@@ -31,6 +37,32 @@ use std::str::FromStr;
 /// always have an array as its root. This constraint means that any Json
 /// CRDT with a numeric, boolean, or null root is immutable.
 ///
+/// Array elements can be reordered with [`move_item`](#method.move_item).
+/// A moved element keeps its identity, so edits made concurrently to
+/// it are not lost.
+///
+/// A value can also be a counter or a register, which are inserted
+/// as a [`Counter`](../counter/Counter.t.html) or a
+/// [`Register<Value>`](../register/Register.t.html). A counter is
+/// edited with [`increment`](#method.increment), so concurrent
+/// increments add up instead of overwriting each other, and a
+/// register is replaced with [`update_register`](#method.update_register).
+/// Both appear in the local value as plain JSON values.
+///
+/// Several edits can be grouped into a single op with
+/// [`transaction`](#method.transaction); remote sites validate the
+/// op's edits all together and accept them all or none.
+///
+/// Local edits can be undone and redone through an
+/// [`UndoManager`](struct.UndoManager.html). A whole new value can
+/// be applied with [`set_value`](#method.set_value), which edits only
+/// the parts that changed. [JSON Patch](https://tools.ietf.org/html/rfc6902)
+/// documents can be applied with [`apply_patch`](#method.apply_patch)
+/// (a `move` within one array becomes a `move_item`) and [JSON Merge Patch](https://tools.ietf.org/html/rfc7396) documents
+/// with [`apply_merge_patch`](#method.apply_merge_patch),
+/// and local ops can be converted to JSON Patch with a
+/// [`PatchWriter`](struct.PatchWriter.html).
+///
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Json {
     inner:      Inner,
@@ -45,6 +77,12 @@ pub struct JsonState<'a> {
     summary: Cow<'a, Summary>,
 }
 
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JsonDelta {
+    inner: InnerDelta,
+    summary: Summary,
+}
+
 #[doc(hidden)]
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Inner {
@@ -54,6 +92,24 @@ pub enum Inner {
     Number(f64),
     Bool(bool),
     Null,
+    Counter(CounterInner),
+    Register(RegisterInner<SJValue>),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) enum InnerDelta {
+    Object(#[serde(with = "map_tuple_vec")] HashMap<String, Vec<(Dot, ElementDelta)>>),
+    Array(Vec<(sequence::uid::Uid, Option<list::Moved>, ElementDelta)>),
+    String(text::InnerDelta),
+    Unchanged,
+    Counter(CounterInner),
+    Register(RegisterInner<SJValue>, Vec<Dot>),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) enum ElementDelta {
+    Inserted(Inner),
+    Retained(InnerDelta),
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -67,6 +123,20 @@ pub enum OpInner {
     Object(map::Op<String, Inner>),
     Array(list::Op<Inner>),
     String(text::Op),
+    Transaction(TransactionOp),
+    Counter(counter::Op),
+    Register(register::Op<SJValue>),
+}
+
+/// The ops of a [`Transaction`](struct.Transaction.html). They are
+/// validated and assigned a site id together, so a remote site
+/// accepts either all of them or none. They are executed like
+/// separate ops: an op whose value was removed by a concurrent
+/// edit is skipped, as it would be on its own, and the rest are
+/// still applied. The ops insert consecutive dots from a single site.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TransactionOp {
+    ops: Vec<Op>,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -80,7 +150,10 @@ pub enum Uid {
 pub enum LocalOp {
     Insert{pointer: Vec<LocalUid>, value: SJValue},
     Remove{pointer: Vec<LocalUid>},
+    Move{pointer: Vec<LocalUid>, to: usize},
     ReplaceText{pointer: Vec<LocalUid>, changes: Vec<text::LocalOp>},
+    Transaction{changes: Vec<LocalOp>},
+    Update{pointer: Vec<LocalUid>, value: SJValue},
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -90,6 +163,72 @@ pub enum LocalUid {
     Array(usize),
 }
 
+/// An operation of a [JSON Patch](https://tools.ietf.org/html/rfc6902)
+/// document. It serializes to the RFC 6902 format, so a patch
+/// document deserializes to a `Vec<PatchOp>`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all="snake_case")]
+pub enum PatchOp {
+    Add{path: String, value: SJValue},
+    Remove{path: String},
+    Replace{path: String, value: SJValue},
+    Move{from: String, path: String},
+    Copy{from: String, path: String},
+    Test{path: String, value: SJValue},
+}
+
+/// A PatchWriter converts the local ops of a Json CRDT into
+/// [JSON Patch](https://tools.ietf.org/html/rfc6902) operations,
+/// for clients that apply changes to a plain JSON value.
+///
+/// JSON Patch cannot edit part of a string, so a text change
+/// becomes a `replace` of the whole string. The PatchWriter keeps
+/// its own copy of the value to compute the new strings; it must
+/// be constructed before the local ops are generated and given
+/// every local op in order.
+///
+/// ```
+/// # extern crate ditto;
+/// # #[macro_use] extern crate serde_json;
+/// # fn main() {
+/// use ditto::json::{Json, PatchOp, PatchWriter};
+///
+/// let mut json1 = Json::from_str(r#"{"a":"hello"}"#).unwrap();
+/// let mut json2 = Json::from_state(json1.clone_state(), Some(2)).unwrap();
+/// let op = json1.replace_text("/a", 5, 0, "!").unwrap();
+///
+/// let mut writer = PatchWriter::new(&json2);
+/// let local_op = json2.execute_op(op).unwrap();
+/// assert_eq!(writer.write(&local_op), [PatchOp::Replace{path: "/a".into(), value: json!("hello!")}]);
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct PatchWriter {
+    value: SJValue,
+}
+
+/// A Transaction groups several edits of a Json CRDT into a single
+/// op. It edits the CRDT in place and records how to undo each edit,
+/// so the edits can be rolled back if one of them fails. Transactions
+/// are run with [`Json::transaction`](struct.Json.html#method.transaction).
+#[derive(Debug)]
+pub struct Transaction<'a> {
+    json:      &'a mut Json,
+    ops:       Vec<Op>,
+    rollbacks: Vec<Rollback>,
+}
+
+/// Restores what one of a transaction's edits changed.
+#[derive(Debug)]
+enum Rollback {
+    Object{pointer: Vec<Uid>, key: String, elements: Option<Vec<map::Element<Inner>>>},
+    ArrayInsert{pointer: Vec<Uid>, uid: sequence::uid::Uid},
+    ArrayRemove{pointer: Vec<Uid>, element: list::Element<Inner>},
+    ArrayMove{pointer: Vec<Uid>, uid: sequence::uid::Uid, old_uid: sequence::uid::Uid, old_moved: Option<list::Moved>},
+    String{pointer: Vec<Uid>, inserted_uids: Vec<sequence::uid::Uid>, removed_elements: Vec<text::Element>},
+    Value{pointer: Vec<Uid>, value: Inner},
+}
+
 pub trait IntoJson {
     fn into_json(self, dot: Dot) -> Result<Inner, Error>;
 }
@@ -134,9 +273,7 @@ impl Json {
     /// If the CRDT does not have a site id allocated, it caches
     /// the op and returns an `AwaitingSite` error.
     pub fn insert<T: IntoJson>(&mut self, pointer: &str, value: T) -> Result<Op, Error> {
-        let dot   = self.summary.get_dot(self.site_id);
-        let value = value.into_json(dot)?;
-        let op    = self.inner.insert(pointer, value, dot)?;
+        let op = self.do_insert(pointer, value)?;
         self.after_op(op)
     }
 
@@ -163,15 +300,338 @@ impl Json {
         self.after_op(op)
     }
 
+    /// Moves the array element at the given json pointer to index
+    /// `to` of the same array. The element keeps its identity, so
+    /// concurrent edits to it are kept, and concurrent moves of it
+    /// converge on a single position. Returns an `OutOfBounds` error
+    /// if either index is out of bounds.
+    ///
+    /// If the CRDT does not have a site id allocated, it caches
+    /// the op and returns an `AwaitingSite` error.
+    pub fn move_item(&mut self, pointer: &str, to: usize) -> Result<Op, Error> {
+        let op = self.do_move_item(pointer, to)?;
+        self.after_op(op)
+    }
+
     /// Replaces a text range in a text value in the Json CRDT.
     /// If the CRDT does not have a site id allocated, it caches
     /// the op and returns an `AwaitingSite` error.
     pub fn replace_text(&mut self, pointer: &str, index: usize, len: usize, text: &str) -> Result<Op, Error> {
-        let dot = self.summary.get_dot(self.site_id);
-        let op = self.inner.replace_text(pointer, index, len, text, dot)?;
+        let op = self.do_replace_text(pointer, index, len, text)?;
+        self.after_op(op)
+    }
+
+    /// Increments the counter at the given json pointer by `amount`.
+    /// Returns a `WrongJsonType` error if the value is not a counter.
+    ///
+    /// If the CRDT does not have a site id allocated, it caches
+    /// the op and returns an `AwaitingSite` error.
+    pub fn increment(&mut self, pointer: &str, amount: i64) -> Result<Op, Error> {
+        let op = self.do_increment(pointer, amount)?;
+        self.after_op(op)
+    }
+
+    /// Replaces the value of the register at the given json pointer.
+    /// Returns a `WrongJsonType` error if the value is not a register.
+    ///
+    /// If the CRDT does not have a site id allocated, it caches
+    /// the op and returns an `AwaitingSite` error.
+    pub fn update_register(&mut self, pointer: &str, value: SJValue) -> Result<Op, Error> {
+        let op = self.do_update_register(pointer, value)?;
         self.after_op(op)
     }
 
+    /// Runs a transaction, which groups several edits into a single
+    /// op. The closure makes the edits through the
+    /// [`Transaction`](struct.Transaction.html) it is given. If the
+    /// closure returns an error, none of its edits are kept and the
+    /// error is returned. If it makes no edits, a `Noop` error is
+    /// returned.
+    ///
+    /// If the CRDT does not have a site id allocated, it caches
+    /// the op and returns an `AwaitingSite` error.
+    ///
+    /// ```
+    /// # extern crate ditto;
+    /// # #[macro_use] extern crate serde_json;
+    /// # fn main() {
+    /// use ditto::Json;
+    ///
+    /// let mut json = Json::from_str(r#"{"status":"open"}"#).unwrap();
+    /// let op = json.transaction(|transaction| {
+    ///     transaction.insert("/status", "done")?;
+    ///     transaction.insert("/completed_at", 1500000000.0)
+    /// }).unwrap();
+    ///
+    /// assert_eq!(json.local_value(), json!({"status":"done","completed_at":1500000000.0}));
+    /// # }
+    /// ```
+    pub fn transaction<F>(&mut self, f: F) -> Result<Op, Error>
+        where F: FnOnce(&mut Transaction<'_>) -> Result<(), Error>
+    {
+        let summary = self.summary.clone();
+        let ops = {
+            let mut transaction = Transaction{json: self, ops: vec![], rollbacks: vec![]};
+            match f(&mut transaction) {
+                Ok(()) => Ok(transaction.ops),
+                Err(err) => {
+                    for rollback in transaction.rollbacks.into_iter().rev() {
+                        rollback.apply(&mut transaction.json.inner);
+                    }
+                    Err(err)
+                }
+            }
+        };
+
+        let ops = match ops {
+            Ok(ops) => ops,
+            Err(err) => { self.summary = summary; return Err(err) }
+        };
+
+        if ops.is_empty() {
+            return Err(Error::Noop)
+        }
+
+        let op = OpInner::Transaction(TransactionOp{ops});
+        self.after_op(Op{pointer: vec![], op})
+    }
+
+    fn do_insert<T: IntoJson>(&mut self, pointer: &str, value: T) -> Result<Op, Error> {
+        let dot   = self.summary.next_dot(self.site_id);
+        let value = value.into_json(dot)?;
+        let op    = self.inner.insert(pointer, value, dot)?;
+        self.summary.insert(dot);
+        Ok(op)
+    }
+
+    fn do_move_item(&mut self, pointer: &str, to: usize) -> Result<Op, Error> {
+        let dot = self.summary.next_dot(self.site_id);
+        let op  = self.inner.move_item(pointer, to, dot)?;
+        self.summary.insert(dot);
+        Ok(op)
+    }
+
+    fn do_increment(&mut self, pointer: &str, amount: i64) -> Result<Op, Error> {
+        let dot = self.summary.next_dot(self.site_id);
+        let op  = self.inner.increment(pointer, amount, dot)?;
+        self.summary.insert(dot);
+        Ok(op)
+    }
+
+    fn do_update_register(&mut self, pointer: &str, value: SJValue) -> Result<Op, Error> {
+        let dot = self.summary.next_dot(self.site_id);
+        let op  = self.inner.update_register(pointer, normalize_numbers(value)?, dot)?;
+        self.summary.insert(dot);
+        Ok(op)
+    }
+
+    fn do_replace_text(&mut self, pointer: &str, index: usize, len: usize, text: &str) -> Result<Op, Error> {
+        let dot = self.summary.next_dot(self.site_id);
+        let op = self.inner.replace_text(pointer, index, len, text, dot)?;
+        for dot in op.inserted_dots() {
+            self.summary.insert(dot);
+        }
+        Ok(op)
+    }
+
+    /// Replaces the Json CRDT's value with `value`. The current value
+    /// is diffed against `value`: objects key by key, arrays by their
+    /// longest common subsequence, and strings by text diff. Only the
+    /// changed parts are edited, so the returned ops merge with
+    /// concurrent edits to the rest of the value.
+    ///
+    /// Returns a `WrongJsonType` error if the root value would have
+    /// to be replaced. If the CRDT does not have a site id allocated,
+    /// it caches the ops and returns an `AwaitingSite` error.
+    pub fn set_value(&mut self, value: SJValue) -> Result<Vec<Op>, Error> {
+        let value = normalize_numbers(value)?;
+        let mut ops = vec![];
+        self.inner.set_value(value, &mut vec![], &mut self.summary, self.site_id, &mut ops)?;
+
+        let mut result = Ok(());
+        let ops = ops.into_iter()
+            .filter_map(|op| self.after_op(op).map_err(|err| result = Err(err)).ok())
+            .collect();
+        result.map(|_| ops)
+    }
+
+    /// Applies a [JSON Patch](https://tools.ietf.org/html/rfc6902)
+    /// document and returns the ops for its changes. The patch is
+    /// applied to a copy of the CRDT first, so if any operation fails
+    /// (including a failed `test`) the CRDT is left unchanged.
+    /// Replacing the root value follows the rules of
+    /// [`set_value`](#method.set_value).
+    ///
+    /// If the CRDT does not have a site id allocated, it caches
+    /// the ops and returns an `AwaitingSite` error.
+    pub fn apply_patch(&mut self, patch: &[PatchOp]) -> Result<Vec<Op>, Error> {
+        let mut json = self.clone();
+        let mut ops = vec![];
+        for patch_op in patch {
+            json.apply_patch_op(patch_op, &mut ops)?;
+        }
+
+        *self = json;
+        if self.site_id == 0 { Err(Error::AwaitingSiteId) } else { Ok(ops) }
+    }
+
+    /// Applies a [JSON Merge Patch](https://tools.ietf.org/html/rfc7396)
+    /// and returns the ops for its changes. Object members of the patch
+    /// are merged into the Json's objects key by key: members set to
+    /// `null` are removed and other values are inserted, overwriting
+    /// the current value unless both are objects. Arrays and other
+    /// values are replaced as a whole.
+    ///
+    /// Returns a `WrongJsonType` error if the root value would have
+    /// to be replaced. If the CRDT does not have a site id allocated,
+    /// it caches the ops and returns an `AwaitingSite` error.
+    pub fn apply_merge_patch(&mut self, patch: &SJValue) -> Result<Vec<Op>, Error> {
+        let mut ops = vec![];
+        match (&self.inner, normalize_numbers(patch.clone())?) {
+            (&Inner::Object(_), SJValue::Object(patch)) =>
+                self.merge_patch_object("", patch, &mut ops)?,
+            (inner, patch) =>
+                if inner.local_value() != remove_nulls(patch) { return Err(Error::WrongJsonType) },
+        }
+        if self.site_id == 0 { Err(Error::AwaitingSiteId) } else { Ok(ops) }
+    }
+
+    fn merge_patch_object(&mut self, path: &str, patch: serde_json::Map<String, SJValue>, ops: &mut Vec<Op>) -> Result<(), Error> {
+        for (key, value) in patch {
+            let path = format!("{}/{}", path, escape_key(&key));
+            let current_value = self.patch_value(&path).ok();
+
+            match (current_value, value) {
+                (None, SJValue::Null) => (),
+                (Some(_), SJValue::Null) =>
+                    push_op(self.remove(&path), ops)?,
+                (Some(SJValue::Object(_)), SJValue::Object(patch)) =>
+                    self.merge_patch_object(&path, patch, ops)?,
+                (current_value, value) => {
+                    let value = remove_nulls(value);
+                    if current_value.as_ref() != Some(&value) {
+                        push_op(self.insert(&path, value), ops)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn apply_patch_op(&mut self, patch_op: &PatchOp, ops: &mut Vec<Op>) -> Result<(), Error> {
+        match *patch_op {
+            PatchOp::Add{ref path, ref value} =>
+                self.patch_add(path, value.clone(), ops),
+            PatchOp::Remove{ref path} => {
+                let path = self.patch_path(path, false)?;
+                push_op(self.remove(&path), ops)
+            }
+            PatchOp::Replace{ref path, ref value} => {
+                if !path.is_empty() {
+                    let path = self.patch_path(path, false)?;
+                    push_op(self.remove(&path), ops)?;
+                }
+                self.patch_add(path, value.clone(), ops)
+            }
+            PatchOp::Move{ref from, ref path} => {
+                if from == path { return Ok(()) }
+                if path.starts_with(&format!("{}/", from)) { return Err(Error::InvalidPointer) }
+                let value = self.patch_value(from)?;
+                let from = self.patch_path(from, false)?;
+                if let Some(to) = self.patch_move_idx(&from, path)? {
+                    return push_op(self.move_item(&from, to), ops)
+                }
+                push_op(self.remove(&from), ops)?;
+                self.patch_add(path, value, ops)
+            }
+            PatchOp::Copy{ref from, ref path} => {
+                let value = self.patch_value(from)?;
+                self.patch_add(path, value, ops)
+            }
+            PatchOp::Test{ref path, ref value} => {
+                if self.patch_value(path)? == normalize_numbers(value.clone())? {
+                    Ok(())
+                } else {
+                    Err(Error::TestFailed)
+                }
+            }
+        }
+    }
+
+    fn patch_add(&mut self, path: &str, value: SJValue, ops: &mut Vec<Op>) -> Result<(), Error> {
+        if path.is_empty() {
+            match self.set_value(value) {
+                Ok(mut set_ops) => ops.append(&mut set_ops),
+                Err(Error::AwaitingSiteId) => (),
+                Err(err) => return Err(err),
+            }
+            return Ok(())
+        }
+
+        let path = self.patch_path(path, true)?;
+        push_op(self.insert(&path, value), ops)
+    }
+
+    /// Returns the local value at a patch path.
+    fn patch_value(&self, path: &str) -> Result<SJValue, Error> {
+        let pointer = Inner::split_pointer(path)?;
+        Ok(self.inner.get_nested_local(&pointer).ok_or(Error::DoesNotExist)?.local_value())
+    }
+
+    /// Checks that a patch path points into an object or array and
+    /// returns it with a trailing `-` array index resolved. The path
+    /// must point to an existing value unless `adding` is true,
+    /// in which case it may point one past the end of an array
+    /// or to a new object key.
+    fn patch_path<'a>(&self, path: &'a str, adding: bool) -> Result<Cow<'a, str>, Error> {
+        let split_idx = path.rfind('/').ok_or(Error::InvalidPointer)?;
+        let (parent_path, key) = (&path[..split_idx], &path[split_idx+1..]);
+        let parent_pointer = Inner::split_pointer(parent_path)?;
+
+        match *self.inner.get_nested_local(&parent_pointer).ok_or(Error::DoesNotExist)? {
+            Inner::Object(ref map) => {
+                if adding || map.0.contains_key(&unescape_key(key)[..]) {
+                    Ok(Cow::Borrowed(path))
+                } else {
+                    Err(Error::DoesNotExist)
+                }
+            }
+            Inner::Array(ref list) => {
+                let len = list.0.len();
+                if adding && key == "-" {
+                    return Ok(Cow::Owned(format!("{}/{}", parent_path, len)))
+                }
+                let idx = usize::from_str(key)?;
+                if idx < len || (adding && idx == len) {
+                    Ok(Cow::Borrowed(path))
+                } else {
+                    Err(Error::OutOfBounds)
+                }
+            }
+            _ => Err(Error::DoesNotExist),
+        }
+    }
+
+    /// Returns the index that a patch move targets if it moves an
+    /// element within a single array. The index is resolved as if
+    /// the element had already been removed.
+    fn patch_move_idx(&self, from: &str, path: &str) -> Result<Option<usize>, Error> {
+        let from_idx = from.rfind('/').ok_or(Error::InvalidPointer)?;
+        let path_idx = path.rfind('/').ok_or(Error::InvalidPointer)?;
+        let parent_path = &from[..from_idx];
+        if parent_path != &path[..path_idx] { return Ok(None) }
+
+        let parent_pointer = Inner::split_pointer(parent_path)?;
+        let len = match self.inner.get_nested_local(&parent_pointer) {
+            Some(Inner::Array(list)) => list.0.len(),
+            _ => return Ok(None),
+        };
+
+        let key = &path[path_idx+1..];
+        let idx = if key == "-" { len - 1 } else { usize::from_str(key)? };
+        if idx < len { Ok(Some(idx)) } else { Err(Error::OutOfBounds) }
+    }
+
     crdt_impl2! {
         Json,
         JsonState,
@@ -180,7 +640,561 @@ impl Json {
         Inner,
         Op,
         Option<LocalOp>,
+        LocalOp,
+        SJValue,
+        JsonDelta,
+        JsonDelta,
+    }
+}
+
+impl Crdt for Json {
+    crdt_trait_impl! {
+        Json,
+        JsonState<'static>,
+        Op,
+        Option<LocalOp>,
+        Some,
         SJValue,
+        JsonDelta,
+    }
+}
+
+/// An UndoManager records the local edits made to a `Json` CRDT and
+/// reverts them on request. Undo and redo only touch the local
+/// site's own edits:
+///
+/// * a value inserted into an object is removed, and the value it
+///   replaced (if any) is restored;
+/// * a value removed from an object is restored;
+/// * a value inserted into an array is removed;
+/// * a value removed from an array is re-inserted at its original
+///   position with a new uid;
+/// * text edits are reverted as in [`text::UndoManager`](../text/struct.UndoManager.html).
+///
+/// Restored values keep their type, so a restored counter or
+/// register is still a counter or register.
+///
+/// Concurrent remote edits are left intact. An edit whose value has
+/// since been replaced or removed by another site is skipped.
+///
+/// Undo and redo generate ordinary ops that must be sent to other
+/// sites like any other edit.
+///
+/// ```
+/// use ditto::Json;
+/// use ditto::json::UndoManager;
+///
+/// let mut json = Json::from_str(r#"{"a":1,"b":[true,false]}"#).unwrap();
+/// let mut undo = UndoManager::new();
+/// let _ = undo.insert(&mut json, "/a", 2.0).unwrap();
+/// let _ = undo.remove(&mut json, "/b/0").unwrap();
+///
+/// let _ = undo.undo(&mut json).unwrap();
+/// let _ = undo.undo(&mut json).unwrap();
+/// assert_eq!(json.local_value().to_string(), r#"{"a":1.0,"b":[true,false]}"#);
+///
+/// let _ = undo.redo(&mut json).unwrap();
+/// assert_eq!(json.local_value()["a"], 2.0);
+/// ```
+#[derive(Debug, Clone)]
+pub struct UndoManager {
+    undo_stack: Vec<UndoEntry>,
+    redo_stack: Vec<UndoEntry>,
+    can_extend: bool,
+}
+
+/// The net effect of an edit, or of a group of text edits.
+#[derive(Debug, Clone)]
+enum UndoEntry {
+    Object{pointer: Vec<Uid>, key: String, inserted_dot: Option<Dot>, removed_value: Option<Inner>},
+    Array{pointer: Vec<Uid>, inserted_uid: Option<sequence::uid::Uid>, removed: Option<(sequence::uid::Uid, Inner)>},
+    String{pointer: Vec<Uid>, inserted_uids: Vec<sequence::uid::Uid>, removed_elements: Vec<text::Element>},
+}
+
+impl UndoManager {
+
+    /// Constructs and returns a new UndoManager.
+    pub fn new() -> Self {
+        UndoManager{undo_stack: vec![], redo_stack: vec![], can_extend: false}
+    }
+
+    /// Returns true if there is an edit to undo.
+    pub fn can_undo(&self) -> bool {
+        !self.undo_stack.is_empty()
+    }
+
+    /// Returns true if there is an undone edit to redo.
+    pub fn can_redo(&self) -> bool {
+        !self.redo_stack.is_empty()
+    }
+
+    /// Inserts a value like [`Json::insert`](struct.Json.html#method.insert)
+    /// and records the edit. Recording an edit clears the redo stack.
+    pub fn insert<T: IntoJson>(&mut self, json: &mut Json, pointer: &str, value: T) -> Result<Op, Error> {
+        let previous_value = Self::element_at(json, pointer);
+        let dot = json.summary.next_dot(json.site_id);
+        let value = value.into_json(dot)?;
+        let op = json.inner.insert(pointer, value, dot)?;
+        json.summary.insert(dot);
+        self.record(&op, previous_value);
+        json.after_op(op)
+    }
+
+    /// Removes a value like [`Json::remove`](struct.Json.html#method.remove)
+    /// and records the edit. Recording an edit clears the redo stack.
+    pub fn remove(&mut self, json: &mut Json, pointer: &str) -> Result<Op, Error> {
+        let previous_value = Self::element_at(json, pointer);
+        let op = json.inner.remove(pointer)?;
+        self.record(&op, previous_value);
+        json.after_op(op)
+    }
+
+    /// Replaces text like [`Json::replace_text`](struct.Json.html#method.replace_text)
+    /// and records the edit. Consecutive text edits that are merged
+    /// into a single text element are grouped and undone together.
+    /// Recording an edit clears the redo stack.
+    pub fn replace_text(&mut self, json: &mut Json, pointer: &str, index: usize, len: usize, text: &str) -> Result<Op, Error> {
+        let dot = json.summary.next_dot(json.site_id);
+        let (op, removed_elements) = json.inner.replace_text_and_remove(pointer, index, len, text, dot)?;
+        for dot in op.inserted_dots() {
+            json.summary.insert(dot);
+        }
+
+        if let OpInner::String(ref text_op) = op.op {
+            let can_extend = self.can_extend;
+            let group = match self.undo_stack.last_mut() {
+                Some(&mut UndoEntry::String{ref pointer, ref mut inserted_uids, ref mut removed_elements})
+                    if can_extend && *pointer == op.pointer && text_op.removed_uids().iter().any(|uid| inserted_uids.contains(uid)) =>
+                    Some((inserted_uids, removed_elements)),
+                _ => None,
+            };
+
+            match group {
+                Some((inserted_uids, group_removed_elements)) =>
+                    UndoEntry::record_text(inserted_uids, group_removed_elements, text_op, removed_elements),
+                None => {
+                    let mut inserted_uids = vec![];
+                    let mut group_removed_elements = vec![];
+                    UndoEntry::record_text(&mut inserted_uids, &mut group_removed_elements, text_op, removed_elements);
+                    self.undo_stack.push(UndoEntry::String{pointer: op.pointer.clone(), inserted_uids, removed_elements: group_removed_elements});
+                }
+            }
+        }
+
+        self.redo_stack.clear();
+        self.can_extend = true;
+        json.after_op(op)
+    }
+
+    /// Reverts the most recent recorded edit that still has an
+    /// effect. Returns the local change and an op to send to other
+    /// sites, or None if there is nothing to undo.
+    pub fn undo(&mut self, json: &mut Json) -> Option<(LocalOp, Result<Op, Error>)> {
+        self.can_extend = false;
+        while let Some(entry) = self.undo_stack.pop() {
+            if let Some((local_op, op, entry)) = entry.revert(json) {
+                self.redo_stack.push(entry);
+                return Some((local_op, op))
+            }
+        }
+        None
+    }
+
+    /// Reapplies the most recently undone edit. Returns the local
+    /// change and an op to send to other sites, or None if there
+    /// is nothing to redo.
+    pub fn redo(&mut self, json: &mut Json) -> Option<(LocalOp, Result<Op, Error>)> {
+        self.can_extend = false;
+        while let Some(entry) = self.redo_stack.pop() {
+            if let Some((local_op, op, entry)) = entry.revert(json) {
+                self.undo_stack.push(entry);
+                return Some((local_op, op))
+            }
+        }
+        None
+    }
+
+    /// Returns a copy of the value at the pointer, along with its
+    /// position uid if the value is an array element.
+    fn element_at(json: &Json, pointer: &str) -> Option<(Inner, Option<sequence::uid::Uid>)> {
+        let mut pointer = Inner::split_pointer(pointer).ok()?;
+        let key = pointer.pop()?;
+        match *json.inner.get_nested_local(&pointer)? {
+            Inner::Object(ref map) => Some((map.0.get(&key[..])?[0].value.clone(), None)),
+            Inner::Array(ref list) => {
+                let element = list.get(usize::from_str(&key).ok()?)?;
+                Some((element.value.clone(), Some(element.uid.clone())))
+            }
+            _ => None,
+        }
+    }
+
+    fn record(&mut self, op: &Op, previous_element: Option<(Inner, Option<sequence::uid::Uid>)>) {
+        let pointer = op.pointer.clone();
+        let entry = match op.op {
+            OpInner::Object(ref op) => {
+                let key = op.key().clone();
+                let inserted_dot = op.inserted_element().map(|e| e.dot);
+                let removed_value = if op.removed_dots().is_empty() { None } else { previous_element.map(|e| e.0) };
+                UndoEntry::Object{pointer, key, inserted_dot, removed_value}
+            }
+            OpInner::Array(list::Op::Insert(ref element)) =>
+                UndoEntry::Array{pointer, inserted_uid: Some(element.uid.clone()), removed: None},
+            OpInner::Array(list::Op::Remove(_)) => {
+                let removed = previous_element.and_then(|(value, uid)| Some((uid?, value)));
+                UndoEntry::Array{pointer, inserted_uid: None, removed}
+            }
+            OpInner::Array(list::Op::Edit{ref op, ..}) => match *op {},
+            OpInner::Array(list::Op::Move{..}) | OpInner::String(_) | OpInner::Transaction(_) |
+            OpInner::Counter(_) | OpInner::Register(_) => return,
+        };
+
+        self.undo_stack.push(entry);
+        self.redo_stack.clear();
+        self.can_extend = false;
+    }
+}
+
+impl Default for UndoManager {
+    fn default() -> Self {
+        UndoManager::new()
+    }
+}
+
+impl UndoEntry {
+    fn record_text(inserted_uids: &mut Vec<sequence::uid::Uid>, removed_elements: &mut Vec<text::Element>, op: &text::Op, removed: Vec<text::Element>) {
+        for element in removed {
+            match inserted_uids.iter().position(|uid| *uid == element.uid) {
+                Some(idx) => { inserted_uids.remove(idx); }
+                None => removed_elements.push(element),
+            }
+        }
+        inserted_uids.extend(op.inserted_elements().iter().map(|e| e.uid.clone()));
+    }
+
+    /// Reverts the entry and returns the local change, the op,
+    /// and an entry that reverts the revert.
+    fn revert(self, json: &mut Json) -> Option<(LocalOp, Result<Op, Error>, UndoEntry)> {
+        let dot = json.summary.next_dot(json.site_id);
+
+        let (op, local_op, entry) = match self {
+            UndoEntry::Object{pointer, key, inserted_dot, removed_value} => {
+                let map_op = {
+                    let map = json.inner.get_nested_remote(&pointer)?.0.as_map().ok()?;
+                    let current = match inserted_dot {
+                        Some(inserted_dot) => Some(map.get_mut_element(key.as_str(), inserted_dot)?.value.clone()),
+                        None => None,
+                    };
+                    let inserted_element = removed_value.map(|mut value| {
+                        value.nested_reset(dot);
+                        map::Element{value, dot}
+                    });
+                    if current.is_none() && inserted_element.is_none() { return None }
+
+                    let entry = UndoEntry::Object{
+                        pointer: pointer.clone(),
+                        key: key.clone(),
+                        inserted_dot: inserted_element.as_ref().map(|e| e.dot),
+                        removed_value: current,
+                    };
+                    (map::Op::new(key, inserted_element, inserted_dot.into_iter().collect()), entry)
+                };
+
+                let op = Op{pointer, op: OpInner::Object(map_op.0)};
+                let local_op = json.inner.execute_op(op.clone())?;
+                (op, local_op, map_op.1)
+            }
+            UndoEntry::Array{pointer, inserted_uid, removed} => {
+                let (list_op, entry) = {
+                    let list = json.inner.get_nested_remote(&pointer)?.0.as_list().ok()?;
+                    match inserted_uid {
+                        Some(uid) => {
+                            let idx = list.get_idx(&uid)?;
+                            let element = list.get(idx)?;
+                            let removed = Some((element.uid.clone(), element.value.clone()));
+                            let entry = UndoEntry::Array{pointer: pointer.clone(), inserted_uid: None, removed};
+                            (list::Op::Remove(uid), entry)
+                        }
+                        None => {
+                            let (uid, mut value) = removed?;
+                            let uid = list.uid_before(list.0.get_insert_idx(&uid), dot);
+                            value.nested_reset(dot);
+                            let entry = UndoEntry::Array{pointer: pointer.clone(), inserted_uid: Some(uid.clone()), removed: None};
+                            (list::Op::Insert(list::Element{uid, value, moved: None}), entry)
+                        }
+                    }
+                };
+
+                let op = Op{pointer, op: OpInner::Array(list_op)};
+                let local_op = json.inner.execute_op(op.clone())?;
+                (op, local_op, entry)
+            }
+            UndoEntry::String{pointer, inserted_uids, removed_elements} => {
+                let (inner, local_pointer) = json.inner.get_nested_remote(&pointer)?;
+                let text = inner.as_text().ok()?;
+                let (text_op, removed_elements, changes) = text.revert(&inserted_uids, &removed_elements, dot)?;
+                let inserted_uids = text_op.inserted_elements().iter().map(|e| e.uid.clone()).collect();
+                let entry = UndoEntry::String{pointer: pointer.clone(), inserted_uids, removed_elements};
+                let op = Op{pointer, op: OpInner::String(text_op)};
+                (op, LocalOp::ReplaceText{pointer: local_pointer, changes}, entry)
+            }
+        };
+
+        for dot in op.inserted_dots() {
+            json.summary.insert(dot);
+        }
+        Some((local_op, json.after_op(op), entry))
+    }
+}
+
+impl<'a> Transaction<'a> {
+    /// Returns the Json CRDT as edited so far by the transaction.
+    pub fn json(&self) -> &Json {
+        self.json
+    }
+
+    /// Inserts a value like [`Json::insert`](struct.Json.html#method.insert).
+    pub fn insert<T: IntoJson>(&mut self, pointer: &str, value: T) -> Result<(), Error> {
+        let elements = self.object_elements(pointer);
+        let op = self.json.do_insert(pointer, value)?;
+        let rollback = match op.op {
+            OpInner::Object(ref map_op) =>
+                Rollback::Object{pointer: op.pointer.clone(), key: map_op.key().clone(), elements},
+            OpInner::Array(list::Op::Insert(ref element)) =>
+                Rollback::ArrayInsert{pointer: op.pointer.clone(), uid: element.uid.clone()},
+            _ => unreachable!(),
+        };
+        self.push(op, rollback);
+        Ok(())
+    }
+
+    /// Inserts a value like [`Json::insert_str`](struct.Json.html#method.insert_str).
+    pub fn insert_str(&mut self, pointer: &str, value: &str) -> Result<(), Error> {
+        let json: SJValue = serde_json::from_str(value)?;
+        self.insert(pointer, json)
+    }
+
+    /// Removes a value like [`Json::remove`](struct.Json.html#method.remove).
+    pub fn remove(&mut self, pointer: &str) -> Result<(), Error> {
+        let elements = self.object_elements(pointer);
+        let element = self.array_element(pointer).cloned();
+        let op = self.json.inner.remove(pointer)?;
+        let rollback = match op.op {
+            OpInner::Object(ref map_op) =>
+                Rollback::Object{pointer: op.pointer.clone(), key: map_op.key().clone(), elements},
+            OpInner::Array(list::Op::Remove(_)) =>
+                Rollback::ArrayRemove{pointer: op.pointer.clone(), element: element.expect("Element must exist!")},
+            _ => unreachable!(),
+        };
+        self.push(op, rollback);
+        Ok(())
+    }
+
+    /// Moves an array element like [`Json::move_item`](struct.Json.html#method.move_item).
+    pub fn move_item(&mut self, pointer: &str, to: usize) -> Result<(), Error> {
+        let old = self.array_element(pointer).map(|e| (e.uid.clone(), e.moved.clone()));
+        let op = self.json.do_move_item(pointer, to)?;
+        let rollback = match (&op.op, old) {
+            (&OpInner::Array(list::Op::Move{ref uid, ..}), Some((old_uid, old_moved))) =>
+                Rollback::ArrayMove{pointer: op.pointer.clone(), uid: uid.clone(), old_uid, old_moved},
+            _ => unreachable!(),
+        };
+        self.push(op, rollback);
+        Ok(())
+    }
+
+    /// Replaces text like [`Json::replace_text`](struct.Json.html#method.replace_text).
+    pub fn replace_text(&mut self, pointer: &str, index: usize, len: usize, text: &str) -> Result<(), Error> {
+        let dot = self.json.summary.next_dot(self.json.site_id);
+        let (op, removed_elements) = self.json.inner.replace_text_and_remove(pointer, index, len, text, dot)?;
+        for dot in op.inserted_dots() {
+            self.json.summary.insert(dot);
+        }
+
+        let inserted_uids = match op.op {
+            OpInner::String(ref text_op) => text_op.inserted_elements().iter().map(|e| e.uid.clone()).collect(),
+            _ => unreachable!(),
+        };
+        let rollback = Rollback::String{pointer: op.pointer.clone(), inserted_uids, removed_elements};
+        self.push(op, rollback);
+        Ok(())
+    }
+
+    /// Increments a counter like [`Json::increment`](struct.Json.html#method.increment).
+    pub fn increment(&mut self, pointer: &str, amount: i64) -> Result<(), Error> {
+        let value = self.value(pointer);
+        let op = self.json.do_increment(pointer, amount)?;
+        let rollback = Rollback::Value{pointer: op.pointer.clone(), value: value.expect("Value must exist!")};
+        self.push(op, rollback);
+        Ok(())
+    }
+
+    /// Replaces a register's value like [`Json::update_register`](struct.Json.html#method.update_register).
+    pub fn update_register(&mut self, pointer: &str, value: SJValue) -> Result<(), Error> {
+        let old_value = self.value(pointer);
+        let op = self.json.do_update_register(pointer, value)?;
+        let rollback = Rollback::Value{pointer: op.pointer.clone(), value: old_value.expect("Value must exist!")};
+        self.push(op, rollback);
+        Ok(())
+    }
+
+    fn push(&mut self, op: Op, rollback: Rollback) {
+        self.ops.push(op);
+        self.rollbacks.push(rollback);
+    }
+
+    /// Returns a copy of the value at the pointer.
+    fn value(&self, pointer: &str) -> Option<Inner> {
+        let pointer = Inner::split_pointer(pointer).ok()?;
+        self.json.inner.get_nested_local(&pointer).cloned()
+    }
+
+    /// Returns a copy of the elements stored under the pointer's
+    /// key if the pointer points into an object.
+    fn object_elements(&self, pointer: &str) -> Option<Vec<map::Element<Inner>>> {
+        let mut pointer = Inner::split_pointer(pointer).ok()?;
+        let key = pointer.pop()?;
+        match *self.json.inner.get_nested_local(&pointer)? {
+            Inner::Object(ref map) => map.0.get(&key[..]).cloned(),
+            _ => None,
+        }
+    }
+
+    /// Returns the element at the pointer if the
+    /// pointer points into an array.
+    fn array_element(&self, pointer: &str) -> Option<&list::Element<Inner>> {
+        let mut pointer = Inner::split_pointer(pointer).ok()?;
+        let key = pointer.pop()?;
+        match *self.json.inner.get_nested_local(&pointer)? {
+            Inner::Array(ref list) => list.get(usize::from_str(&key).ok()?),
+            _ => None,
+        }
+    }
+}
+
+impl Rollback {
+    /// Restores the state from before the edit. Edits must be
+    /// rolled back in the reverse of the order they were made.
+    fn apply(self, inner: &mut Inner) {
+        match self {
+            Rollback::Object{pointer, key, elements} => {
+                let map = Self::nested(inner, &pointer).as_map().expect("Value must be an object!");
+                match elements {
+                    Some(elements) => { let _ = map.0.insert(key, elements); }
+                    None => { let _ = map.0.remove(&key); }
+                }
+            }
+            Rollback::ArrayInsert{pointer, uid} => {
+                let list = Self::nested(inner, &pointer).as_list().expect("Value must be an array!");
+                let _ = list.rollback_insert(&uid);
+            }
+            Rollback::ArrayRemove{pointer, element} => {
+                let list = Self::nested(inner, &pointer).as_list().expect("Value must be an array!");
+                list.rollback_remove(element);
+            }
+            Rollback::ArrayMove{pointer, uid, old_uid, old_moved} => {
+                let list = Self::nested(inner, &pointer).as_list().expect("Value must be an array!");
+                let mut element = list.rollback_insert(&uid);
+                element.uid = old_uid;
+                element.moved = old_moved;
+                list.rollback_remove(element);
+            }
+            Rollback::String{pointer, inserted_uids, removed_elements} => {
+                let text = Self::nested(inner, &pointer).as_text().expect("Value must be a string!");
+                text.rollback(&inserted_uids, removed_elements);
+            }
+            Rollback::Value{pointer, value} =>
+                *Self::nested(inner, &pointer) = value,
+        }
+    }
+
+    fn nested<'b>(inner: &'b mut Inner, pointer: &[Uid]) -> &'b mut Inner {
+        inner.get_nested_remote(pointer).expect("Value must exist!").0
+    }
+}
+
+impl PatchWriter {
+    /// Constructs and returns a new PatchWriter that starts from
+    /// the Json CRDT's current value.
+    pub fn new(json: &Json) -> Self {
+        PatchWriter{value: json.local_value()}
+    }
+
+    /// Returns the JSON Patch operations for a local op. Every
+    /// local op except a transaction becomes a single operation.
+    pub fn write(&mut self, local_op: &LocalOp) -> Vec<PatchOp> {
+        let mut patch = vec![];
+        self.write_into(local_op, &mut patch);
+        patch
+    }
+
+    fn write_into(&mut self, local_op: &LocalOp, patch: &mut Vec<PatchOp>) {
+        let patch_op = match *local_op {
+            LocalOp::Insert{ref pointer, ref value} => {
+                let (parent, key) = self.parent_mut(pointer);
+                match *parent {
+                    SJValue::Object(ref mut map) => { let _ = map.insert(key, value.clone()); }
+                    SJValue::Array(ref mut vec) => vec.insert(key.parse().unwrap(), value.clone()),
+                    _ => panic!("Pointer must point into an object or array!"),
+                }
+                PatchOp::Add{path: patch_path(pointer), value: value.clone()}
+            }
+            LocalOp::Remove{ref pointer} => {
+                let (parent, key) = self.parent_mut(pointer);
+                match *parent {
+                    SJValue::Object(ref mut map) => { let _ = map.remove(&key); }
+                    SJValue::Array(ref mut vec) => { let _ = vec.remove(key.parse().unwrap()); }
+                    _ => panic!("Pointer must point into an object or array!"),
+                }
+                PatchOp::Remove{path: patch_path(pointer)}
+            }
+            LocalOp::Move{ref pointer, to} => {
+                let (parent, key) = self.parent_mut(pointer);
+                match *parent {
+                    SJValue::Array(ref mut vec) => {
+                        let value = vec.remove(key.parse().unwrap());
+                        vec.insert(to, value);
+                    }
+                    _ => panic!("Pointer must point into an array!"),
+                }
+                let mut path = pointer.clone();
+                path.pop();
+                path.push(LocalUid::Array(to));
+                PatchOp::Move{from: patch_path(pointer), path: patch_path(&path)}
+            }
+            LocalOp::ReplaceText{ref pointer, ref changes} => {
+                let path = patch_path(pointer);
+                let value = self.value.pointer_mut(&path).expect("Pointer must exist!");
+                if let SJValue::String(ref mut string) = *value {
+                    for change in changes {
+                        string.replace_range(change.idx..change.idx + change.len, &change.text);
+                    }
+                }
+                PatchOp::Replace{path, value: value.clone()}
+            }
+            LocalOp::Transaction{ref changes} => {
+                for local_op in changes {
+                    self.write_into(local_op, patch);
+                }
+                return
+            }
+            LocalOp::Update{ref pointer, ref value} => {
+                let path = patch_path(pointer);
+                *self.value.pointer_mut(&path).expect("Pointer must exist!") = value.clone();
+                PatchOp::Replace{path, value: value.clone()}
+            }
+        };
+        patch.push(patch_op);
+    }
+
+    fn parent_mut(&mut self, pointer: &[LocalUid]) -> (&mut SJValue, String) {
+        let (key, parent_pointer) = pointer.split_last().expect("Pointer must not be empty!");
+        let parent = self.value.pointer_mut(&patch_path(parent_pointer)).expect("Pointer must exist!");
+        let key = match *key {
+            LocalUid::Object(ref key) => key.clone(),
+            LocalUid::Array(idx) => idx.to_string(),
+        };
+        (parent, key)
     }
 }
 
@@ -193,12 +1207,12 @@ impl Inner {
 
         match *json_value {
             Inner::Object(ref mut map) => {
-                let op = map.insert(key.into(), value, dot);
+                let op = map.insert(key.into_owned(), value, dot);
                 let op = OpInner::Object(op);
                 Ok(Op{pointer: remote_pointer, op})
             }
             Inner::Array(ref mut list) => {
-                let idx = usize::from_str(key)?;
+                let idx = usize::from_str(&key)?;
                 let op = list.insert(idx, value, dot);
                 let op = OpInner::Array(op);
                 Ok(Op{pointer: remote_pointer, op})
@@ -214,11 +1228,11 @@ impl Inner {
 
         match *json_value {
             Inner::Object(ref mut map) => {
-                let op = map.remove(key).ok_or(Error::Noop)?;
+                let op = map.remove(&key[..]).ok_or(Error::Noop)?;
                 Ok(Op{pointer: remote_pointer, op: OpInner::Object(op)})
             }
             Inner::Array(ref mut list) => {
-                let idx = usize::from_str(key)?;
+                let idx = usize::from_str(&key)?;
                 let (_, op) = list.remove(idx);
                 Ok(Op{pointer: remote_pointer, op: OpInner::Array(op)})
             }
@@ -226,15 +1240,54 @@ impl Inner {
         }
     }
 
+    pub fn move_item(&mut self, pointer: &str, to: usize, dot: Dot) -> Result<Op, Error> {
+        let mut pointer = Self::split_pointer(pointer)?;
+        let key = pointer.pop().ok_or(Error::DoesNotExist)?;
+        let (json_value, remote_pointer) = self.mut_nested_local(&pointer)?;
+        let list = json_value.as_list()?;
+        let from = usize::from_str(&key)?;
+        if from >= list.0.len() || to >= list.0.len() { return Err(Error::OutOfBounds) }
+
+        let op = list.move_item(from, to, dot);
+        Ok(Op{pointer: remote_pointer, op: OpInner::Array(op)})
+    }
+
+    pub fn increment(&mut self, pointer: &str, amount: i64, dot: Dot) -> Result<Op, Error> {
+        let pointer = Self::split_pointer(pointer)?;
+        let (inner, remote_pointer) = self.mut_nested_local(&pointer)?;
+        let op = inner.as_counter()?.increment_at(amount, dot);
+        Ok(Op{pointer: remote_pointer, op: OpInner::Counter(op)})
+    }
+
+    pub fn update_register(&mut self, pointer: &str, value: SJValue, dot: Dot) -> Result<Op, Error> {
+        let pointer = Self::split_pointer(pointer)?;
+        let (inner, remote_pointer) = self.mut_nested_local(&pointer)?;
+        let op = inner.as_register()?.update(value, dot);
+        Ok(Op{pointer: remote_pointer, op: OpInner::Register(op)})
+    }
+
     pub fn replace_text(&mut self, pointer: &str, index: usize, len: usize, text: &str, dot: Dot) -> Result<Op, Error> {
+        self.replace_text_and_remove(pointer, index, len, text, dot).map(|(op, _)| op)
+    }
+
+    fn replace_text_and_remove(&mut self, pointer: &str, index: usize, len: usize, text: &str, dot: Dot) -> Result<(Op, Vec<text::Element>), Error> {
         let pointer = Self::split_pointer(pointer)?;
         let (inner, remote_pointer) = self.mut_nested_local(&pointer)?;
         let text_inner = inner.as_text()?;
-        let op = text_inner.replace(index, len, text, dot).ok_or(Error::Noop)?;
-        Ok(Op{pointer: remote_pointer, op: OpInner::String(op)})
+        let (op, removed_elements) = text_inner.replace_and_remove(index, len, text, dot).ok_or(Error::Noop)?;
+        Ok((Op{pointer: remote_pointer, op: OpInner::String(op)}, removed_elements))
     }
 
     pub fn execute_op(&mut self, op: Op) -> Option<LocalOp> {
+        if let OpInner::Transaction(transaction) = op.op {
+            // like separate ops, an op whose value was removed
+            // by a concurrent edit is skipped
+            let changes: Vec<LocalOp> = transaction.ops.into_iter()
+                .filter_map(|op| self.execute_op(op))
+                .collect();
+            return if changes.is_empty() { None } else { Some(LocalOp::Transaction{changes}) }
+        }
+
         let (inner, mut pointer) = self.get_nested_remote(&op.pointer)?;
         match op.op {
             OpInner::Object(op) => {
@@ -251,30 +1304,87 @@ impl Inner {
                         pointer.push(LocalUid::Object(key));
                         Some(LocalOp::Remove{pointer})
                     }
+                    map::LocalOp::Edit{..} => None,
                 }
             }
             OpInner::Array(op) => {
-                match inner.as_list().ok()?.execute_op(op)? {
-                    list::LocalOp::Insert{idx, value} => {
-                        pointer.push(LocalUid::Array(idx));
-                        Some(LocalOp::Insert{pointer, value: value.local_value()})
-                    }
-                    list::LocalOp::Remove{idx} => {
-                        pointer.push(LocalUid::Array(idx));
-                        Some(LocalOp::Remove{pointer})
-                    }
-                }
+                let local_op = inner.as_list().ok()?.execute_op(op)?;
+                Some(Self::array_local_op(local_op, pointer))
             }
             OpInner::String(op) => {
                 let changes = inner.as_text().ok()?.execute_op(op);
                 if changes.is_empty() { return None };
                 Some(LocalOp::ReplaceText{pointer, changes})
             }
+            OpInner::Counter(op) => {
+                let _ = inner.as_counter().ok()?.execute_op(&op)?;
+                Some(LocalOp::Update{pointer, value: inner.local_value()})
+            }
+            OpInner::Register(op) => {
+                let value = inner.as_register().ok()?.execute_op(op)?.clone();
+                Some(LocalOp::Update{pointer, value})
+            }
+            OpInner::Transaction(_) => unreachable!(),
+        }
+    }
+
+    /// Converts a local op of the array at `pointer`.
+    fn array_local_op(local_op: list::LocalOp<Inner>, mut pointer: Vec<LocalUid>) -> LocalOp {
+        match local_op {
+            list::LocalOp::Insert{idx, value} => {
+                pointer.push(LocalUid::Array(idx));
+                LocalOp::Insert{pointer, value: value.local_value()}
+            }
+            list::LocalOp::Remove{idx} => {
+                pointer.push(LocalUid::Array(idx));
+                LocalOp::Remove{pointer}
+            }
+            list::LocalOp::Move{from, to} => {
+                pointer.push(LocalUid::Array(from));
+                LocalOp::Move{pointer, to}
+            }
+            list::LocalOp::Edit{..} => unreachable!("Json values are not edited through list ops"),
+        }
+    }
+
+    pub fn merge(&mut self, other: Inner, summary: &Summary, other_summary: &Summary) -> Vec<LocalOp> {
+        assert!(self.nested_can_merge(&other), "Json values must have the same type");
+        let mut local_ops = vec![];
+        self.force_merge(other, summary, other_summary, &mut vec![], &mut local_ops);
+        local_ops
+    }
+
+    pub(crate) fn delta_since(&self, since: &Summary, summary: &Summary) -> InnerDelta {
+        match *self {
+            Inner::Object(ref map) => {
+                let delta_map = map.0.iter().map(|(key, elements)| {
+                    let element_deltas = elements.iter()
+                        .map(|e| (e.dot, e.value.element_delta(&e.dot, since, summary)))
+                        .collect();
+                    (key.clone(), element_deltas)
+                }).collect();
+                InnerDelta::Object(delta_map)
+            }
+            Inner::Array(ref list) => {
+                let delta_list = list.0.iter()
+                    .map(|e| (e.uid.clone(), e.moved.clone(), e.value.element_delta(&e.uid.dot(), since, summary)))
+                    .collect();
+                InnerDelta::Array(delta_list)
+            }
+            Inner::String(ref text) => InnerDelta::String(text.delta_since(since, summary)),
+            Inner::Counter(ref counter) => InnerDelta::Counter(counter.delta_since(since)),
+            Inner::Register(ref register) => {
+                let (elements, retained) = register.delta_since(since);
+                InnerDelta::Register(elements, retained)
+            }
+            _ => InnerDelta::Unchanged,
         }
     }
 
-    pub fn merge(&mut self, other: Inner, summary: &Summary, other_summary: &Summary) {
-        self.nested_merge(other, summary, other_summary).unwrap()
+    pub(crate) fn merge_delta(&mut self, delta: InnerDelta, summary: &Summary, delta_summary: &Summary) -> Result<(), Error> {
+        self.validate_delta(&delta, summary, delta_summary)?;
+        self.force_merge_delta(delta, summary, delta_summary);
+        Ok(())
     }
 
     pub fn add_site_id(&mut self, site_id: SiteId) {
@@ -306,26 +1416,158 @@ impl Inner {
                 SJValue::Bool(bool_value),
             Inner::Null =>
                 SJValue::Null,
+            Inner::Counter(ref counter) => {
+                let number = serde_json::Number::from_f64(counter.get() as f64).unwrap();
+                SJValue::Number(number)
+            }
+            Inner::Register(ref register) =>
+                register.get().clone(),
         }
     }
 
-    fn split_pointer(pointer_str: &str) -> Result<Vec<&str>, Error> {
+    /// Edits the value to equal `target`, pushing an op for each edit.
+    /// Objects, arrays and strings are diffed if `target` has the same
+    /// type; nested values that cannot be diffed are replaced.
+    fn set_value(&mut self, target: SJValue, pointer: &mut Vec<Uid>, summary: &mut Summary, site_id: SiteId, ops: &mut Vec<Op>) -> Result<(), Error> {
+        match (self, target) {
+            (&mut Inner::Object(ref mut map), SJValue::Object(target)) => {
+                let removed_keys: Vec<String> = map.0.keys()
+                    .filter(|key| !target.contains_key(*key))
+                    .cloned()
+                    .collect();
+
+                for key in removed_keys {
+                    let op = map.remove(&key).expect("Key must exist!");
+                    ops.push(Op{pointer: pointer.clone(), op: OpInner::Object(op)});
+                }
+
+                for (key, value) in target {
+                    if let Some(element) = map.get_mut(&key) {
+                        if element.value.can_set_value(&value) {
+                            pointer.push(Uid::Object(key, element.dot));
+                            element.value.set_value(value, pointer, summary, site_id, ops)?;
+                            let _ = pointer.pop();
+                            continue
+                        } else if element.value.local_value() == value {
+                            continue
+                        }
+                    }
+
+                    let dot = summary.next_dot(site_id);
+                    let op = map.insert(key, value.into_json(dot)?, dot);
+                    summary.insert(dot);
+                    ops.push(Op{pointer: pointer.clone(), op: OpInner::Object(op)});
+                }
+            }
+            (&mut Inner::Array(ref mut list), SJValue::Array(mut target)) => {
+                let values: Vec<SJValue> = list.iter().map(|e| e.value.local_value()).collect();
+
+                for (idx, len, range) in diff_array(&values, &target).into_iter().rev() {
+                    let paired_len = min(len, range.len());
+
+                    for (offset, target_idx) in range.clone().enumerate() {
+                        let value = mem::replace(&mut target[target_idx], SJValue::Null);
+                        let idx = idx + offset;
+
+                        if offset < paired_len {
+                            let element = list.get_mut(idx).unwrap();
+                            if element.value.can_set_value(&value) {
+                                pointer.push(Uid::Array(element.id().clone()));
+                                element.value.set_value(value, pointer, summary, site_id, ops)?;
+                                let _ = pointer.pop();
+                                continue
+                            }
+                            let (_, op) = list.remove(idx);
+                            ops.push(Op{pointer: pointer.clone(), op: OpInner::Array(op)});
+                        }
+
+                        let dot = summary.next_dot(site_id);
+                        let op = list.insert(idx, value.into_json(dot)?, dot);
+                        summary.insert(dot);
+                        ops.push(Op{pointer: pointer.clone(), op: OpInner::Array(op)});
+                    }
+
+                    for _ in paired_len..len {
+                        let (_, op) = list.remove(idx + range.len());
+                        ops.push(Op{pointer: pointer.clone(), op: OpInner::Array(op)});
+                    }
+                }
+            }
+            (&mut Inner::String(ref mut text), SJValue::String(target)) => {
+                // don't merge into the previous edit, or its text would be resent
+                text.1 = None;
+                for hunk in text.diff(&target) {
+                    let dot = summary.next_dot(site_id);
+                    if let Some(op) = text.replace(hunk.idx, hunk.len, hunk.text, dot) {
+                        for dot in op.inserted_dots() {
+                            summary.insert(dot);
+                        }
+                        ops.push(Op{pointer: pointer.clone(), op: OpInner::String(op)});
+                    }
+                }
+            }
+            (&mut Inner::Counter(ref mut counter), SJValue::Number(target)) => {
+                let amount = Self::counter_target(&target).ok_or(Error::WrongJsonType)? - counter.get();
+                if amount != 0 {
+                    let dot = summary.next_dot(site_id);
+                    let op = counter.increment_at(amount, dot);
+                    summary.insert(dot);
+                    ops.push(Op{pointer: pointer.clone(), op: OpInner::Counter(op)});
+                }
+            }
+            (&mut Inner::Register(ref mut register), target) => {
+                if *register.get() != target {
+                    let dot = summary.next_dot(site_id);
+                    let op = register.update(target, dot);
+                    summary.insert(dot);
+                    ops.push(Op{pointer: pointer.clone(), op: OpInner::Register(op)});
+                }
+            }
+            (value, target) => {
+                if value.local_value() != target {
+                    return Err(Error::WrongJsonType)
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn can_set_value(&self, target: &SJValue) -> bool {
+        match (self, target) {
+            (Inner::Counter(_), SJValue::Number(number)) =>
+                Self::counter_target(number).is_some(),
+            _ => matches!((self, target),
+                (&Inner::Object(_), &SJValue::Object(_)) |
+                (&Inner::Array(_), &SJValue::Array(_)) |
+                (&Inner::String(_), &SJValue::String(_)) |
+                (&Inner::Register(_), _)),
+        }
+    }
+
+    /// Returns the value that a counter can be set to
+    /// if `number` is an integer.
+    fn counter_target(number: &serde_json::Number) -> Option<i64> {
+        let float = number.as_f64()?;
+        if float.fract() == 0.0 && float.abs() < i64::MAX as f64 { Some(float as i64) } else { None }
+    }
+
+    fn split_pointer(pointer_str: &str) -> Result<Vec<Cow<'_, str>>, Error> {
         if !(pointer_str.is_empty() || pointer_str.starts_with('/')) {
             return Err(Error::DoesNotExist)
         }
-        Ok(pointer_str.split('/').skip(1).collect())
+        Ok(pointer_str.split('/').skip(1).map(unescape_key).collect())
     }
 
-    fn get_nested_local(&self, pointer: &[&str]) -> Option<&Inner> {
+    fn get_nested_local(&self, pointer: &[Cow<'_, str>]) -> Option<&Inner> {
         let mut value = self;
 
         for key in pointer {
             value = match *value {
                 Inner::Object(ref map_inner) =>
-                    &map_inner.0.get(*key)?[0].value,
+                    &map_inner.0.get(&key[..])?[0].value,
                 Inner::Array(ref list_inner) => {
                     let idx = usize::from_str(key).ok()?;
-                    let element = list_inner.0.get(idx)?;
+                    let element = list_inner.get(idx)?;
                     &element.value
                 }
                 _ => return None,
@@ -335,22 +1577,22 @@ impl Inner {
         Some(value)
     }
 
-    fn mut_nested_local(&mut self, pointer: &[&str]) -> Result<(&mut Inner, Vec<Uid>), Error> {
+    fn mut_nested_local(&mut self, pointer: &[Cow<'_, str>]) -> Result<(&mut Inner, Vec<Uid>), Error> {
         let mut value = Some(self);
         let mut remote_pointer = vec![];
 
         for key in pointer {
             value = match *value.unwrap() {
                 Inner::Object(ref mut map_inner) => {
-                    let element = map_inner.get_mut(*key).ok_or(Error::DoesNotExist)?;
+                    let element = map_inner.get_mut(&key[..]).ok_or(Error::DoesNotExist)?;
                     let uid = Uid::Object(key.to_string(), element.dot);
                     remote_pointer.push(uid);
                     Some(&mut element.value)
                 }
                 Inner::Array(ref mut list_inner) => {
                     let idx = usize::from_str(key)?;
-                    let element = list_inner.0.get_mut(idx).ok_or(Error::DoesNotExist)?;
-                    let uid = Uid::Array(element.uid.clone());
+                    let element = list_inner.get_mut(idx).ok_or(Error::DoesNotExist)?;
+                    let uid = Uid::Array(element.id().clone());
                     remote_pointer.push(uid);
                     Some(&mut element.value)
                 }
@@ -385,6 +1627,245 @@ impl Inner {
         Some((value.unwrap(), local_pointer))
     }
 
+    fn element_delta(&self, dot: &Dot, since: &Summary, summary: &Summary) -> ElementDelta {
+        if since.contains(dot) {
+            ElementDelta::Retained(self.delta_since(since, summary))
+        } else {
+            ElementDelta::Inserted(self.clone())
+        }
+    }
+
+    fn validate_delta(&self, delta: &InnerDelta, summary: &Summary, delta_summary: &Summary) -> Result<(), Error> {
+        match (self, delta) {
+            (Inner::Object(map), InnerDelta::Object(delta_map)) => {
+                for (key, element_deltas) in delta_map {
+                    let elements = map.0.get(key).map(Vec::as_slice).unwrap_or(&[]);
+                    for (dot, element_delta) in element_deltas {
+                        let value = elements.binary_search_by(|e| e.dot.cmp(dot)).ok().map(|idx| &elements[idx].value);
+                        Self::validate_element_delta(value, dot, element_delta, summary, delta_summary)?;
+                    }
+                }
+                Ok(())
+            }
+            (Inner::Array(list), InnerDelta::Array(delta_list)) => {
+                for (uid, moved, element_delta) in delta_list {
+                    let id = moved.as_ref().map_or(uid, |moved| &moved.id);
+                    let value = list.get_idx(id).and_then(|idx| list.get(idx)).map(|element| &element.value);
+                    Self::validate_element_delta(value, &uid.dot(), element_delta, summary, delta_summary)?;
+                }
+                Ok(())
+            }
+            (Inner::String(text), InnerDelta::String(text_delta)) =>
+                text.validate_delta(text_delta, summary, delta_summary),
+            (Inner::Register(_), InnerDelta::Register(_, retained)) => {
+                if retained.iter().any(|dot| !summary.contains(dot)) { return Err(Error::InvalidDelta) }
+                Ok(())
+            }
+            (Inner::Counter(_), InnerDelta::Counter(_)) |
+            (Inner::Number(_), InnerDelta::Unchanged) |
+            (Inner::Bool(_),   InnerDelta::Unchanged) |
+            (Inner::Null,      InnerDelta::Unchanged) => Ok(()),
+            _ => Err(Error::CannotMerge),
+        }
+    }
+
+    fn validate_element_delta(value: Option<&Inner>, dot: &Dot, delta: &ElementDelta, summary: &Summary, delta_summary: &Summary) -> Result<(), Error> {
+        match *delta {
+            ElementDelta::Inserted(ref other) => match value {
+                Some(value) if !value.nested_can_merge(other) => Err(Error::CannotMerge),
+                _ => Ok(()),
+            },
+            ElementDelta::Retained(ref delta) => match value {
+                Some(value) => value.validate_delta(delta, summary, delta_summary),
+                None if !summary.contains(dot) => Err(Error::InvalidDelta),
+                None => Ok(()),
+            },
+        }
+    }
+
+    fn force_merge_delta(&mut self, delta: InnerDelta, summary: &Summary, delta_summary: &Summary) {
+        match delta {
+            InnerDelta::Object(mut delta_map) => {
+                let map = self.as_map().unwrap();
+
+                map.0.retain(|key, elements| {
+                    let delta_elements = delta_map.remove(key).unwrap_or_default();
+                    let mut retained_dots = Vec::with_capacity(delta_elements.len());
+                    let mut new_elements = vec![];
+
+                    for (dot, element_delta) in delta_elements {
+                        match elements.binary_search_by(|e| e.dot.cmp(&dot)) {
+                            Ok(idx) => {
+                                elements[idx].value.force_merge_element_delta(element_delta, summary, delta_summary);
+                                retained_dots.push(dot);
+                            }
+                            Err(_) => {
+                                if let ElementDelta::Inserted(value) = element_delta {
+                                    if !summary.contains(&dot) {
+                                        new_elements.push(map::Element{value, dot});
+                                    }
+                                }
+                            }
+                        }
+                    }
+
+                    elements.retain(|e| retained_dots.contains(&e.dot) || !delta_summary.contains(&e.dot));
+                    elements.append(&mut new_elements);
+                    elements.sort();
+                    !elements.is_empty()
+                });
+
+                for (key, delta_elements) in delta_map {
+                    let mut elements: Vec<map::Element<Inner>> = delta_elements.into_iter()
+                        .filter_map(|(dot, element_delta)| match element_delta {
+                            ElementDelta::Inserted(value) => Some(map::Element{value, dot}),
+                            ElementDelta::Retained(_) => None,
+                        })
+                        .filter(|e| !summary.contains(&e.dot))
+                        .collect();
+
+                    if !elements.is_empty() {
+                        elements.sort();
+                        map.0.insert(key, elements);
+                    }
+                }
+            }
+            InnerDelta::Array(delta_list) => {
+                let list = self.as_list().unwrap();
+                let mut retained = HashSet::new();
+                let mut inserted = vec![];
+
+                for (uid, moved, element_delta) in delta_list {
+                    match element_delta {
+                        ElementDelta::Retained(delta) => {
+                            let id = moved.map_or(uid, |moved| moved.id);
+                            if let Some(idx) = list.get_idx(&id) {
+                                list.get_mut(idx).unwrap().value.force_merge_delta(delta, summary, delta_summary);
+                            }
+                            retained.insert(id);
+                        }
+                        ElementDelta::Inserted(value) =>
+                            inserted.push(list::Element{uid, value, moved}),
+                    }
+                }
+
+                let (_, shared) = list.merge_elements(inserted, &retained, summary, delta_summary);
+                for (idx, value) in shared {
+                    list.get_mut(idx).unwrap().value.nested_force_merge(value, summary, delta_summary);
+                }
+            }
+            InnerDelta::String(text_delta) => {
+                self.as_text().unwrap().merge_delta(text_delta, summary, delta_summary).unwrap();
+            }
+            InnerDelta::Counter(counter_delta) => {
+                self.as_counter().unwrap().merge(counter_delta);
+            }
+            InnerDelta::Register(elements, retained) => {
+                self.as_register().unwrap().merge_delta(elements, retained, summary, delta_summary);
+            }
+            InnerDelta::Unchanged => (),
+        }
+    }
+
+    /// Merges a value of the same type into `self` and records the
+    /// local ops it applies. Nested values are only diffed while they
+    /// stay visible; a value that becomes visible is reported as a
+    /// single insert.
+    fn force_merge(&mut self, other: Inner, summary: &Summary, other_summary: &Summary, pointer: &mut Vec<LocalUid>, local_ops: &mut Vec<LocalOp>) {
+        match other {
+            Inner::Object(other_map) => {
+                let map = self.as_map().unwrap();
+                let mut other_values = other_map.0;
+
+                map.0.retain(|key, elements| {
+                    let visible_dot = elements[0].dot;
+                    let mut other_elements = other_values.remove(key).unwrap_or_default();
+                    elements.retain(|e| other_elements.contains(e) || !other_summary.contains(&e.dot));
+                    other_elements.retain(|e| elements.contains(e) || !summary.contains(&e.dot));
+
+                    let (other_merge, mut other_insert): (Vec<_>, Vec<_>) = other_elements.into_iter()
+                        .partition(|e| elements.contains(e));
+
+                    let new_visible_dot = elements.iter().chain(other_insert.iter()).map(|e| e.dot).min();
+                    pointer.push(LocalUid::Object(key.clone()));
+
+                    for element in other_merge {
+                        let idx = elements.binary_search_by(|e| e.cmp(&element)).expect("Element must be present");
+                        if element.dot == visible_dot && new_visible_dot == Some(visible_dot) {
+                            elements[idx].value.force_merge(element.value, summary, other_summary, pointer, local_ops);
+                        } else {
+                            elements[idx].value.nested_force_merge(element.value, summary, other_summary);
+                        }
+                    }
+
+                    elements.append(&mut other_insert);
+                    elements.sort();
+
+                    if elements.is_empty() {
+                        local_ops.push(LocalOp::Remove{pointer: pointer.clone()});
+                    } else if elements[0].dot != visible_dot {
+                        local_ops.push(LocalOp::Insert{pointer: pointer.clone(), value: elements[0].value.local_value()});
+                    }
+
+                    pointer.pop();
+                    !elements.is_empty()
+                });
+
+                for (key, mut elements) in other_values {
+                    elements.retain(|e| !summary.contains(&e.dot));
+                    if !elements.is_empty() {
+                        elements.sort();
+                        let mut pointer = pointer.clone();
+                        pointer.push(LocalUid::Object(key.clone()));
+                        local_ops.push(LocalOp::Insert{pointer, value: elements[0].value.local_value()});
+                        map.0.insert(key, elements);
+                    }
+                }
+            }
+            Inner::Array(other_list) => {
+                let list = self.as_list().unwrap();
+                let (list_ops, shared) = list.merge_elements(other_list.0, &HashSet::new(), summary, other_summary);
+                for local_op in list_ops {
+                    local_ops.push(Self::array_local_op(local_op, pointer.clone()));
+                }
+
+                for (idx, value) in shared {
+                    pointer.push(LocalUid::Array(idx));
+                    list.get_mut(idx).unwrap().value.force_merge(value, summary, other_summary, pointer, local_ops);
+                    pointer.pop();
+                }
+            }
+            Inner::String(other_text) => {
+                let changes = self.as_text().unwrap().merge(other_text, summary, other_summary);
+                if !changes.is_empty() {
+                    local_ops.push(LocalOp::ReplaceText{pointer: pointer.clone(), changes});
+                }
+            }
+            Inner::Counter(other_counter) => {
+                let counter = self.as_counter().unwrap();
+                let value = counter.get();
+                counter.merge(other_counter);
+                if counter.get() != value {
+                    local_ops.push(LocalOp::Update{pointer: pointer.clone(), value: self.local_value()});
+                }
+            }
+            Inner::Register(other_register) => {
+                let register = self.as_register().unwrap();
+                if let Some(value) = register.merge(other_register, summary, other_summary) {
+                    local_ops.push(LocalOp::Update{pointer: pointer.clone(), value: value.clone()});
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn force_merge_element_delta(&mut self, delta: ElementDelta, summary: &Summary, delta_summary: &Summary) {
+        match delta {
+            ElementDelta::Inserted(other) => self.nested_force_merge(other, summary, delta_summary),
+            ElementDelta::Retained(delta) => self.force_merge_delta(delta, summary, delta_summary),
+        }
+    }
+
     fn as_map(&mut self) -> Result<&mut MapInner<String, Inner>, Error> {
         match *self {
             Inner::Object(ref mut map_value) => Ok(map_value),
@@ -405,63 +1886,202 @@ impl Inner {
             _ => Err(Error::WrongJsonType)
         }
     }
+
+    fn as_counter(&mut self) -> Result<&mut CounterInner, Error> {
+        match *self {
+            Inner::Counter(ref mut counter) => Ok(counter),
+            _ => Err(Error::WrongJsonType)
+        }
+    }
+
+    fn as_register(&mut self) -> Result<&mut RegisterInner<SJValue>, Error> {
+        match *self {
+            Inner::Register(ref mut register) => Ok(register),
+            _ => Err(Error::WrongJsonType)
+        }
+    }
 }
 
-impl NestedInner for Inner {
+impl Nested for Inner {
+    type NestedOp = NoOp;
+    type NestedLocalOp = NoOp;
+
+    fn nested_reset(&mut self, dot: Dot) {
+        match *self {
+            Inner::Object(ref mut map) => map.reset(dot),
+            Inner::Array(ref mut list) => list.reset(dot),
+            Inner::String(ref mut text) => text.reset(dot),
+            Inner::Counter(ref mut counter) => *counter = CounterInner::new(counter.get(), dot),
+            Inner::Register(ref mut register) => *register = RegisterInner::new(register.get().clone(), dot),
+            _ => (),
+        }
+    }
+
+    fn nested_execute_op(&mut self, op: NoOp) -> Vec<NoOp> {
+        match op {}
+    }
+
+    fn nested_merge(&mut self, other: Inner, summary: &Summary, other_summary: &Summary) -> Vec<NoOp> {
+        self.nested_force_merge(other, summary, other_summary);
+        vec![]
+    }
+
+    fn nested_force_merge(&mut self, other: Inner, summary: &Summary, other_summary: &Summary) {
+        match other {
+            Inner::Object(other_map) => {
+                let _ = self.as_map().unwrap().merge(other_map, summary, other_summary);
+            }
+            Inner::Array(other_list) => {
+                let _ = self.as_list().unwrap().merge(other_list, summary, other_summary);
+            }
+            Inner::String(other_text) => {
+                let _ = self.as_text().unwrap().merge(other_text, summary, other_summary);
+            }
+            Inner::Counter(other_counter) => {
+                self.as_counter().unwrap().merge(other_counter);
+            }
+            Inner::Register(other_register) => {
+                let _ = self.as_register().unwrap().merge(other_register, summary, other_summary);
+            }
+            _ => (),
+        }
+    }
+
+    fn nested_unchanged_since(&self, _: &Summary) -> bool {
+        false
+    }
+
     fn nested_add_site_id(&mut self, site_id: SiteId) {
         match *self {
-            Inner::Object(ref mut map) => map.nested_add_site_id(site_id),
-            Inner::Array(ref mut list) => list.nested_add_site_id(site_id),
+            Inner::Object(ref mut map) => map.add_site_id(site_id),
+            Inner::Array(ref mut list) => list.add_site_id(site_id),
             Inner::String(ref mut text) => text.add_site_id(site_id),
+            Inner::Counter(ref mut counter) => counter.add_site_id(site_id),
+            Inner::Register(ref mut register) => register.add_site_id(site_id),
             _ => (),
         }
     }
 
     fn nested_validate_no_unassigned_sites(&self) -> Result<(), Error> {
         match *self {
-            Inner::Object(ref map) => map.nested_validate_no_unassigned_sites(),
-            Inner::Array(ref list) => list.nested_validate_no_unassigned_sites(),
+            Inner::Object(ref map) => map.validate_no_unassigned_sites(),
+            Inner::Array(ref list) => list.validate_no_unassigned_sites(),
             Inner::String(ref text) => text.validate_no_unassigned_sites(),
+            Inner::Counter(ref counter) => counter.validate_no_unassigned_sites(),
+            Inner::Register(ref register) => register.validate_no_unassigned_sites(),
             _ => Ok(())
         }
     }
 
     fn nested_validate_all(&self, site_id: SiteId) -> Result<(), Error> {
         match *self {
-            Inner::Object(ref map) => map.nested_validate_all(site_id),
-            Inner::Array(ref list) => list.nested_validate_all(site_id),
+            Inner::Object(ref map) => map.validate_all(site_id),
+            Inner::Array(ref list) => list.validate_all(site_id),
             Inner::String(ref text) => text.validate_all(site_id),
+            Inner::Counter(ref counter) => counter.validate_all(site_id),
+            Inner::Register(ref register) => register.validate_all(site_id),
             _ => Ok(())
         }
     }
 
     fn nested_can_merge(&self, other: &Inner) -> bool {
         match (self, other) {
-            (&Inner::Object(ref v1), &Inner::Object(ref v2)) => v1.nested_can_merge(v2),
-            (&Inner::Array(ref v1), &Inner::Array(ref v2)) => v1.nested_can_merge(v2),
-            (&Inner::String(_), &Inner::String(_)) |
-            (&Inner::Number(_), &Inner::Number(_)) |
-            (&Inner::Bool(_),   &Inner::Bool(_))   |
-            (&Inner::Null,      &Inner::Null)      => true,
+            (Inner::Object(v1), Inner::Object(v2)) => v1.can_merge(v2),
+            (Inner::Array(v1), Inner::Array(v2)) => v1.can_merge(v2),
+            (Inner::String(_), Inner::String(_)) |
+            (Inner::Counter(_), Inner::Counter(_)) |
+            (Inner::Register(_), Inner::Register(_)) |
+            (Inner::Number(_), Inner::Number(_)) |
+            (Inner::Bool(_),   Inner::Bool(_))   |
+            (Inner::Null,      Inner::Null)      => true,
             _ => false,
         }
     }
+}
 
-    fn nested_force_merge(&mut self, other: Inner, summary: &Summary, other_summary: &Summary) {
-        match other {
-            Inner::Object(other_map) => {
-                self.as_map().unwrap().nested_force_merge(other_map, summary, other_summary);
+impl Nested for Json {
+    type NestedOp = Op;
+    type NestedLocalOp = LocalOp;
+
+    fn nested_reset(&mut self, dot: Dot) {
+        self.inner.nested_reset(dot);
+        self.summary = Summary::default();
+        self.site_id = 0;
+        self.cached_ops.clear();
+    }
+
+    fn nested_execute_op(&mut self, op: Op) -> Vec<LocalOp> {
+        self.inner.execute_op(op).into_iter().collect()
+    }
+
+    fn nested_merge(&mut self, other: Json, summary: &Summary, other_summary: &Summary) -> Vec<LocalOp> {
+        self.inner.merge(other.inner, summary, other_summary)
+    }
+
+    fn nested_unchanged_since(&self, _: &Summary) -> bool {
+        false
+    }
+
+    fn nested_add_site_id(&mut self, site_id: SiteId) {
+        self.inner.nested_add_site_id(site_id)
+    }
+
+    fn nested_validate_all(&self, site_id: SiteId) -> Result<(), Error> {
+        self.inner.nested_validate_all(site_id)
+    }
+
+    fn nested_validate_no_unassigned_sites(&self) -> Result<(), Error> {
+        self.inner.nested_validate_no_unassigned_sites()
+    }
+
+    fn nested_can_merge(&self, other: &Json) -> bool {
+        self.inner.nested_can_merge(&other.inner)
+    }
+}
+
+impl NestedCrdt for Json {
+    nested_crdt_impl!();
+}
+
+impl InnerDelta {
+    fn validate_no_unassigned_sites(&self) -> Result<(), Error> {
+        match *self {
+            InnerDelta::Object(ref delta_map) => {
+                for element_deltas in delta_map.values() {
+                    for (dot, element_delta) in element_deltas {
+                        if dot.site_id == 0 { return Err(Error::InvalidSiteId) }
+                        element_delta.validate_no_unassigned_sites()?;
+                    }
+                }
+                Ok(())
             }
-            Inner::Array(other_list) => {
-                self.as_list().unwrap().nested_force_merge(other_list, summary, other_summary);
+            InnerDelta::Array(ref delta_list) => {
+                for (uid, moved, element_delta) in delta_list {
+                    if uid.site_id == 0 { return Err(Error::InvalidSiteId) }
+                    if moved.iter().any(|moved| moved.id.site_id == 0) { return Err(Error::InvalidSiteId) }
+                    element_delta.validate_no_unassigned_sites()?;
+                }
+                Ok(())
             }
-            Inner::String(other_text) =>
-                self.as_text().unwrap().merge(other_text, summary, other_summary),
-            _ => (),
+            InnerDelta::String(ref text_delta) => text_delta.validate_no_unassigned_sites(),
+            InnerDelta::Counter(ref counter_delta) => counter_delta.validate_no_unassigned_sites(),
+            InnerDelta::Register(ref elements, ref retained) => {
+                if retained.iter().any(|dot| dot.site_id == 0) { return Err(Error::InvalidSiteId) }
+                elements.validate_no_unassigned_sites()
+            }
+            InnerDelta::Unchanged => Ok(()),
         }
     }
 }
 
+impl ElementDelta {
+    fn validate_no_unassigned_sites(&self) -> Result<(), Error> {
+        match *self {
+            ElementDelta::Inserted(ref value) => value.nested_validate_no_unassigned_sites(),
+            ElementDelta::Retained(ref delta) => delta.validate_no_unassigned_sites(),
+        }
+    }
+}
 
 impl Op {
     fn add_site_id(&mut self, site_id: SiteId) {
@@ -477,7 +2097,44 @@ impl Op {
             OpInner::Object(ref op) => op.inserted_dots(),
             OpInner::Array(ref op) => op.inserted_dots(),
             OpInner::String(ref op) => op.inserted_dots(),
+            OpInner::Counter(ref op) => op.inserted_dots(),
+            OpInner::Register(ref op) => op.inserted_dots(),
+            OpInner::Transaction(ref transaction) =>
+                transaction.ops.iter().flat_map(|op| op.inserted_dots()).collect(),
+        }
+    }
+}
+
+impl CausalOp for Op {
+    fn inserted_dots(&self) -> Vec<Dot> {
+        Op::inserted_dots(self)
+    }
+
+    fn referenced_dots(&self) -> Vec<Dot> {
+        // a transaction's ops may reference elements inserted earlier in it
+        if let OpInner::Transaction(ref transaction) = self.op {
+            let inserted_dots = self.inserted_dots();
+            return transaction.ops.iter()
+                .flat_map(|op| op.referenced_dots())
+                .filter(|dot| !inserted_dots.contains(dot))
+                .collect()
         }
+
+        let mut dots: Vec<Dot> = self.pointer.iter().map(|uid| match *uid {
+            Uid::Object(_, dot) => dot,
+            Uid::Array(ref uid) => uid.dot(),
+        }).collect();
+
+        dots.append(&mut match self.op {
+            OpInner::Object(ref op) => op.referenced_dots(),
+            OpInner::Array(ref op) => op.referenced_dots(),
+            OpInner::String(ref op) => op.referenced_dots(),
+            OpInner::Counter(ref op) => op.referenced_dots(),
+            OpInner::Register(ref op) => op.referenced_dots(),
+            OpInner::Transaction(_) => unreachable!(),
+        });
+
+        dots
     }
 }
 
@@ -500,6 +2157,13 @@ impl NestedOp for Op {
             OpInner::Object(ref mut op) => op.nested_add_site_id(site_id),
             OpInner::Array(ref mut op) => op.nested_add_site_id(site_id),
             OpInner::String(ref mut op) => op.add_site_id(site_id),
+            OpInner::Counter(ref mut op) => if op.site_id() == 0 { op.add_site_id(site_id) },
+            OpInner::Register(ref mut op) => if op.site_id() == 0 { op.add_site_id(site_id) },
+            OpInner::Transaction(ref mut transaction) => {
+                for op in &mut transaction.ops {
+                    op.nested_add_site_id(site_id);
+                }
+            }
         }
     }
 
@@ -508,11 +2172,122 @@ impl NestedOp for Op {
             OpInner::Object(ref op) => op.nested_validate(site_id),
             OpInner::Array(ref op) => op.nested_validate(site_id),
             OpInner::String(ref op) => op.validate(site_id),
+            OpInner::Counter(ref op) => op.validate(site_id),
+            OpInner::Register(ref op) => op.validate(site_id),
+            OpInner::Transaction(ref transaction) =>
+                transaction.ops.iter().try_for_each(|op| op.nested_validate(site_id)),
         }
     }
 }
 
 
+/// Returns the hunks that turn array `old` into array `new`, found
+/// from their longest common subsequence. Each hunk replaces `len`
+/// elements of `old` starting at `idx` with the `range` elements of
+/// `new`. Hunks are in ascending order.
+fn diff_array(old: &[SJValue], new: &[SJValue]) -> Vec<(usize, usize, Range<usize>)> {
+    let prefix_len = old.iter().zip(new).take_while(|&(a, b)| a == b).count();
+    let (old, new) = (&old[prefix_len..], &new[prefix_len..]);
+    let suffix_len = old.iter().rev().zip(new.iter().rev()).take_while(|&(a, b)| a == b).count();
+    let (old, new) = (&old[..old.len() - suffix_len], &new[..new.len() - suffix_len]);
+
+    // lcs_lens[i][j] is the length of the LCS of old[i..] and new[j..]
+    let (n, m) = (old.len(), new.len());
+    let mut lcs_lens = vec![vec![0; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lcs_lens[i][j] = if old[i] == new[j] {
+                lcs_lens[i+1][j+1] + 1
+            } else {
+                max(lcs_lens[i+1][j], lcs_lens[i][j+1])
+            };
+        }
+    }
+
+    let mut hunks = vec![];
+    let (mut i, mut j) = (0, 0);
+    while i < n || j < m {
+        let (start_i, start_j) = (i, j);
+        while (i < n || j < m) && !(i < n && j < m && old[i] == new[j]) {
+            if j == m || (i < n && lcs_lens[i+1][j] >= lcs_lens[i][j+1]) { i += 1 } else { j += 1 }
+        }
+
+        if i > start_i || j > start_j {
+            hunks.push((prefix_len + start_i, i - start_i, prefix_len + start_j..prefix_len + j));
+        }
+
+        i += 1;
+        j += 1;
+    }
+
+    hunks
+}
+
+/// Returns the JSON pointer of a local pointer.
+fn patch_path(pointer: &[LocalUid]) -> String {
+    let mut path = String::new();
+    for uid in pointer {
+        path.push('/');
+        match *uid {
+            LocalUid::Object(ref key) => path.push_str(&escape_key(key)),
+            LocalUid::Array(idx) => path.push_str(&idx.to_string()),
+        }
+    }
+    path
+}
+
+/// Encodes a key for use in a JSON pointer.
+fn escape_key(key: &str) -> String {
+    key.replace('~', "~0").replace('/', "~1")
+}
+
+/// Decodes the `~0` and `~1` escapes of a JSON pointer key.
+fn unescape_key(key: &str) -> Cow<'_, str> {
+    if key.contains('~') {
+        Cow::Owned(key.replace("~1", "/").replace("~0", "~"))
+    } else {
+        Cow::Borrowed(key)
+    }
+}
+
+fn push_op(result: Result<Op, Error>, ops: &mut Vec<Op>) -> Result<(), Error> {
+    match result {
+        Ok(op) => ops.push(op),
+        Err(Error::AwaitingSiteId) => (),
+        Err(err) => return Err(err),
+    }
+    Ok(())
+}
+
+/// Removes the null members of every object in a JSON value.
+fn remove_nulls(value: SJValue) -> SJValue {
+    match value {
+        SJValue::Object(map) => SJValue::Object(map.into_iter()
+            .filter(|(_, value)| !value.is_null())
+            .map(|(key, value)| (key, remove_nulls(value)))
+            .collect()),
+        value => value,
+    }
+}
+
+/// Converts every number in a JSON value to the f64 representation
+/// that Json stores, so that it can be compared with local values.
+fn normalize_numbers(value: SJValue) -> Result<SJValue, Error> {
+    Ok(match value {
+        SJValue::Object(map) => SJValue::Object(map.into_iter()
+            .map(|(key, value)| Ok((key, normalize_numbers(value)?)))
+            .collect::<Result<_, Error>>()?),
+        SJValue::Array(vec) => SJValue::Array(vec.into_iter()
+            .map(normalize_numbers)
+            .collect::<Result<_, Error>>()?),
+        SJValue::Number(number) => {
+            let float = number.as_f64().ok_or(Error::InvalidJson)?;
+            SJValue::Number(serde_json::Number::from_f64(float).ok_or(Error::InvalidJson)?)
+        }
+        value => value,
+    })
+}
+
 impl IntoJson for Inner {
     #[inline]
     fn into_json(self, _: Dot) -> Result<Inner, Error> {
@@ -556,7 +2331,7 @@ impl<S: Into<String> + Hash + Eq, T: IntoJson> IntoJson for HashMap<S, T> {
 
 impl<T: IntoJson> IntoJson for Vec<T> {
     fn into_json(self, dot: Dot) -> Result<Inner, Error> {
-        let mut list_inner = ListInner::with_capacity(self.len());
+        let mut list_inner = ListInner::new();
         for (idx, elt) in self.into_iter().enumerate() {
             let _ = list_inner.insert(idx, elt.into_json(dot)?, dot);
         }
@@ -594,6 +2369,19 @@ impl IntoJson for bool {
     }
 }
 
+impl IntoJson for Counter {
+    fn into_json(self, dot: Dot) -> Result<Inner, Error> {
+        Ok(Inner::Counter(CounterInner::new(self.get(), dot)))
+    }
+}
+
+impl IntoJson for Register<SJValue> {
+    fn into_json(self, dot: Dot) -> Result<Inner, Error> {
+        let value = normalize_numbers(self.get().clone())?;
+        Ok(Inner::Register(RegisterInner::new(value, dot)))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -920,9 +2708,9 @@ mod tests {
         }
         {
             let list = as_list(nested_value(&mut crdt2, "/baz/abc").unwrap());
-            assert_eq!(list.0[0].uid.site_id, 11);
-            assert_eq!(list.0[1].uid.site_id, 11);
-            assert_eq!(list.0[2].uid.site_id, 11);
+            assert_eq!(list.get(0).unwrap().uid.site_id, 11);
+            assert_eq!(list.get(1).unwrap().uid.site_id, 11);
+            assert_eq!(list.get(2).unwrap().uid.site_id, 11);
         }
 
         // check that the remote ops' elements have the correct sites
@@ -67,6 +67,21 @@
 //! op sent from another site, you receive a `LocalOp` that shows
 //! exactly how the CRDT's value has changed.
 //!
+//! Every CRDT implements the [`Crdt`](trait.Crdt.html) trait,
+//! so replication code that only needs to move ops and state
+//! between sites can be written once for all CRDT types.
+//!
+//! CRDTs can also be nested: the values of a `Map` or `List` may
+//! themselves be CRDTs, as in `Map<String, Text>` or `List<Counter>`.
+//! Nested values are edited in place with `Map::edit` and `List::edit`,
+//! and merges recurse into them. Plain values must implement the
+//! [`Plain`](trait.Plain.html) marker trait, which Ditto already
+//! implements for the standard library's types.
+//!
+//! Structs whose fields are all CRDTs can `#[derive(Crdt)]` with the
+//! companion `ditto-derive` crate. The derive generates a composite
+//! CRDT that replicates every field with one summary and site id.
+//!
 //! The two complications of CRDTs that users have to worry about are:
 //!
 //!   * How to send ops/state from one site to another
@@ -92,6 +107,12 @@
 //! layer for op-based replication. State can be sent via a protocol
 //! that does not guarantee in-order delivery.
 //!
+//! If your transport cannot guarantee in-order delivery, wrap the
+//! CRDT in a [`CausalReplica`](causal/struct.CausalReplica.html).
+//! It holds back any op that arrives before the ops it depends on,
+//! executes the op once those ops arrive, and reports the dots that
+//! are still missing so they can be requested again.
+//!
 //! In general, when replicating a CRDT state you should send its
 //! state struct, not the CRDT struct, because the CRDT struct includes
 //! the site ID. For example, to replicate a `Json` CRDT you should
@@ -155,6 +176,17 @@
 //! edits at once). You should replicate exclusively via state if you
 //! cannot guarantee in-order op delivery.
 //!
+//! A middle ground is to send a *delta*. If a site sends you its
+//! summary, `crdt.delta_since(&summary)` returns only the changes
+//! that site hasn't seen yet, and the site applies them with
+//! `merge_delta`. Merging a delta is idempotent, so a lost or duplicated
+//! delta can simply be resent.
+//!
+//! The [`sync`](sync/index.html) module wraps this exchange in a
+//! [`SyncSession`](sync/struct.SyncSession.html). Two sessions bring
+//! a pair of replicas up to date in three messages; your code only
+//! has to carry the serialized messages between sites.
+//!
 //! ### Other Notes
 //!
 //! Collection CRDTs are inherently larger than their native equivalents
@@ -203,13 +235,16 @@ extern crate rmp_serde;
 
 #[macro_use] mod traits;
 
+pub mod causal;
 pub mod dot;
 pub mod counter;
 pub mod json;
 pub mod list;
 pub mod map;
 pub mod register;
+pub mod resolver;
 pub mod set;
+pub mod sync;
 pub mod text;
 
 mod error;
@@ -219,10 +254,19 @@ mod tree;
 mod vlq;
 
 pub use error::Error;
+pub use traits::{Crdt, CausalOp, Nested, NestedOp, NestedCrdt, Plain, NoOp};
+pub use causal::CausalReplica;
 pub use counter::{Counter, CounterState};
 pub use json::{Json, JsonState};
 pub use list::{List, ListState};
 pub use map::{Map, MapState};
 pub use register::{Register, RegisterState};
+pub use resolver::Resolver;
 pub use set::{Set, SetState};
 pub use text::{Text, TextState};
+
+#[doc(hidden)]
+pub mod __private {
+    pub extern crate serde;
+    pub use serde_derive::{Serialize, Deserialize};
+}
@@ -4,34 +4,48 @@ use Error;
 use dot::{Dot, Summary, SiteId};
 use sequence::uid::{self, Uid};
 use traits::*;
+use tree::{self, Tree};
+use serde::{Serialize, Serializer, Deserialize, Deserializer};
+use serde::de::DeserializeOwned;
 use std::borrow::Cow;
-use std::mem;
+use std::collections::{HashMap, HashSet};
 use std::cmp::Ordering;
+use std::fmt::Debug;
+use std::iter::FromIterator;
+use std::mem;
 
 /// A List is a `Vec`-like ordered sequence of elements.
 /// List elements must implement the `Clone`, `Serialize`,
 /// and `Deserialize` traits.
 ///
+/// Elements must also implement [`Nested`](../trait.Nested.html).
+/// Plain values do so through [`Plain`](../trait.Plain.html). An
+/// element that is itself a CRDT, as in `List<Text>`, can be edited
+/// in place with [`edit`](#method.edit).
+///
 /// Internally, List is based on LSEQ. It allows op-based replication
 /// via [`execute_op`](#method.execute_op) and state-based replication
 /// via [`merge`](#method.merge). State-based replication allows
 /// out-of-order delivery but op-based replication does not.
 ///
-/// List's performance characteristics are similar to Vec:
+/// List's elements are stored in an order-statistic tree, so
+/// positional edits stay fast as the list grows:
 ///
-///   * [`push`](#method.push): *O(1)*
-///   * [`pop`](#method.pop): *O(1)*
-///   * [`insert`](#method.insert): *O(N)*
-///   * [`remove`](#method.remove): *O(N)*
-///   * [`get`](#method.get): *O(1)*
-///   * [`execute_op`](#method.execute_op): *O(N)*
-///   * [`merge`](#method.merge): *O(N1 + N2 + S1 + S2)*, where *N1* and
-///     *N2* are the number of values in each list being merged,
-///     and *S1* and *S2* are the number of sites that have edited
-///     each list being merged.
+///   * [`push`](#method.push): *O(log N)*
+///   * [`pop`](#method.pop): *O(log N)*
+///   * [`insert`](#method.insert): *O(log N)*
+///   * [`remove`](#method.remove): *O(log N)*
+///   * [`move_item`](#method.move_item): *O(log N)*
+///   * [`get`](#method.get): *O(log N)*
+///   * [`execute_op`](#method.execute_op): *O(log N)*
+///   * [`merge`](#method.merge): *O((N1 + N2) log (N1 + N2) + S1 + S2)*,
+///     where *N1* and *N2* are the number of values in each list being
+///     merged, and *S1* and *S2* are the number of sites that have
+///     edited each list being merged. Elements are matched by id in
+///     a hash map, so this is the expected time.
 ///
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
-pub struct List<T: 'static> {
+pub struct List<T: Nested + 'static> {
     inner:      Inner<T>,
     summary:    Summary,
     site_id:    SiteId,
@@ -39,30 +53,98 @@ pub struct List<T: 'static> {
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
-pub struct ListState<'a, T: Clone + 'a + 'static> {
+pub struct ListState<'a, T: Nested + 'a + 'static> {
     inner: Cow<'a, Inner<T>>,
     summary: Cow<'a, Summary>,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
-pub struct Inner<T: 'static>(pub Vec<Element<T>>);
+pub struct ListDelta<T> {
+    inner: InnerDelta<T>,
+    summary: Summary,
+}
+
+/// The list's elements, ordered by position, and the position of
+/// every moved element by id. Only the elements are serialized.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Inner<T: 'static>(pub Tree<Element<T>>, pub HashMap<Uid, Uid>);
+
+/// The serialized form of `Inner`.
+#[derive(Deserialize)]
+#[serde(rename = "Inner")]
+struct InnerElements<T>(Vec<Element<T>>);
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
-pub enum Op<T> {
+pub(crate) struct InnerDelta<T> {
+    inserted: Vec<Element<T>>,
+    retained: Vec<Dot>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Op<T: Nested> {
     Insert(Element<T>),
     Remove(Uid),
+    Move { uid: Uid, moved: Moved },
+    Edit { id: Uid, op: T::NestedOp },
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
-pub enum LocalOp<T> {
+pub enum LocalOp<T: Nested> {
     Insert { idx: usize, value: T },
     Remove { idx: usize },
+    Move { from: usize, to: usize },
+    Edit { idx: usize, ops: Vec<T::NestedLocalOp> },
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Element<T> {
     pub uid: Uid,
     pub value: T,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub moved: Option<Moved>,
+}
+
+/// Marks an element that has been moved. The element's `uid` is
+/// its current position and `id` is the uid it was inserted with.
+/// `version` counts the moves that led to the current position.
+/// Concurrent moves of an element are resolved in favor of the
+/// highest version, then the highest position.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Moved {
+    pub id: Uid,
+    pub version: u32,
+}
+
+impl<T> Element<T> {
+    /// Returns the uid that identifies the element across moves.
+    pub fn id(&self) -> &Uid {
+        self.moved.as_ref().map_or(&self.uid, |moved| &moved.id)
+    }
+
+    fn version(&self) -> u32 {
+        self.moved.as_ref().map_or(0, |moved| moved.version)
+    }
+
+    fn add_site_id(&mut self, site_id: SiteId) {
+        if self.uid.site_id == 0 { self.uid.site_id = site_id; }
+        if let Some(ref mut moved) = self.moved {
+            if moved.id.site_id == 0 { moved.id.site_id = site_id; }
+        }
+    }
+}
+
+// The tree is ordered by position, not by the element's id.
+impl<T> tree::Element for Element<T> {
+    type Id = Uid;
+    type Len = usize;
+
+    fn id(&self) -> &Uid {
+        &self.uid
+    }
+
+    fn element_len(&self) -> usize {
+        1
+    }
 }
 
 impl<T> PartialEq for Element<T> {
@@ -85,7 +167,7 @@ impl<T> Ord for Element<T> {
     }
 }
 
-impl<T: Clone> List<T> {
+impl<T: Nested> List<T> {
 
     /// Constructs and returns a new List with site id 1.
     pub fn new() -> Self {
@@ -97,26 +179,27 @@ impl<T: Clone> List<T> {
 
     /// Returns the number of elements in the list.
     pub fn len(&self) -> usize {
-        self.inner.0.len()
+        self.inner.len()
     }
 
     /// Returns true if the List has a length of 0.
     /// Returns false otherwise
     pub fn is_empty(&self) -> bool {
-        self.inner.0.is_empty()
+        self.inner.is_empty()
     }
 
     /// Returns a reference to the element at position `idx`.
     /// Returns None if idx is out-of-bounds.
     pub fn get(&self, idx: usize) -> Option<&T> {
-        Some(&self.inner.0.get(idx)?.value)
+        Some(&self.inner.get(idx)?.value)
     }
 
     /// Pushes a value onto the end of the list. If the list does
     /// not have a site id, it caches the resulting op and returns an
     /// `AwaitingSiteId` error.
-    pub fn push(&mut self, value: T) -> Result<Op<T>, Error> {
+    pub fn push(&mut self, mut value: T) -> Result<Op<T>, Error> {
         let dot = self.summary.get_dot(self.site_id);
+        value.nested_reset(dot);
         let op = self.inner.push(value, dot);
         self.after_op(op)
     }
@@ -135,8 +218,9 @@ impl<T: Clone> List<T> {
     /// bounds. If the insert succeeds but the list does not have a
     /// site id, it caches the resulting op and returns an
     /// `AwaitingSiteId` error.
-    pub fn insert(&mut self, idx: usize, value: T) -> Result<Op<T>, Error> {
+    pub fn insert(&mut self, idx: usize, mut value: T) -> Result<Op<T>, Error> {
         let dot = self.summary.get_dot(self.site_id);
+        value.nested_reset(dot);
         let op = self.inner.insert(idx, value, dot);
         self.after_op(op)
     }
@@ -151,6 +235,34 @@ impl<T: Clone> List<T> {
         (value, self.after_op(op))
     }
 
+    /// Moves the element at position `from` to position `to`,
+    /// shifting the elements in between. Unlike a remove followed
+    /// by an insert, the element keeps its identity: if two sites
+    /// move the same element concurrently, it ends up at one of the
+    /// two positions instead of being duplicated. Returns an
+    /// `OutOfBounds` error if either idx is out-of-bounds. If the
+    /// move succeeds but the list does not have a site id, it caches
+    /// the resulting op and returns an `AwaitingSiteId` error.
+    pub fn move_item(&mut self, from: usize, to: usize) -> Result<Op<T>, Error> {
+        if from >= self.inner.len() || to >= self.inner.len() { return Err(Error::OutOfBounds) }
+        let dot = self.summary.get_dot(self.site_id);
+        let op = self.inner.move_item(from, to, dot);
+        self.after_op(op)
+    }
+
+    /// Edits the CRDT at position `idx` in place. `f` makes a single
+    /// edit through the element's own methods and returns its op.
+    /// Panics if the idx is out-of-bounds. If the edit succeeds but
+    /// the list does not have a site id, it caches the resulting op
+    /// and returns an `AwaitingSiteId` error.
+    pub fn edit<F>(&mut self, idx: usize, f: F) -> Result<Op<T>, Error>
+        where T: NestedCrdt,
+              F: FnOnce(&mut T) -> Result<T::NestedOp, Error>,
+    {
+        let op = self.inner.edit(idx, &mut self.summary, self.site_id, f)?;
+        self.after_op(op)
+    }
+
     crdt_impl2! {
         List,
         ListState<T>,
@@ -159,11 +271,66 @@ impl<T: Clone> List<T> {
         Inner<T>,
         Op<T>,
         Option<LocalOp<T>>,
+        LocalOp<T>,
+        Vec<T>,
+        ListDelta<T>,
+        ListDelta,
+    }
+}
+
+impl<T: Nested + Serialize + DeserializeOwned + 'static> Crdt for List<T> {
+    crdt_trait_impl! {
+        List,
+        ListState<'static, T>,
+        Op<T>,
+        Option<LocalOp<T>>,
+        Some,
         Vec<T>,
+        ListDelta<T>,
     }
 }
 
-impl<T: Clone> From<Vec<T>> for List<T> {
+impl<T: Nested + Debug + PartialEq + Serialize + DeserializeOwned + 'static> Nested for List<T> {
+    type NestedOp = Op<T>;
+    type NestedLocalOp = LocalOp<T>;
+
+    fn nested_reset(&mut self, dot: Dot) {
+        self.inner.reset(dot);
+        self.summary = Summary::default();
+        self.site_id = 0;
+        self.cached_ops.clear();
+    }
+
+    fn nested_execute_op(&mut self, op: Op<T>) -> Vec<LocalOp<T>> {
+        self.inner.execute_op(op).into_iter().collect()
+    }
+
+    fn nested_merge(&mut self, other: Self, summary: &Summary, other_summary: &Summary) -> Vec<LocalOp<T>> {
+        self.inner.merge(other.inner, summary, other_summary)
+    }
+
+    fn nested_unchanged_since(&self, _: &Summary) -> bool {
+        false
+    }
+
+    fn nested_add_site_id(&mut self, site_id: SiteId) {
+        self.inner.add_site_id(site_id)
+    }
+
+    fn nested_validate_all(&self, site_id: SiteId) -> Result<(), Error> {
+        self.inner.validate_all(site_id)
+    }
+
+    fn nested_validate_no_unassigned_sites(&self) -> Result<(), Error> {
+        self.inner.validate_no_unassigned_sites()
+    }
+}
+
+impl<T: Nested + Debug + PartialEq + Serialize + DeserializeOwned + 'static> NestedCrdt for List<T> {
+    nested_crdt_impl!();
+}
+
+impl<T: Nested> From<Vec<T>> for List<T> {
     fn from(local_value: Vec<T>) -> Self {
         let mut list = List::new();
         for element in local_value {
@@ -173,223 +340,370 @@ impl<T: Clone> From<Vec<T>> for List<T> {
     }
 }
 
-impl<T: Clone> Inner<T> {
+impl<T> Inner<T> {
     pub fn new() -> Self {
-        Inner(Vec::new())
+        Inner(Tree::new(), HashMap::new())
     }
 
-    pub fn with_capacity(capacity: usize) -> Self {
-        Inner(Vec::with_capacity(capacity))
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn get(&self, idx: usize) -> Option<&Element<T>> {
+        self.0.get_elt(idx).ok().map(|(element, _)| element)
     }
 
-    pub fn iter(&self) -> ::std::slice::Iter<Element<T>> {
+    pub(crate) fn get_mut(&mut self, idx: usize) -> Option<&mut Element<T>> {
+        self.0.get_mut_elt(idx).ok().map(|(element, _)| element)
+    }
+
+    pub fn iter(&self) -> tree::Iter<'_, Element<T>> {
         self.0.iter()
     }
 
+    /// Returns the index of the element whose id is `id`.
+    pub(crate) fn get_idx(&self, id: &Uid) -> Option<usize> {
+        self.0.get_idx(self.1.get(id).unwrap_or(id))
+    }
+
+    /// Inserts an element at the position of its uid
+    /// and returns its index.
+    fn insert_element(&mut self, element: Element<T>) -> usize {
+        let uid = element.uid.clone();
+        if let Some(ref moved) = element.moved {
+            let _ = self.1.insert(moved.id.clone(), uid.clone());
+        }
+        let _ = self.0.insert(element);
+        self.0.get_idx(&uid).expect("Element must exist!")
+    }
+
+    /// Removes the element whose uid is `uid`, undoing a local insert
+    /// or move that has not been sent to other sites.
+    pub(crate) fn rollback_insert(&mut self, uid: &Uid) -> Element<T> {
+        let idx = self.0.get_idx(uid).expect("Element must exist!");
+        self.remove_element(idx)
+    }
+
+    /// Re-inserts an element, undoing a local remove or move
+    /// that has not been sent to other sites.
+    pub(crate) fn rollback_remove(&mut self, element: Element<T>) {
+        let _ = self.insert_element(element);
+    }
+
+    /// Removes the element at `idx`. Panics if the idx is out of bounds.
+    fn remove_element(&mut self, idx: usize) -> Element<T> {
+        let uid = self.get(idx).expect("Index out of bounds!").uid.clone();
+        let element = self.0.remove(&uid).expect("Element must exist!");
+        if let Some(ref moved) = element.moved {
+            let _ = self.1.remove(&moved.id);
+        }
+        element
+    }
+}
+
+impl<T: Nested> Inner<T> {
     pub fn push(&mut self, value: T, dot: Dot) -> Op<T> {
-        let uid = {
-            let len = self.0.len();
-            let uid1 = if len == 0 { &*uid::MIN } else { &self.0[len-1].uid };
-            let uid2 = &*uid::MAX;
-            Uid::between(uid1, uid2, dot)
-        };
-
-        let element = Element{uid, value};
-        self.0.push(element.clone());
-        Op::Insert(element)
+        let idx = self.len();
+        self.insert(idx, value, dot)
     }
 
     pub fn insert(&mut self, idx: usize, value: T, dot: Dot) -> Op<T> {
-        let uid = {
-            let len = self.0.len();
-            let uid1 = if idx == 0 { &*uid::MIN } else { &self.0[idx-1].uid };
-            let uid2 = if idx == len { &*uid::MAX } else { &self.0[idx].uid };
-            Uid::between(uid1, uid2, dot)
-        };
-
-        let element = Element{uid, value};
-        self.0.insert(idx, element.clone());
+        let uid = self.uid_before(idx, dot);
+        let element = Element{uid, value, moved: None};
+        let _ = self.insert_element(element.clone());
         Op::Insert(element)
     }
 
     pub fn pop(&mut self) -> Option<(T, Op<T>)> {
-        let element = self.0.pop()?;
-        let value = element.value;
-        let op = Op::Remove(element.uid);
-        Some((value, op))
+        if self.is_empty() { return None }
+        let idx = self.len() - 1;
+        Some(self.remove(idx))
     }
 
     pub fn remove(&mut self, idx: usize) -> (T, Op<T>) {
-        let element = self.0.remove(idx);
+        let element = self.remove_element(idx);
+        let op = Op::Remove(element.id().clone());
         let value = element.value;
-        let op = Op::Remove(element.uid);
         (value, op)
     }
 
+    /// Moves the element at `from` to `to`. Panics if either idx is
+    /// out of bounds.
+    pub fn move_item(&mut self, from: usize, to: usize, dot: Dot) -> Op<T> {
+        assert!(from < self.len() && to < self.len(), "Index out of bounds!");
+        let mut element = self.remove_element(from);
+        let uid = self.uid_before(to, dot);
+        let moved = Moved{id: element.id().clone(), version: element.version() + 1};
+        element.uid = uid.clone();
+        element.moved = Some(moved.clone());
+        let _ = self.insert_element(element);
+        Op::Move{uid, moved}
+    }
+
+    /// Edits the value at `idx` with `f`. Panics if the idx is out of bounds.
+    pub(crate) fn edit<F>(&mut self, idx: usize, summary: &mut Summary, site_id: SiteId, f: F) -> Result<Op<T>, Error>
+        where T: NestedCrdt,
+              F: FnOnce(&mut T) -> Result<T::NestedOp, Error>,
+    {
+        let element = self.get_mut(idx).expect("Index out of bounds!");
+        let id = element.id().clone();
+        let op = element.value.nested_edit(summary, site_id, f)?;
+        Ok(Op::Edit{id, op})
+    }
+
+    /// Reinserts every value with a uid and nested elements
+    /// that have the dot `dot`, keeping their order.
+    pub(crate) fn reset(&mut self, dot: Dot) {
+        let elements = mem::replace(self, Inner::new());
+        for element in elements.0 {
+            let mut value = element.value;
+            value.nested_reset(dot);
+            let _ = self.push(value, dot);
+        }
+    }
+
+    /// Returns a new uid that falls between the elements at `idx - 1`
+    /// and `idx`. Panics if the idx is out of bounds.
+    pub(crate) fn uid_before(&self, idx: usize, dot: Dot) -> Uid {
+        let uid1 = if idx == 0 { &*uid::MIN } else { &self.get(idx-1).expect("Index out of bounds!").uid };
+        let uid2 = if idx == self.len() { &*uid::MAX } else { &self.get(idx).expect("Index out of bounds!").uid };
+        Uid::between(uid1, uid2, dot)
+    }
+
     pub fn execute_op(&mut self, op: Op<T>) -> Option<LocalOp<T>> {
         match op {
             Op::Insert(elt) => {
-                let idx = self.0.binary_search_by(|e| e.uid.cmp(&elt.uid)).err()?;
+                if self.get_idx(elt.id()).is_some() { return None }
                 let value = elt.value.clone();
-                self.0.insert(idx, elt);
+                let idx = self.insert_element(elt);
                 Some(LocalOp::Insert{idx, value})
             }
             Op::Remove(uid) => {
-                let idx = self.0.binary_search_by(|e| e.uid.cmp(&uid)).ok()?;
-                let _   = self.0.remove(idx).value;
+                let idx = self.get_idx(&uid)?;
+                let _   = self.remove_element(idx);
                 Some(LocalOp::Remove{idx})
             }
+            Op::Move{uid, moved} => {
+                let from = self.get_idx(&moved.id)?;
+                let winner = {
+                    let element = self.get(from)?;
+                    (moved.version, &uid) > (element.version(), &element.uid)
+                };
+                if !winner { return None }
+                let to = self.reposition(from, uid, Some(moved));
+                Some(LocalOp::Move{from, to})
+            }
+            Op::Edit{id, op} => {
+                let idx = self.get_idx(&id)?;
+                let ops = self.get_mut(idx)?.value.nested_execute_op(op);
+                if ops.is_empty() { return None }
+                Some(LocalOp::Edit{idx, ops})
+            }
         }
     }
 
-    pub fn merge(&mut self, other: Inner<T>, summary: &Summary, other_summary: &Summary) {
-        let capacity = self.0.capacity();
-        let elements = mem::replace(&mut self.0, Vec::with_capacity(capacity));
-        let mut iter = elements.into_iter().peekable();
-        let mut other_iter = other.0.into_iter().peekable();
+    /// Moves the element at `idx` to the position `uid` and
+    /// returns its new index.
+    fn reposition(&mut self, idx: usize, uid: Uid, moved: Option<Moved>) -> usize {
+        let mut element = self.remove_element(idx);
+        element.uid = uid;
+        element.moved = moved;
+        self.insert_element(element)
+    }
+
+    pub fn merge(&mut self, other: Inner<T>, summary: &Summary, other_summary: &Summary) -> Vec<LocalOp<T>> {
+        let (mut local_ops, shared) = self.merge_elements(other.0, &HashSet::new(), summary, other_summary);
+        for (idx, value) in shared {
+            let ops = self.get_mut(idx).unwrap().value.nested_merge(value, summary, other_summary);
+            if !ops.is_empty() {
+                local_ops.push(LocalOp::Edit{idx, ops});
+            }
+        }
+        local_ops
+    }
+
+    /// Returns true if the values of elements that both lists have
+    /// can be merged.
+    pub(crate) fn can_merge(&self, other: &Inner<T>) -> bool {
+        let others: HashMap<&Uid, &T> = other.iter().map(|e| (e.id(), &e.value)).collect();
+        self.iter().all(|e| {
+            others.get(e.id()).map(|value| e.value.nested_can_merge(value)).unwrap_or(true)
+        })
+    }
+
+    /// Merges another site's elements into the list by identity.
+    /// An element that only one site has is kept unless the other
+    /// site has seen and removed it. `retained` holds the ids of
+    /// elements that the other site has but left out of `others`.
+    /// An element that both sites have ends up at the position of
+    /// its winning move. Returns the local ops, along with the
+    /// index and the other site's value of every element that
+    /// both sites have.
+    pub(crate) fn merge_elements<I>(&mut self, others: I, retained: &HashSet<Uid>, summary: &Summary, other_summary: &Summary) -> (Vec<LocalOp<T>>, Vec<(usize, T)>)
+        where I: IntoIterator<Item = Element<T>>
+    {
+        let mut others: HashMap<Uid, Element<T>> = others.into_iter().map(|e| (e.id().clone(), e)).collect();
+        let ids: Vec<Uid> = self.iter().map(|e| e.id().clone()).collect();
+        let mut local_ops = vec![];
+        let mut shared = vec![];
+        let mut idx = 0;
+
+        for id in ids {
+            if let Some(other) = others.remove(&id) {
+                shared.push(other);
+            } else if !retained.contains(&id) && other_summary.contains(&id.dot()) {
+                let _ = self.remove_element(idx);
+                local_ops.push(LocalOp::Remove{idx});
+                continue
+            }
+            idx += 1;
+        }
 
-        while iter.peek().is_some() || other_iter.peek().is_some() {
-            let ordering = {
-                let uid1 = iter.peek().and_then(|e| Some(&e.uid)).unwrap_or(&uid::MAX);
-                let uid2 = other_iter.peek().and_then(|e| Some(&e.uid)).unwrap_or(&uid::MAX);
-                uid1.cmp(uid2)
+        let mut shared_values = Vec::with_capacity(shared.len());
+        for other in shared {
+            let id = other.id().clone();
+            let from = self.get_idx(&id).unwrap();
+            let winner = {
+                let element = self.get(from).unwrap();
+                (other.version(), &other.uid) > (element.version(), &element.uid)
             };
-
-            match ordering {
-                Ordering::Less => {
-                    let element = iter.next().unwrap();
-                    if !other_summary.contains_pair(element.uid.site_id, element.uid.counter) {
-                        self.0.push(element);
-                    }
-                }
-                Ordering::Equal => {
-                    let element = iter.next().unwrap();
-                    let _ = other_iter.next().unwrap();
-                    self.0.push(element);
-                }
-                Ordering::Greater => {
-                    let element = other_iter.next().unwrap();
-                    if !summary.contains_pair(element.uid.site_id, element.uid.counter) {
-                        self.0.push(element);
-                    }
-                }
+            if winner {
+                let to = self.reposition(from, other.uid, other.moved);
+                local_ops.push(LocalOp::Move{from, to});
             }
+            shared_values.push((id, other.value));
         }
-    }
 
-    pub fn add_site_id(&mut self, site_id: SiteId) {
-        for element in &mut self.0 {
-            if element.uid.site_id == 0 { element.uid.site_id = site_id; }
+        let mut inserted: Vec<Element<T>> = others.into_values()
+            .filter(|element| !summary.contains(&element.id().dot()))
+            .collect();
+        inserted.sort();
+
+        for element in inserted {
+            let value = element.value.clone();
+            let idx = self.insert_element(element);
+            local_ops.push(LocalOp::Insert{idx, value});
         }
-    }
 
-    pub fn validate_no_unassigned_sites(&self) -> Result<(), Error> {
-        if self.0.iter().any(|e| e.uid.site_id == 0) { return Err(Error::InvalidSiteId) }
-        Ok(())
-    }
+        let shared_values = shared_values.into_iter()
+            .map(|(id, value)| (self.get_idx(&id).unwrap(), value))
+            .collect();
 
-    pub fn local_value(&self) -> Vec<T> {
-        self.0.iter().map(|e| e.value.clone()).collect()
+        (local_ops, shared_values)
     }
 
-    pub(crate) fn get_idx(&mut self, uid: &Uid) -> Option<usize> {
-        self.0.binary_search_by(|e| e.uid.cmp(uid)).ok()
+    pub(crate) fn delta_since(&self, since: &Summary, _: &Summary) -> InnerDelta<T> {
+        let mut inserted = vec![];
+        let mut retained = vec![];
+
+        for element in self.iter() {
+            if since.contains(&element.uid.dot()) && element.value.nested_unchanged_since(since) {
+                retained.push(element.id().dot());
+            } else {
+                inserted.push(element.clone());
+            }
+        }
+
+        InnerDelta{inserted, retained}
     }
 
-    pub(crate) fn get_mut(&mut self, idx: usize) -> Option<&mut Element<T>> {
-        self.0.get_mut(idx)
+    pub(crate) fn merge_delta(&mut self, delta: InnerDelta<T>, summary: &Summary, delta_summary: &Summary) -> Result<(), Error> {
+        if delta.retained.iter().any(|dot| !summary.contains(dot)) {
+            return Err(Error::InvalidDelta)
+        }
+
+        let retained: HashSet<Dot> = delta.retained.into_iter().collect();
+        let retained: HashSet<Uid> = self.iter()
+            .map(|e| e.id())
+            .filter(|id| retained.contains(&id.dot()))
+            .cloned()
+            .collect();
+
+        let (_, shared) = self.merge_elements(delta.inserted, &retained, summary, delta_summary);
+        for (idx, value) in shared {
+            self.get_mut(idx).unwrap().value.nested_force_merge(value, summary, delta_summary);
+        }
+        Ok(())
     }
-}
 
-impl<T: Clone + NestedInner> NestedInner for Inner<T> {
-    fn nested_add_site_id(&mut self, site_id: SiteId) {
-        for element in &mut self.0 {
-            element.value.nested_add_site_id(site_id);
-            if element.uid.site_id == 0 { element.uid.site_id = site_id; }
+    pub fn add_site_id(&mut self, site_id: SiteId) {
+        for idx in 0..self.len() {
+            self.get_mut(idx).unwrap().value.nested_add_site_id(site_id);
+        }
+
+        let uids: Vec<Uid> = self.iter()
+            .filter(|e| e.uid.site_id == 0 || e.id().site_id == 0)
+            .map(|e| e.uid.clone())
+            .collect();
+
+        for uid in uids {
+            let idx = self.0.get_idx(&uid).expect("Element must exist!");
+            let mut element = self.remove_element(idx);
+            element.add_site_id(site_id);
+            let _ = self.insert_element(element);
         }
     }
 
-    fn nested_validate_no_unassigned_sites(&self) -> Result<(), Error> {
-        for element in &self.0 {
+    pub fn validate_no_unassigned_sites(&self) -> Result<(), Error> {
+        for element in self.iter() {
             element.value.nested_validate_no_unassigned_sites()?;
-            if element.uid.site_id == 0 { return Err(Error::InvalidSiteId) };
+            if element.uid.site_id == 0 || element.id().site_id == 0 { return Err(Error::InvalidSiteId) };
         }
         Ok(())
     }
 
-    fn nested_validate_all(&self, site_id: SiteId) -> Result<(), Error> {
-        for element in &self.0 {
+    pub fn validate_all(&self, site_id: SiteId) -> Result<(), Error> {
+        for element in self.iter() {
             element.value.nested_validate_all(site_id)?;
             if element.uid.site_id != site_id { return Err(Error::InvalidSiteId) };
         }
         Ok(())
     }
 
-    fn nested_can_merge(&self, other: &Inner<T>) -> bool {
-        let mut iter1 = self.0.iter().peekable();
-        let mut iter2 = other.0.iter().peekable();
-
-        while iter1.peek().is_some() || iter2.peek().is_some() {
-            let uid1 = iter1.peek().and_then(|e| Some(&e.uid)).unwrap_or(&uid::MAX);
-            let uid2 = iter2.peek().and_then(|e| Some(&e.uid)).unwrap_or(&uid::MAX);
-            match uid1.cmp(uid2) {
-                Ordering::Less => {
-                    iter1.next();
-                }
-                Ordering::Equal => {
-                    let v1 = &iter1.next().unwrap().value;
-                    let v2 = &iter2.next().unwrap().value;
-                    if !v1.nested_can_merge(v2) {
-                        return false
-                    }
-                }
-                Ordering::Greater => {
-                    iter2.next();
-                }
-            }
-        }
+    pub fn local_value(&self) -> Vec<T> {
+        self.iter().map(|e| e.value.clone()).collect()
+    }
+}
 
-        true
+impl<T> FromIterator<Element<T>> for Inner<T> {
+    fn from_iter<I: IntoIterator<Item = Element<T>>>(iter: I) -> Self {
+        let mut inner = Inner::new();
+        for element in iter {
+            let _ = inner.insert_element(element);
+        }
+        inner
     }
+}
 
-    fn nested_force_merge(&mut self, other: Inner<T>, summary: &Summary, other_summary: &Summary) {
-        let capacity = self.0.capacity();
-        let elements = mem::replace(&mut self.0, Vec::with_capacity(capacity));
-        let mut iter = elements.into_iter().peekable();
-        let mut other_iter = other.0.into_iter().peekable();
+impl<T: Serialize> Serialize for Inner<T> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error> where S: Serializer {
+        serializer.serialize_newtype_struct("Inner", &self.0)
+    }
+}
 
-        while iter.peek().is_some() || other_iter.peek().is_some() {
-            let ordering = {
-                let uid1 = iter.peek().and_then(|e| Some(&e.uid)).unwrap_or(&uid::MAX);
-                let uid2 = other_iter.peek().and_then(|e| Some(&e.uid)).unwrap_or(&uid::MAX);
-                uid1.cmp(uid2)
-            };
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for Inner<T> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error> where D: Deserializer<'de> {
+        let InnerElements(elements) = InnerElements::deserialize(deserializer)?;
+        Ok(elements.into_iter().collect())
+    }
+}
 
-            match ordering {
-                Ordering::Less => {
-                    let element = iter.next().unwrap();
-                    if !other_summary.contains_pair(element.uid.site_id, element.uid.counter) {
-                        self.0.push(element);
-                    }
-                }
-                Ordering::Equal => {
-                    let mut element = iter.next().unwrap();
-                    let other_element = other_iter.next().unwrap();
-                    element.value.nested_force_merge(other_element.value, summary, other_summary);
-                    self.0.push(element);
-                }
-                Ordering::Greater => {
-                    let element = other_iter.next().unwrap();
-                    if !summary.contains_pair(element.uid.site_id, element.uid.counter) {
-                        self.0.push(element);
-                    }
-                }
-            }
+impl<T: Nested> InnerDelta<T> {
+    pub fn validate_no_unassigned_sites(&self) -> Result<(), Error> {
+        for element in &self.inserted {
+            element.value.nested_validate_no_unassigned_sites()?;
+            if element.uid.site_id == 0 || element.id().site_id == 0 { return Err(Error::InvalidSiteId) }
         }
+        if self.retained.iter().any(|dot| dot.site_id == 0) { return Err(Error::InvalidSiteId) }
+        Ok(())
     }
 }
 
-impl<T> Op<T> {
+impl<T: Nested> Op<T> {
     pub fn inserted_element(&self) -> Option<&Element<T>> {
         if let Op::Insert(ref elt) = *self { Some(elt) } else { None }
     }
@@ -398,51 +712,79 @@ impl<T> Op<T> {
         if let Op::Remove(ref uid) = *self { Some(uid) } else { None }
     }
 
+    pub fn edit(&self) -> Option<(&Uid, &T::NestedOp)> {
+        if let Op::Edit{ref id, ref op} = *self { Some((id, op)) } else { None }
+    }
+
     pub fn add_site_id(&mut self, site_id: SiteId) {
         match *self {
             Op::Insert(ref mut elt) => {
+                elt.value.nested_add_site_id(site_id);
                 if elt.uid.site_id == 0 { elt.uid.site_id = site_id; }
             }
             Op::Remove(ref mut uid) => {
                 if uid.site_id == 0 { uid.site_id = site_id; }
             }
+            Op::Move{ref mut uid, ref mut moved} => {
+                if uid.site_id == 0 { uid.site_id = site_id; }
+                if moved.id.site_id == 0 { moved.id.site_id = site_id; }
+            }
+            Op::Edit{ref mut id, ref mut op} => {
+                if id.site_id == 0 { id.site_id = site_id; }
+                op.nested_add_site_id(site_id);
+            }
         }
     }
 
     pub fn validate(&self, site_id: SiteId) -> Result<(), Error> {
-        if let Op::Insert(ref elt) = *self {
-            if elt.uid.site_id != site_id { return Err(Error::InvalidOp) };
+        match *self {
+            Op::Insert(Element{ref uid, ref value, ..}) => {
+                if uid.site_id != site_id { return Err(Error::InvalidOp) };
+                value.nested_validate_all(site_id)?;
+            }
+            Op::Move{ref uid, ..} => {
+                if uid.site_id != site_id { return Err(Error::InvalidOp) };
+            }
+            Op::Remove(_) => (),
+            Op::Edit{ref op, ..} => op.nested_validate(site_id)?,
         }
         Ok(())
     }
 
     pub(crate) fn inserted_dots(&self) -> Vec<Dot> {
-        if let Op::Insert(ref elt) = *self {
-            vec![Dot::new(elt.uid.site_id, elt.uid.counter)]
-        } else {
-            vec![]
+        match *self {
+            Op::Insert(Element{ref uid, ..}) | Op::Move{ref uid, ..} => vec![uid.dot()],
+            Op::Remove(_) => vec![],
+            Op::Edit{ref op, ..} => op.inserted_dots(),
         }
     }
 }
 
-impl<T: NestedInner> NestedOp for Op<T> {
-    fn nested_add_site_id(&mut self, site_id: SiteId) {
+impl<T: Nested> CausalOp for Op<T> {
+    fn inserted_dots(&self) -> Vec<Dot> {
+        Op::inserted_dots(self)
+    }
+
+    fn referenced_dots(&self) -> Vec<Dot> {
         match *self {
-            Op::Insert(ref mut elt) => {
-                elt.value.nested_add_site_id(site_id);
-                if elt.uid.site_id == 0 { elt.uid.site_id = site_id; }
-            }
-            Op::Remove(ref mut uid) => {
-                if uid.site_id == 0 { uid.site_id = site_id; }
+            Op::Insert(_) => vec![],
+            Op::Remove(ref uid) => vec![uid.dot()],
+            Op::Move{ref moved, ..} => vec![moved.id.dot()],
+            Op::Edit{ref id, ref op} => {
+                let mut dots = vec![id.dot()];
+                dots.append(&mut op.referenced_dots());
+                dots
             }
         }
     }
+}
+
+impl<T: Nested> NestedOp for Op<T> {
+    fn nested_add_site_id(&mut self, site_id: SiteId) {
+        self.add_site_id(site_id)
+    }
 
     fn nested_validate(&self, site_id: SiteId) -> Result<(), Error> {
-        if let Op::Insert(ref elt) = *self {
-            if elt.uid.site_id != site_id { return Err(Error::InvalidOp) };
-            elt.value.nested_validate_all(site_id)?;
-        }
-        Ok(())
+        self.validate(site_id)
     }
 }
@@ -3,20 +3,23 @@
 use Error;
 use dot::{Dot, Summary, SiteId};
 use map_tuple_vec;
+use resolver::{self, Resolver, LowestSite};
 use traits::*;
 
-use serde::ser::Serialize;
+use serde::{Serialize, Serializer, Deserialize, Deserializer};
 use serde::de::DeserializeOwned;
 use std::borrow::{Borrow, Cow};
 use std::cmp::Ordering;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Debug;
 use std::hash::Hash;
+use std::marker::PhantomData;
 
 pub trait Key: Clone + Eq + Hash + Serialize + DeserializeOwned {}
 impl<T: Clone + Eq + Hash + Serialize + DeserializeOwned> Key for T {}
 
-pub trait Value: Clone + PartialEq + Serialize + DeserializeOwned {}
-impl<T: Clone + PartialEq + Serialize + DeserializeOwned> Value for T {}
+pub trait Value: Nested + PartialEq + Serialize + DeserializeOwned {}
+impl<T: Nested + PartialEq + Serialize + DeserializeOwned> Value for T {}
 
 /// A Map is a `HashMap`-like collection of key-value pairs.
 /// As with `HashMap`, `Map` requires that the elements implement
@@ -24,6 +27,26 @@ impl<T: Clone + PartialEq + Serialize + DeserializeOwned> Value for T {}
 /// must also implement the `Clone`, `Serialize`, and `Deserialize`
 /// traits.
 ///
+/// Values must implement [`Nested`](../trait.Nested.html). Plain
+/// values do so through [`Plain`](../trait.Plain.html) and are
+/// replaced as a whole. A value that is itself a CRDT, as in
+/// `Map<String, Text>`, can be edited in place with
+/// [`edit`](#method.edit); concurrent edits to it are merged
+/// rather than overwritten.
+///
+/// When values are inserted under the same key concurrently, Map
+/// keeps all of them. The [`Resolver`](../resolver/trait.Resolver.t.html)
+/// type parameter picks the visible one; by default it is the value
+/// inserted by the lowest site id. Use [`with_resolver`](#method.with_resolver)
+/// to pick another rule.
+///
+/// [`LastWriterWins`](../resolver/struct.LastWriterWins.html) compares
+/// timestamps that the values carry themselves: `V` must implement
+/// [`Timestamped`](../resolver/trait.Timestamped.html), and each site
+/// must put the time of the insert inside the value it inserts. The
+/// Map does not record when a value was inserted, so sites whose
+/// clocks disagree can let an older value win.
+///
 /// Internally, Map is based on OR-Set. It allows op-based replication
 /// via [`execute_op`](#method.execute_op) and state-based replication
 /// via [`merge`](#method.merge). State-based replication allows
@@ -42,36 +65,53 @@ impl<T: Clone + PartialEq + Serialize + DeserializeOwned> Value for T {}
 ///     being merged.
 ///
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
-#[serde(bound(deserialize = ""))]
-pub struct Map<K: Key, V: Value> {
-    inner:      Inner<K, V>,
+#[serde(bound(serialize = "", deserialize = ""))]
+pub struct Map<K: Key, V: Value, R: Resolver<V> = LowestSite> {
+    inner:      Inner<K, V, R>,
     summary:    Summary,
     site_id:    SiteId,
     cached_ops: Vec<Op<K, V>>,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
-#[serde(bound(deserialize = ""))]
-pub struct MapState<'a, K: Key + 'a, V: Value + 'a> {
-    inner: Cow<'a, Inner<K,V>>,
+#[serde(bound(serialize = "", deserialize = ""))]
+pub struct MapState<'a, K: Key + 'a, V: Value + 'a, R: Resolver<V> + 'a = LowestSite> {
+    inner: Cow<'a, Inner<K, V, R>>,
     summary: Cow<'a, Summary>,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(bound(serialize = "", deserialize = ""))]
+pub struct MapDelta<K: Key, V: Value, R: Resolver<V> = LowestSite> {
+    inner: InnerDelta<K, V, R>,
+    summary: Summary,
+}
+
+#[derive(Debug, Clone, PartialEq)]
 #[doc(hidden)]
-pub struct Inner<K: Key, V: Value>(#[serde(with = "map_tuple_vec")] pub HashMap<K, Vec<Element<V>>>);
+pub struct Inner<K: Key, V: Value, R = LowestSite>(pub HashMap<K, Vec<Element<V>>>, PhantomData<R>);
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
-pub struct Op<K, V> {
+#[serde(bound(serialize = "", deserialize = ""))]
+pub(crate) struct InnerDelta<K: Key, V: Value, R: Resolver<V> = LowestSite> {
+    inserted: Inner<K, V, R>,
+    retained: Vec<Dot>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Op<K, V: Nested> {
     key: K,
     inserted_element: Option<Element<V>>,
     removed_dots: Vec<Dot>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    edit: Option<(Dot, V::NestedOp)>,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
-pub enum LocalOp<K, V> {
+pub enum LocalOp<K, V: Nested> {
     Insert{key: K, value: V},
     Remove{key: K},
+    Edit{key: K, ops: Vec<V::NestedLocalOp>},
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -106,11 +146,20 @@ impl<K: Key, V: Value> Map<K, V> {
     /// Constructs and returns a new map.
     /// The map has site id 1.
     pub fn new() -> Self {
+        Map::with_resolver::<LowestSite>()
+    }
+
+    /// Constructs and returns a new map that uses the resolver `R`
+    /// to pick the visible value of a key. The map has site id 1.
+    pub fn with_resolver<R: Resolver<V>>() -> Map<K, V, R> {
         let inner   = Inner::new();
         let summary = Summary::default();
         let site_id = 1;
         Map{inner, summary, site_id, cached_ops: vec![]}
     }
+}
+
+impl<K: Key, V: Value, R: Resolver<V>> Map<K, V, R> {
 
     /// Returns true iff the map has the key.
     pub fn contains_key(&self, key: &K) -> bool {
@@ -119,16 +168,16 @@ impl<K: Key, V: Value> Map<K, V> {
 
     /// Returns a reference to the value corresponding to the key.
     pub fn get(&self, key: &K) -> Option<&V> {
-        let elements = self.inner.0.get(key)?;
-        Some(&elements[0].value)
+        Some(&self.inner.get(key)?.value)
     }
 
     /// Inserts a key-value pair into the map and returns a remote
     /// op that can be sent to remote sites for replication. If the
     /// map does not have a site allocated, it caches the op and
     /// returns an `AwaitingSite` error.
-    pub fn insert(&mut self, key: K, value: V) -> Result<Op<K, V>, Error> {
+    pub fn insert(&mut self, key: K, mut value: V) -> Result<Op<K, V>, Error> {
         let dot = self.summary.get_dot(self.site_id);
+        value.nested_reset(dot);
         let op = self.inner.insert(key, value, dot);
         self.after_op(op)
     }
@@ -142,15 +191,46 @@ impl<K: Key, V: Value> Map<K, V> {
         Some(self.after_op(op))
     }
 
+    /// Edits the CRDT stored under a key in place and returns a remote
+    /// op that can be sent to remote sites for replication. `f` makes
+    /// a single edit through the value's own methods and returns its
+    /// op. Returns None if the map does not have the key. If the map
+    /// does not have a site allocated, it caches the op and returns
+    /// an `AwaitingSite` error.
+    pub fn edit<F>(&mut self, key: &K, f: F) -> Option<Result<Op<K, V>, Error>>
+        where V: NestedCrdt,
+              F: FnOnce(&mut V) -> Result<V::NestedOp, Error>,
+    {
+        match self.inner.edit(key, &mut self.summary, self.site_id, f)? {
+            Ok(op) => Some(self.after_op(op)),
+            Err(err) => Some(Err(err)),
+        }
+    }
+
     crdt_impl2! {
         Map,
-        MapState<K, V>,
-        MapState<'static, K, V>,
+        MapState<K, V, R>,
+        MapState<'static, K, V, R>,
         MapState,
-        Inner<K, V>,
+        Inner<K, V, R>,
         Op<K, V>,
         LocalOp<K, V>,
+        LocalOp<K, V>,
         HashMap<K, V>,
+        MapDelta<K, V, R>,
+        MapDelta,
+    }
+}
+
+impl<K: Key + 'static, V: Value + 'static, R: Resolver<V> + 'static> Crdt for Map<K, V, R> {
+    crdt_trait_impl! {
+        Map,
+        MapState<'static, K, V, R>,
+        Op<K, V>,
+        LocalOp<K, V>,
+        ::std::convert::identity,
+        HashMap<K, V>,
+        MapDelta<K, V, R>,
     }
 }
 
@@ -162,29 +242,79 @@ impl<K: Key, V: Value> From<HashMap<K, V>> for Map<K, V> {
     }
 }
 
-impl<K: Key, V: Value> Inner<K, V> {
+impl<K: Key + Debug + 'static, V: Value + Debug + 'static, R: Resolver<V> + 'static> Nested for Map<K, V, R> {
+    type NestedOp = Op<K, V>;
+    type NestedLocalOp = LocalOp<K, V>;
+
+    fn nested_reset(&mut self, dot: Dot) {
+        self.inner.reset(dot);
+        self.summary = Summary::default();
+        self.site_id = 0;
+        self.cached_ops.clear();
+    }
+
+    fn nested_execute_op(&mut self, op: Op<K, V>) -> Vec<LocalOp<K, V>> {
+        vec![self.inner.execute_op(op)]
+    }
+
+    fn nested_merge(&mut self, other: Self, summary: &Summary, other_summary: &Summary) -> Vec<LocalOp<K, V>> {
+        self.inner.merge(other.inner, summary, other_summary)
+    }
+
+    fn nested_unchanged_since(&self, _: &Summary) -> bool {
+        false
+    }
+
+    fn nested_add_site_id(&mut self, site_id: SiteId) {
+        self.inner.add_site_id(site_id)
+    }
+
+    fn nested_validate_all(&self, site_id: SiteId) -> Result<(), Error> {
+        self.inner.validate_all(site_id)
+    }
+
+    fn nested_validate_no_unassigned_sites(&self) -> Result<(), Error> {
+        self.inner.validate_no_unassigned_sites()
+    }
+}
+
+impl<K: Key + Debug + 'static, V: Value + Debug + 'static, R: Resolver<V> + 'static> NestedCrdt for Map<K, V, R> {
+    nested_crdt_impl!();
+}
+
+impl<K: Key, V: Value, R: Resolver<V>> Inner<K, V, R> {
     pub fn new() -> Self {
-        Inner(HashMap::new())
+        Inner(HashMap::new(), PhantomData)
     }
 
     pub fn with_capacity(capacity: usize) -> Self {
-        Inner(HashMap::with_capacity(capacity))
+        Inner(HashMap::with_capacity(capacity), PhantomData)
     }
 
     pub fn len(&self) -> usize {
         self.0.len()
     }
 
-    pub fn iter(&self) -> ::std::collections::hash_map::Iter<K,Vec<Element<V>>> {
+    pub fn iter(&self) -> ::std::collections::hash_map::Iter<'_, K, Vec<Element<V>>> {
         self.0.iter()
     }
 
+    /// Returns the visible element of a key.
+    pub fn get<Q: ?Sized>(&self, key: &Q) -> Option<&Element<V>>
+        where Q: Hash + Eq,
+              K: Borrow<Q>,
+    {
+        let elements = self.0.get(key)?;
+        Some(&elements[Self::visible_idx(elements)])
+    }
+
     pub fn get_mut<Q: ?Sized>(&mut self, key: &Q) -> Option<&mut Element<V>>
         where Q: Hash + Eq,
               K: Borrow<Q>,
     {
         let elements = self.0.get_mut(key)?;
-        Some(&mut elements[0])
+        let idx = Self::visible_idx(elements);
+        Some(&mut elements[idx])
     }
 
     pub fn get_mut_element<Q: ?Sized>(&mut self, key: &Q, dot: Dot) -> Option<&mut Element<V>>
@@ -200,7 +330,7 @@ impl<K: Key, V: Value> Inner<K, V> {
         let inserted_element = Element{value, dot};
         let removed_elements = self.0.insert(key.clone(), vec![inserted_element.clone()]).unwrap_or_else(|| vec![]);
         let removed_dots = removed_elements.into_iter().map(|e| e.dot).collect();
-        Op{key, inserted_element: Some(inserted_element), removed_dots}
+        Op{key, inserted_element: Some(inserted_element), removed_dots, edit: None}
     }
 
     pub fn remove<Q: ?Sized>(&mut self, key: &Q) -> Option<Op<K, V>>
@@ -209,10 +339,37 @@ impl<K: Key, V: Value> Inner<K, V> {
     {
         let removed_elements = self.0.remove(key)?;
         let removed_dots = removed_elements.into_iter().map(|e| e.dot).collect();
-        Some(Op{key: key.to_owned(), inserted_element: None, removed_dots})
+        Some(Op{key: key.to_owned(), inserted_element: None, removed_dots, edit: None})
+    }
+
+    /// Edits the visible value of a key with `f`.
+    pub(crate) fn edit<F>(&mut self, key: &K, summary: &mut Summary, site_id: SiteId, f: F) -> Option<Result<Op<K, V>, Error>>
+        where V: NestedCrdt,
+              F: FnOnce(&mut V) -> Result<V::NestedOp, Error>,
+    {
+        let element = self.get_mut(key)?;
+        let dot = element.dot;
+        let result = element.value.nested_edit(summary, site_id, f);
+        Some(result.map(|op| Op{key: key.clone(), inserted_element: None, removed_dots: vec![], edit: Some((dot, op))}))
+    }
+
+    /// Gives every visible value the dot `dot` and drops the
+    /// values that are hidden by a concurrent insert.
+    pub(crate) fn reset(&mut self, dot: Dot) {
+        for elements in self.0.values_mut() {
+            let idx = Self::visible_idx(elements);
+            elements.swap(0, idx);
+            elements.truncate(1);
+            elements[0].dot = dot;
+            elements[0].value.nested_reset(dot);
+        }
     }
 
     pub fn execute_op(&mut self, op: Op<K, V>) -> LocalOp<K, V> {
+        if let Some((dot, nested_op)) = op.edit {
+            return self.execute_edit(op.key, dot, nested_op)
+        }
+
         let mut elements = self.0.remove(&op.key).unwrap_or_else(|| vec![]);
         elements.retain(|e| !op.removed_dots.contains(&e.dot));
 
@@ -225,24 +382,77 @@ impl<K: Key, V: Value> Inner<K, V> {
         if elements.is_empty() {
             LocalOp::Remove{key: op.key}
         } else {
-            let value = elements[0].value.clone();
+            let value = elements[Self::visible_idx(&elements)].value.clone();
             self.0.insert(op.key.clone(), elements);
             LocalOp::Insert{key: op.key, value}
         }
     }
 
-    pub fn merge(&mut self, other: Self, summary: &Summary, other_summary: &Summary) {
+    /// Edits the value with the given dot. Only edits to the
+    /// visible value produce local ops. If the edit changes which
+    /// value is visible, the new visible value is inserted.
+    fn execute_edit(&mut self, key: K, dot: Dot, op: V::NestedOp) -> LocalOp<K, V> {
+        let elements = match self.0.get_mut(&key) {
+            Some(elements) => elements,
+            None => return LocalOp::Remove{key},
+        };
+
+        let idx = match elements.binary_search_by(|e| e.dot.cmp(&dot)) {
+            Ok(idx) => idx,
+            Err(_) => return LocalOp::Edit{key, ops: vec![]},
+        };
+
+        let visible_idx = Self::visible_idx(elements);
+        let ops = elements[idx].value.nested_execute_op(op);
+        let new_visible_idx = Self::visible_idx(elements);
+
+        if new_visible_idx != visible_idx {
+            LocalOp::Insert{key, value: elements[new_visible_idx].value.clone()}
+        } else if idx == visible_idx {
+            LocalOp::Edit{key, ops}
+        } else {
+            LocalOp::Edit{key, ops: vec![]}
+        }
+    }
+
+    pub fn merge(&mut self, other: Self, summary: &Summary, other_summary: &Summary) -> Vec<LocalOp<K, V>> {
         let mut other_values = other.0;
+        let mut local_ops = vec![];
 
         // retain an element in self iff
         // - the element is in both self and other, OR
         // - the element has not been inserted into other
         self.0.retain(|key, elements| {
+            let visible_dot = elements[Self::visible_idx(elements)].dot;
             let mut other_elements = other_values.remove(key).unwrap_or_else(|| vec![]);
             elements.retain(|e| other_elements.contains(e) || !other_summary.contains(&e.dot));
-            other_elements.retain(|e| !elements.contains(e) && !summary.contains(&e.dot));
-            elements.append(&mut other_elements);
+            other_elements.retain(|e| elements.contains(e) || !summary.contains(&e.dot));
+
+            let (other_merge, mut other_insert): (Vec<Element<V>>, Vec<Element<V>>) = other_elements.into_iter()
+                .partition(|e| elements.contains(e));
+
+            // merge the values of elements that are in both self and other
+            let mut nested_ops = vec![];
+            for element in other_merge {
+                let idx = elements.binary_search_by(|e| e.cmp(&element)).expect("Element must be present");
+                let ops = elements[idx].value.nested_merge(element.value, summary, other_summary);
+                if element.dot == visible_dot { nested_ops = ops; }
+            }
+
+            elements.append(&mut other_insert);
             elements.sort();
+
+            if elements.is_empty() {
+                local_ops.push(LocalOp::Remove{key: key.clone()});
+                return false
+            }
+
+            let visible = &elements[Self::visible_idx(elements)];
+            if visible.dot != visible_dot {
+                local_ops.push(LocalOp::Insert{key: key.clone(), value: visible.value.clone()});
+            } else if !nested_ops.is_empty() {
+                local_ops.push(LocalOp::Edit{key: key.clone(), ops: nested_ops});
+            }
             !elements.is_empty()
         });
 
@@ -250,52 +460,103 @@ impl<K: Key, V: Value> Inner<K, V> {
         for (key, mut elements) in other_values {
             elements.retain(|e| !summary.contains(&e.dot));
             if !elements.is_empty() {
+                let value = elements[Self::visible_idx(&elements)].value.clone();
+                local_ops.push(LocalOp::Insert{key: key.clone(), value});
                 self.0.insert(key, elements);
             }
         }
+
+        local_ops
     }
 
-    pub fn add_site_id(&mut self, site_id: SiteId) {
-        for elements in self.0.values_mut() {
-            for element in elements {
-                if element.dot.site_id == 0 { element.dot.site_id = site_id };
+    /// Returns true if the values of elements that both maps have can
+    /// be merged.
+    pub(crate) fn can_merge(&self, other: &Inner<K, V, R>) -> bool {
+        for (key, elements) in &self.0 {
+            if let Some(other_elements) = other.0.get(key) {
+                for element in elements {
+                    if let Ok(idx) = other_elements.binary_search_by(|e| e.cmp(element)) {
+                        let other_value = &other_elements[idx].value;
+                        if !element.value.nested_can_merge(other_value) {
+                            return false
+                        }
+                    }
+                }
             }
         }
+        true
     }
 
-    pub fn validate_no_unassigned_sites(&self) -> Result<(), Error> {
-        for elements in self.0.values() {
-            for element in elements {
-                if element.dot.site_id == 0 {
-                    return Err(Error::InvalidSiteId);
-                }
+    /// Returns the elements that a site with the given summary has not
+    /// seen, and the dots of the rest. Elements whose values have
+    /// changed since are sent whole.
+    pub(crate) fn delta_since(&self, since: &Summary, _: &Summary) -> InnerDelta<K, V, R> {
+        let mut inserted = Inner::new();
+        let mut retained = vec![];
+
+        for (key, elements) in &self.0 {
+            let (seen, unseen): (Vec<&Element<V>>, Vec<&Element<V>>) = elements.iter()
+                .partition(|e| since.contains(&e.dot) && e.value.nested_unchanged_since(since));
+
+            retained.extend(seen.into_iter().map(|e| e.dot));
+            if !unseen.is_empty() {
+                inserted.0.insert(key.clone(), unseen.into_iter().cloned().collect());
             }
         }
-        Ok(())
+
+        InnerDelta{inserted, retained}
     }
 
-    pub fn local_value(&self) -> HashMap<K, V> {
-        let mut hashmap = HashMap::with_capacity(self.0.len());
-        for (key, elements) in &self.0 {
-            hashmap.insert(key.clone(), elements[0].value.clone());
+    pub(crate) fn merge_delta(&mut self, delta: InnerDelta<K, V, R>, summary: &Summary, delta_summary: &Summary) -> Result<(), Error> {
+        if delta.retained.iter().any(|dot| !summary.contains(dot)) {
+            return Err(Error::InvalidDelta)
         }
-        hashmap
+
+        let retained: HashSet<Dot> = delta.retained.into_iter().collect();
+        let mut inserted = delta.inserted.0;
+
+        // retain an element in self iff
+        // - the element is in the delta, OR
+        // - the element has not been inserted into the delta's source
+        self.0.retain(|key, elements| {
+            let inserted_elements = inserted.remove(key).unwrap_or_default();
+            elements.retain(|e| retained.contains(&e.dot) || inserted_elements.contains(e) || !delta_summary.contains(&e.dot));
+
+            let (merged_elements, mut inserted_elements): (Vec<Element<V>>, Vec<Element<V>>) = inserted_elements.into_iter()
+                .partition(|e| elements.contains(e));
+
+            for element in merged_elements {
+                let idx = elements.binary_search_by(|e| e.cmp(&element)).expect("Element must be present");
+                elements[idx].value.nested_force_merge(element.value, summary, delta_summary);
+            }
+
+            inserted_elements.retain(|e| !summary.contains(&e.dot));
+            elements.append(&mut inserted_elements);
+            elements.sort();
+            !elements.is_empty()
+        });
+
+        // insert any element that is in the delta but not yet inserted into self
+        for (key, mut elements) in inserted {
+            elements.retain(|e| !summary.contains(&e.dot));
+            if !elements.is_empty() {
+                self.0.insert(key, elements);
+            }
+        }
+
+        Ok(())
     }
-}
 
-impl<K: Key, V: Value + NestedInner> NestedInner for Inner<K, V> {
-    fn nested_add_site_id(&mut self, site_id: SiteId) {
+    pub fn add_site_id(&mut self, site_id: SiteId) {
         for elements in self.0.values_mut() {
             for element in elements {
                 element.value.nested_add_site_id(site_id);
-                if element.dot.site_id == 0 {
-                    element.dot.site_id = site_id;
-                }
+                if element.dot.site_id == 0 { element.dot.site_id = site_id };
             }
         }
     }
 
-    fn nested_validate_no_unassigned_sites(&self) -> Result<(), Error> {
+    pub fn validate_no_unassigned_sites(&self) -> Result<(), Error> {
         for elements in self.0.values() {
             for element in elements {
                 if element.dot.site_id == 0 {
@@ -307,7 +568,7 @@ impl<K: Key, V: Value + NestedInner> NestedInner for Inner<K, V> {
         Ok(())
     }
 
-    fn nested_validate_all(&self, site_id: SiteId) -> Result<(), Error> {
+    pub fn validate_all(&self, site_id: SiteId) -> Result<(), Error> {
         for elements in self.0.values() {
             for element in elements {
                 if element.dot.site_id != site_id {
@@ -319,58 +580,60 @@ impl<K: Key, V: Value + NestedInner> NestedInner for Inner<K, V> {
         Ok(())
     }
 
-    fn nested_can_merge(&self, other: &Inner<K,V>) -> bool {
+    pub fn local_value(&self) -> HashMap<K, V> {
+        let mut hashmap = HashMap::with_capacity(self.0.len());
         for (key, elements) in &self.0 {
-            if let Some(other_elements) = other.0.get(key) {
-                for element in elements {
-                    if let Ok(idx) = other_elements.binary_search_by(|e| e.cmp(element)) {
-                        let other_value = &other_elements[idx].value;
-                        if !element.value.nested_can_merge(other_value) {
-                            return false
-                        }
-                    }
-                }
-            }
+            hashmap.insert(key.clone(), elements[Self::visible_idx(elements)].value.clone());
         }
-        true
+        hashmap
     }
 
-    fn nested_force_merge(&mut self, other: Inner<K, V>, summary: &Summary, other_summary: &Summary) {
-        let mut other_values = other.0;
+    /// Returns the index of the visible element among the
+    /// concurrent elements of a key.
+    fn visible_idx(elements: &[Element<V>]) -> usize {
+        let values = elements.iter().map(|e| (e.dot, &e.value));
+        resolver::visible_index::<V, R, _>(values).expect("Key must have an element")
+    }
+}
 
-        self.0.retain(|key, elements| {
-            let mut other_elements = other_values.remove(key).unwrap_or_else(|| vec![]);
+impl<K: Key, V: Value, R> Serialize for Inner<K, V, R> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error> where S: Serializer {
+        struct Elements<'a, K: 'a, V: 'a>(&'a HashMap<K, Vec<Element<V>>>);
 
-            // remove elements that have been removed from other
-            elements.retain(|e| other_elements.contains(e) || !other_summary.contains(&e.dot));
-            other_elements.retain(|e| elements.contains(e) || !summary.contains(&e.dot));
+        impl<'a, K: Key, V: Value> Serialize for Elements<'a, K, V> {
+            fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error> where S: Serializer {
+                map_tuple_vec::serialize(self.0, serializer)
+            }
+        }
 
-            let (other_merge, mut other_insert) = other_elements.into_iter()
-                .partition(|e| elements.contains(e));
+        serializer.serialize_newtype_struct("Inner", &Elements(&self.0))
+    }
+}
 
-            // merge elements that are in both self and other
-            for element in other_merge {
-                let idx = elements.binary_search_by(|e| e.cmp(&element)).expect("Element must be present");
-                elements[idx].value.nested_force_merge(element.value, summary, other_summary);
-            }
+impl<'de, K: Key, V: Value, R> Deserialize<'de> for Inner<K, V, R> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error> where D: Deserializer<'de> {
+        #[derive(Deserialize)]
+        #[serde(rename = "Inner", bound(deserialize = ""))]
+        struct Elements<K: Key, V: Value>(#[serde(with = "map_tuple_vec")] HashMap<K, Vec<Element<V>>>);
 
-            // append elements from other that have not been inserted into self
-            elements.append(&mut other_insert);
-            elements.sort();
-            !elements.is_empty()
-        });
+        let elements = Elements::deserialize(deserializer)?;
+        Ok(Inner(elements.0, PhantomData))
+    }
+}
 
-        // insert any element that is in other but not yet inserted into self
-        for (key, mut elements) in other_values {
-            elements.retain(|e| !summary.contains(&e.dot));
-            if !elements.is_empty() {
-                self.0.insert(key, elements);
-            }
-        }
+impl<K: Key, V: Value, R: Resolver<V>> InnerDelta<K, V, R> {
+    pub fn validate_no_unassigned_sites(&self) -> Result<(), Error> {
+        self.inserted.validate_no_unassigned_sites()?;
+        if self.retained.iter().any(|dot| dot.site_id == 0) { return Err(Error::InvalidSiteId) }
+        Ok(())
     }
 }
 
 impl<K: Key, V: Value> Op<K, V> {
+    pub(crate) fn new(key: K, inserted_element: Option<Element<V>>, removed_dots: Vec<Dot>) -> Self {
+        Op{key, inserted_element, removed_dots, edit: None}
+    }
+
     /// Returns the `Op`'s key.
     pub fn key(&self) -> &K { &self.key }
 
@@ -380,48 +643,70 @@ impl<K: Key, V: Value> Op<K, V> {
     /// Returns a reference to the `Op`'s removed dots.
     pub fn removed_dots(&self) -> &[Dot] { &self.removed_dots }
 
+    /// Returns the dot of the element the `Op` edits, and the
+    /// op that edits the element's value.
+    pub fn edit(&self) -> Option<&(Dot, V::NestedOp)> { self.edit.as_ref() }
+
     /// Assigns a site id to any unassigned inserts and removes
     pub fn add_site_id(&mut self, site_id: SiteId) {
         if let Some(ref mut e) = self.inserted_element {
+            e.value.nested_add_site_id(site_id);
             if e.dot.site_id == 0 { e.dot.site_id = site_id };
         }
         for r in &mut self.removed_dots {
             if r.site_id == 0 { r.site_id = site_id };
         }
+        if let Some((ref mut dot, ref mut op)) = self.edit {
+            if dot.site_id == 0 { dot.site_id = site_id };
+            op.nested_add_site_id(site_id);
+        }
     }
 
     /// Validates that the `Op`'s site id is equal to the given site id.
     pub fn validate(&self, site_id: SiteId) -> Result<(), Error> {
         if let Some(ref e) = self.inserted_element {
             if e.dot.site_id != site_id { return Err(Error::InvalidOp) };
+            e.value.nested_validate_all(site_id)?;
+        }
+        if let Some((_, ref op)) = self.edit {
+            op.nested_validate(site_id)?;
         }
         Ok(())
     }
 
     pub(crate) fn inserted_dots(&self) -> Vec<Dot> {
-        match self.inserted_element {
+        let mut dots = match self.inserted_element {
             Some(ref e) => vec![e.dot],
             None => vec![],
+        };
+        if let Some((_, ref op)) = self.edit {
+            dots.append(&mut op.inserted_dots());
         }
+        dots
     }
 }
 
-impl<K: Key, V: Value + NestedInner> NestedOp for Op<K, V> {
-    fn nested_add_site_id(&mut self, site_id: SiteId) {
-        if let Some(ref mut e) = self.inserted_element {
-            e.value.nested_add_site_id(site_id);
-            if e.dot.site_id == 0 { e.dot.site_id = site_id };
-        }
-        for r in &mut self.removed_dots {
-            if r.site_id == 0 { r.site_id = site_id };
+impl<K: Key, V: Value> CausalOp for Op<K, V> {
+    fn inserted_dots(&self) -> Vec<Dot> {
+        Op::inserted_dots(self)
+    }
+
+    fn referenced_dots(&self) -> Vec<Dot> {
+        let mut dots = self.removed_dots.clone();
+        if let Some((dot, ref op)) = self.edit {
+            dots.push(dot);
+            dots.append(&mut op.referenced_dots());
         }
+        dots
+    }
+}
+
+impl<K: Key, V: Value> NestedOp for Op<K, V> {
+    fn nested_add_site_id(&mut self, site_id: SiteId) {
+        self.add_site_id(site_id)
     }
 
     fn nested_validate(&self, site_id: SiteId) -> Result<(), Error> {
-        if let Some(ref e) = self.inserted_element {
-            if e.dot.site_id != site_id { return Err(Error::InvalidOp) };
-            e.value.nested_validate_all(site_id)?;
-        }
-        Ok(())
+        self.validate(site_id)
     }
 }
@@ -2,8 +2,14 @@
 
 use Error;
 use dot::{Dot, SiteId, Counter, Summary};
+use resolver::{self, Resolver, LowestSite};
+use traits::{Crdt, CausalOp, Nested, NestedOp, NestedCrdt};
+use serde::{Serialize, Serializer, Deserialize, Deserializer};
+use serde::de::DeserializeOwned;
 use std::borrow::Cow;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::Debug;
+use std::marker::PhantomData;
 use std::mem;
 
 /// A Register is a replaceable value that can be updated
@@ -14,6 +20,22 @@ use std::mem;
 /// Both replication methods are idempotent and can handle
 /// out-of-order delivery.
 ///
+/// Concurrent updates from different sites are all kept. One of
+/// them is visible through [`get`](#method.get), and
+/// [`conflicts`](#method.conflicts) returns the others until
+/// [`resolve`](#method.resolve) or another update supersedes them.
+/// The [`Resolver`](../resolver/trait.Resolver.t.html) type parameter
+/// picks the visible value; by default it is the value from the
+/// lowest site id. Use [`with_resolver`](#method.with_resolver) to
+/// pick another rule.
+///
+/// [`LastWriterWins`](../resolver/struct.LastWriterWins.html) compares
+/// timestamps that the values carry themselves: `T` must implement
+/// [`Timestamped`](../resolver/trait.Timestamped.html), and each site
+/// must put the time of the update inside the value it writes. The
+/// Register does not record when a value was written, so sites whose
+/// clocks disagree can let an older value win.
+///
 /// `Register` has a spatial complexity of *O(N + S)*, where
 /// *N* is the number of values concurrently held in the `Register` and
 /// *S* is the number of sites that have updated the `Register`.
@@ -27,19 +49,29 @@ use std::mem;
 ///     merged into and the `RegisterState` being merged, respectively.
 ///
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
-pub struct Register<T: Clone> {
-    elements:  BTreeMap<SiteId, SiteValue<T>>,
+#[serde(bound(serialize = "T: Serialize", deserialize = "T: Deserialize<'de>"))]
+pub struct Register<T: Clone, R: Resolver<T> = LowestSite> {
+    elements:  Inner<T, R>,
     summary:   Summary,
     site_id:   SiteId,
     cached_op: Option<Op<T>>,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
-pub struct RegisterState<'a, T: Clone + 'a> {
-    elements: Cow<'a, BTreeMap<SiteId, SiteValue<T>>>,
+#[serde(bound(serialize = "T: Serialize", deserialize = "T: Deserialize<'de>"))]
+pub struct RegisterState<'a, T: Clone + 'a, R: Resolver<T> + 'a = LowestSite> {
+    elements: Cow<'a, Inner<T, R>>,
     summary:  Cow<'a, Summary>,
 }
 
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(bound(serialize = "T: Serialize", deserialize = "T: Deserialize<'de>"))]
+pub struct RegisterDelta<T: Clone, R: Resolver<T> = LowestSite> {
+    elements: Inner<T, R>,
+    retained: Vec<Dot>,
+    summary:  Summary,
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Op<T: Clone> {
     site_id: SiteId,
@@ -48,6 +80,15 @@ pub struct Op<T: Clone> {
     removed_dots: Vec<Dot>,
 }
 
+/// The values that a register holds, at most one per site. Values
+/// that were written concurrently are all kept; the resolver `R`
+/// picks the visible one. The dots of the values are
+/// tracked by the summary of the enclosing CRDT, which lets other
+/// CRDTs such as [`Json`](../json/Json.t.html) nest registers.
+#[doc(hidden)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Inner<T: Clone, R = LowestSite>(BTreeMap<SiteId, SiteValue<T>>, PhantomData<R>);
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 struct SiteValue<T: Clone> {
     value:   T,
@@ -58,18 +99,58 @@ impl<T: Clone> Register<T> {
 
     /// Constructs and returns a new `Register` with site id 1.
     pub fn new(value: T) -> Self {
+        Register::with_resolver::<LowestSite>(value)
+    }
+
+    /// Constructs and returns a new `Register` with site id 1
+    /// that uses the resolver `R` to pick its visible value.
+    ///
+    /// ```
+    /// use ditto::Register;
+    /// use ditto::resolver::MaxValue;
+    ///
+    /// let register = Register::with_resolver::<MaxValue>(5);
+    /// assert_eq!(register.get(), &5);
+    /// ```
+    pub fn with_resolver<R: Resolver<T>>(value: T) -> Register<T, R> {
         let site_id = 1;
-        let counter = 1;
-        let mut elements = BTreeMap::new();
         let mut summary = Summary::default();
-        let _ = elements.insert(site_id, SiteValue{value, counter});
-        summary.insert_pair(site_id, counter);
+        let elements = Inner::new(value, summary.get_dot(site_id));
         Register{elements, summary, site_id, cached_op: None}
     }
+}
+
+impl<T: Clone, R: Resolver<T>> Register<T, R> {
 
     /// Returns a reference to the `Register`'s value.
     pub fn get(&self) -> &T {
-        &self.elements.values().next().as_ref().unwrap().value
+        self.elements.get()
+    }
+
+    /// Returns every value the `Register` holds with the dot that
+    /// wrote it. Values written concurrently at different sites are
+    /// all kept until an update supersedes them. The visible value
+    /// comes first.
+    pub fn get_all(&self) -> Vec<(Dot, &T)> {
+        self.elements.get_all()
+    }
+
+    /// Returns the values that were written concurrently with the
+    /// visible value and are hidden by it. It is empty unless the
+    /// `Register` holds a conflict.
+    pub fn conflicts(&self) -> Vec<(Dot, &T)> {
+        let mut values = self.elements.get_all();
+        let _ = values.remove(0);
+        values
+    }
+
+    /// Resolves a conflict by replacing every concurrent value with
+    /// `value`, and returns an op that can be replicated to other
+    /// sites. The value may be one of the conflicting values or a
+    /// new one. If the register does not have a site id allocated,
+    /// it caches the op and returns an `AwaitingSiteId` error.
+    pub fn resolve(&mut self, value: T) -> Result<Op<T>, Error> {
+        self.update(value)
     }
 
     /// Updates the `Register`'s value and returns an op
@@ -77,22 +158,8 @@ impl<T: Clone> Register<T> {
     /// If the register does not have a site id allocated, it
     /// caches the op and returns an `AwaitingSiteId` error.
     pub fn update(&mut self, value: T) -> Result<Op<T>, Error> {
-        let counter = self.summary.increment(self.site_id);
-
-        let mut new_elements = BTreeMap::new();
-        new_elements.insert(self.site_id, SiteValue{value: value.clone(), counter});
-
-        let removed_dots = mem::replace(&mut self.elements, new_elements)
-            .into_iter()
-            .filter_map(|(site_id, site_value)|
-                if site_id == self.site_id {
-                    None
-                } else {
-                    Some(Dot::new(site_id, site_value.counter))
-                })
-            .collect();
-
-        let op = Op{site_id: self.site_id, value, counter, removed_dots};
+        let dot = self.summary.get_dot(self.site_id);
+        let op = self.elements.update(value, dot);
 
         if self.site_id == 0 {
             self.cached_op = Some(op);
@@ -106,54 +173,39 @@ impl<T: Clone> Register<T> {
     /// the value has changed. If the op has already been executed
     /// or superceded, nothing is done.
     pub fn execute_op(&mut self, op: Op<T>) -> &T {
-        for Dot{site_id, counter} in op.removed_dots {
-            // remove any elements that were removed by the op.
-            if let Some(site_value) = self.elements.remove(&site_id) {
-                if site_value.counter > counter {
-                    self.elements.insert(site_id, site_value);
-                }
-            }
-        }
-
-        // insert the element that is inserted by the op
         self.summary.insert_pair(op.site_id, op.counter);
-        let sv_other = SiteValue{value: op.value, counter: op.counter};
-        if let Some(sv_self) = self.elements.insert(op.site_id, sv_other) {
-            if sv_self.counter > op.counter {
-                self.elements.insert(op.site_id, sv_self);
-            }
-        }
-
+        let _ = self.elements.execute_op(op);
         self.get()
     }
 
-    /// Merges remote state into the Register
-    pub fn merge(&mut self, other: RegisterState<T>) {
-        let mut other_elements = other.elements.into_owned();
-        let self_elements = mem::replace(&mut self.elements, BTreeMap::new());
+    /// Merges remote state into the Register. Returns a reference
+    /// to the new value if the merge changed the value.
+    pub fn merge(&mut self, other: RegisterState<T, R>) -> Option<&T> {
+        let changed = self.elements.merge(other.elements.into_owned(), &self.summary, &other.summary).is_some();
+        self.summary.merge(&other.summary);
+        if changed { Some(self.get()) } else { None }
+    }
 
-        // retain any element that is either:
-        // - in both self and other
-        // - in self and not yet inserted into other
-        // - in other and not yet inserted into self
-        for (site_id, sv_self) in self_elements {
-            if let Some(sv_other) = other_elements.remove(&site_id) {
-                let sv = if sv_self.counter > sv_other.counter { sv_self } else { sv_other };
-                self.summary.insert_pair(site_id, sv.counter);
-                self.elements.insert(site_id, sv);
-            } else if !other.summary.contains_pair(site_id, sv_self.counter) {
-                self.summary.insert_pair(site_id, sv_self.counter);
-                self.elements.insert(site_id, sv_self);
-            }
-        }
+    /// Returns a delta containing every value that a site with the
+    /// given summary has not yet seen. Values the site has already
+    /// seen are sent as dots only.
+    pub fn delta_since(&self, since: &Summary) -> RegisterDelta<T, R> {
+        let (elements, retained) = self.elements.delta_since(since);
+        RegisterDelta{elements, retained, summary: self.summary.clone()}
+    }
 
-        // insert any element that has been inserted into other but not self
-        for (site_id, sv) in other_elements {
-            if !self.summary.contains_pair(site_id, sv.counter) {
-                self.summary.insert_pair(site_id, sv.counter);
-                self.elements.insert(site_id, sv);
-            }
+    /// Merges a delta from a remote Register into the Register. The
+    /// delta must have been generated from a summary that the Register
+    /// has already seen; otherwise an `InvalidDelta` error is returned
+    /// and the Register is left unchanged.
+    pub fn merge_delta(&mut self, delta: RegisterDelta<T, R>) -> Result<(), Error> {
+        if delta.retained.iter().any(|dot| !self.summary.contains(dot)) {
+            return Err(Error::InvalidDelta)
         }
+
+        self.elements.merge_delta(delta.elements, delta.retained, &self.summary, &delta.summary);
+        self.summary.merge(&delta.summary);
+        Ok(())
     }
 
     /// Assigns a site id and returns a cached op if it exists.
@@ -164,10 +216,7 @@ impl<T: Clone> Register<T> {
 
         self.site_id = site_id;
         self.summary.add_site_id(site_id);
-
-        if let Some(site_value) = self.elements.remove(&0) {
-            self.elements.insert(site_id, site_value);
-        }
+        self.elements.add_site_id(site_id);
 
         if let Some(mut op) = self.cached_op.take() {
             op.add_site_id(site_id);
@@ -188,7 +237,7 @@ impl<T: Clone> Register<T> {
     }
 
     /// Returns a borrowed RegisterState.
-    pub fn state(&self) -> RegisterState<T> {
+    pub fn state(&self) -> RegisterState<'_, T, R> {
         RegisterState{
             elements: Cow::Borrowed(&self.elements),
             summary: Cow::Borrowed(&self.summary),
@@ -196,7 +245,7 @@ impl<T: Clone> Register<T> {
     }
 
     /// Returns an owned RegisterState of cloned values.
-    pub fn clone_state(&self) -> RegisterState<'static, T> {
+    pub fn clone_state(&self) -> RegisterState<'static, T, R> {
         RegisterState {
             elements: Cow::Owned(self.elements.clone()),
             summary: Cow::Owned(self.summary.clone()),
@@ -204,7 +253,7 @@ impl<T: Clone> Register<T> {
     }
 
     /// Consumes the Register and returns its RegisterState
-    pub fn into_state(self) -> RegisterState<'static, T> {
+    pub fn into_state(self) -> RegisterState<'static, T, R> {
         RegisterState {
             elements: Cow::Owned(self.elements),
             summary: Cow::Owned(self.summary),
@@ -213,7 +262,7 @@ impl<T: Clone> Register<T> {
 
     /// Constructs a new Register from a RegisterState and an
     /// optional site id. If the site id is given, it must be nonzero.
-    pub fn from_state(state: RegisterState<T>, site_id: Option<SiteId>) -> Result<Self, Error> {
+    pub fn from_state(state: RegisterState<T, R>, site_id: Option<SiteId>) -> Result<Self, Error> {
         let site_id = match site_id {
             None => 0,
             Some(0) => return Err(Error::InvalidSiteId),
@@ -236,6 +285,294 @@ impl<T: Clone> Register<T> {
     }
 }
 
+impl<T: Clone, R: Resolver<T>> Inner<T, R> {
+    pub fn new(value: T, dot: Dot) -> Self {
+        let mut elements = BTreeMap::new();
+        let _ = elements.insert(dot.site_id, SiteValue{value, counter: dot.counter});
+        Inner(elements, PhantomData)
+    }
+
+    pub fn get(&self) -> &T {
+        &self.visible().expect("Register must have a value").1.value
+    }
+
+    /// Returns every value with its dot, visible value first.
+    pub fn get_all(&self) -> Vec<(Dot, &T)> {
+        let mut values: Vec<(Dot, &T)> = self.0.iter()
+            .map(|(site_id, sv)| (Dot::new(*site_id, sv.counter), &sv.value))
+            .collect();
+
+        if let Some(idx) = resolver::visible_index::<T, R, _>(values.iter().cloned()) {
+            let visible = values.remove(idx);
+            values.insert(0, visible);
+        }
+        values
+    }
+
+    /// Replaces every value with `value`, which is written at `dot`.
+    pub fn update(&mut self, value: T, dot: Dot) -> Op<T> {
+        let mut new_elements = BTreeMap::new();
+        new_elements.insert(dot.site_id, SiteValue{value: value.clone(), counter: dot.counter});
+
+        let removed_dots = mem::replace(&mut self.0, new_elements)
+            .into_iter()
+            .filter_map(|(site_id, site_value)|
+                if site_id == dot.site_id {
+                    None
+                } else {
+                    Some(Dot::new(site_id, site_value.counter))
+                })
+            .collect();
+
+        Op{site_id: dot.site_id, value, counter: dot.counter, removed_dots}
+    }
+
+    /// Executes an op and returns a reference to the new value
+    /// if the visible value has changed.
+    pub fn execute_op(&mut self, op: Op<T>) -> Option<&T> {
+        let visible_dot = self.visible_dot();
+
+        for Dot{site_id, counter} in op.removed_dots {
+            // remove any elements that were removed by the op.
+            if let Some(site_value) = self.0.remove(&site_id) {
+                if site_value.counter > counter {
+                    self.0.insert(site_id, site_value);
+                }
+            }
+        }
+
+        // insert the element that is inserted by the op
+        let sv_other = SiteValue{value: op.value, counter: op.counter};
+        if let Some(sv_self) = self.0.insert(op.site_id, sv_other) {
+            if sv_self.counter > op.counter {
+                self.0.insert(op.site_id, sv_self);
+            }
+        }
+
+        if self.visible_dot() != visible_dot { Some(self.get()) } else { None }
+    }
+
+    /// Merges another site's values and returns a reference to
+    /// the new value if the visible value has changed.
+    pub fn merge(&mut self, other: Inner<T, R>, summary: &Summary, other_summary: &Summary) -> Option<&T> {
+        let visible_dot = self.visible_dot();
+        let mut other_elements = other.0;
+        let self_elements = mem::take(&mut self.0);
+
+        // retain any element that is either:
+        // - in both self and other
+        // - in self and not yet inserted into other
+        // - in other and not yet inserted into self
+        for (site_id, sv_self) in self_elements {
+            if let Some(sv_other) = other_elements.remove(&site_id) {
+                let sv = if sv_self.counter > sv_other.counter { sv_self } else { sv_other };
+                self.0.insert(site_id, sv);
+            } else if !other_summary.contains_pair(site_id, sv_self.counter) {
+                self.0.insert(site_id, sv_self);
+            }
+        }
+
+        // insert any element that has been inserted into other but not self
+        for (site_id, sv) in other_elements {
+            if !summary.contains_pair(site_id, sv.counter) {
+                self.0.insert(site_id, sv);
+            }
+        }
+
+        if self.visible_dot() != visible_dot { Some(self.get()) } else { None }
+    }
+
+    /// Returns the values that a site with the given summary has
+    /// not yet seen, and the dots of the values it has seen.
+    pub fn delta_since(&self, since: &Summary) -> (Inner<T, R>, Vec<Dot>) {
+        let mut elements = BTreeMap::new();
+        let mut retained = vec![];
+
+        for (site_id, sv) in &self.0 {
+            if since.contains_pair(*site_id, sv.counter) {
+                retained.push(Dot::new(*site_id, sv.counter));
+            } else {
+                elements.insert(*site_id, sv.clone());
+            }
+        }
+
+        (Inner(elements, PhantomData), retained)
+    }
+
+    /// Merges the values of a delta. The retained dots must
+    /// already have been checked against `summary`.
+    pub fn merge_delta(&mut self, elements: Inner<T, R>, retained: Vec<Dot>, summary: &Summary, delta_summary: &Summary) {
+        let retained: HashSet<Dot> = retained.into_iter().collect();
+        let mut delta_elements = elements.0;
+        let self_elements = mem::take(&mut self.0);
+
+        // retain any element that is either:
+        // - in both self and the delta
+        // - in self and not yet inserted into the delta's source
+        // - in the delta and not yet inserted into self
+        for (site_id, sv_self) in self_elements {
+            if let Some(sv_delta) = delta_elements.remove(&site_id) {
+                let sv = if sv_self.counter > sv_delta.counter { sv_self } else { sv_delta };
+                self.0.insert(site_id, sv);
+            } else if retained.contains(&Dot::new(site_id, sv_self.counter)) || !delta_summary.contains_pair(site_id, sv_self.counter) {
+                self.0.insert(site_id, sv_self);
+            }
+        }
+
+        for (site_id, sv) in delta_elements {
+            if !summary.contains_pair(site_id, sv.counter) {
+                self.0.insert(site_id, sv);
+            }
+        }
+    }
+
+    pub fn add_site_id(&mut self, site_id: SiteId) {
+        if let Some(site_value) = self.0.remove(&0) {
+            self.0.insert(site_id, site_value);
+        }
+    }
+
+    pub fn validate_no_unassigned_sites(&self) -> Result<(), Error> {
+        if self.0.contains_key(&0) { Err(Error::InvalidSiteId) } else { Ok(()) }
+    }
+
+    pub fn validate_all(&self, site_id: SiteId) -> Result<(), Error> {
+        if self.0.keys().all(|s| *s == site_id) { Ok(()) } else { Err(Error::InvalidSiteId) }
+    }
+
+    fn visible(&self) -> Option<(&SiteId, &SiteValue<T>)> {
+        let values = self.0.iter().map(|(site_id, sv)| (Dot::new(*site_id, sv.counter), &sv.value));
+        let idx = resolver::visible_index::<T, R, _>(values)?;
+        self.0.iter().nth(idx)
+    }
+
+    fn visible_dot(&self) -> Option<Dot> {
+        let (site_id, site_value) = self.visible()?;
+        Some(Dot::new(*site_id, site_value.counter))
+    }
+}
+
+impl<T: Clone + Serialize, R> Serialize for Inner<T, R> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error> where S: Serializer {
+        self.0.serialize(serializer)
+    }
+}
+
+impl<'de, T: Clone + Deserialize<'de>, R> Deserialize<'de> for Inner<T, R> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error> where D: Deserializer<'de> {
+        Ok(Inner(BTreeMap::deserialize(deserializer)?, PhantomData))
+    }
+}
+
+impl<T: Clone + Serialize + DeserializeOwned + 'static, R: Resolver<T> + 'static> Crdt for Register<T, R> {
+    type State = RegisterState<'static, T, R>;
+    type Op = Op<T>;
+    type LocalOp = T;
+    type LocalValue = T;
+    type Delta = RegisterDelta<T, R>;
+
+    fn site_id(&self) -> SiteId {
+        self.site_id
+    }
+
+    fn summary(&self) -> Cow<'_, Summary> {
+        Cow::Borrowed(Register::summary(self))
+    }
+
+    fn clone_state(&self) -> RegisterState<'static, T, R> {
+        Register::clone_state(self)
+    }
+
+    fn into_state(self) -> RegisterState<'static, T, R> {
+        Register::into_state(self)
+    }
+
+    fn from_state(state: RegisterState<'static, T, R>, site_id: Option<SiteId>) -> Result<Self, Error> {
+        Register::from_state(state, site_id)
+    }
+
+    fn local_value(&self) -> T {
+        self.get().clone()
+    }
+
+    fn execute_op(&mut self, op: Op<T>) -> T {
+        Register::execute_op(self, op).clone()
+    }
+
+    fn validate_and_execute_op(&mut self, op: Op<T>, site_id: SiteId) -> Result<T, Error> {
+        Ok(Register::validate_and_execute_op(self, op, site_id)?.clone())
+    }
+
+    fn merge(&mut self, other: RegisterState<'static, T, R>) -> Result<Vec<T>, Error> {
+        Ok(Register::merge(self, other).into_iter().cloned().collect())
+    }
+
+    fn delta_since(&self, since: &Summary) -> RegisterDelta<T, R> {
+        Register::delta_since(self, since)
+    }
+
+    fn merge_delta(&mut self, delta: RegisterDelta<T, R>) -> Result<(), Error> {
+        Register::merge_delta(self, delta)
+    }
+
+    fn add_site_id(&mut self, site_id: SiteId) -> Result<Vec<Op<T>>, Error> {
+        Ok(Register::add_site_id(self, site_id)?.into_iter().collect())
+    }
+}
+
+impl<T: Clone + Debug + PartialEq + Serialize + DeserializeOwned, R: Resolver<T>> Nested for Register<T, R> {
+    type NestedOp = Op<T>;
+    type NestedLocalOp = T;
+
+    fn nested_reset(&mut self, dot: Dot) {
+        self.elements = Inner::new(self.get().clone(), dot);
+        self.summary = Summary::default();
+        self.site_id = 0;
+        self.cached_op = None;
+    }
+
+    fn nested_execute_op(&mut self, op: Op<T>) -> Vec<T> {
+        self.elements.execute_op(op).cloned().into_iter().collect()
+    }
+
+    fn nested_merge(&mut self, other: Self, summary: &Summary, other_summary: &Summary) -> Vec<T> {
+        self.elements.merge(other.elements, summary, other_summary).cloned().into_iter().collect()
+    }
+
+    fn nested_unchanged_since(&self, since: &Summary) -> bool {
+        self.elements.0.iter().all(|(site_id, element)| since.contains_pair(*site_id, element.counter))
+    }
+
+    fn nested_add_site_id(&mut self, site_id: SiteId) {
+        self.elements.add_site_id(site_id)
+    }
+
+    fn nested_validate_all(&self, site_id: SiteId) -> Result<(), Error> {
+        self.elements.validate_all(site_id)
+    }
+
+    fn nested_validate_no_unassigned_sites(&self) -> Result<(), Error> {
+        self.elements.validate_no_unassigned_sites()
+    }
+}
+
+impl<T: Clone + Debug + PartialEq + Serialize + DeserializeOwned, R: Resolver<T>> NestedCrdt for Register<T, R> {
+    fn nested_edit<F>(&mut self, summary: &mut Summary, site_id: SiteId, f: F) -> Result<Op<T>, Error>
+        where F: FnOnce(&mut Self) -> Result<Op<T>, Error>
+    {
+        mem::swap(&mut self.summary, summary);
+        self.site_id = site_id;
+        let result = f(self);
+        mem::swap(&mut self.summary, summary);
+        self.site_id = 0;
+
+        match self.cached_op.take() {
+            Some(op) => Ok(op),
+            None => result,
+        }
+    }
+}
+
 impl<T: Clone> Op<T> {
     /// Returns the `Op`'s site_id
     pub fn site_id(&self) -> SiteId { self.site_id }
@@ -261,3 +598,26 @@ impl<T: Clone> Op<T> {
         if self.site_id == site_id { Ok(()) } else { Err(Error::InvalidOp) }
     }
 }
+
+impl<T: Clone> CausalOp for Op<T> {
+    fn inserted_dots(&self) -> Vec<Dot> {
+        vec![Dot::new(self.site_id, self.counter)]
+    }
+
+    fn referenced_dots(&self) -> Vec<Dot> {
+        self.removed_dots.clone()
+    }
+}
+
+impl<T: Clone> NestedOp for Op<T> {
+    fn nested_add_site_id(&mut self, site_id: SiteId) {
+        if self.site_id == 0 { self.site_id = site_id; }
+        for dot in &mut self.removed_dots {
+            if dot.site_id == 0 { dot.site_id = site_id; }
+        }
+    }
+
+    fn nested_validate(&self, site_id: SiteId) -> Result<(), Error> {
+        self.validate(site_id)
+    }
+}
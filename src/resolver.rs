@@ -0,0 +1,141 @@
+//! Rules that decide which of several concurrent values is visible.
+//!
+//! A [`Register`](../register/Register.t.html) keeps every value that
+//! was written concurrently, and a [`Map`](../map/Map.t.html) keeps
+//! every value that was concurrently inserted under the same key. Only
+//! one of them is visible through `get`, `local_value`, the local ops
+//! returned by `execute_op` and `merge`. A `Resolver` picks that value.
+//!
+//! The resolver is a type parameter that defaults to
+//! [`LowestSite`](struct.LowestSite.html), so `Register<T>` and
+//! `Map<K, V>` behave as they always have. Another resolver is
+//! picked with `Register::with_resolver` or `Map::with_resolver`.
+//! Every site that replicates a CRDT must use the same resolver;
+//! otherwise sites will show different values for the same state.
+//!
+//! A custom resolver is a type that implements `Resolver`:
+//!
+//! ```rust
+//! extern crate ditto;
+//! use ditto::{Register, Resolver};
+//! use ditto::dot::Dot;
+//! use std::cmp::Ordering;
+//!
+//! /// Site 1 is the server; its writes always win.
+//! #[derive(Debug, Clone, PartialEq)]
+//! struct ServerWins;
+//!
+//! impl<T> Resolver<T> for ServerWins {
+//!     fn compare(a: (Dot, &T), b: (Dot, &T)) -> Ordering {
+//!         (a.0.site_id == 1).cmp(&(b.0.site_id == 1))
+//!     }
+//! }
+//!
+//! fn main() {
+//!     let mut register1 = Register::with_resolver::<ServerWins>("a");
+//!     let mut register2 = Register::from_state(register1.clone_state(), Some(2)).unwrap();
+//!     let op1 = register1.update("b").unwrap();
+//!     let op2 = register2.update("c").unwrap();
+//!     register1.execute_op(op2);
+//!     register2.execute_op(op1);
+//!     assert_eq!(register2.get(), &"b");
+//! }
+//! ```
+
+use dot::Dot;
+use std::cmp::Ordering;
+use std::fmt::Debug;
+
+/// Decides which of several concurrent values is visible.
+pub trait Resolver<T>: Debug + Clone + PartialEq {
+    /// Compares two values that were written concurrently. The
+    /// greatest value is visible. Values that compare equal fall
+    /// back to the lowest dot, so every site makes the same choice.
+    fn compare(a: (Dot, &T), b: (Dot, &T)) -> Ordering;
+}
+
+/// A value that records when it was written.
+pub trait Timestamped {
+    /// Returns the time at which the value was written.
+    fn timestamp(&self) -> u64;
+}
+
+/// The value written by the lowest site id is visible. This is
+/// the default resolver.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct LowestSite;
+
+/// The value written by the highest site id is visible.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct HighestSite;
+
+/// The greatest value is visible.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MaxValue;
+
+/// The least value is visible.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MinValue;
+
+/// The value with the latest timestamp is visible (last writer wins).
+/// Values must implement [`Timestamped`](trait.Timestamped.html).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct LastWriterWins;
+
+impl<T> Resolver<T> for LowestSite {
+    fn compare(a: (Dot, &T), b: (Dot, &T)) -> Ordering {
+        b.0.site_id.cmp(&a.0.site_id)
+    }
+}
+
+impl<T> Resolver<T> for HighestSite {
+    fn compare(a: (Dot, &T), b: (Dot, &T)) -> Ordering {
+        a.0.site_id.cmp(&b.0.site_id)
+    }
+}
+
+impl<T: Ord> Resolver<T> for MaxValue {
+    fn compare(a: (Dot, &T), b: (Dot, &T)) -> Ordering {
+        a.1.cmp(b.1)
+    }
+}
+
+impl<T: Ord> Resolver<T> for MinValue {
+    fn compare(a: (Dot, &T), b: (Dot, &T)) -> Ordering {
+        b.1.cmp(a.1)
+    }
+}
+
+impl<T: Timestamped> Resolver<T> for LastWriterWins {
+    fn compare(a: (Dot, &T), b: (Dot, &T)) -> Ordering {
+        a.1.timestamp().cmp(&b.1.timestamp())
+    }
+}
+
+/// Returns the index of the visible value, or None if there
+/// are no values.
+pub(crate) fn visible_index<'a, T: 'a, R, I>(values: I) -> Option<usize>
+    where R: Resolver<T>,
+          I: IntoIterator<Item = (Dot, &'a T)>,
+{
+    values.into_iter()
+        .enumerate()
+        .max_by(|a, b| R::compare(a.1, b.1).then_with(|| (b.1).0.cmp(&(a.1).0)))
+        .map(|(idx, _)| idx)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_visible_index() {
+        let values = [(Dot::new(2,1), 5), (Dot::new(1,4), 3), (Dot::new(3,2), 5)];
+        let iter = || values.iter().map(|v| (v.0, &v.1));
+        assert_eq!(visible_index::<_, LowestSite, _>(iter()), Some(1));
+        assert_eq!(visible_index::<_, HighestSite, _>(iter()), Some(2));
+        assert_eq!(visible_index::<_, MaxValue, _>(iter()), Some(0));
+        assert_eq!(visible_index::<_, MinValue, _>(iter()), Some(1));
+        assert_eq!(visible_index::<i32, LowestSite, _>(vec![]), None);
+    }
+}
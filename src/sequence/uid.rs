@@ -34,7 +34,7 @@ const BASE_LEVEL: usize = 20;
 const MAX_LEVEL:  usize = 64;
 const BOUNDARY:   usize = 40;
 
-#[derive(Clone,PartialEq,Eq)]
+#[derive(Clone,PartialEq,Eq,Hash)]
 pub struct Uid {
     pub position: BigUint,
     pub site_id:  SiteId,
@@ -3,11 +3,13 @@
 use Error;
 use dot::{Dot, SiteId, Counter, Summary};
 use map_tuple_vec;
+use traits::*;
 
 use serde::ser::Serialize;
 use serde::de::DeserializeOwned;
 use std::borrow::Cow;
 use std::collections::{HashMap, HashSet};
+use std::fmt::Debug;
 use std::hash::Hash;
 
 pub trait SetElement: Clone + Eq + Hash + Serialize + DeserializeOwned {}
@@ -54,9 +56,23 @@ pub struct SetState<'a, T: SetElement + 'a>{
     summary: Cow<'a, Summary>,
 }
 
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(bound(deserialize = ""))]
+pub struct SetDelta<T: SetElement> {
+    inner: InnerDelta<T>,
+    summary: Summary,
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub(crate) struct Inner<T: SetElement>(#[serde(with = "map_tuple_vec")] pub HashMap<T, Vec<Dot>>);
 
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(bound(deserialize = ""))]
+pub(crate) struct InnerDelta<T: SetElement> {
+    inserted: Inner<T>,
+    retained: Vec<Dot>,
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Op<T> {
     value: T,
@@ -113,8 +129,65 @@ impl<T: SetElement> Set<T> {
         Inner<T>,
         Op<T>,
         Option<LocalOp<T>>,
+        LocalOp<T>,
         HashSet<T>,
+        SetDelta<T>,
+        SetDelta,
+    }
+}
+
+impl<T: SetElement + 'static> Crdt for Set<T> {
+    crdt_trait_impl! {
+        Set,
+        SetState<'static, T>,
+        Op<T>,
+        Option<LocalOp<T>>,
+        Some,
+        HashSet<T>,
+        SetDelta<T>,
+    }
+}
+
+impl<T: SetElement + Debug + 'static> Nested for Set<T> {
+    type NestedOp = Op<T>;
+    type NestedLocalOp = LocalOp<T>;
+
+    fn nested_reset(&mut self, dot: Dot) {
+        for dots in self.inner.0.values_mut() {
+            *dots = vec![dot];
+        }
+        self.summary = Summary::default();
+        self.site_id = 0;
+        self.cached_ops.clear();
     }
+
+    fn nested_execute_op(&mut self, op: Op<T>) -> Vec<LocalOp<T>> {
+        self.inner.execute_op(op).into_iter().collect()
+    }
+
+    fn nested_merge(&mut self, other: Self, summary: &Summary, other_summary: &Summary) -> Vec<LocalOp<T>> {
+        self.inner.merge(other.inner, summary, other_summary)
+    }
+
+    fn nested_unchanged_since(&self, _: &Summary) -> bool {
+        false
+    }
+
+    fn nested_add_site_id(&mut self, site_id: SiteId) {
+        self.inner.add_site_id(site_id)
+    }
+
+    fn nested_validate_all(&self, site_id: SiteId) -> Result<(), Error> {
+        self.inner.validate_all(site_id)
+    }
+
+    fn nested_validate_no_unassigned_sites(&self) -> Result<(), Error> {
+        self.inner.validate_no_unassigned_sites()
+    }
+}
+
+impl<T: SetElement + Debug + 'static> NestedCrdt for Set<T> {
+    nested_crdt_impl!();
 }
 
 impl<T: SetElement> Inner<T> {
@@ -163,8 +236,9 @@ impl<T: SetElement> Inner<T> {
         }
     }
 
-    fn merge(&mut self,  other: Inner<T>, summary: &Summary, other_summary: &Summary) {
+    fn merge(&mut self,  other: Inner<T>, summary: &Summary, other_summary: &Summary) -> Vec<LocalOp<T>> {
         let mut other_elements = other.0;
+        let mut local_ops = vec![];
 
         // retain an element in self iff:
         // - the element is in in both self and other, OR
@@ -175,6 +249,10 @@ impl<T: SetElement> Inner<T> {
             other_dots.retain(|r| !dots.contains(r) && !summary.contains(r));
             dots.append(&mut other_dots);
             dots.sort();
+
+            if dots.is_empty() {
+                local_ops.push(LocalOp::Remove(value.clone()));
+            }
             !dots.is_empty()
         });
 
@@ -182,9 +260,58 @@ impl<T: SetElement> Inner<T> {
         for (value, mut dots) in other_elements.to_owned() {
             dots.retain(|r| !summary.contains(r));
             if !dots.is_empty() {
+                local_ops.push(LocalOp::Insert(value.clone()));
                 self.0.insert(value, dots);
             }
         }
+
+        local_ops
+    }
+
+    fn delta_since(&self, since: &Summary, _: &Summary) -> InnerDelta<T> {
+        let mut inserted = Inner::new();
+        let mut retained = vec![];
+
+        for (value, dots) in &self.0 {
+            let (seen, unseen): (Vec<Dot>, Vec<Dot>) = dots.iter().partition(|r| since.contains(r));
+            retained.extend(seen);
+            if !unseen.is_empty() {
+                inserted.0.insert(value.clone(), unseen);
+            }
+        }
+
+        InnerDelta{inserted, retained}
+    }
+
+    fn merge_delta(&mut self, delta: InnerDelta<T>, summary: &Summary, delta_summary: &Summary) -> Result<(), Error> {
+        if delta.retained.iter().any(|r| !summary.contains(r)) {
+            return Err(Error::InvalidDelta)
+        }
+
+        let retained: HashSet<Dot> = delta.retained.into_iter().collect();
+        let mut inserted = delta.inserted.0;
+
+        // retain an element in self iff:
+        // - the element is in the delta, OR
+        // - the element has not been inserted into the delta's source
+        self.0.retain(|value, dots| {
+            let mut inserted_dots = inserted.remove(value).unwrap_or_default();
+            dots.retain(|r| retained.contains(r) || inserted_dots.contains(r) || !delta_summary.contains(r));
+            inserted_dots.retain(|r| !dots.contains(r) && !summary.contains(r));
+            dots.append(&mut inserted_dots);
+            dots.sort();
+            !dots.is_empty()
+        });
+
+        // insert any element that is in the delta but not yet inserted into self
+        for (value, mut dots) in inserted {
+            dots.retain(|r| !summary.contains(r));
+            if !dots.is_empty() {
+                self.0.insert(value, dots);
+            }
+        }
+
+        Ok(())
     }
 
     fn add_site_id(&mut self, site_id: SiteId) {
@@ -206,12 +333,29 @@ impl<T: SetElement> Inner<T> {
         Ok(())
     }
 
+    fn validate_all(&self, site_id: SiteId) -> Result<(), Error> {
+        for dots in self.0.values() {
+            if dots.iter().any(|dot| dot.site_id != site_id) {
+                return Err(Error::InvalidSiteId);
+            }
+        }
+        Ok(())
+    }
+
 
     fn local_value(&self) -> HashSet<T> {
         self.0.keys().cloned().collect()
     }
 }
 
+impl<T: SetElement> InnerDelta<T> {
+    fn validate_no_unassigned_sites(&self) -> Result<(), Error> {
+        self.inserted.validate_no_unassigned_sites()?;
+        if self.retained.iter().any(|r| r.site_id == 0) { return Err(Error::InvalidSiteId) }
+        Ok(())
+    }
+}
+
 impl<T: SetElement> Op<T> {
     /// Returns the `Op`'s value.
     pub fn value(&self) -> &T { &self.value }
@@ -244,3 +388,23 @@ impl<T: SetElement> Op<T> {
         if let Some(dot) = self.inserted_dot { vec![dot] } else { vec![] }
     }
 }
+
+impl<T: SetElement> CausalOp for Op<T> {
+    fn inserted_dots(&self) -> Vec<Dot> {
+        Op::inserted_dots(self)
+    }
+
+    fn referenced_dots(&self) -> Vec<Dot> {
+        self.removed_dots.clone()
+    }
+}
+
+impl<T: SetElement> NestedOp for Op<T> {
+    fn nested_add_site_id(&mut self, site_id: SiteId) {
+        self.add_site_id(site_id)
+    }
+
+    fn nested_validate(&self, site_id: SiteId) -> Result<(), Error> {
+        self.validate(site_id)
+    }
+}
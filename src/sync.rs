@@ -0,0 +1,129 @@
+//! A transport-agnostic protocol for syncing two replicas.
+
+use Error;
+use dot::Summary;
+use traits::Crdt;
+
+/// A SyncSession brings two replicas of a CRDT up to date with
+/// each other. Ditto does not move any bytes itself; the session
+/// produces serializable [`Message`](enum.Message.html)s, and the
+/// caller sends each message to the other site and passes it to
+/// that site's session.
+///
+/// A sync always takes three messages:
+///
+///   1. The initiator calls [`start`](#method.start) and sends a
+///      `Request` with its summary.
+///   2. The responder receives the request and sends a `Reply`
+///      with a delta of the changes the initiator is missing, plus
+///      its own summary.
+///   3. The initiator merges the delta and sends a `Delta` of the
+///      changes the responder is missing.
+///
+/// After the responder receives the final delta, both replicas
+/// have the same value. Either replica may be edited while the
+/// session is in progress; edits that are not included in the
+/// session's deltas are picked up by the next sync.
+///
+/// ```
+/// use ditto::List;
+/// use ditto::sync::SyncSession;
+///
+/// let mut list1 = List::from(vec![1, 2]);
+/// let mut list2 = List::from_state(list1.clone_state(), Some(2)).unwrap();
+/// let _ = list1.push(3);
+/// let _ = list2.remove(0);
+///
+/// let mut session1 = SyncSession::new();
+/// let mut session2 = SyncSession::new();
+/// let request = session1.start(&list1);
+/// let reply = session2.receive(&mut list2, request).unwrap().unwrap();
+/// let delta = session1.receive(&mut list1, reply).unwrap().unwrap();
+/// assert!(session2.receive(&mut list2, delta).unwrap().is_none());
+///
+/// assert!(session1.is_done() && session2.is_done());
+/// assert_eq!(list1.local_value(), [2, 3]);
+/// assert_eq!(list2.local_value(), [2, 3]);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyncSession {
+    stage: Stage,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Stage {
+    Idle,
+    AwaitingReply,
+    AwaitingDelta,
+    Done,
+}
+
+/// A message sent between two sync sessions.
+#[derive(Serialize, Deserialize)]
+#[serde(bound = "")]
+pub enum Message<C: Crdt> {
+    /// Opens a sync with the initiator's summary.
+    Request(Summary),
+    /// Answers a request with the changes the initiator has not
+    /// seen and the responder's summary.
+    Reply{delta: C::Delta, summary: Summary},
+    /// Closes a sync with the changes the responder has not seen.
+    Delta(C::Delta),
+}
+
+impl SyncSession {
+
+    /// Constructs and returns a new SyncSession.
+    pub fn new() -> Self {
+        SyncSession{stage: Stage::Idle}
+    }
+
+    /// Returns true if the session has sent or received its
+    /// final message.
+    pub fn is_done(&self) -> bool {
+        self.stage == Stage::Done
+    }
+
+    /// Starts a sync and returns a request to send to the other
+    /// site. Starting a sync discards any sync in progress.
+    pub fn start<C: Crdt>(&mut self, crdt: &C) -> Message<C> {
+        self.stage = Stage::AwaitingReply;
+        Message::Request(crdt.summary().into_owned())
+    }
+
+    /// Receives a message from the other site and returns the
+    /// message to send back, if any. If the message does not
+    /// follow the protocol, it returns an `InvalidMessage` error.
+    /// If the message contains an invalid delta, it returns the
+    /// delta's error and the CRDT is left unchanged.
+    pub fn receive<C: Crdt>(&mut self, crdt: &mut C, message: Message<C>) -> Result<Option<Message<C>>, Error> {
+        match (self.stage, message) {
+            (Stage::Idle, Message::Request(summary)) |
+            (Stage::Done, Message::Request(summary)) => {
+                summary.validate_no_unassigned_sites()?;
+                self.stage = Stage::AwaitingDelta;
+                let delta = crdt.delta_since(&summary);
+                let summary = crdt.summary().into_owned();
+                Ok(Some(Message::Reply{delta, summary}))
+            }
+            (Stage::AwaitingReply, Message::Reply{delta, summary}) => {
+                summary.validate_no_unassigned_sites()?;
+                crdt.merge_delta(delta)?;
+                self.stage = Stage::Done;
+                Ok(Some(Message::Delta(crdt.delta_since(&summary))))
+            }
+            (Stage::AwaitingDelta, Message::Delta(delta)) => {
+                crdt.merge_delta(delta)?;
+                self.stage = Stage::Done;
+                Ok(None)
+            }
+            _ => Err(Error::InvalidMessage),
+        }
+    }
+}
+
+impl Default for SyncSession {
+    fn default() -> Self {
+        SyncSession::new()
+    }
+}
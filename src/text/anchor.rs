@@ -0,0 +1,65 @@
+use sequence::uid::Uid;
+
+/// An Anchor marks a position in a `Text` that moves with the
+/// surrounding text as local and remote edits are applied. It is
+/// tied to the uid of a text element and an offset within that
+/// element, so it can be stored by an editor (for a caret or the
+/// ends of a selection) or serialized and sent to other sites
+/// (for remote cursors).
+///
+/// Anchors are created with [`Text::anchor`](struct.Text.html#method.anchor)
+/// and resolved with [`Text::resolve_anchor`](struct.Text.html#method.resolve_anchor).
+/// When an edit splits or replaces the anchored element, the anchor
+/// follows its character into the element that now holds it. If the
+/// character has been removed, the anchor resolves to the position
+/// where the character used to be.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Anchor {
+    #[serde(rename = "u")]
+    pub(super) uid: Uid,
+    #[serde(rename = "o")]
+    pub(super) offset: usize,
+    #[serde(rename = "g")]
+    pub(super) gravity: Gravity,
+}
+
+/// Determines which side of an anchor text inserted exactly
+/// at the anchor's position ends up on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Gravity {
+    /// The anchor sticks to the character before it; text
+    /// inserted at the anchor is inserted after the anchor.
+    Left,
+    /// The anchor sticks to the character after it; text
+    /// inserted at the anchor is inserted before the anchor.
+    Right,
+}
+
+/// Records that the `len` bytes of an element starting at `at` are
+/// the bytes of the element `uid` starting at `offset`. Elements that
+/// split or replace other elements record where their text came from,
+/// so anchors into the old elements can be moved to the new ones.
+/// `uid` is always the first element that held the text.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Origin {
+    #[serde(rename = "a")]
+    pub(super) at: usize,
+    #[serde(rename = "u")]
+    pub(super) uid: Uid,
+    #[serde(rename = "o")]
+    pub(super) offset: usize,
+    #[serde(rename = "l")]
+    pub(super) len: usize,
+    /// Whether the bytes run to the end of the first element's
+    /// text. Only then may text appended by a merged local edit
+    /// extend the origin past them.
+    #[serde(rename = "e")]
+    pub(super) is_end: bool,
+}
+
+impl Anchor {
+    /// Returns the anchor's gravity.
+    pub fn gravity(&self) -> Gravity {
+        self.gravity
+    }
+}
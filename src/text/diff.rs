@@ -0,0 +1,216 @@
+//! Computes the replacements that turn one string into another.
+//! The common prefix and suffix are trimmed, then the rest is
+//! diffed by char with Myers' algorithm.
+
+use std::cmp::min;
+
+const MAX_EDIT_DISTANCE: isize = 1024;
+
+/// A replacement of the range [idx..<idx+len] of the old string.
+#[derive(Debug, PartialEq)]
+pub struct Hunk<'a> {
+    pub idx:  usize,
+    pub len:  usize,
+    pub text: &'a str,
+}
+
+/// Returns the hunks that turn `old` into `new`, measured in bytes
+/// and in ascending order.
+/// If the strings differ by more than `MAX_EDIT_DISTANCE` chars,
+/// everything between the common prefix and suffix is replaced
+/// by a single hunk.
+pub fn diff<'a>(old: &str, new: &'a str) -> Vec<Hunk<'a>> {
+    let prefix_len = old.char_indices().zip(new.chars())
+        .find(|&((_, a), b)| a != b)
+        .map(|((idx, _), _)| idx)
+        .unwrap_or_else(|| min(old.len(), new.len()));
+
+    let (old_rest, new_rest) = (&old[prefix_len..], &new[prefix_len..]);
+    let suffix_len = old_rest.chars().rev().zip(new_rest.chars().rev())
+        .take_while(|&(a, b)| a == b)
+        .map(|(a, _)| a.len_utf8())
+        .sum::<usize>();
+
+    let old_mid = &old_rest[..old_rest.len() - suffix_len];
+    let new_mid = &new_rest[..new_rest.len() - suffix_len];
+
+    if old_mid.is_empty() && new_mid.is_empty() {
+        return vec![]
+    }
+
+    let whole_hunk = Hunk{idx: prefix_len, len: old_mid.len(), text: new_mid};
+    if old_mid.is_empty() || new_mid.is_empty() {
+        return vec![whole_hunk]
+    }
+
+    let old_chars = CharOffsets::new(old_mid);
+    let new_chars = CharOffsets::new(new_mid);
+    let (deleted, inserted) = match myers(&old_chars.chars, &new_chars.chars) {
+        Some(edits) => edits,
+        None => return vec![whole_hunk],
+    };
+
+    let mut hunks = vec![];
+    let (mut i, mut j) = (0, 0);
+    while i < deleted.len() || j < inserted.len() {
+        let (start_i, start_j) = (i, j);
+        while i < deleted.len() && deleted[i] { i += 1 }
+        while j < inserted.len() && inserted[j] { j += 1 }
+
+        if i > start_i || j > start_j {
+            let idx = old_chars.offsets[start_i];
+            hunks.push(Hunk{
+                idx: prefix_len + idx,
+                len: old_chars.offsets[i] - idx,
+                text: &new_mid[new_chars.offsets[start_j]..new_chars.offsets[j]],
+            });
+        }
+
+        i += 1;
+        j += 1;
+    }
+
+    hunks
+}
+
+/// The chars of a string and the byte offset of each char,
+/// followed by the string's length.
+struct CharOffsets {
+    chars:   Vec<char>,
+    offsets: Vec<usize>,
+}
+
+impl CharOffsets {
+    fn new(string: &str) -> Self {
+        let (mut offsets, chars): (Vec<usize>, Vec<char>) = string.char_indices().unzip();
+        offsets.push(string.len());
+        CharOffsets{chars, offsets}
+    }
+}
+
+/// Returns which chars of `a` are deleted and which chars of `b`
+/// are inserted by a shortest edit script from `a` to `b`, or None
+/// if the script is longer than `MAX_EDIT_DISTANCE`.
+fn myers(a: &[char], b: &[char]) -> Option<(Vec<bool>, Vec<bool>)> {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let max = n + m;
+    let offset = max + 1;
+    let mut v = vec![0isize; 2 * max as usize + 3];
+    let mut trace: Vec<Vec<isize>> = vec![];
+
+    let mut distance = None;
+    'search: for d in 0..=max {
+        if d > MAX_EDIT_DISTANCE { return None }
+        trace.push(v[(offset - d) as usize..=(offset + d) as usize].to_vec());
+
+        for k in (-d..=d).step_by(2) {
+            let mut x = if k == -d || (k != d && v[(offset + k - 1) as usize] < v[(offset + k + 1) as usize]) {
+                v[(offset + k + 1) as usize]
+            } else {
+                v[(offset + k - 1) as usize] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[(offset + k) as usize] = x;
+            if x >= n && y >= m {
+                distance = Some(d);
+                break 'search;
+            }
+        }
+    }
+
+    let mut deleted = vec![false; a.len()];
+    let mut inserted = vec![false; b.len()];
+    let (mut x, mut y) = (n, m);
+
+    for d in (1..=distance?).rev() {
+        let v = &trace[d as usize];
+        let get = |k: isize| v[(k + d) as usize];
+        let k = x - y;
+        let prev_k = if k == -d || (k != d && get(k - 1) < get(k + 1)) { k + 1 } else { k - 1 };
+        let prev_x = get(prev_k);
+        let prev_y = prev_x - prev_k;
+
+        while x > prev_x && y > prev_y {
+            x -= 1;
+            y -= 1;
+        }
+
+        if x == prev_x {
+            inserted[prev_y as usize] = true;
+        } else {
+            deleted[prev_x as usize] = true;
+        }
+
+        x = prev_x;
+        y = prev_y;
+    }
+
+    Some((deleted, inserted))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diff_equal() {
+        assert!(diff("hello", "hello").is_empty());
+        assert!(diff("", "").is_empty());
+    }
+
+    #[test]
+    fn diff_insert_and_remove() {
+        assert_eq!(diff("hello", "hello world"), [hunk(5, 0, " world")]);
+        assert_eq!(diff("hello world", "world"), [hunk(0, 6, "")]);
+        assert_eq!(diff("", "abc"), [hunk(0, 0, "abc")]);
+    }
+
+    #[test]
+    fn diff_multiple_hunks() {
+        assert_eq!(diff("the quick fox", "the slow brown fox!"), [
+            hunk(4, 5, "slow"),
+            hunk(10, 0, "brown "),
+            hunk(13, 0, "!"),
+        ]);
+        assert_eq!(diff("abcdef", "aXcdeYf"), [hunk(1, 1, "X"), hunk(5, 0, "Y")]);
+    }
+
+    #[test]
+    fn diff_multibyte() {
+        assert_eq!(diff("añb", "aéb"), [hunk(1, 2, "é")]);
+        assert_eq!(diff("😀a😀", "😀b😀"), [hunk(4, 1, "b")]);
+    }
+
+    #[test]
+    fn diff_applies() {
+        let pairs = [
+            ("kitten", "sitting"),
+            ("abcabba", "cbabac"),
+            ("one two three", "three two one"),
+            ("αβγδ", "βγδε"),
+        ];
+
+        for &(old, new) in &pairs {
+            let mut string = old.to_owned();
+            for hunk in diff(old, new).into_iter().rev() {
+                string.replace_range(hunk.idx..hunk.idx + hunk.len, hunk.text);
+            }
+            assert_eq!(string, new);
+        }
+    }
+
+    #[test]
+    fn diff_too_distant() {
+        let old = "a".repeat(2000);
+        let new = "b".repeat(2000);
+        assert_eq!(diff(&old, &new), [hunk(0, 2000, &new)]);
+    }
+
+    fn hunk(idx: usize, len: usize, text: &str) -> Hunk<'_> {
+        Hunk{idx, len, text}
+    }
+}
@@ -0,0 +1,19 @@
+/// A zero-based line and column in a `Text`. The column is
+/// measured in the Text's index unit from the start of the line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Position {
+    pub line: usize,
+    pub col: usize,
+}
+
+/// A local change expressed as a line/column range, in the style
+/// of an LSP `TextEdit`: the text between `start` and `end` is
+/// replaced with `text`. A sequence of LineEdits must be applied in
+/// order; each edit's range refers to the text produced by the
+/// edits before it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LineEdit {
+    pub start: Position,
+    pub end: Position,
+    pub text: String,
+}
@@ -1,31 +1,65 @@
 //! A CRDT that stores mutable text
 
+mod anchor;
+mod diff;
+mod line;
 mod text_edit;
+mod undo;
+mod unit;
 
+use self::anchor::Origin;
+use self::diff::Hunk;
 use self::text_edit::TextEdit;
+use self::unit::ElementLen;
+pub use self::anchor::{Anchor, Gravity};
+pub use self::line::{LineEdit, Position};
+pub use self::undo::UndoManager;
+pub use self::unit::IndexUnit;
 use dot::{Dot, Summary, SiteId};
 use Error;
+use traits::*;
 use tree::{self, Tree};
 use sequence::uid::Uid;
 use std::borrow::Cow;
-use std::cmp::Ordering;
+use std::cmp::{max, min, Ordering};
+use std::collections::HashMap;
+use std::fmt;
+use std::mem;
+use std::ops::Range;
 
 pub type LocalOp = TextEdit;
 
 lazy_static! {
-    pub static ref START_ELEMENT: Element = Element{uid: Uid::min(), text: String::new()};
-    pub static ref END_ELEMENT: Element = Element{uid: Uid::max(), text: String::new()};
+    pub static ref START_ELEMENT: Element = Element::new(Uid::min(), String::new(), vec![]);
+    pub static ref END_ELEMENT: Element = Element::new(Uid::max(), String::new(), vec![]);
 }
 
 /// Text is a `String`-like UTF-encoded growable string.
 /// It contains a number of optimizations that improve
 /// replacement and op execution performance on large strings.
 ///
+/// The text can be read without copying it: [`chunks`](#method.chunks)
+/// borrows its internal strings, [`slice`](#method.slice) and
+/// [`char_at`](#method.char_at) only visit the part of the text they
+/// return, and the `Display` impl writes the text chunk by chunk.
+///
 /// Internally, Text is based on LSEQ. It allows op-based replication
 /// via [`execute_op`](#method.execute_op) and state-based replication
 /// via [`merge`](#method.merge). State-based replication allows
 /// out-of-order delivery but op-based replication does not.
 ///
+/// Edits made through an [`UndoManager`](struct.UndoManager.html)
+/// can be undone and redone without reverting remote edits.
+/// Positions that must follow the text as it changes, such as
+/// cursors and selections, can be held as [`Anchor`](struct.Anchor.html)s.
+/// When only the new contents are known, [`set_value`](#method.set_value)
+/// diffs them against the text and replaces just what changed.
+///
+/// Indexes and lengths are measured in UTF-8 bytes by default.
+/// Clients that count in chars or UTF-16 code units can switch
+/// units with [`set_index_unit`](#method.set_index_unit). Text can
+/// also be addressed by line and column, as code editors do.
+///
 /// Text has the following performance characteristics:
 ///
 /// * [`replace`](#method.replace): *O(log N)*
@@ -52,15 +86,78 @@ pub struct TextState<'a> {
     summary: Cow<'a, Summary>,
 }
 
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TextDelta {
+    #[serde(rename = "i")]
+    inner: InnerDelta,
+    #[serde(rename = "s")]
+    summary: Summary,
+}
+
+/// The text's elements, the last local edit, the index unit, the
+/// uids of the elements that hold text from each origin's uid, and
+/// the elements that share a dot.
 #[derive(Debug)]
-pub struct Inner(pub Tree<Element>, pub Option<TextEdit>);
+pub struct Inner(pub Tree<Element>, pub Option<TextEdit>, pub IndexUnit, HashMap<Uid, Vec<Uid>>, SharedDots);
+
+/// Earlier versions gave one dot to every element that an edit
+/// inserted, so states written by them have elements that share a
+/// dot. Once one of those elements is removed its dot is still in
+/// use, so its uid is kept until the last element with that dot is
+/// removed too.
+#[derive(Debug, Clone, Default)]
+struct SharedDots {
+    counts: HashMap<Dot, usize>,
+    removed: Vec<Uid>,
+}
+
+/// The elements a site has not seen, the dots of every removed
+/// element, and the uids of removed elements whose dot is still held
+/// by another element.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct InnerDelta {
+    #[serde(rename = "i")]
+    inserted: Vec<Element>,
+    #[serde(rename = "r")]
+    removed: Summary,
+    #[serde(rename = "u", default, skip_serializing_if = "Vec::is_empty")]
+    removed_uids: Vec<Uid>,
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(from = "ElementData")]
 pub struct Element {
     #[serde(rename = "u")]
     pub uid: Uid,
     #[serde(rename = "t")]
     pub text: String,
+    #[serde(rename = "o", default, skip_serializing_if = "Vec::is_empty")]
+    pub origins: Vec<Origin>,
+    /// The length of `text`, cached for the tree.
+    #[serde(skip)]
+    len: ElementLen,
+}
+
+/// An `Element` as it is serialized, without its cached length.
+#[derive(Deserialize)]
+struct ElementData {
+    #[serde(rename = "u")]
+    uid: Uid,
+    #[serde(rename = "t")]
+    text: String,
+    #[serde(rename = "o", default)]
+    origins: Vec<Origin>,
+}
+
+/// An iterator over the strings that make up a `Text`.
+/// Created by [`Text::chunks`](struct.Text.html#method.chunks).
+pub struct Chunks<'a>(tree::Iter<'a, Element>);
+
+/// The local changes made by executing an op or merging.
+#[derive(Default)]
+struct Changes {
+    local_ops: Vec<LocalOp>,
+    line_edits: Option<Vec<LineEdit>>,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -69,6 +166,8 @@ pub struct Op {
     inserted_elements: Vec<Element>,
     #[serde(rename = "r")]
     removed_uids: Vec<Uid>,
+    #[serde(rename = "d", default, skip_serializing_if = "Option::is_none")]
+    dot: Option<Dot>,
 }
 
 impl Text {
@@ -88,9 +187,25 @@ impl Text {
         text
     }
 
-    /// Returns the number of unicode characters in the text.
+    /// Returns the length of the text in the Text's index unit.
     pub fn len(&self) -> usize {
-        self.inner.0.len()
+        self.inner.len()
+    }
+
+    /// Returns the unit in which the Text's indexes and
+    /// lengths are measured.
+    pub fn index_unit(&self) -> IndexUnit {
+        self.inner.2
+    }
+
+    /// Sets the unit in which the Text's indexes and lengths are
+    /// measured. The unit applies to `len`, `replace`, anchors, and
+    /// the local ops returned by `execute_op` and `merge`. It is
+    /// local configuration and is not part of the Text's state;
+    /// a Text constructed from a deserialized state measures in bytes.
+    pub fn set_index_unit(&mut self, unit: IndexUnit) {
+        self.inner.2 = unit;
+        self.inner.1 = None;
     }
 
     /// Returns true if the Text CRDT has a length of 0.
@@ -100,16 +215,113 @@ impl Text {
     }
 
     /// Replaces the text in the range [idx..<idx+len] with new text.
+    /// The range is measured in the Text's index unit.
     /// Panics if the start or stop idx is larger than the `Text`'s
     /// length, or if it does not lie on a `char` boundary. If the
     /// Text does not have a site id, it caches the op and returns an
     /// `AwaitingSiteId` error.
     pub fn replace(&mut self, idx: usize, len: usize, text: &str) -> Option<Result<Op, Error>> {
-        let dot = self.summary.get_dot(self.site_id);
+        let dot = self.summary.next_dot(self.site_id);
         let op = self.inner.replace(idx, len, text, dot)?;
+        for dot in op.inserted_dots() {
+            self.summary.insert(dot);
+        }
         Some(self.after_op(op))
     }
 
+    /// Replaces the Text's contents with `value`. The current contents
+    /// are diffed against `value` and only the changed ranges are
+    /// replaced, so the returned ops merge with concurrent edits to
+    /// the unchanged text. Returns an empty Vec if the contents already
+    /// equal `value`. If the Text does not have a site id, it caches
+    /// the ops and returns an `AwaitingSiteId` error.
+    pub fn set_value(&mut self, value: &str) -> Result<Vec<Op>, Error> {
+        let mut ops = vec![];
+        let mut result = Ok(());
+
+        // don't merge into the previous edit, or its text would be resent
+        self.inner.1 = None;
+        for hunk in self.inner.diff(value) {
+            match self.replace(hunk.idx, hunk.len, hunk.text) {
+                Some(Ok(op)) => ops.push(op),
+                Some(Err(err)) => result = Err(err),
+                None => (),
+            }
+        }
+
+        result.map(|_| ops)
+    }
+
+    /// Returns an anchor at the given index. Panics if the index
+    /// is larger than the `Text`'s length.
+    pub fn anchor(&self, idx: usize, gravity: Gravity) -> Anchor {
+        self.inner.anchor(idx, gravity)
+    }
+
+    /// Returns the current index of an anchor.
+    pub fn resolve_anchor(&self, anchor: &Anchor) -> usize {
+        self.inner.resolve_anchor(anchor)
+    }
+
+    /// Returns an iterator over the strings that make up the text,
+    /// in order. Concatenated, they equal the text's local value.
+    pub fn chunks(&self) -> Chunks<'_> {
+        Chunks(self.inner.0.iter())
+    }
+
+    /// Returns the text in the given range, measured in the Text's
+    /// index unit. Panics if the range is out of bounds or does not
+    /// lie on `char` boundaries.
+    pub fn slice(&self, range: Range<usize>) -> String {
+        self.inner.slice(range)
+    }
+
+    /// Returns the char at the given index, measured in the Text's
+    /// index unit, or None if the index is out of bounds.
+    pub fn char_at(&self, idx: usize) -> Option<char> {
+        self.inner.char_at(idx)
+    }
+
+    /// Returns the number of lines in the text. An empty
+    /// text has one line.
+    pub fn line_count(&self) -> usize {
+        self.inner.line_count()
+    }
+
+    /// Returns the index of a line and column, or None if the
+    /// line does not exist or the column is past the line's end.
+    pub fn position_to_index(&self, line: usize, col: usize) -> Option<usize> {
+        self.inner.position_to_index(line, col)
+    }
+
+    /// Returns the line and column of an index. Panics if the
+    /// index is larger than the `Text`'s length.
+    pub fn index_to_position(&self, idx: usize) -> Position {
+        self.inner.index_to_position(idx)
+    }
+
+    /// Executes an op like [`execute_op`](#method.execute_op), but
+    /// returns the local changes as line/column edits.
+    pub fn execute_op_lines(&mut self, op: Op) -> Vec<LineEdit> {
+        for dot in op.inserted_dots() {
+            self.summary.insert(dot);
+        }
+        let mut changes = Changes{local_ops: vec![], line_edits: Some(vec![])};
+        self.inner.apply_op(op, &mut changes);
+        changes.line_edits.unwrap()
+    }
+
+    /// Merges a remote state like [`merge`](#method.merge), but
+    /// returns the local changes as line/column edits.
+    pub fn merge_lines(&mut self, other: TextState) -> Result<Vec<LineEdit>, Error> {
+        other.inner.validate_no_unassigned_sites()?;
+        other.summary.validate_no_unassigned_sites()?;
+        let mut changes = Changes{local_ops: vec![], line_edits: Some(vec![])};
+        self.inner.apply_merge(other.inner.into_owned(), &self.summary, &other.summary, &mut changes);
+        self.summary.merge(&other.summary);
+        Ok(changes.line_edits.unwrap())
+    }
+
     crdt_impl2! {
         Text,
         TextState,
@@ -118,7 +330,109 @@ impl Text {
         Inner,
         Op,
         Vec<LocalOp>,
+        LocalOp,
         String,
+        TextDelta,
+        TextDelta,
+    }
+}
+
+impl Crdt for Text {
+    crdt_trait_impl! {
+        Text,
+        TextState<'static>,
+        Op,
+        Vec<LocalOp>,
+        |edit| vec![edit],
+        String,
+        TextDelta,
+    }
+}
+
+impl Nested for Text {
+    type NestedOp = Op;
+    type NestedLocalOp = LocalOp;
+
+    // A nested Text keeps the dots of the ops that edited it in its
+    // own summary, so it can tell whether another site has seen them.
+
+    fn nested_reset(&mut self, dot: Dot) {
+        self.inner.reset(dot);
+        self.summary = Summary::default();
+        self.summary.insert(dot);
+        self.site_id = 0;
+        self.cached_ops.clear();
+    }
+
+    fn nested_execute_op(&mut self, op: Op) -> Vec<LocalOp> {
+        for dot in op.inserted_dots() {
+            self.summary.insert(dot);
+        }
+        self.inner.execute_op(op)
+    }
+
+    fn nested_merge(&mut self, other: Text, summary: &Summary, other_summary: &Summary) -> Vec<LocalOp> {
+        self.summary.merge(&other.summary);
+        self.inner.merge(other.inner, summary, other_summary)
+    }
+
+    fn nested_unchanged_since(&self, since: &Summary) -> bool {
+        self.summary.missing_from(since).is_empty()
+    }
+
+    fn nested_add_site_id(&mut self, site_id: SiteId) {
+        self.inner.add_site_id(site_id);
+        self.summary.add_site_id(site_id);
+    }
+
+    fn nested_validate_all(&self, site_id: SiteId) -> Result<(), Error> {
+        self.inner.validate_all(site_id)
+    }
+
+    fn nested_validate_no_unassigned_sites(&self) -> Result<(), Error> {
+        self.inner.validate_no_unassigned_sites()?;
+        self.summary.validate_no_unassigned_sites()
+    }
+}
+
+impl NestedCrdt for Text {
+    fn nested_edit<F>(&mut self, summary: &mut Summary, site_id: SiteId, f: F) -> Result<Op, Error>
+        where F: FnOnce(&mut Self) -> Result<Op, Error>
+    {
+        mem::swap(&mut self.summary, summary);
+        self.site_id = site_id;
+        let result = f(self);
+        mem::swap(&mut self.summary, summary);
+        self.site_id = 0;
+
+        let result = match self.cached_ops.pop() {
+            Some(op) => { self.cached_ops.clear(); Ok(op) }
+            None => result,
+        };
+
+        if let Ok(ref op) = result {
+            for dot in op.inserted_dots() {
+                self.summary.insert(dot);
+            }
+        }
+        result
+    }
+}
+
+impl fmt::Display for Text {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for chunk in self.chunks() {
+            f.write_str(chunk)?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a> Iterator for Chunks<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        self.0.next().map(|element| element.text.as_str())
     }
 }
 
@@ -130,15 +444,21 @@ impl<'a> From<&'a str> for Text {
 
 impl Inner {
     pub fn new() -> Self {
-        Inner(Tree::new(), None)
+        Inner(Tree::new(), None, IndexUnit::Byte, HashMap::new(), SharedDots::default())
     }
 
     pub fn len(&self) -> usize {
-        self.0.len()
+        let unit = self.2;
+        self.0.len_by(|len| unit.measure(len))
     }
 
 
     pub fn replace(&mut self, idx: usize, len: usize, text: &str, dot: Dot) -> Option<Op> {
+        self.replace_and_remove(idx, len, text, dot).map(|(op, _)| op)
+    }
+
+    /// Replaces text like `replace` and also returns the removed elements.
+    pub(crate) fn replace_and_remove(&mut self, idx: usize, len: usize, text: &str, dot: Dot) -> Option<(Op, Vec<Element>)> {
         if idx + len > self.len() {
             panic!("index is out of bounds");
         }
@@ -146,11 +466,15 @@ impl Inner {
             return None;
         }
 
+        let end = self.byte_idx(idx + len);
+        let idx = self.byte_idx(idx);
+        let len = end - idx;
+
         let merged_edit = self.gen_merged_edit(idx, len, text);
         let offset = self.get_element_offset(merged_edit.idx);
 
         if offset == 0 && merged_edit.len == 0 {
-            Some(self.do_insert(merged_edit.idx, merged_edit.text, dot))
+            Some((self.do_insert(merged_edit.idx, merged_edit.text, dot), vec![]))
         } else {
             Some(self.do_replace(merged_edit.idx, merged_edit.len, merged_edit.text, dot))
         }
@@ -163,11 +487,16 @@ impl Inner {
             Element::between(prev, next, text, dot)
         };
 
-        self.0.insert(element.clone()).unwrap();
-        Op{inserted_elements: vec![element], removed_uids: vec![]}
+        self.insert_indexed(element.clone()).unwrap();
+        Op{inserted_elements: vec![element], removed_uids: vec![], dot: None}
     }
 
-    pub fn do_replace(&mut self, idx: usize, len: usize, text: String, dot: Dot) -> Op {
+    /// Replaces text at a byte index. Each inserted element gets its
+    /// own dot, counting up from `dot`; if nothing is inserted, the
+    /// op records `dot` as the dot of the removal. Inserted elements
+    /// record the origins of the text they keep from the removed ones.
+    pub fn do_replace(&mut self, idx: usize, len: usize, text: String, dot: Dot) -> (Op, Vec<Element>) {
+        let mut dots = (dot.counter..).map(|counter| Dot::new(dot.site_id, counter));
         let (element, offset) = self.remove_at(idx);
         let border_idx = idx - offset;
         let mut removed_len = element.text.len() - offset;
@@ -186,11 +515,33 @@ impl Inner {
 
             if offset > 0 {
                 let text = removes[0].text[..offset].to_owned();
-                inserts.push(Element::between(prev, next, text, dot));
+                let mut element = Element::between(prev, next, text, dots.next().unwrap());
+                push_removed_origins(&removes, 0, offset, 0, &mut element.origins);
+                inserts.push(element);
             }
 
             if !text.is_empty() {
-                let element = Element::between(inserts.last().unwrap_or(prev), next, text, dot);
+                // merged local edits rewrite all the text they have
+                // typed so far; the text that begins and ends like
+                // the text it replaces keeps that text's origins.
+                let removed: String = removes.iter().map(|e| e.text.as_str()).collect();
+                let replaced = &removed[offset..offset + len];
+                let prefix_len = common_prefix_len(replaced, &text);
+                let suffix_len = common_suffix_len(&replaced[prefix_len..], &text[prefix_len..]);
+
+                let mut origins = vec![];
+                push_removed_origins(&removes, offset, prefix_len, 0, &mut origins);
+                if prefix_len == replaced.len() {
+                    if let Some(origin) = origins.last_mut() {
+                        if origin.is_end && origin.at + origin.len == prefix_len {
+                            origin.len += text.len() - prefix_len;
+                        }
+                    }
+                }
+                push_removed_origins(&removes, offset + len - suffix_len, suffix_len, text.len() - suffix_len, &mut origins);
+
+                let mut element = Element::between(inserts.last().unwrap_or(prev), next, text, dots.next().unwrap());
+                element.origins = origins;
                 inserts.push(element);
             }
 
@@ -198,41 +549,213 @@ impl Inner {
                 let old_elt = &removes.last().unwrap();
                 let offset  = old_elt.text.len() + len - removed_len;
                 let text    = old_elt.text[offset..].to_owned();
-                let element = Element::between(inserts.last().unwrap_or(prev), next, text, dot);
+                let mut element = Element::between(inserts.last().unwrap_or(prev), next, text, dots.next().unwrap());
+                old_elt.push_origins(offset, old_elt.text.len() - offset, 0, &mut element.origins);
                 inserts.push(element);
             }
         }
 
         for element in &inserts {
-            self.0.insert(element.clone()).unwrap();
+            self.insert_indexed(element.clone()).unwrap();
         }
 
-        let removed_uids = removes.into_iter().map(|e| e.uid).collect();
-        Op{inserted_elements: inserts, removed_uids}
+        let removed_uids = removes.iter().map(|e| e.uid.clone()).collect();
+        let dot = if inserts.is_empty() { Some(dot) } else { None };
+        (Op{inserted_elements: inserts, removed_uids, dot}, removes)
     }
 
-    pub fn execute_op(&mut self, op: Op) -> Vec<LocalOp> {
-        let mut local_ops = vec![];
+    /// Removes the elements with the given uids and re-inserts the
+    /// removed elements, undoing a local replace that has not been
+    /// sent to other sites.
+    pub(crate) fn rollback(&mut self, uids: &[Uid], elements: Vec<Element>) {
+        for uid in uids {
+            let _ = self.remove_indexed(uid);
+        }
+        for element in elements {
+            let _ = self.insert_indexed(element);
+        }
+        self.1 = None;
+    }
 
-        for uid in &op.removed_uids {
-            if let Some(idx) = self.0.get_idx(uid) {
-                let element = self.0.remove(uid).expect("Element must exist H!");
-                TextEdit::push(&mut local_ops, idx, element.text.len(), "");
+    /// Removes the elements with the given uids and re-inserts the
+    /// text of each removed element at its original position. Runs of
+    /// adjacent re-inserted elements are joined into a single element
+    /// with a new uid that records the origins of the run's text.
+    /// Elements get dots like in `do_replace`. Returns None if
+    /// nothing changes.
+    pub(crate) fn revert(&mut self, uids: &[Uid], elements: &[Element], dot: Dot) -> Option<(Op, Vec<Element>, Vec<LocalOp>)> {
+        let mut changes = Changes::default();
+        let removes: Vec<Element> = uids.iter()
+            .filter_map(|uid| self.remove_element(uid, &mut changes))
+            .collect();
+
+        // find the existing neighbors of each run of elements
+        let mut placeholders: Vec<&Element> = elements.iter()
+            .filter(|e| self.0.insert((*e).clone()).is_ok())
+            .collect();
+        placeholders.sort();
+
+        let mut runs: Vec<(Uid, Uid, String, Vec<Origin>)> = vec![];
+        let mut run_end = None;
+        for element in &placeholders {
+            let idx = self.0.get_idx(&element.uid).expect("Element must exist N!");
+            if run_end != Some(idx) {
+                let prev = self.get_prev_element(idx).uid.clone();
+                runs.push((prev, Uid::max(), String::new(), vec![]));
+            }
+            {
+                let run = runs.last_mut().unwrap();
+                element.push_origins(0, element.text.len(), run.2.len(), &mut run.3);
+                run.2.push_str(&element.text);
+            }
+            run_end = Some(idx + element.text.len());
+            runs.last_mut().unwrap().1 = self.get_element(idx + element.text.len()).uid.clone();
+        }
+
+        for element in placeholders {
+            let _ = self.0.remove(&element.uid);
+        }
+
+        let mut inserts = vec![];
+        let mut dots = (dot.counter..).map(|counter| Dot::new(dot.site_id, counter));
+        for (prev, next, text, origins) in runs {
+            let element = Element::new(Uid::between(&prev, &next, dots.next().unwrap()), text, origins);
+            assert!(self.insert_element(element.clone(), &mut changes), "Element must not exist O!");
+            inserts.push(element);
+        }
+
+        self.1 = None;
+        if removes.is_empty() && inserts.is_empty() { return None }
+        let removed_uids = removes.iter().map(|e| e.uid.clone()).collect();
+        let dot = if inserts.is_empty() { Some(dot) } else { None };
+        Some((Op{inserted_elements: inserts, removed_uids, dot}, removes, changes.local_ops))
+    }
+
+    /// Returns the replacements that turn the text into `value`,
+    /// measured in the index unit. They are in descending order so
+    /// that each can be applied without shifting the ones after it.
+    pub(crate) fn diff<'a>(&self, value: &'a str) -> Vec<Hunk<'a>> {
+        let old = self.local_value();
+        let unit = self.2;
+        let mut hunks = vec![];
+        let (mut byte_idx, mut unit_idx) = (0, 0);
+
+        for hunk in diff::diff(&old, value) {
+            unit_idx += unit.len(&old[byte_idx..hunk.idx]);
+            let len = unit.len(&old[hunk.idx..hunk.idx + hunk.len]);
+            hunks.push(Hunk{idx: unit_idx, len, text: hunk.text});
+            byte_idx = hunk.idx + hunk.len;
+            unit_idx += len;
+        }
+
+        hunks.reverse();
+        hunks
+    }
+
+    pub fn anchor(&self, idx: usize, gravity: Gravity) -> Anchor {
+        if idx > self.len() {
+            panic!("index is out of bounds");
+        }
+
+        let idx = self.byte_idx(idx);
+        let (uid, offset) = match gravity {
+            Gravity::Left if idx == 0 => (Uid::min(), 0),
+            Gravity::Right if idx == self.0.len() => (Uid::max(), 0),
+            Gravity::Left => {
+                let (element, offset) = self.0.get_elt(idx - 1).unwrap();
+                element.anchor_point(offset + 1, gravity)
+            }
+            Gravity::Right => {
+                let (element, offset) = self.0.get_elt(idx).unwrap();
+                element.anchor_point(offset, gravity)
             }
+        };
+
+        Anchor{uid, offset, gravity}
+    }
+
+    pub fn resolve_anchor(&self, anchor: &Anchor) -> usize {
+        let unit = self.2;
+        let found = match self.0.lookup(&anchor.uid) {
+            Some(element) => Some((element, anchor.offset)),
+            None => self.find_origin(anchor),
+        };
+
+        match found {
+            Some((element, offset)) => {
+                let mut offset = min(offset, element.text.len());
+                while !element.text.is_char_boundary(offset) { offset -= 1; }
+                self.unit_idx(&element.uid).unwrap() + unit.len(&element.text[..offset])
+            }
+            None => self.0.get_insert_idx_by(&anchor.uid, |len| unit.measure(len)),
         }
+    }
 
-        for element in &op.inserted_elements {
-            if self.0.insert(element.clone()).is_ok() {
-                let idx = self.0.get_idx(&element.uid).expect("Element must exist I!");
-                TextEdit::push(&mut local_ops, idx, 0, &element.text);
+    /// Finds the element that holds the anchor's character after
+    /// the anchored element has been split or replaced. If that
+    /// character has been removed, returns the nearest position in
+    /// the elements that hold the anchored element's other text,
+    /// preferring the side given by the anchor's gravity.
+    fn find_origin(&self, anchor: &Anchor) -> Option<(&Element, usize)> {
+        let offset = anchor.offset;
+        let mut before: Option<(&Element, usize, usize)> = None;
+        let mut after: Option<(&Element, usize, usize)> = None;
+
+        for uid in self.3.get(&anchor.uid)? {
+            let element = self.0.lookup(uid).expect("Element must exist P!");
+            for origin in element.origins.iter().filter(|o| o.uid == anchor.uid) {
+                let (lo, hi) = (origin.offset, origin.offset + origin.len);
+                let contains = match anchor.gravity {
+                    Gravity::Left => lo < offset && offset <= hi,
+                    Gravity::Right => lo <= offset && offset < hi,
+                };
+
+                if contains {
+                    return Some((element, origin.at + offset - lo))
+                } else if hi <= offset && before.map(|(_, _, end)| hi > end).unwrap_or(true) {
+                    before = Some((element, origin.at + origin.len, hi));
+                } else if lo >= offset && after.map(|(_, _, start)| lo < start).unwrap_or(true) {
+                    after = Some((element, origin.at, lo));
+                }
             }
         }
 
-        self.shift_merged_edit(&local_ops);
-        local_ops
+        let (before, after) = (before.map(|(e, at, _)| (e, at)), after.map(|(e, at, _)| (e, at)));
+        match anchor.gravity {
+            Gravity::Left => before.or(after),
+            Gravity::Right => after.or(before),
+        }
+    }
+
+    pub fn execute_op(&mut self, op: Op) -> Vec<LocalOp> {
+        let mut changes = Changes::default();
+        self.apply_op(op, &mut changes);
+        changes.local_ops
     }
 
-    pub fn merge(&mut self, other: Inner, summary: &Summary, other_summary: &Summary) {
+    fn apply_op(&mut self, op: Op, changes: &mut Changes) {
+        for uid in &op.removed_uids {
+            let _ = self.remove_element(uid, changes);
+        }
+
+        for element in op.inserted_elements {
+            let _ = self.insert_element(element, changes);
+        }
+
+        if self.2 == IndexUnit::Byte {
+            self.shift_merged_edit(&changes.local_ops);
+        } else {
+            self.1 = None;
+        }
+    }
+
+    pub fn merge(&mut self, other: Inner, summary: &Summary, other_summary: &Summary) -> Vec<LocalOp> {
+        let mut changes = Changes::default();
+        self.apply_merge(other, summary, other_summary, &mut changes);
+        changes.local_ops
+    }
+
+    fn apply_merge(&mut self, other: Inner, summary: &Summary, other_summary: &Summary, changes: &mut Changes) {
         // ids that are in other_summary and not in other
         let removed_uids: Vec<Uid> = self.0.iter()
             .filter(|e| other.0.get_idx(&e.uid).is_none() && other_summary.contains(&e.uid.dot()))
@@ -245,26 +768,200 @@ impl Inner {
             .map(|e| e.clone())
             .collect();
 
+        for uid in &removed_uids {
+            self.remove_element(uid, changes).expect("Element must exist J!");
+        }
+
+        for element in new_elements {
+            let _ = self.insert_element(element, changes);
+        }
+
+        self.1 = None;
+    }
+
+    pub fn slice(&self, range: Range<usize>) -> String {
+        if range.start > range.end || range.end > self.len() {
+            panic!("index is out of bounds");
+        }
+
+        let start = self.byte_idx(range.start);
+        let end = self.byte_idx(range.end);
+        let mut string = String::with_capacity(end - start);
+        let mut offset = self.0.get_elt(start).map(|(_, offset)| offset).unwrap_or(0);
+        let mut remaining = end - start;
+
+        for element in self.0.iter_from(start) {
+            if remaining == 0 { break }
+            let hi = min(element.text.len(), offset + remaining);
+            string.push_str(&element.text[offset..hi]);
+            remaining -= hi - offset;
+            offset = 0;
+        }
+
+        string
+    }
+
+    pub fn char_at(&self, idx: usize) -> Option<char> {
+        if idx >= self.len() { return None }
+        let (element, offset) = self.0.get_elt(self.byte_idx(idx)).ok()?;
+        element.text[offset..].chars().next()
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.0.len_by(ElementLen::newlines) + 1
+    }
+
+    pub fn position_to_index(&self, line: usize, col: usize) -> Option<usize> {
+        let line_start = self.unit_idx_of_byte(self.line_start(line)?);
+        let line_end = match self.line_start(line + 1) {
+            Some(next_line_start) => self.unit_idx_of_byte(next_line_start - 1),
+            None => self.len(),
+        };
+
+        if line_start + col > line_end { return None }
+        Some(line_start + col)
+    }
+
+    pub fn index_to_position(&self, idx: usize) -> Position {
+        if idx > self.len() {
+            panic!("index is out of bounds");
+        }
+        self.position_of_byte(self.byte_idx(idx))
+    }
+
+    /// Removes an element and records the change.
+    /// Returns None if the element does not exist.
+    fn remove_element(&mut self, uid: &Uid, changes: &mut Changes) -> Option<Element> {
+        let idx = self.unit_idx(uid)?;
+        if let Some(ref mut line_edits) = changes.line_edits {
+            let start = self.0.get_idx(uid).unwrap();
+            let end = start + self.0.lookup(uid).unwrap().text.len();
+            line_edits.push(LineEdit{start: self.position_of_byte(start), end: self.position_of_byte(end), text: String::new()});
+        }
+
+        let element = self.remove_indexed(uid).expect("Element must exist H!");
+        TextEdit::push(&mut changes.local_ops, self.2, idx, self.2.len(&element.text), "");
+        Some(element)
+    }
+
+    /// Inserts an element and records the change.
+    /// Returns false if the element already exists.
+    fn insert_element(&mut self, element: Element, changes: &mut Changes) -> bool {
+        let uid = element.uid.clone();
+        let text = element.text.clone();
+        if self.insert_indexed(element).is_err() { return false }
+
+        let idx = self.unit_idx(&uid).expect("Element must exist I!");
+        TextEdit::push(&mut changes.local_ops, self.2, idx, 0, &text);
+        if let Some(ref mut line_edits) = changes.line_edits {
+            let start = self.position_of_byte(self.0.get_idx(&uid).unwrap());
+            line_edits.push(LineEdit{start, end: start, text});
+        }
+        true
+    }
+
+    /// Returns the elements that a site with summary `since` has not
+    /// seen, the dots in `summary` that no longer have an element, and
+    /// the removed elements whose dot is still held by another element.
+    pub(crate) fn delta_since(&self, since: &Summary, summary: &Summary) -> InnerDelta {
+        let inserted = self.0.iter()
+            .filter(|e| !since.contains(&e.uid.dot()))
+            .cloned()
+            .collect();
+
+        let removed = summary.without(self.0.iter().map(|e| e.uid.dot()).collect());
+        let removed_uids = self.4.removed.iter()
+            .filter(|uid| since.contains(&uid.dot()))
+            .cloned()
+            .collect();
+
+        InnerDelta{inserted, removed, removed_uids}
+    }
+
+    pub(crate) fn merge_delta(&mut self, delta: InnerDelta, summary: &Summary, delta_summary: &Summary) -> Result<(), Error> {
+        self.validate_delta(&delta, summary, delta_summary)?;
+
+        let mut removed_uids: Vec<Uid> = self.0.iter()
+            .filter(|e| delta.removed.contains(&e.uid.dot()))
+            .map(|e| e.uid.clone())
+            .collect();
+        removed_uids.extend(delta.removed_uids);
+
+        // ids that are not in self and not in summary
+        let new_elements: Vec<Element> = delta.inserted.into_iter()
+            .filter(|e| self.0.get_idx(&e.uid).is_none() && !summary.contains(&e.uid.dot()))
+            .collect();
+
         for uid in removed_uids {
-            let _ = self.0.remove(&uid);
+            let _ = self.remove_indexed(&uid);
         }
 
         for element in new_elements {
-            let _ = self.0.insert(element);
+            let _ = self.insert_indexed(element);
         }
 
         self.1 = None;
+        Ok(())
+    }
+
+    /// Every dot the sender has seen must either be known here, be
+    /// one of the delta's inserted elements, or be removed by the
+    /// delta. Otherwise the delta was computed for a different
+    /// summary and elements the sender assumed were here are missing.
+    pub(crate) fn validate_delta(&self, delta: &InnerDelta, summary: &Summary, delta_summary: &Summary) -> Result<(), Error> {
+        let mut known = summary.clone();
+        known.merge(&delta.removed);
+        for element in &delta.inserted {
+            known.insert(element.uid.dot());
+        }
+        if delta_summary.missing_from(&known).is_empty() { Ok(()) } else { Err(Error::InvalidDelta) }
+    }
+
+    /// Replaces the text's elements with a single element
+    /// that has the dot `dot`.
+    pub(crate) fn reset(&mut self, dot: Dot) {
+        let text = self.local_value();
+        let unit = self.2;
+        *self = Inner::new();
+        self.2 = unit;
+        let _ = self.replace(0, 0, &text, dot);
+        self.1 = None;
     }
 
     pub fn add_site_id(&mut self, site_id: SiteId) {
-        let uids: Vec<Uid> = self.0.iter().filter(|e| e.uid.site_id == 0).map(|e| e.uid.clone()).collect();
+        let uids: Vec<Uid> = self.0.iter()
+            .filter(|e| e.uid.site_id == 0 || e.origins.iter().any(|o| o.uid.site_id == 0))
+            .map(|e| e.uid.clone())
+            .collect();
+
         for uid in uids {
-            let mut element = self.0.remove(&uid).unwrap();
-            element.uid.site_id = site_id;
-            self.0.insert(element).unwrap();
+            let mut element = self.remove_indexed(&uid).unwrap();
+            element.add_site_id(site_id);
+            self.insert_indexed(element).unwrap();
         }
     }
 
+    /// Inserts an element into the tree and indexes its origins
+    /// and dot.
+    fn insert_indexed(&mut self, element: Element) -> Result<(), Error> {
+        index_origins(&mut self.3, &element);
+        if let Err(err) = self.0.insert(element.clone()) {
+            unindex_origins(&mut self.3, &element);
+            return Err(err)
+        }
+        self.4.insert(&element.uid);
+        Ok(())
+    }
+
+    /// Removes an element from the tree and from the origin and
+    /// dot indexes.
+    fn remove_indexed(&mut self, uid: &Uid) -> Option<Element> {
+        let element = self.0.remove(uid)?;
+        unindex_origins(&mut self.3, &element);
+        self.4.remove(uid);
+        Some(element)
+    }
+
     pub fn validate_no_unassigned_sites(&self) -> Result<(), Error> {
         if self.0.iter().any(|e| e.uid.site_id == 0) {
             Err(Error::InvalidSiteId)
@@ -294,12 +991,12 @@ impl Inner {
             let (element, offset) = self.0.get_elt(idx).expect("Element must exist for Uid!");
             (element.uid.clone(), offset)
         };
-        let element = self.0.remove(&uid).expect("Element must exist for Uid!");
+        let element = self.remove_indexed(&uid).expect("Element must exist for Uid!");
         (element, offset)
     }
 
     fn get_element(&self, idx: usize) -> &Element {
-        if idx == self.len() { return &*END_ELEMENT }
+        if idx == self.0.len() { return &*END_ELEMENT }
         self.0.get_elt(idx).unwrap().0
     }
 
@@ -309,10 +1006,58 @@ impl Inner {
     }
 
     fn get_element_offset(&self, idx: usize) -> usize {
-        if idx == self.len() { return 0 }
+        if idx == self.0.len() { return 0 }
         self.0.get_elt(idx).unwrap().1
     }
 
+    /// Returns the index of an element in the index unit.
+    fn unit_idx(&self, uid: &Uid) -> Option<usize> {
+        let unit = self.2;
+        self.0.get_idx_by(uid, |len| unit.measure(len))
+    }
+
+    /// Converts a byte index to an index in the index unit.
+    fn unit_idx_of_byte(&self, byte_idx: usize) -> usize {
+        if self.2 == IndexUnit::Byte { return byte_idx }
+        if byte_idx == self.0.len() { return self.len() }
+
+        let (element, offset) = self.0.get_elt(byte_idx).unwrap();
+        self.unit_idx(&element.uid).unwrap() + self.2.len(&element.text[..offset])
+    }
+
+    /// Returns the byte index of the start of a line,
+    /// or None if the line does not exist.
+    fn line_start(&self, line: usize) -> Option<usize> {
+        if line == 0 { return Some(0) }
+        let (element, offset) = self.0.get_elt_by(line - 1, ElementLen::newlines).ok()?;
+        let (newline_offset, _) = element.text.match_indices('\n').nth(offset).unwrap();
+        Some(self.0.get_idx(&element.uid).unwrap() + newline_offset + 1)
+    }
+
+    fn position_of_byte(&self, byte_idx: usize) -> Position {
+        let line = if byte_idx == self.0.len() {
+            self.line_count() - 1
+        } else {
+            let (element, offset) = self.0.get_elt(byte_idx).unwrap();
+            let newlines_before = self.0.get_idx_by(&element.uid, ElementLen::newlines).unwrap();
+            newlines_before + element.text[..offset].matches('\n').count()
+        };
+
+        let line_start = self.line_start(line).unwrap();
+        let col = self.unit_idx_of_byte(byte_idx) - self.unit_idx_of_byte(line_start);
+        Position{line, col}
+    }
+
+    /// Converts an index in the index unit to a byte index.
+    fn byte_idx(&self, idx: usize) -> usize {
+        let unit = self.2;
+        if unit == IndexUnit::Byte { return idx }
+        if idx == self.len() { return self.0.len() }
+
+        let (element, offset) = self.0.get_elt_by(idx, |len| unit.measure(len)).unwrap();
+        self.0.get_idx(&element.uid).unwrap() + unit.element_byte_offset(&element.text, element.len, offset)
+    }
+
     fn gen_merged_edit(&mut self, idx: usize, len: usize, text: &str) -> TextEdit {
         if let Some(ref mut old_edit) = self.1 {
             if old_edit.try_overwrite(idx, len, text) {
@@ -336,26 +1081,68 @@ impl Inner {
     }
 }
 
+impl SharedDots {
+    fn insert(&mut self, uid: &Uid) {
+        *self.counts.entry(uid.dot()).or_insert(0) += 1;
+        if !self.removed.is_empty() {
+            self.removed.retain(|removed| removed != uid);
+        }
+    }
+
+    fn remove(&mut self, uid: &Uid) {
+        let dot = uid.dot();
+        let count = match self.counts.get_mut(&dot) {
+            Some(count) => { *count -= 1; *count }
+            None => return,
+        };
+        if count == 0 {
+            let _ = self.counts.remove(&dot);
+            if !self.removed.is_empty() {
+                self.removed.retain(|removed| removed.dot() != dot);
+            }
+        } else if !self.removed.contains(uid) {
+            self.removed.push(uid.clone());
+        }
+    }
+}
+
+impl InnerDelta {
+    pub fn validate_no_unassigned_sites(&self) -> Result<(), Error> {
+        if self.inserted.iter().any(|e| e.uid.site_id == 0) || self.removed_uids.iter().any(|uid| uid.site_id == 0) {
+            Err(Error::InvalidSiteId)
+        } else {
+            self.removed.validate_no_unassigned_sites()
+        }
+    }
+}
+
 impl Op {
     pub fn add_site_id(&mut self, site_id: SiteId) {
         for e in &mut self.inserted_elements {
-            if e.uid.site_id == 0 { e.uid.site_id = site_id };
+            e.add_site_id(site_id);
         }
         for uid in &mut self.removed_uids {
             if uid.site_id == 0 { uid.site_id = site_id };
         }
+        if let Some(ref mut dot) = self.dot {
+            if dot.site_id == 0 { dot.site_id = site_id };
+        }
     }
 
     pub fn validate(&self, site_id: SiteId) -> Result<(), Error> {
-        if self.inserted_elements.iter().any(|e| e.uid.site_id != site_id) {
+        if self.inserted_dots().iter().any(|dot| dot.site_id != site_id) {
             Err(Error::InvalidOp)
         } else {
             Ok(())
         }
     }
 
+    /// Returns the dots of the inserted elements, or the dot of
+    /// the removal if the op only removes text.
     pub fn inserted_dots(&self) -> Vec<Dot> {
-        self.inserted_elements.iter().map(|elt| elt.uid.dot()).collect()
+        let mut dots: Vec<Dot> = self.inserted_elements.iter().map(|elt| elt.uid.dot()).collect();
+        dots.extend(self.dot);
+        dots
     }
 
     #[doc(hidden)]
@@ -369,12 +1156,153 @@ impl Op {
     }
 }
 
+impl CausalOp for Op {
+    fn inserted_dots(&self) -> Vec<Dot> {
+        let mut dots = Op::inserted_dots(self);
+        dots.dedup();
+        dots
+    }
+
+    fn referenced_dots(&self) -> Vec<Dot> {
+        self.removed_uids.iter().map(|uid| uid.dot()).collect()
+    }
+}
+
+impl NestedOp for Op {
+    fn nested_add_site_id(&mut self, site_id: SiteId) {
+        self.add_site_id(site_id)
+    }
+
+    fn nested_validate(&self, site_id: SiteId) -> Result<(), Error> {
+        self.validate(site_id)
+    }
+}
+
 impl Element {
+    fn new(uid: Uid, text: String, origins: Vec<Origin>) -> Self {
+        let len = ElementLen::new(&text);
+        Element{uid, text, origins, len}
+    }
+
     fn between(elt1: &Element, elt2: &Element, text: String, dot: Dot) -> Self {
-        Element{text, uid: Uid::between(&elt1.uid, &elt2.uid, dot)}
+        Element::new(Uid::between(&elt1.uid, &elt2.uid, dot), text, vec![])
+    }
+
+    /// Pushes the origins of `len` bytes of the element's text,
+    /// starting at `offset`, for text that is moved to `at` in
+    /// another element. Text without an origin came from this element.
+    fn push_origins(&self, offset: usize, len: usize, at: usize, origins: &mut Vec<Origin>) {
+        if len == 0 { return }
+        if self.origins.is_empty() {
+            let is_end = offset + len == self.text.len();
+            return push_origin(origins, Origin{at, uid: self.uid.clone(), offset, len, is_end})
+        }
+
+        for origin in &self.origins {
+            let lo = max(origin.at, offset);
+            let hi = min(origin.at + origin.len, offset + len);
+            if lo >= hi { continue }
+            push_origin(origins, Origin{
+                at: at + lo - offset,
+                uid: origin.uid.clone(),
+                offset: origin.offset + lo - origin.at,
+                len: hi - lo,
+                is_end: origin.is_end && hi == origin.at + origin.len,
+            });
+        }
+    }
+
+    /// Returns the uid and offset that anchor a position in this
+    /// element. Positions in text with an origin are anchored to
+    /// the origin, so they survive later splits and replacements.
+    fn anchor_point(&self, offset: usize, gravity: Gravity) -> (Uid, usize) {
+        let found = self.origins.iter().find(|o| match gravity {
+            Gravity::Left => o.at < offset && offset <= o.at + o.len,
+            Gravity::Right => o.at <= offset && offset < o.at + o.len,
+        });
+
+        match found {
+            Some(origin) => (origin.uid.clone(), origin.offset + offset - origin.at),
+            None => (self.uid.clone(), offset),
+        }
+    }
+
+    fn add_site_id(&mut self, site_id: SiteId) {
+        if self.uid.site_id == 0 { self.uid.site_id = site_id; }
+        for origin in &mut self.origins {
+            if origin.uid.site_id == 0 { origin.uid.site_id = site_id; }
+        }
+    }
+}
+
+/// Pushes an origin, joining it with the last origin if
+/// the two cover adjacent text from the same element.
+fn push_origin(origins: &mut Vec<Origin>, origin: Origin) {
+    if let Some(last) = origins.last_mut() {
+        if last.uid == origin.uid && last.at + last.len == origin.at && last.offset + last.len == origin.offset {
+            last.len += origin.len;
+            last.is_end = origin.is_end;
+            return
+        }
+    }
+    origins.push(origin);
+}
+
+/// Pushes the origins of `len` bytes of the removed elements'
+/// joined text, starting at `offset`, for text moved to `at`.
+fn push_removed_origins(removes: &[Element], offset: usize, len: usize, at: usize, origins: &mut Vec<Origin>) {
+    let mut start = 0;
+    for element in removes {
+        let end = start + element.text.len();
+        let lo = max(start, offset);
+        let hi = min(end, offset + len);
+        if lo < hi {
+            element.push_origins(lo - start, hi - lo, at + lo - offset, origins);
+        }
+        start = end;
     }
 }
 
+/// Indexes an element under the uid of each of its origins.
+fn index_origins(index: &mut HashMap<Uid, Vec<Uid>>, element: &Element) {
+    for origin in &element.origins {
+        let uids = index.entry(origin.uid.clone()).or_default();
+        if uids.last() != Some(&element.uid) {
+            uids.push(element.uid.clone());
+        }
+    }
+}
+
+/// Removes an element from the origin index.
+fn unindex_origins(index: &mut HashMap<Uid, Vec<Uid>>, element: &Element) {
+    for origin in &element.origins {
+        let is_empty = match index.get_mut(&origin.uid) {
+            Some(uids) => { uids.retain(|uid| *uid != element.uid); uids.is_empty() }
+            None => false,
+        };
+        if is_empty { let _ = index.remove(&origin.uid); }
+    }
+}
+
+/// Returns the length in bytes of the longest common
+/// prefix of two strings.
+fn common_prefix_len(a: &str, b: &str) -> usize {
+    a.char_indices()
+        .zip(b.chars())
+        .find(|&((_, c1), c2)| c1 != c2)
+        .map_or(min(a.len(), b.len()), |((idx, _), _)| idx)
+}
+
+/// Returns the length in bytes of the longest common
+/// suffix of two strings.
+fn common_suffix_len(a: &str, b: &str) -> usize {
+    a.chars().rev()
+        .zip(b.chars().rev())
+        .take_while(|&(c1, c2)| c1 == c2)
+        .map(|(c, _)| c.len_utf8())
+        .sum()
+}
+
 impl PartialEq for Element {
     fn eq(&self, other: &Element) -> bool {
         self.uid.eq(&other.uid)
@@ -397,13 +1325,20 @@ impl Ord for Element {
 
 impl tree::Element for Element {
     type Id = Uid;
+    type Len = ElementLen;
 
     fn id(&self) -> &Uid {
         &self.uid
     }
 
-    fn element_len(&self) -> usize {
-        self.text.len()
+    fn element_len(&self) -> ElementLen {
+        self.len
+    }
+}
+
+impl From<ElementData> for Element {
+    fn from(data: ElementData) -> Self {
+        Element::new(data.uid, data.text, data.origins)
     }
 }
 
@@ -411,7 +1346,7 @@ use serde::{Serialize, Deserialize, Serializer, Deserializer};
 
 impl Clone for Inner {
     fn clone(&self) -> Self {
-        Inner(self.0.clone(), None)
+        Inner(self.0.clone(), None, self.2, self.3.clone(), self.4.clone())
     }
 }
 
@@ -421,15 +1356,32 @@ impl PartialEq for Inner {
     }
 }
 
+/// Removed elements whose dot is still in use are written after the
+/// text's elements as elements with no text.
 impl Serialize for Inner {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error> where S: Serializer {
-        self.0.serialize(serializer)
+        let removed: Vec<Element> = self.4.removed.iter()
+            .map(|uid| Element::new(uid.clone(), String::new(), vec![]))
+            .collect();
+        self.0.iter().chain(&removed).collect::<Vec<&Element>>().serialize(serializer)
     }
 }
 
 impl<'de> Deserialize<'de> for Inner {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error> where D: Deserializer<'de> {
-        let tree: Tree<Element> = Tree::deserialize(deserializer)?;
-        Ok(Inner(tree, None))
+        let elements: Vec<Element> = Vec::deserialize(deserializer)?;
+        let (removed, elements): (Vec<Element>, Vec<Element>) = elements.into_iter().partition(|e| e.text.is_empty());
+        let tree: Tree<Element> = elements.into_iter().collect();
+        let mut origins = HashMap::new();
+        let mut shared_dots = SharedDots::default();
+        for element in tree.iter() {
+            index_origins(&mut origins, element);
+            shared_dots.insert(&element.uid);
+        }
+        shared_dots.removed = removed.into_iter()
+            .map(|e| e.uid)
+            .filter(|uid| shared_dots.counts.contains_key(&uid.dot()))
+            .collect();
+        Ok(Inner(tree, None, IndexUnit::Byte, origins, shared_dots))
     }
 }
@@ -1,4 +1,5 @@
 use std::cmp::min;
+use super::IndexUnit;
 
 const MAX_EDIT_LEN: usize = 64;
 
@@ -38,18 +39,27 @@ impl TextEdit {
     /// this is a straightforward merge of the effects of the new edit
     /// into the effects of the existing edit.
     pub fn try_merge(&mut self, idx: usize, len: usize, text: &str) -> bool {
-        if self.can_merge(idx, len) {
+        self.try_merge_in(IndexUnit::Byte, idx, len, text)
+    }
+
+    /// Like `try_merge`, but the indexes and lengths of both edits
+    /// are measured in the given unit.
+    pub fn try_merge_in(&mut self, unit: IndexUnit, idx: usize, len: usize, text: &str) -> bool {
+        let text_len = unit.len(&self.text);
+        if (idx + len >= self.idx) && (idx <= self.idx + text_len) {
             let deletes_before = self.idx.saturating_sub(idx);
             let insert_idx     = idx.saturating_sub(self.idx);
 
             let mut deletes_after = len - deletes_before;
-            let text_len          = self.text.len();
             let text_delete_len   = min(deletes_after, text_len - insert_idx);
             deletes_after         = deletes_after.saturating_sub(text_delete_len);
 
+            let splice_lo = unit.byte_offset(&self.text, insert_idx);
+            let splice_hi = unit.byte_offset(&self.text, insert_idx + text_delete_len);
+
             self.idx = min(self.idx, idx);
             self.len += deletes_before + deletes_after;
-            splice(&mut self.text, insert_idx, insert_idx + text_delete_len, text);
+            splice(&mut self.text, splice_lo, splice_hi, text);
             true
         } else {
             false
@@ -72,9 +82,10 @@ impl TextEdit {
 
     /// Tries to merge a new text edit into the last element of
     /// a sequence. If the new edit can't be merged, it is pushed
-    /// to the end of the sequence.
-    pub fn push(text_edits: &mut Vec<TextEdit>, idx: usize, len: usize, text: &str) {
-        if text_edits.is_empty() || !text_edits.last_mut().unwrap().try_merge(idx, len, text) {
+    /// to the end of the sequence. Indexes and lengths are
+    /// measured in the given unit.
+    pub fn push(text_edits: &mut Vec<TextEdit>, unit: IndexUnit, idx: usize, len: usize, text: &str) {
+        if text_edits.is_empty() || !text_edits.last_mut().unwrap().try_merge_in(unit, idx, len, text) {
             text_edits.push(TextEdit{idx, len, text: text.into()});
         }
     }
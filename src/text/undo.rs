@@ -0,0 +1,146 @@
+use Error;
+use sequence::uid::Uid;
+use super::{Element, LocalOp, Op, Text};
+
+/// An UndoManager records the local edits made to a `Text` and
+/// reverts them on request. Undo and redo only touch the local
+/// site's own edits: text inserted by an edit is removed, and text
+/// removed by an edit is re-inserted with new uids at its original
+/// position. Concurrent remote edits are left intact.
+///
+/// Undo and redo generate ordinary ops that must be sent to other
+/// sites like any other edit. Consecutive edits that `Text` merges
+/// into a single element, such as typing a word, are grouped and
+/// undone together.
+///
+/// ```
+/// use ditto::Text;
+/// use ditto::text::UndoManager;
+///
+/// let mut text = Text::from_str("hello");
+/// let mut undo = UndoManager::new();
+/// let _ = undo.replace(&mut text, 5, 0, " w");
+/// let _ = undo.replace(&mut text, 7, 0, "orld");
+/// assert_eq!(text.local_value(), "hello world");
+///
+/// let (local_ops, _op) = undo.undo(&mut text).unwrap();
+/// assert_eq!(local_ops.len(), 1);
+/// assert_eq!(text.local_value(), "hello");
+///
+/// let _ = undo.redo(&mut text).unwrap();
+/// assert_eq!(text.local_value(), "hello world");
+/// ```
+#[derive(Debug, Clone)]
+pub struct UndoManager {
+    undo_stack: Vec<UndoGroup>,
+    redo_stack: Vec<UndoGroup>,
+    can_extend: bool,
+}
+
+/// The net effect of a group of edits.
+#[derive(Debug, Clone)]
+struct UndoGroup {
+    inserted_uids: Vec<Uid>,
+    removed_elements: Vec<Element>,
+}
+
+impl UndoManager {
+
+    /// Constructs and returns a new UndoManager.
+    pub fn new() -> Self {
+        UndoManager{undo_stack: vec![], redo_stack: vec![], can_extend: false}
+    }
+
+    /// Returns true if there is an edit to undo.
+    pub fn can_undo(&self) -> bool {
+        !self.undo_stack.is_empty()
+    }
+
+    /// Returns true if there is an undone edit to redo.
+    pub fn can_redo(&self) -> bool {
+        !self.redo_stack.is_empty()
+    }
+
+    /// Replaces text like [`Text::replace`](struct.Text.html#method.replace)
+    /// and records the edit. Recording an edit clears the redo stack.
+    pub fn replace(&mut self, text: &mut Text, idx: usize, len: usize, new_text: &str) -> Option<Result<Op, Error>> {
+        let dot = text.summary.next_dot(text.site_id);
+        let (op, removed_elements) = text.inner.replace_and_remove(idx, len, new_text, dot)?;
+        for dot in op.inserted_dots() {
+            text.summary.insert(dot);
+        }
+
+        let extends_group = self.can_extend && self.undo_stack.last()
+            .into_iter()
+            .any(|group| op.removed_uids.iter().any(|uid| group.inserted_uids.contains(uid)));
+
+        if !extends_group {
+            self.undo_stack.push(UndoGroup{inserted_uids: vec![], removed_elements: vec![]});
+        }
+
+        self.undo_stack.last_mut().unwrap().record(&op, removed_elements);
+        self.redo_stack.clear();
+        self.can_extend = true;
+        Some(text.after_op(op))
+    }
+
+    /// Reverts the most recent group of recorded edits that still
+    /// has an effect. Returns the local changes and an op to send
+    /// to other sites, or None if there is nothing to undo.
+    pub fn undo(&mut self, text: &mut Text) -> Option<(Vec<LocalOp>, Result<Op, Error>)> {
+        self.can_extend = false;
+        while let Some(group) = self.undo_stack.pop() {
+            if let Some((local_ops, op, group)) = group.revert(text) {
+                self.redo_stack.push(group);
+                return Some((local_ops, op))
+            }
+        }
+        None
+    }
+
+    /// Reapplies the most recently undone group of edits. Returns
+    /// the local changes and an op to send to other sites, or None
+    /// if there is nothing to redo.
+    pub fn redo(&mut self, text: &mut Text) -> Option<(Vec<LocalOp>, Result<Op, Error>)> {
+        self.can_extend = false;
+        while let Some(group) = self.redo_stack.pop() {
+            if let Some((local_ops, op, group)) = group.revert(text) {
+                self.undo_stack.push(group);
+                return Some((local_ops, op))
+            }
+        }
+        None
+    }
+}
+
+impl Default for UndoManager {
+    fn default() -> Self {
+        UndoManager::new()
+    }
+}
+
+impl UndoGroup {
+    fn record(&mut self, op: &Op, removed_elements: Vec<Element>) {
+        for element in removed_elements {
+            match self.inserted_uids.iter().position(|uid| *uid == element.uid) {
+                Some(idx) => { self.inserted_uids.remove(idx); }
+                None => self.removed_elements.push(element),
+            }
+        }
+        self.inserted_uids.extend(op.inserted_elements.iter().map(|e| e.uid.clone()));
+    }
+
+    /// Reverts the group and returns the local changes, the op,
+    /// and a group that reverts the revert.
+    fn revert(self, text: &mut Text) -> Option<(Vec<LocalOp>, Result<Op, Error>, UndoGroup)> {
+        let dot = text.summary.next_dot(text.site_id);
+        let (op, removed_elements, local_ops) = text.inner.revert(&self.inserted_uids, &self.removed_elements, dot)?;
+        for dot in op.inserted_dots() {
+            text.summary.insert(dot);
+        }
+
+        let inserted_uids = op.inserted_elements.iter().map(|e| e.uid.clone()).collect();
+        let group = UndoGroup{inserted_uids, removed_elements};
+        Some((local_ops, text.after_op(op), group))
+    }
+}
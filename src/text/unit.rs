@@ -0,0 +1,135 @@
+use std::ops::{Add, AddAssign, Sub, SubAssign};
+use tree::Length;
+
+/// The unit in which a `Text`'s indexes and lengths are measured.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum IndexUnit {
+    /// UTF-8 bytes, as used by Rust's `str`. This is the default.
+    Byte,
+    /// Unicode scalar values, as used by Rust's `char`.
+    Char,
+    /// UTF-16 code units, as used by JavaScript and Swift's `String.utf16`.
+    Utf16,
+}
+
+/// The length of a text element in every index unit,
+/// plus the number of newlines it contains.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ElementLen {
+    bytes: usize,
+    chars: usize,
+    utf16: usize,
+    newlines: usize,
+}
+
+impl IndexUnit {
+    /// Returns the length of a string in this unit.
+    pub fn len(self, text: &str) -> usize {
+        match self {
+            IndexUnit::Byte => text.len(),
+            IndexUnit::Char => text.chars().count(),
+            IndexUnit::Utf16 => text.chars().map(char::len_utf16).sum(),
+        }
+    }
+
+    /// Returns the byte offset of an offset in this unit. Panics
+    /// if the offset is out of bounds or does not lie on a `char`
+    /// boundary.
+    pub fn byte_offset(self, text: &str, offset: usize) -> usize {
+        if self == IndexUnit::Byte { return offset }
+
+        let mut unit_offset = 0;
+        for (byte_offset, c) in text.char_indices() {
+            if unit_offset == offset { return byte_offset }
+            if unit_offset > offset { break }
+            unit_offset += if self == IndexUnit::Char { 1 } else { c.len_utf16() };
+        }
+
+        if unit_offset == offset { return text.len() }
+        panic!("index is out of bounds or not on a char boundary");
+    }
+
+    /// Returns the byte offset of an offset in this unit within
+    /// an element's text. The element's cached length lets it skip
+    /// the walk for ASCII text and walk from the nearer end otherwise.
+    pub(crate) fn element_byte_offset(self, text: &str, len: ElementLen, offset: usize) -> usize {
+        let unit_len = self.measure(len);
+        if len.bytes == unit_len { return offset }
+        if offset == unit_len { return text.len() }
+        if offset <= unit_len / 2 { return self.byte_offset(text, offset) }
+
+        let mut unit_offset = unit_len;
+        for (byte_offset, c) in text.char_indices().rev() {
+            unit_offset -= if self == IndexUnit::Char { 1 } else { c.len_utf16() };
+            if unit_offset == offset { return byte_offset }
+            if unit_offset < offset { break }
+        }
+        panic!("index is out of bounds or not on a char boundary");
+    }
+
+    pub(crate) fn measure(self, len: ElementLen) -> usize {
+        match self {
+            IndexUnit::Byte => len.bytes,
+            IndexUnit::Char => len.chars,
+            IndexUnit::Utf16 => len.utf16,
+        }
+    }
+}
+
+impl ElementLen {
+    pub fn new(text: &str) -> Self {
+        let mut len = ElementLen{bytes: text.len(), chars: 0, utf16: 0, newlines: 0};
+        for c in text.chars() {
+            len.chars += 1;
+            len.utf16 += c.len_utf16();
+            if c == '\n' { len.newlines += 1 }
+        }
+        len
+    }
+
+    pub fn newlines(self) -> usize {
+        self.newlines
+    }
+}
+
+impl Length for ElementLen {
+    fn primary(self) -> usize {
+        self.bytes
+    }
+}
+
+impl Add for ElementLen {
+    type Output = ElementLen;
+
+    fn add(mut self, other: ElementLen) -> ElementLen {
+        self += other;
+        self
+    }
+}
+
+impl Sub for ElementLen {
+    type Output = ElementLen;
+
+    fn sub(mut self, other: ElementLen) -> ElementLen {
+        self -= other;
+        self
+    }
+}
+
+impl AddAssign for ElementLen {
+    fn add_assign(&mut self, other: ElementLen) {
+        self.bytes += other.bytes;
+        self.chars += other.chars;
+        self.utf16 += other.utf16;
+        self.newlines += other.newlines;
+    }
+}
+
+impl SubAssign for ElementLen {
+    fn sub_assign(&mut self, other: ElementLen) {
+        self.bytes -= other.bytes;
+        self.chars -= other.chars;
+        self.utf16 -= other.utf16;
+        self.newlines -= other.newlines;
+    }
+}
@@ -1,5 +1,104 @@
 use Error;
-use dot::{Summary, SiteId};
+use dot::{Dot, Summary, SiteId};
+use serde::Serialize;
+use std::borrow::Cow;
+use std::fmt::Debug;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::hash::Hash;
+use serde::de::DeserializeOwned;
+
+/// A replicated data type. Every Ditto CRDT implements `Crdt`,
+/// which allows replication, storage, and test code to be written
+/// once for all CRDTs rather than once per CRDT.
+///
+/// Each CRDT also exposes these functions as inherent methods,
+/// so the trait only needs to be imported by generic code.
+pub trait Crdt: Sized {
+    /// The CRDT's owned, serializable state.
+    type State: Clone + Serialize + DeserializeOwned;
+
+    /// An op that can be sent to remote sites for replication.
+    type Op: Clone + Serialize + DeserializeOwned;
+
+    /// The local change that results from executing an op.
+    type LocalOp;
+
+    /// The CRDT's equivalent local value.
+    type LocalValue;
+
+    /// A partial state that only contains the changes a remote
+    /// site has not yet seen.
+    type Delta: Clone + Serialize + DeserializeOwned;
+
+    /// Returns the site id.
+    fn site_id(&self) -> SiteId;
+
+    /// Returns the CRDT's summary of all the ops it has seen.
+    fn summary(&self) -> Cow<'_, Summary>;
+
+    /// Returns an owned CRDT state of cloned values.
+    fn clone_state(&self) -> Self::State;
+
+    /// Consumes the CRDT and returns its state.
+    fn into_state(self) -> Self::State;
+
+    /// Constructs a new CRDT from a state and optional site id.
+    /// If the site id is present, it must be nonzero.
+    fn from_state(state: Self::State, site_id: Option<SiteId>) -> Result<Self, Error>;
+
+    /// Returns the CRDT value's equivalent local value.
+    fn local_value(&self) -> Self::LocalValue;
+
+    /// Executes an op and returns the equivalent local op.
+    fn execute_op(&mut self, op: Self::Op) -> Self::LocalOp;
+
+    /// Validates that an op only inserts elements from a given site id,
+    /// then executes the op and returns the equivalent local op.
+    fn validate_and_execute_op(&mut self, op: Self::Op, site_id: SiteId) -> Result<Self::LocalOp, Error>;
+
+    /// Merges a remote CRDT state into the CRDT and returns the
+    /// local ops that the merge applied, in the order they were applied.
+    fn merge(&mut self, other: Self::State) -> Result<Vec<Self::LocalOp>, Error>;
+
+    /// Returns a delta containing every change that a site with
+    /// the given summary has not yet seen.
+    fn delta_since(&self, since: &Summary) -> Self::Delta;
+
+    /// Merges a delta from a remote CRDT into the CRDT.
+    fn merge_delta(&mut self, delta: Self::Delta) -> Result<(), Error>;
+
+    /// Assigns a site id to the CRDT and returns any cached ops.
+    /// If the CRDT already has a site id, it returns an error.
+    fn add_site_id(&mut self, site_id: SiteId) -> Result<Vec<Self::Op>, Error>;
+}
+
+/// An op that knows which dots it inserts and which dots it
+/// depends on. This lets a [`CausalReplica`](causal/struct.CausalReplica.html)
+/// hold back an op until the CRDT is ready to execute it.
+pub trait CausalOp {
+    /// Returns the dots of the elements inserted by the op.
+    fn inserted_dots(&self) -> Vec<Dot>;
+
+    /// Returns the dots of the existing elements that the op
+    /// removes or edits.
+    fn referenced_dots(&self) -> Vec<Dot>;
+
+    /// Returns every dot a site must have seen before it executes
+    /// the op: the preceding dot of each inserting site, plus every
+    /// dot the op references.
+    fn required_dots(&self) -> Vec<Dot> {
+        let inserted_dots = self.inserted_dots();
+        let mut required_dots: Vec<Dot> = inserted_dots.iter()
+            .filter_map(|dot| dot.predecessor())
+            .filter(|dot| !inserted_dots.contains(dot))
+            .collect();
+
+        required_dots.append(&mut self.referenced_dots());
+        required_dots.sort();
+        required_dots.dedup();
+        required_dots
+    }
+}
 
 macro_rules! crdt_impl2 {
     ($self_ident:ident,
@@ -9,7 +108,10 @@ macro_rules! crdt_impl2 {
      $inner:ty,
      $op:ty,
      $local_op:ty,
+     $merge_local_op:ty,
      $local_value:ty,
+     $delta:ty,
+     $delta_ident:ident,
     ) => {
 
         /// Returns the site id.
@@ -17,7 +119,7 @@ macro_rules! crdt_impl2 {
             self.site_id
         }
 
-        #[doc(hidden)]
+        /// Returns the CRDT's summary of all the ops it has seen.
         pub fn summary(&self) -> &Summary {
             &self.summary
         }
@@ -91,12 +193,36 @@ macro_rules! crdt_impl2 {
         }
 
         /// Merges a remote CRDT state into the CRDT. The remote
-        /// CRDT state must have a site id.
-        pub fn merge(&mut self, other: $state) -> Result<(), Error> {
+        /// CRDT state must have a site id. Returns the local ops
+        /// that the merge applied, in the order they were applied.
+        pub fn merge(&mut self, other: $state) -> Result<Vec<$merge_local_op>, Error> {
             other.inner.validate_no_unassigned_sites()?;
             other.summary.validate_no_unassigned_sites()?;
-            self.inner.merge(other.inner.into_owned(), &self.summary, &other.summary);
+            let local_ops = self.inner.merge(other.inner.into_owned(), &self.summary, &other.summary);
             self.summary.merge(&other.summary);
+            Ok(local_ops)
+        }
+
+        /// Returns a delta containing every element that a site with
+        /// the given summary has not yet seen, plus enough information
+        /// for that site to remove the elements that have been removed
+        /// here.
+        pub fn delta_since(&self, since: &Summary) -> $delta {
+            $delta_ident {
+                inner: self.inner.delta_since(since, &self.summary),
+                summary: self.summary.clone(),
+            }
+        }
+
+        /// Merges a delta from a remote CRDT into the CRDT. The delta
+        /// must have been generated from a summary that this CRDT has
+        /// already seen; otherwise an `InvalidDelta` error is returned
+        /// and the CRDT is left unchanged.
+        pub fn merge_delta(&mut self, delta: $delta) -> Result<(), Error> {
+            delta.inner.validate_no_unassigned_sites()?;
+            delta.summary.validate_no_unassigned_sites()?;
+            self.inner.merge_delta(delta.inner, &self.summary, &delta.summary)?;
+            self.summary.merge(&delta.summary);
             Ok(())
         }
 
@@ -127,29 +253,258 @@ macro_rules! crdt_impl2 {
     }
 }
 
-pub(crate) trait NestedInner: Sized {
-    fn nested_add_site_id(&mut self, site_id: SiteId);
+macro_rules! crdt_trait_impl {
+    ($self_ident:ident,
+     $state_static:ty,
+     $op:ty,
+     $local_op:ty,
+     $into_local_op:expr,
+     $local_value:ty,
+     $delta:ty,
+    ) => {
+        type State = $state_static;
+        type Op = $op;
+        type LocalOp = $local_op;
+        type LocalValue = $local_value;
+        type Delta = $delta;
 
-    fn nested_validate_all(&self, site_id: SiteId) -> Result<(), Error>;
+        fn site_id(&self) -> SiteId {
+            $self_ident::site_id(self)
+        }
 
-    fn nested_validate_no_unassigned_sites(&self) -> Result<(), Error>;
+        fn summary(&self) -> Cow<'_, Summary> {
+            Cow::Borrowed($self_ident::summary(self))
+        }
 
-    fn nested_can_merge(&self, other: &Self) -> bool;
+        fn clone_state(&self) -> $state_static {
+            $self_ident::clone_state(self)
+        }
 
-    fn nested_merge(&mut self, other: Self, summary: &Summary, other_summary: &Summary) -> Result<(), Error> {
-        if self.nested_can_merge(&other) {
-            self.nested_force_merge(other, summary, other_summary);
-            Ok(())
-        } else {
-            Err(Error::CannotMerge)
+        fn into_state(self) -> $state_static {
+            $self_ident::into_state(self)
+        }
+
+        fn from_state(state: $state_static, site_id: Option<SiteId>) -> Result<Self, Error> {
+            $self_ident::from_state(state, site_id)
+        }
+
+        fn local_value(&self) -> $local_value {
+            $self_ident::local_value(self)
+        }
+
+        fn execute_op(&mut self, op: $op) -> $local_op {
+            $self_ident::execute_op(self, op)
+        }
+
+        fn validate_and_execute_op(&mut self, op: $op, site_id: SiteId) -> Result<$local_op, Error> {
+            $self_ident::validate_and_execute_op(self, op, site_id)
+        }
+
+        fn merge(&mut self, other: $state_static) -> Result<Vec<$local_op>, Error> {
+            let local_ops = $self_ident::merge(self, other)?;
+            Ok(local_ops.into_iter().map($into_local_op).collect())
+        }
+
+        fn delta_since(&self, since: &Summary) -> $delta {
+            $self_ident::delta_since(self, since)
+        }
+
+        fn merge_delta(&mut self, delta: $delta) -> Result<(), Error> {
+            $self_ident::merge_delta(self, delta)
+        }
+
+        fn add_site_id(&mut self, site_id: SiteId) -> Result<Vec<$op>, Error> {
+            $self_ident::add_site_id(self, site_id)
         }
     }
+}
+
+/// A value that can be stored in a [`Map`](map/struct.Map.html) or
+/// a [`List`](list/struct.List.html).
+///
+/// Plain values such as numbers and strings implement `Nested`
+/// through the [`Plain`](trait.Plain.html) marker trait and can only
+/// be replaced as a whole. Ditto's CRDTs implement `Nested` directly,
+/// so the values of a `Map<String, Text>` or a `List<Counter>` can be
+/// edited in place, and merges recurse into them.
+///
+/// A nested CRDT shares the summary and site id of the CRDT that
+/// holds it; its own summary and site id are unused. When a value is
+/// inserted, `nested_reset` gives each of its elements the dot of the
+/// insert, so its dots never collide with those of the enclosing CRDT.
+pub trait Nested: Clone {
+    /// An op that edits the value in place.
+    type NestedOp: NestedOp + Debug + Clone + PartialEq + Serialize + DeserializeOwned;
+
+    /// The local change that results from editing the value.
+    type NestedLocalOp: Debug + Clone + PartialEq + Serialize + DeserializeOwned;
+
+    /// Gives every element of the value the dot `dot` and clears
+    /// the value's own summary and site id.
+    fn nested_reset(&mut self, dot: Dot);
+
+    /// Executes an op that edits the value and returns the
+    /// equivalent local ops.
+    fn nested_execute_op(&mut self, op: Self::NestedOp) -> Vec<Self::NestedLocalOp>;
+
+    /// Merges another site's copy of the value into the value and
+    /// returns the local ops that the merge applied. `summary` and
+    /// `other_summary` are the summaries of the enclosing CRDTs.
+    fn nested_merge(&mut self, other: Self, summary: &Summary, other_summary: &Summary) -> Vec<Self::NestedLocalOp>;
+
+    /// Merges like `nested_merge` but does not report local ops.
+    fn nested_force_merge(&mut self, other: Self, summary: &Summary, other_summary: &Summary) {
+        let _ = self.nested_merge(other, summary, other_summary);
+    }
+
+    /// Returns true if a site with the given summary has seen every
+    /// change to the value. Deltas send changed values whole.
+    fn nested_unchanged_since(&self, since: &Summary) -> bool;
+
+    /// Assigns a site id to every unassigned element of the value.
+    fn nested_add_site_id(&mut self, site_id: SiteId);
+
+    /// Validates that every element of the value was inserted
+    /// by the given site.
+    fn nested_validate_all(&self, site_id: SiteId) -> Result<(), Error>;
 
-    fn nested_force_merge(&mut self, other: Self, summary: &Summary, other_summary: &Summary);
+    /// Validates that every element of the value has a site id.
+    fn nested_validate_no_unassigned_sites(&self) -> Result<(), Error>;
+
+    /// Returns false if the value cannot be merged with `other`.
+    fn nested_can_merge(&self, _other: &Self) -> bool {
+        true
+    }
 }
 
-pub(crate) trait NestedOp {
+/// An op that edits a [`Nested`](trait.Nested.html) value.
+pub trait NestedOp: CausalOp {
+    /// Assigns a site id to any unassigned elements in the op.
     fn nested_add_site_id(&mut self, site_id: SiteId);
 
+    /// Validates that the op only inserts elements from the given site.
     fn nested_validate(&self, site_id: SiteId) -> Result<(), Error>;
 }
+
+/// A CRDT that can be edited in place while it is nested in a
+/// `Map` or a `List`.
+pub trait NestedCrdt: Nested {
+    /// Calls `f` with the value, which uses the given summary and
+    /// site id in place of its own while `f` runs. `f` makes a single
+    /// edit through the value's own methods and returns its op. If
+    /// the site id is 0, the op is returned rather than cached.
+    fn nested_edit<F>(&mut self, summary: &mut Summary, site_id: SiteId, f: F) -> Result<Self::NestedOp, Error>
+        where F: FnOnce(&mut Self) -> Result<Self::NestedOp, Error>;
+}
+
+/// A value that is only ever replaced as a whole. Implementing
+/// `Plain` for a type lets it be stored in a `Map` or `List`:
+///
+/// ```rust
+/// # extern crate ditto;
+/// # #[macro_use] extern crate serde_derive;
+/// #[derive(Clone, PartialEq, Serialize, Deserialize)]
+/// struct Point { x: i32, y: i32 }
+///
+/// impl ditto::Plain for Point {}
+///
+/// # fn main() {
+/// let mut list = ditto::List::new();
+/// list.push(Point{x: 1, y: 2}).unwrap();
+/// # }
+/// ```
+pub trait Plain: Clone {}
+
+/// The op of a value that cannot be edited in place.
+/// It has no values.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum NoOp {}
+
+impl CausalOp for NoOp {
+    fn inserted_dots(&self) -> Vec<Dot> {
+        match *self {}
+    }
+
+    fn referenced_dots(&self) -> Vec<Dot> {
+        match *self {}
+    }
+}
+
+impl NestedOp for NoOp {
+    fn nested_add_site_id(&mut self, _: SiteId) {
+        match *self {}
+    }
+
+    fn nested_validate(&self, _: SiteId) -> Result<(), Error> {
+        match *self {}
+    }
+}
+
+impl<T: Plain> Nested for T {
+    type NestedOp = NoOp;
+    type NestedLocalOp = NoOp;
+
+    fn nested_reset(&mut self, _: Dot) { }
+
+    fn nested_execute_op(&mut self, op: NoOp) -> Vec<NoOp> {
+        match op {}
+    }
+
+    fn nested_merge(&mut self, _: Self, _: &Summary, _: &Summary) -> Vec<NoOp> {
+        vec![]
+    }
+
+    fn nested_unchanged_since(&self, _: &Summary) -> bool {
+        true
+    }
+
+    fn nested_add_site_id(&mut self, _: SiteId) { }
+
+    fn nested_validate_all(&self, _: SiteId) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn nested_validate_no_unassigned_sites(&self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+macro_rules! plain_impl {
+    ($($ty:ty),*) => { $(impl Plain for $ty {})* }
+}
+
+plain_impl!(bool, char, String, &'static str, (), ::serde_json::Value);
+plain_impl!(i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, u128, usize, f32, f64);
+
+impl<T: Plain> Plain for Option<T> {}
+impl<T: Plain> Plain for Box<T> {}
+impl<T: Plain> Plain for Vec<T> {}
+impl<T: Plain> Plain for VecDeque<T> {}
+impl<T: Plain + Eq + Hash> Plain for HashSet<T> {}
+impl<T: Plain + Ord> Plain for BTreeSet<T> {}
+impl<K: Plain + Eq + Hash, V: Plain> Plain for HashMap<K, V> {}
+impl<K: Plain + Ord, V: Plain> Plain for BTreeMap<K, V> {}
+impl<A: Plain, B: Plain> Plain for (A, B) {}
+impl<A: Plain, B: Plain, C: Plain> Plain for (A, B, C) {}
+impl<A: Plain, B: Plain, C: Plain, D: Plain> Plain for (A, B, C, D) {}
+
+/// Implements `NestedCrdt` for a CRDT that keeps its summary, site
+/// id, and cached ops in `summary`, `site_id`, and `cached_ops`.
+macro_rules! nested_crdt_impl {
+    () => {
+        fn nested_edit<F>(&mut self, summary: &mut Summary, site_id: SiteId, f: F) -> Result<Self::NestedOp, Error>
+            where F: FnOnce(&mut Self) -> Result<Self::NestedOp, Error>
+        {
+            ::std::mem::swap(&mut self.summary, summary);
+            self.site_id = site_id;
+            let result = f(self);
+            ::std::mem::swap(&mut self.summary, summary);
+            self.site_id = 0;
+
+            match self.cached_ops.pop() {
+                Some(op) => { self.cached_ops.clear(); Ok(op) }
+                None => result,
+            }
+        }
+    }
+}
@@ -7,36 +7,59 @@
 //!   distance from its beginning to idx.
 //! * `get_idx(id)` finds the start index of the tree element
 //!   with id == x.
+//! * `get_insert_idx(id)` finds the start index that a tree
+//!   element with id == x has or would have.
+//! * `iter_from(idx)` iterates over the tree elements, starting
+//!   with the element at index i.
 //!
 //! All operations can be performed in O(log n) time.
+//!
+//! An element's length may be measured in several units at once.
+//! Each node keeps the total length of its subtree in every unit,
+//! and the `_by` variants of the index operations take a function
+//! that selects the unit to index by.
 
 use error::Error;
 use serde::{Serialize, Serializer, Deserialize, Deserializer};
 use std::mem;
 use std::iter::FromIterator;
+use std::ops::{Add, AddAssign, Sub, SubAssign};
 
 const B: usize = 6;
 const MIN_LEN: usize = B - 1;
 const CAPACITY: usize = 2 * B - 1;
 
 #[derive(Debug, Clone, PartialEq)]
-pub struct Tree<T> {
+pub struct Tree<T: Element> {
     root: Node<T>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
-struct Node<T> {
-    len: usize,
+struct Node<T: Element> {
+    len: T::Len,
     elements: Vec<T>,
     children: Vec<Node<T>>,
 }
 
 pub trait Element {
     type Id: Clone + PartialEq + Ord;
+    type Len: Length;
 
     fn id(&self) -> &Self::Id;
 
-    fn element_len(&self) -> usize { 1 }
+    fn element_len(&self) -> Self::Len;
+}
+
+/// The length of a tree element or subtree. The primary length
+/// is the unit used by `len`, `get_elt` and `get_idx`.
+pub trait Length: Copy + Default + PartialEq + ::std::fmt::Debug
+    + Add<Output = Self> + Sub<Output = Self> + AddAssign + SubAssign
+{
+    fn primary(self) -> usize;
+}
+
+impl Length for usize {
+    fn primary(self) -> usize { self }
 }
 
 impl<T: Element> Tree<T> {
@@ -47,13 +70,19 @@ impl<T: Element> Tree<T> {
 
     /// Returns the length of the tree.
     pub fn len(&self) -> usize {
-        self.root.len
+        self.root.len.primary()
+    }
+
+    /// Returns the length of the tree in the unit selected
+    /// by `measure`.
+    pub fn len_by<F: Fn(T::Len) -> usize>(&self, measure: F) -> usize {
+        measure(self.root.len)
     }
 
     /// Returns true if the tree has length 0.
     /// Returns false otherwise.
     pub fn is_empty(&self) -> bool {
-        self.root.len == 0
+        self.len() == 0
     }
 
     /// Inserts an element into the tree or returns an error
@@ -95,8 +124,14 @@ impl<T: Element> Tree<T> {
     /// within the element. Returns an error if the index
     /// is out-of-bounds.
     pub fn get_elt(&self, idx: usize) -> Result<(&T, usize), Error> {
-        if idx >= self.len() { return Err(Error::OutOfBounds) }
-        Ok(self.root.get_elt(idx))
+        self.get_elt_by(idx, T::Len::primary)
+    }
+
+    /// Like `get_elt`, but the index and offset are in the unit
+    /// selected by `measure`.
+    pub fn get_elt_by<F: Fn(T::Len) -> usize + Copy>(&self, idx: usize, measure: F) -> Result<(&T, usize), Error> {
+        if idx >= self.len_by(measure) { return Err(Error::OutOfBounds) }
+        Ok(self.root.get_elt(idx, measure))
     }
 
     pub fn get_mut_elt(&mut self, idx: usize) -> Result<(&mut T, usize), Error> {
@@ -107,7 +142,25 @@ impl<T: Element> Tree<T> {
     /// Returns the start index of a tree element, or None
     /// if the element does not exist.
     pub fn get_idx(&self, id: &T::Id) -> Option<usize> {
-        self.root.get_idx(id)
+        self.get_idx_by(id, T::Len::primary)
+    }
+
+    /// Like `get_idx`, but the index is in the unit selected
+    /// by `measure`.
+    pub fn get_idx_by<F: Fn(T::Len) -> usize + Copy>(&self, id: &T::Id, measure: F) -> Option<usize> {
+        self.root.get_idx(id, measure)
+    }
+
+    /// Returns the start index of a tree element if it exists,
+    /// or the index at which it would be inserted if it does not.
+    pub fn get_insert_idx(&self, id: &T::Id) -> usize {
+        self.get_insert_idx_by(id, T::Len::primary)
+    }
+
+    /// Like `get_insert_idx`, but the index is in the unit
+    /// selected by `measure`.
+    pub fn get_insert_idx_by<F: Fn(T::Len) -> usize + Copy>(&self, id: &T::Id, measure: F) -> usize {
+        self.root.get_insert_idx(id, measure)
     }
 
     /// Returns an iterator that visits the tree elements in
@@ -116,6 +169,41 @@ impl<T: Element> Tree<T> {
         self.into_iter()
     }
 
+    /// Returns an iterator that visits the tree elements in
+    /// ascending order, starting with the element that contains
+    /// location `idx`. Only the nodes on the path to that element
+    /// are visited to find it.
+    pub fn iter_from(&self, mut idx: usize) -> Iter<'_, T> {
+        let mut node = &self.root;
+        let mut stack = vec![];
+
+        'descend: while node.is_internal() {
+            for (child_idx, child) in node.children.iter().enumerate() {
+                let child_len = child.len.primary();
+                if idx < child_len {
+                    stack.push((node, child_idx));
+                    node = child;
+                    continue 'descend
+                }
+                idx -= child_len;
+
+                if let Some(element) = node.elements.get(child_idx) {
+                    let element_len = element.element_len().primary();
+                    if idx < element_len { return Iter{stack, node, next_idx: child_idx} }
+                    idx -= element_len;
+                }
+            }
+            return Iter{stack, node, next_idx: node.elements.len()}
+        }
+
+        for (next_idx, element) in node.elements.iter().enumerate() {
+            let element_len = element.element_len().primary();
+            if idx < element_len { return Iter{stack, node, next_idx} }
+            idx -= element_len;
+        }
+        Iter{stack, node, next_idx: node.elements.len()}
+    }
+
     pub fn into_iter(self) -> IntoIter<T> {
         <Self as IntoIterator>::into_iter(self)
     }
@@ -124,7 +212,7 @@ impl<T: Element> Tree<T> {
 impl<T: Element> Node<T> {
 
     fn new() -> Self {
-        Node{len: 0, elements: vec![], children: vec![]}
+        Node{len: T::Len::default(), elements: vec![], children: vec![]}
     }
 
     fn lookup(&self, id: &T::Id) -> Option<&T> {
@@ -154,20 +242,23 @@ impl<T: Element> Node<T> {
         }
     }
 
-    fn get_elt(&self, mut idx: usize) -> (&T, usize) {
+    fn get_elt<F: Fn(T::Len) -> usize + Copy>(&self, mut idx: usize, measure: F) -> (&T, usize) {
         if self.is_leaf() {
             for element in &self.elements {
-                if idx < element.element_len() { return (element, idx) }
-                else { idx -= element.element_len() }
+                let element_len = measure(element.element_len());
+                if idx < element_len { return (element, idx) }
+                else { idx -= element_len }
             }
         } else {
             let mut elements = self.elements.iter();
             for child in &self.children {
-                if idx < child.len { return child.get_elt(idx) }
-                else { idx -= child.len }
+                let child_len = measure(child.len);
+                if idx < child_len { return child.get_elt(idx, measure) }
+                else { idx -= child_len }
                 if let Some(element) = elements.next() {
-                    if idx < element.element_len() { return (element, idx) }
-                    else { idx -= element.element_len() }
+                    let element_len = measure(element.element_len());
+                    if idx < element_len { return (element, idx) }
+                    else { idx -= element_len }
                 }
             }
         }
@@ -177,47 +268,67 @@ impl<T: Element> Node<T> {
     fn get_mut_elt(&mut self, mut idx: usize) -> (&mut T, usize) {
         if self.is_leaf() {
             for element in &mut self.elements {
-                if idx < element.element_len() { return (element, idx) }
-                else { idx -= element.element_len() }
+                let element_len = element.element_len().primary();
+                if idx < element_len { return (element, idx) }
+                else { idx -= element_len }
             }
         } else {
             let mut elements = self.elements.iter_mut();
             for child in &mut self.children {
-                if idx < child.len { return child.get_mut_elt(idx) }
-                else { idx -= child.len }
+                let child_len = child.len.primary();
+                if idx < child_len { return child.get_mut_elt(idx) }
+                else { idx -= child_len }
                 if let Some(element) = elements.next() {
-                    if idx < element.element_len() { return (element, idx) }
-                    else { idx -= element.element_len() }
+                    let element_len = element.element_len().primary();
+                    if idx < element_len { return (element, idx) }
+                    else { idx -= element_len }
                 }
             }
         }
         unreachable!();
     }
 
-    fn get_idx(&self, id: &T::Id) -> Option<usize> {
+    fn get_idx<F: Fn(T::Len) -> usize + Copy>(&self, id: &T::Id, measure: F) -> Option<usize> {
         let (contains_element, idx) =
             match self.elements.binary_search_by(|elt| elt.id().cmp(id)) {
                 Ok(idx) => (true, idx),
                 Err(idx) => (false, idx),
             };
 
-        let mut char_idx = self.elements[..idx].iter().map(|e| e.element_len()).sum();
+        let mut char_idx = self.elements[..idx].iter().map(|e| measure(e.element_len())).sum();
         if self.is_leaf() && contains_element {
             Some(char_idx)
         } else if self.is_leaf() {
             None
         } else if contains_element {
-            char_idx += self.children[..idx+1].iter().map(|node| node.len).sum::<usize>();
+            char_idx += self.children[..idx+1].iter().map(|node| measure(node.len)).sum::<usize>();
             Some(char_idx)
         } else {
-            char_idx += self.children[..idx].iter().map(|node| node.len).sum::<usize>();
-            match self.children[idx].get_idx(id) {
+            char_idx += self.children[..idx].iter().map(|node| measure(node.len)).sum::<usize>();
+            match self.children[idx].get_idx(id, measure) {
                 Some(sub_idx) => Some(char_idx + sub_idx),
                 None => None,
             }
         }
     }
 
+    fn get_insert_idx<F: Fn(T::Len) -> usize + Copy>(&self, id: &T::Id, measure: F) -> usize {
+        let (contains_element, idx) =
+            match self.elements.binary_search_by(|elt| elt.id().cmp(id)) {
+                Ok(idx) => (true, idx),
+                Err(idx) => (false, idx),
+            };
+
+        let char_idx: usize = self.elements[..idx].iter().map(|e| measure(e.element_len())).sum();
+        if self.is_leaf() {
+            char_idx
+        } else if contains_element {
+            char_idx + self.children[..idx+1].iter().map(|node| measure(node.len)).sum::<usize>()
+        } else {
+            char_idx + self.children[..idx].iter().map(|node| measure(node.len)).sum::<usize>() + self.children[idx].get_insert_idx(id, measure)
+        }
+    }
+
     /// Insert a new element into a tree. The root node must
     /// not be full (ie it must contain fewer than CAPACITY
     /// elements)
@@ -345,8 +456,8 @@ impl<T: Element> Node<T> {
             let children = if child.is_leaf() { vec![] } else { child.children.split_off(B) };
 
             let new_child_len =
-                elements.iter().fold(0, |sum, e| sum + e.element_len()) +
-                children.iter().fold(0, |sum, e| sum + e.len);
+                elements.iter().fold(T::Len::default(), |sum, e| sum + e.element_len()) +
+                children.iter().fold(T::Len::default(), |sum, e| sum + e.len);
 
             let new_child = Node{len: new_child_len, elements, children};
 
@@ -442,7 +553,7 @@ impl<T: Element> Node<T> {
     }
 }
 
-pub struct Iter<'a, T: 'static> {
+pub struct Iter<'a, T: 'static + Element> {
     stack: Vec<(&'a Node<T>, usize)>,
     node: &'a Node<T>,
     next_idx: usize,
@@ -486,7 +597,7 @@ impl<'a, T: Element> Iterator for Iter<'a, T> {
     }
 }
 
-pub struct IntoIter<T: 'static> {
+pub struct IntoIter<T: 'static + Element> {
     tree: Tree<T>,
 }
 
@@ -556,6 +667,7 @@ mod test {
 
     impl Element for TextElement {
         type Id = u64;
+        type Len = usize;
 
         fn id(&self) -> &Self::Id {
             &self.id
@@ -628,6 +740,33 @@ mod test {
         assert!(tree.get_idx(&id) == Some(2));
     }
 
+    #[test]
+    fn get_insert_idx() {
+        let mut tree: Tree<TextElement> = Tree::new();
+        for i in 0..100 {
+            insert(&mut tree, i * 2, "ab");
+        }
+
+        assert!(tree.get_insert_idx(&0) == 0);
+        assert!(tree.get_insert_idx(&50) == 50);
+        assert!(tree.get_insert_idx(&51) == 52);
+        assert!(tree.get_insert_idx(&500) == 200);
+    }
+
+    #[test]
+    fn iter_from() {
+        let mut tree: Tree<TextElement> = Tree::new();
+        for i in 0..100 {
+            insert(&mut tree, i, "ab");
+        }
+
+        assert!(tree.iter_from(0).map(|e| e.id).eq(0..100));
+        assert!(tree.iter_from(51).map(|e| e.id).eq(25..100));
+        assert!(tree.iter_from(120).map(|e| e.id).eq(60..100));
+        assert!(tree.iter_from(199).map(|e| e.id).eq(99..100));
+        assert!(tree.iter_from(200).next().is_none());
+    }
+
     #[test]
     fn insert_basic() {
         let mut tree: Tree<TextElement> = Tree::new();
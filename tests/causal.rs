@@ -0,0 +1,210 @@
+extern crate ditto;
+
+use ditto::{CausalReplica, Counter, Json, List, Map, Register, Set, Text};
+use ditto::CausalOp;
+use ditto::dot::Dot;
+
+#[test]
+fn test_list_reverse_order() {
+    let mut list1: List<u32> = List::new();
+    let mut replica = CausalReplica::new(List::from_state(list1.clone_state(), Some(2)).unwrap());
+    let op1 = list1.push(1).unwrap();
+    let op2 = list1.push(2).unwrap();
+    let op3 = list1.push(3).unwrap();
+    let op4 = list1.remove(0).1.unwrap();
+
+    assert!(replica.receive(op4).is_empty());
+    assert!(replica.receive(op3).is_empty());
+    assert!(replica.receive(op2).is_empty());
+    assert_eq!(replica.pending_ops().len(), 3);
+    assert_eq!(replica.missing_dots(), [Dot::new(1, 1)]);
+
+    assert_eq!(replica.receive(op1).len(), 4);
+    assert!(replica.pending_ops().is_empty());
+    assert!(replica.missing_dots().is_empty());
+    assert_eq!(replica.crdt().state(), list1.state());
+}
+
+#[test]
+fn test_list_move_before_insert() {
+    let mut list1: List<u32> = List::new();
+    let mut replica = CausalReplica::new(List::from_state(list1.clone_state(), Some(2)).unwrap());
+    let op1 = list1.push(1).unwrap();
+    let op2 = list1.push(2).unwrap();
+    let op3 = list1.move_item(1, 0).unwrap();
+
+    assert_eq!(op3.required_dots(), [Dot::new(1, 2)]);
+    assert!(replica.receive(op3).is_empty());
+    assert!(replica.receive(op2).is_empty());
+    assert_eq!(replica.receive(op1).len(), 3);
+    assert_eq!(replica.crdt().local_value(), [2, 1]);
+    assert_eq!(replica.crdt().state(), list1.state());
+}
+
+#[test]
+fn test_remove_before_insert() {
+    let mut map1: Map<u32, bool> = Map::new();
+    let mut map2 = Map::from_state(map1.clone_state(), Some(2)).unwrap();
+    let mut replica = CausalReplica::new(Map::from_state(map1.clone_state(), Some(3)).unwrap());
+
+    let op1 = map1.insert(1, true).unwrap();
+    let _ = map2.execute_op(op1.clone());
+    let op2 = map2.remove(&1).unwrap().unwrap();
+
+    assert!(replica.receive(op2).is_empty());
+    assert_eq!(replica.missing_dots(), [Dot::new(1, 1)]);
+    assert_eq!(replica.receive(op1).len(), 2);
+    assert!(!replica.crdt().contains_key(&1));
+    assert_eq!(replica.crdt().state(), map2.state());
+}
+
+#[test]
+fn test_duplicate_ops() {
+    let mut set1: Set<u32> = Set::new();
+    let mut replica = CausalReplica::new(Set::from_state(set1.clone_state(), Some(2)).unwrap());
+    let op1 = set1.insert(10).unwrap();
+    let op2 = set1.insert(20).unwrap();
+
+    assert_eq!(replica.receive(op1.clone()).len(), 1);
+    assert!(replica.receive(op1).is_empty());
+    assert!(replica.receive(op2.clone()).len() == 1);
+    assert!(replica.receive(op2).is_empty());
+    assert_eq!(replica.crdt().state(), set1.state());
+}
+
+#[test]
+fn test_counter() {
+    let mut counter1 = Counter::new(0);
+    let mut replica = CausalReplica::new(Counter::from_state(counter1.clone_state(), Some(2)).unwrap());
+    let op1 = counter1.increment(5).unwrap();
+    let op2 = counter1.increment(-2).unwrap();
+
+    assert_eq!(replica.receive(op2), [Some(3)]);
+    assert!(replica.receive(op1).is_empty());
+    assert!(replica.pending_ops().is_empty());
+    assert_eq!(replica.crdt().get(), 3);
+}
+
+#[test]
+fn test_register() {
+    let mut register1 = Register::new(1u32);
+    let mut register2 = Register::from_state(register1.clone_state(), Some(2)).unwrap();
+    let mut replica = CausalReplica::new(Register::from_state(register1.clone_state(), Some(3)).unwrap());
+
+    let op1 = register1.update(2).unwrap();
+    let _ = register2.execute_op(op1.clone());
+    let op2 = register2.update(3).unwrap();
+
+    assert!(replica.receive(op2).is_empty());
+    assert_eq!(replica.missing_dots(), [Dot::new(1, 2)]);
+    assert_eq!(replica.receive(op1), [2, 3]);
+    assert_eq!(replica.crdt().state(), register2.state());
+}
+
+#[test]
+fn test_text() {
+    let mut text1 = Text::from_str("hello");
+    let mut replica = CausalReplica::new(Text::from_state(text1.clone_state(), Some(2)).unwrap());
+    let op1 = text1.replace(5, 0, " world").unwrap().unwrap();
+    assert!(text1.replace(0, 0, "").is_none());
+    let op2 = text1.replace(0, 1, "H").unwrap().unwrap();
+    let op3 = text1.replace(11, 0, "!").unwrap().unwrap();
+
+    assert!(replica.receive(op3).is_empty());
+    assert!(replica.receive(op2).is_empty());
+    assert_eq!(replica.missing_dots(), [Dot::new(1, 2)]);
+    assert_eq!(replica.receive(op1).len(), 3);
+    assert_eq!(replica.crdt().local_value(), "Hello world!");
+}
+
+#[test]
+fn test_json() {
+    let mut crdt1 = Json::from_str(r#"{"a":[]}"#).unwrap();
+    let mut crdt2 = Json::from_state(crdt1.clone_state(), Some(2)).unwrap();
+    let mut replica = CausalReplica::new(Json::from_state(crdt1.clone_state(), Some(3)).unwrap());
+
+    let op1 = crdt1.insert("/b", "hi").unwrap();
+    assert!(crdt1.insert("/c/d", 1.0).is_err());
+    let op2 = crdt1.insert("/a/0", true).unwrap();
+    let _ = crdt2.execute_op(op1.clone());
+    let op3 = crdt2.replace_text("/b", 2, 0, "!").unwrap();
+
+    assert!(replica.receive(op3).is_empty());
+    assert!(replica.receive(op2).is_empty());
+    assert_eq!(replica.missing_dots(), [Dot::new(1, 2)]);
+    assert_eq!(replica.receive(op1).len(), 3);
+    let value = replica.crdt().local_value();
+    assert_eq!(value["a"][0], true);
+    assert_eq!(value["b"], "hi!");
+}
+
+#[test]
+fn test_json_transaction() {
+    let mut crdt1 = Json::from_str(r#"{"a":[]}"#).unwrap();
+    let mut replica = CausalReplica::new(Json::from_state(crdt1.clone_state(), Some(2)).unwrap());
+
+    let op1 = crdt1.insert("/a/0", 1.0).unwrap();
+    let op2 = crdt1.transaction(|transaction| {
+        transaction.insert("/b", "hi")?;
+        transaction.replace_text("/b", 2, 0, "!")?;
+        transaction.remove("/a/0")
+    }).unwrap();
+
+    assert_eq!(op2.required_dots(), [Dot::new(1, 1), Dot::new(1, 2)]);
+    assert!(replica.receive(op2).is_empty());
+    assert_eq!(replica.missing_dots(), [Dot::new(1, 2)]);
+    assert_eq!(replica.receive(op1).len(), 2);
+    assert_eq!(replica.crdt().state(), crdt1.state());
+}
+
+#[test]
+fn test_merge_releases_ops() {
+    let mut list1: List<u32> = List::new();
+    let mut replica = CausalReplica::new(List::from_state(list1.clone_state(), Some(2)).unwrap());
+    let _ = list1.push(1).unwrap();
+    let state = list1.clone_state();
+    let op2 = list1.push(2).unwrap();
+
+    assert!(replica.receive(op2).is_empty());
+    assert_eq!(replica.merge(state).unwrap().len(), 2);
+    assert_eq!(replica.crdt().local_value(), [1, 2]);
+}
+
+#[test]
+fn test_merge_drops_executed_ops() {
+    let mut list1: List<u32> = List::new();
+    let mut replica = CausalReplica::new(List::from_state(list1.clone_state(), Some(2)).unwrap());
+    let _ = list1.push(1).unwrap();
+    let op2 = list1.push(2).unwrap();
+    let op3 = list1.push(3).unwrap();
+
+    assert!(replica.receive(op3).is_empty());
+    assert!(replica.receive(op2).is_empty());
+    assert_eq!(replica.merge(list1.clone_state()).unwrap().len(), 3);
+    assert!(replica.pending_ops().is_empty());
+    assert!(replica.missing_dots().is_empty());
+    assert_eq!(replica.crdt().state(), list1.state());
+}
+
+#[test]
+fn test_validate_and_receive() {
+    let mut list1: List<u32> = List::new();
+    let mut replica = CausalReplica::new(List::from_state(list1.clone_state(), Some(2)).unwrap());
+    let op = list1.push(1).unwrap();
+    assert!(replica.validate_and_receive(op.clone(), 3).is_err());
+    assert!(replica.pending_ops().is_empty());
+    assert_eq!(replica.validate_and_receive(op, 1).unwrap().len(), 1);
+}
+
+#[test]
+fn test_text_remove_then_insert() {
+    let mut text1 = Text::from_str("hello");
+    let mut replica = CausalReplica::new(Text::from_state(text1.clone_state(), Some(2)).unwrap());
+    let op1 = text1.replace(0, 5, "").unwrap().unwrap();
+    let op2 = text1.replace(0, 0, "bye").unwrap().unwrap();
+
+    assert_eq!(replica.receive(op1).len(), 1);
+    assert_eq!(replica.receive(op2).len(), 1);
+    assert!(replica.pending_ops().is_empty());
+    assert_eq!(replica.crdt().state(), text1.state());
+}
@@ -109,6 +109,43 @@ fn test_merge() {
 }
 
 
+#[test]
+fn test_merge_delta() {
+    let mut counter1 = Counter::new(1);
+    let mut counter2 = Counter::from_state(counter1.clone_state(), Some(2)).unwrap();
+    let mut counter3 = Counter::from_state(counter1.clone_state(), Some(3)).unwrap();
+    let _ = counter2.increment(5);
+    let _ = counter3.increment(-2);
+    counter2.merge(counter3.state());
+
+    let delta = counter2.delta_since(counter1.summary());
+    counter1.merge_delta(delta.clone());
+    assert_eq!(counter1.get(), 4);
+    assert_eq!(counter1.state(), counter2.state());
+    assert!(counter2.delta_since(counter1.summary()) != delta);
+}
+
+#[test]
+fn test_summary() {
+    let mut counter1 = Counter::new(1);
+    let mut counter2 = Counter::from_state(counter1.clone_state(), Some(2)).unwrap();
+    let mut counter3 = Counter::from_state(counter1.clone_state(), None).unwrap();
+    let op1 = counter1.increment(3).unwrap();
+    let _ = counter2.increment(4).unwrap();
+    assert!(counter3.increment(5).is_err());
+
+    let _ = counter2.execute_op(&op1);
+    counter1.merge(counter2.clone_state());
+    let _ = counter3.add_site_id(3).unwrap();
+    counter1.merge(counter3.clone_state());
+    assert!(counter1.summary().contains_pair(1, 2));
+    assert!(counter1.summary().contains_pair(2, 1));
+    assert!(counter1.summary().contains_pair(3, 1));
+    assert!(!counter1.summary().contains_pair(1, 3));
+    assert!(counter2.summary().contains_pair(1, 2));
+    assert!(!counter3.summary().contains_pair(0, 1));
+}
+
 #[test]
 fn test_add_site_id() {
     let mut counter = Counter::from_state(Counter::new(0).clone_state(), None).unwrap();
@@ -0,0 +1,149 @@
+extern crate ditto;
+extern crate serde_json;
+
+
+use ditto::{Crdt, Counter, Json, List, Map, Register, Set, Text};
+use std::collections::{HashMap, HashSet};
+
+#[test]
+fn test_counter() {
+    let counter = Counter::new(5);
+    let (replica1, replica2) = replicate_ops(counter, |c| c.increment(3).unwrap(), |c| c.increment(-1).unwrap());
+    assert_eq!(replica1.local_value(), 7);
+    assert_eq!(replica1.clone_state(), replica2.clone_state());
+}
+
+#[test]
+fn test_register() {
+    let register = Register::new(1u32);
+    let (replica1, replica2) = replicate_ops(register, |r| r.update(2).unwrap(), |r| r.update(3).unwrap());
+    assert_eq!(replica1.local_value(), replica2.local_value());
+    assert_eq!(replica1.clone_state(), replica2.clone_state());
+}
+
+#[test]
+fn test_set() {
+    let set: Set<u32> = Set::new();
+    let (replica1, replica2) = replicate_ops(set, |s| s.insert(1).unwrap(), |s| s.insert(2).unwrap());
+    assert_eq!(replica1.local_value(), [1, 2].iter().cloned().collect::<HashSet<u32>>());
+    assert_eq!(replica1.clone_state(), replica2.clone_state());
+}
+
+#[test]
+fn test_map() {
+    let map: Map<u32, bool> = Map::new();
+    let (replica1, replica2) = replicate_ops(map, |m| m.insert(1, true).unwrap(), |m| m.insert(2, false).unwrap());
+    let expected: HashMap<u32, bool> = [(1, true), (2, false)].iter().cloned().collect();
+    assert_eq!(replica1.local_value(), expected);
+    assert_eq!(replica1.clone_state(), replica2.clone_state());
+}
+
+#[test]
+fn test_list() {
+    let list = List::from(vec![1u32, 2, 3]);
+    let (replica1, replica2) = replicate_ops(list, |l| l.push(4).unwrap(), |l| l.remove(0).1.unwrap());
+    assert_eq!(replica1.local_value(), vec![2, 3, 4]);
+    assert_eq!(replica1.clone_state(), replica2.clone_state());
+}
+
+#[test]
+fn test_text() {
+    let text = replicate_state(&Text::from_str("hello\n"), 1);
+    let (replica1, replica2) = replicate_ops(text, |t| t.replace(6, 0, "!").unwrap().unwrap(), |t| t.replace(0, 0, "Oh, ").unwrap().unwrap());
+    assert_eq!(replica1.local_value(), "Oh, hello\n!");
+    assert_eq!(replica1.clone_state(), replica2.clone_state());
+}
+
+#[test]
+fn test_json() {
+    let json = Json::from_str(r#"{"a":[1.0]}"#).unwrap();
+    let (replica1, replica2) = replicate_ops(json, |j| j.insert("/b", true).unwrap(), |j| j.insert("/a/1", 2.0).unwrap());
+    assert_eq!(replica1.local_value(), serde_json::from_str::<serde_json::Value>(r#"{"a":[1.0,2.0],"b":true}"#).unwrap());
+    assert_eq!(replica1.clone_state(), replica2.clone_state());
+}
+
+#[test]
+fn test_merge() {
+    let mut list1 = List::from(vec![1u32, 2, 3]);
+    let mut list2 = replicate_state(&list1, 2);
+    let _ = list1.remove(0);
+    let _ = list2.push(4);
+    merge_both(&mut list1, &mut list2);
+    assert_eq!(list1.local_value(), vec![2, 3, 4]);
+
+    let mut counter1 = Counter::new(0);
+    let mut counter2 = replicate_state(&counter1, 2);
+    let _ = counter1.increment(10);
+    let _ = counter2.increment(20);
+    merge_both(&mut counter1, &mut counter2);
+    assert_eq!(counter1.local_value(), 30);
+}
+
+#[test]
+fn test_merge_delta() {
+    let mut text1 = replicate_state(&Text::from_str("abc"), 1);
+    let mut text2 = replicate_state(&text1, 2);
+    let _ = text1.replace(0, 0, "A");
+    let _ = text2.replace(3, 0, "d");
+    merge_deltas(&mut text1, &mut text2);
+    assert_eq!(text1.local_value(), "Aabcd");
+    assert_eq!(text1.clone_state(), text2.clone_state());
+
+    let mut counter1 = Counter::new(0);
+    let mut counter2 = replicate_state(&counter1, 2);
+    let _ = counter1.increment(10);
+    let _ = counter2.increment(20);
+    merge_deltas(&mut counter1, &mut counter2);
+    assert_eq!(counter1.local_value(), 30);
+    assert_eq!(counter1.clone_state(), counter2.clone_state());
+}
+
+#[test]
+fn test_add_site_id() {
+    let text1 = Text::from_str("abc");
+    let mut text2: Text = Crdt::from_state(text1.clone_state(), None).unwrap();
+    assert!(text2.replace(3, 0, "d").unwrap().is_err());
+    let ops = Crdt::add_site_id(&mut text2, 2).unwrap();
+    assert_eq!(ops.len(), 1);
+
+    let register1 = Register::new(1u32);
+    let mut register2: Register<u32> = Crdt::from_state(register1.clone_state(), None).unwrap();
+    assert!(register2.update(2).is_err());
+    let ops = Crdt::add_site_id(&mut register2, 2).unwrap();
+    assert_eq!(ops.len(), 1);
+}
+
+fn replicate_state<C: Crdt>(crdt: &C, site_id: u32) -> C {
+    let state: C::State = serde_json::from_str(&serde_json::to_string(&crdt.clone_state()).unwrap()).unwrap();
+    C::from_state(state, Some(site_id)).unwrap()
+}
+
+fn replicate_ops<C, F1, F2>(crdt: C, edit1: F1, edit2: F2) -> (C, C)
+    where C: Crdt,
+          F1: FnOnce(&mut C) -> C::Op,
+          F2: FnOnce(&mut C) -> C::Op,
+{
+    let mut replica2 = replicate_state(&crdt, 2);
+    let mut replica1 = crdt;
+
+    let op1 = edit1(&mut replica1);
+    let op2 = edit2(&mut replica2);
+
+    let _ = replica1.validate_and_execute_op(op2, 2).unwrap();
+    let _ = replica2.validate_and_execute_op(op1, 1).unwrap();
+    (replica1, replica2)
+}
+
+fn merge_deltas<C: Crdt>(crdt1: &mut C, crdt2: &mut C) {
+    let delta1 = crdt1.delta_since(&crdt2.summary());
+    let delta2 = crdt2.delta_since(&crdt1.summary());
+    let delta1: C::Delta = serde_json::from_str(&serde_json::to_string(&delta1).unwrap()).unwrap();
+    crdt1.merge_delta(delta2).unwrap();
+    crdt2.merge_delta(delta1).unwrap();
+}
+
+fn merge_both<C: Crdt>(crdt1: &mut C, crdt2: &mut C) {
+    let state1 = crdt1.clone_state();
+    crdt1.merge(crdt2.clone_state()).unwrap();
+    crdt2.merge(state1).unwrap();
+}
@@ -0,0 +1,182 @@
+extern crate ditto;
+#[macro_use] extern crate ditto_derive;
+
+mod common;
+use ditto::{text, Counter, Crdt, Error, Register, Set, Text};
+use std::collections::HashSet;
+
+#[derive(Clone, Crdt)]
+struct Issue {
+    title: Text,
+    tags: Set<String>,
+    votes: Counter,
+    assignee: Register<u32>,
+}
+
+fn new_issue() -> IssueCrdt {
+    IssueCrdt::new(Issue{
+        title: Text::from("crash"),
+        tags: Set::new(),
+        votes: Counter::new(0),
+        assignee: Register::new(0),
+    })
+}
+
+#[test]
+fn test_new() {
+    let issue = new_issue();
+    assert_eq!(issue.site_id(), 1);
+    assert!(issue.summary().contains_pair(1, 1));
+    assert!(!issue.summary().contains_pair(1, 2));
+
+    let value = issue.local_value();
+    assert_eq!(value.title, "crash");
+    assert!(value.tags.is_empty());
+    assert_eq!(value.votes, 0);
+    assert_eq!(value.assignee, 0);
+}
+
+#[test]
+fn test_edit() {
+    let mut issue1 = new_issue();
+    let mut issue2 = IssueCrdt::from_state(issue1.clone_state(), Some(2)).unwrap();
+
+    let op1 = issue1.edit_title(|title| title.replace(5, 0, " on save").unwrap()).unwrap();
+    let op2 = issue1.edit_tags(|tags| tags.insert("bug".into())).unwrap();
+    let op3 = issue1.edit_votes(|votes| votes.increment(2)).unwrap();
+    let op4 = issue1.edit_assignee(|assignee| assignee.update(7)).unwrap();
+    assert!(issue1.summary().contains_pair(1, 5));
+    assert_eq!(issue1.value().title.local_value(), "crash on save");
+
+    let edit = text::LocalOp{idx: 5, len: 0, text: " on save".into()};
+    assert_eq!(issue2.execute_op(op1), IssueLocalOp::Title(vec![edit]));
+    assert_eq!(issue2.execute_op(op2), IssueLocalOp::Tags(vec![ditto::set::LocalOp::Insert("bug".into())]));
+    assert_eq!(issue2.execute_op(op3), IssueLocalOp::Votes(vec![2]));
+    assert_eq!(issue2.execute_op(op4), IssueLocalOp::Assignee(vec![7]));
+
+    assert_eq!(issue1.clone_state(), issue2.clone_state());
+    assert_eq!(issue1.local_value(), issue2.local_value());
+}
+
+#[test]
+fn test_edit_error() {
+    let mut issue = new_issue();
+    assert_eq!(issue.edit_votes(|_| Err(Error::OutOfBounds)), Err(Error::OutOfBounds));
+    assert!(!issue.summary().contains_pair(1, 2));
+}
+
+#[test]
+fn test_execute_op_concurrent() {
+    let mut issue1 = new_issue();
+    let mut issue2 = IssueCrdt::from_state(issue1.clone_state(), Some(2)).unwrap();
+
+    let op1 = issue1.edit_votes(|votes| votes.increment(1)).unwrap();
+    let op2 = issue2.edit_votes(|votes| votes.increment(3)).unwrap();
+    let op3 = issue1.edit_assignee(|assignee| assignee.update(1)).unwrap();
+    let op4 = issue2.edit_assignee(|assignee| assignee.update(2)).unwrap();
+
+    let _ = issue1.execute_op(op2);
+    let _ = issue1.execute_op(op4);
+    let _ = issue2.execute_op(op1);
+    let _ = issue2.execute_op(op3);
+
+    assert_eq!(issue1.clone_state(), issue2.clone_state());
+    assert_eq!(issue1.local_value().votes, 4);
+}
+
+#[test]
+fn test_validate_and_execute_op() {
+    let mut issue1 = new_issue();
+    let mut issue2 = IssueCrdt::from_state(issue1.clone_state(), Some(2)).unwrap();
+    let op = issue1.edit_votes(|votes| votes.increment(1)).unwrap();
+
+    assert_eq!(issue2.validate_and_execute_op(op.clone(), 2), Err(Error::InvalidOp));
+    assert_eq!(issue2.validate_and_execute_op(op, 1), Ok(IssueLocalOp::Votes(vec![1])));
+}
+
+#[test]
+fn test_merge() {
+    let mut issue1 = new_issue();
+    let mut issue2 = IssueCrdt::from_state(issue1.clone_state(), Some(2)).unwrap();
+
+    let _ = issue1.edit_title(|title| title.replace(0, 0, "bad ").unwrap()).unwrap();
+    let _ = issue1.edit_votes(|votes| votes.increment(1)).unwrap();
+    let _ = issue2.edit_title(|title| title.replace(5, 0, "!").unwrap()).unwrap();
+    let _ = issue2.edit_tags(|tags| tags.insert("ui".into())).unwrap();
+
+    let issue1_state = issue1.clone_state();
+    let local_ops = issue1.merge(issue2.clone_state()).unwrap();
+    assert_eq!(local_ops, [
+        IssueLocalOp::Title(vec![text::LocalOp{idx: 9, len: 0, text: "!".into()}]),
+        IssueLocalOp::Tags(vec![ditto::set::LocalOp::Insert("ui".into())]),
+    ]);
+
+    let _ = issue2.merge(issue1_state).unwrap();
+    assert_eq!(issue1.clone_state(), issue2.clone_state());
+
+    let value = issue1.local_value();
+    assert_eq!(value.title, "bad crash!");
+    assert_eq!(value.tags, ["ui".to_owned()].iter().cloned().collect::<HashSet<String>>());
+    assert_eq!(value.votes, 1);
+    assert!(issue1.merge(issue2.clone_state()).unwrap().is_empty());
+}
+
+#[test]
+fn test_merge_delta() {
+    let mut issue1 = new_issue();
+    let mut issue2 = IssueCrdt::from_state(issue1.clone_state(), Some(2)).unwrap();
+
+    let _ = issue1.edit_votes(|votes| votes.increment(5)).unwrap();
+    let _ = issue2.edit_assignee(|assignee| assignee.update(9)).unwrap();
+
+    let delta1 = issue1.delta_since(&issue2.summary());
+    let delta2 = issue2.delta_since(&issue1.summary());
+    assert!(delta1.votes.is_some());
+    assert!(delta1.assignee.is_none());
+    assert!(delta2.votes.is_none());
+
+    issue1.merge_delta(delta2).unwrap();
+    issue2.merge_delta(delta1).unwrap();
+    assert_eq!(issue1.clone_state(), issue2.clone_state());
+    assert_eq!(issue1.local_value().votes, 5);
+    assert_eq!(issue1.local_value().assignee, 9);
+}
+
+#[test]
+fn test_add_site_id() {
+    let issue1 = new_issue();
+    let mut issue2 = IssueCrdt::from_state(issue1.clone_state(), None).unwrap();
+
+    assert_eq!(issue2.edit_title(|title| title.replace(0, 5, "hang").unwrap()), Err(Error::AwaitingSiteId));
+    assert_eq!(issue2.edit_votes(|votes| votes.increment(1)), Err(Error::AwaitingSiteId));
+    assert_eq!(issue2.local_value().title, "hang");
+
+    let ops = issue2.add_site_id(3).unwrap();
+    assert_eq!(ops.len(), 2);
+    assert_eq!(issue2.site_id(), 3);
+    assert_eq!(issue2.add_site_id(4), Err(Error::AlreadyHasSiteId));
+
+    let mut issue3 = IssueCrdt::from_state(issue1.clone_state(), Some(4)).unwrap();
+    for op in ops {
+        let _ = issue3.validate_and_execute_op(op, 3).unwrap();
+    }
+    assert_eq!(issue2.clone_state(), issue3.clone_state());
+}
+
+#[test]
+fn test_from_state_invalid_site_id() {
+    let issue = new_issue();
+    assert!(IssueCrdt::from_state(issue.clone_state(), Some(0)).is_err());
+}
+
+#[test]
+fn test_serialize() {
+    let mut issue1 = new_issue();
+    let issue2 = IssueCrdt::from_state(issue1.clone_state(), Some(2)).unwrap();
+    let op = issue1.edit_tags(|tags| tags.insert("bug".into())).unwrap();
+
+    common::test_serde(issue1.clone_state());
+    common::test_serde(issue1.delta_since(&issue2.summary()));
+    common::test_serde(op);
+    common::test_serde(IssueLocalOp::Votes(vec![1, -2]));
+}
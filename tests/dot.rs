@@ -0,0 +1,152 @@
+extern crate ditto;
+extern crate serde_json;
+
+mod common;
+
+use common::test_serde;
+use ditto::dot::{Dot, Summary};
+use std::cmp::Ordering;
+
+#[test]
+fn test_insert_out_of_order() {
+    let mut summary = Summary::default();
+    summary.insert(Dot::new(1, 1));
+    summary.insert(Dot::new(1, 4));
+    summary.insert(Dot::new(1, 7));
+
+    assert_eq!(summary.get(1), 7);
+    assert!(summary.contains(&Dot::new(1, 1)));
+    assert!(!summary.contains(&Dot::new(1, 2)));
+    assert!(!summary.contains(&Dot::new(1, 3)));
+    assert!(summary.contains(&Dot::new(1, 4)));
+    assert!(!summary.contains(&Dot::new(1, 6)));
+    assert!(!summary.contains(&Dot::new(1, 8)));
+    assert!(summary.has_gaps());
+
+    summary.insert(Dot::new(1, 5));
+    summary.insert(Dot::new(1, 3));
+    summary.insert(Dot::new(1, 2));
+    assert!(summary.contains(&Dot::new(1, 5)));
+    assert!(!summary.contains(&Dot::new(1, 6)));
+
+    summary.insert(Dot::new(1, 6));
+    assert!(!summary.has_gaps());
+    assert!((1..8).all(|counter| summary.contains_pair(1, counter)));
+}
+
+#[test]
+fn test_insert_up_to() {
+    let mut summary = Summary::default();
+    summary.insert(Dot::new(1, 3));
+    summary.insert(Dot::new(1, 8));
+    summary.insert_up_to(Dot::new(1, 5));
+
+    assert_eq!(summary.get(1), 8);
+    assert!((1..6).all(|counter| summary.contains_pair(1, counter)));
+    assert!(!summary.contains_pair(1, 6));
+    assert!(!summary.contains_pair(1, 7));
+    assert!(summary.contains_pair(1, 8));
+}
+
+#[test]
+fn test_merge() {
+    let mut summary1 = Summary::default();
+    summary1.insert(Dot::new(1, 1));
+    summary1.insert(Dot::new(1, 3));
+    summary1.insert(Dot::new(1, 6));
+    summary1.insert(Dot::new(2, 1));
+
+    let mut summary2 = Summary::default();
+    summary2.insert(Dot::new(1, 2));
+    summary2.insert(Dot::new(1, 4));
+    summary2.insert(Dot::new(3, 2));
+
+    let mut merged1 = summary1.clone();
+    let mut merged2 = summary2.clone();
+    merged1.merge(&summary2);
+    merged2.merge(&summary1);
+    assert_eq!(merged1, merged2);
+
+    assert_eq!(merged1.get(1), 6);
+    assert!((1..5).all(|counter| merged1.contains_pair(1, counter)));
+    assert!(!merged1.contains_pair(1, 5));
+    assert!(merged1.contains_pair(1, 6));
+    assert!(merged1.contains_pair(2, 1));
+    assert!(!merged1.contains_pair(3, 1));
+    assert!(merged1.contains_pair(3, 2));
+
+    merged1.merge(&summary1);
+    assert_eq!(merged1, merged2);
+}
+
+#[test]
+fn test_serialize() {
+    let mut summary = Summary::default();
+    summary.insert(Dot::new(1, 1));
+    summary.insert(Dot::new(1, 2));
+    assert_eq!(serde_json::to_string(&summary).unwrap(), "[[1,2]]");
+    test_serde(summary.clone());
+
+    summary.insert(Dot::new(1, 5));
+    summary.insert(Dot::new(1, 9));
+    assert_eq!(serde_json::to_string(&summary).unwrap(), "[[1,9,[[3,4],[6,8]]]]");
+    test_serde(summary);
+}
+
+#[test]
+fn test_deserialize_invalid_gaps() {
+    assert!(serde_json::from_str::<Summary>("[[1,9,[[3,4],[6,8]]]]").is_ok());
+    assert!(serde_json::from_str::<Summary>("[[1,9,[[6,8],[3,4]]]]").is_err());
+    assert!(serde_json::from_str::<Summary>("[[1,9,[[3,6],[5,8]]]]").is_err());
+    assert!(serde_json::from_str::<Summary>("[[1,9,[[3,4],[6,9]]]]").is_err());
+    assert!(serde_json::from_str::<Summary>("[[1,9,[[6,12]]]]").is_err());
+    assert!(serde_json::from_str::<Summary>("[[1,9,[[4,3]]]]").is_err());
+    assert!(serde_json::from_str::<Summary>("[[1,9,[[0,3]]]]").is_err());
+}
+
+#[test]
+fn test_compare() {
+    let mut summary1 = Summary::default();
+    let mut summary2 = Summary::default();
+    assert_eq!(summary1.compare(&summary2), Some(Ordering::Equal));
+
+    summary1.insert(Dot::new(1, 1));
+    summary1.insert(Dot::new(1, 3));
+    assert_eq!(summary1.compare(&summary2), Some(Ordering::Greater));
+    assert_eq!(summary2.compare(&summary1), Some(Ordering::Less));
+
+    summary2.insert(Dot::new(1, 2));
+    assert_eq!(summary1.compare(&summary2), None);
+    assert_eq!(summary2.compare(&summary1), None);
+
+    summary2.merge(&summary1);
+    assert_eq!(summary1.compare(&summary2), Some(Ordering::Less));
+
+    summary1.insert(Dot::new(1, 2));
+    assert_eq!(summary1.compare(&summary2), Some(Ordering::Equal));
+}
+
+#[test]
+fn test_missing_from() {
+    let mut summary1 = Summary::default();
+    for counter in 1..10 { summary1.insert(Dot::new(1, counter)); }
+    summary1.insert(Dot::new(2, 4));
+    summary1.insert(Dot::new(3, 1));
+
+    let mut summary2 = Summary::default();
+    summary2.insert_up_to(Dot::new(1, 3));
+    summary2.insert(Dot::new(1, 6));
+    summary2.insert(Dot::new(2, 2));
+    summary2.insert_up_to(Dot::new(3, 1));
+    summary2.insert(Dot::new(4, 1));
+
+    let missing = summary1.missing_from(&summary2);
+    assert_eq!(missing.len(), 2);
+    assert_eq!(missing[&1], [(4, 5), (7, 9)]);
+    assert_eq!(missing[&2], [(4, 4)]);
+
+    let missing = summary2.missing_from(&summary1);
+    assert_eq!(missing.len(), 2);
+    assert_eq!(missing[&2], [(2, 2)]);
+    assert_eq!(missing[&4], [(1, 1)]);
+}
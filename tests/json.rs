@@ -3,6 +3,7 @@ extern crate serde_json;
 extern crate ditto;
 
 mod common;
+use ditto::{CausalOp, Counter, Register};
 use ditto::json::*;
 
 #[test]
@@ -15,6 +16,666 @@ fn test_len() {
     assert_eq!(crdt.len("/baz"), None);
 }
 
+#[test]
+fn test_merge_local_ops() {
+    let mut crdt1 = Json::from_str(r#"{"a":{"b":[1.0,2.0]},"c":"hello","d":"x"}"#).unwrap();
+    let mut crdt2 = Json::from_state(crdt1.clone_state(), Some(2)).unwrap();
+    let _ = crdt2.insert("/a/e", true).unwrap();
+    let _ = crdt2.remove("/a/b/0").unwrap();
+    let _ = crdt2.replace_text("/c", 5, 0, "!").unwrap();
+    let _ = crdt2.insert("/d", 1.0).unwrap();
+
+    let local_ops = crdt1.merge(crdt2.clone_state()).unwrap();
+    let pointer = |keys: &[&str]| -> Vec<LocalUid> {
+        keys.iter().map(|key| match key.parse() {
+            Ok(idx) => LocalUid::Array(idx),
+            Err(_) => LocalUid::Object(key.to_string()),
+        }).collect()
+    };
+
+    assert_eq!(local_ops.len(), 4);
+    assert!(local_ops.contains(&LocalOp::Remove{pointer: pointer(&["a", "b", "0"])}));
+    assert!(local_ops.contains(&LocalOp::Insert{pointer: pointer(&["a", "e"]), value: json!(true)}));
+    assert!(local_ops.contains(&LocalOp::Insert{pointer: pointer(&["d"]), value: json!(1.0)}));
+    assert!(local_ops.iter().any(|op| match op {
+        LocalOp::ReplaceText{pointer: p, changes} => *p == pointer(&["c"]) && changes.len() == 1 && changes[0].text == "!",
+        _ => false,
+    }));
+    assert!(crdt1.merge(crdt2.clone_state()).unwrap().is_empty());
+}
+
+#[test]
+fn test_merge_delta() {
+    let mut crdt1 = Json::from_str(r#"{"a":{"b":[1.0,2.0]},"c":"hello"}"#).unwrap();
+    let mut crdt2 = Json::from_state(crdt1.clone_state(), Some(2)).unwrap();
+    let _ = crdt1.insert("/a/b/2", 3.0).unwrap();
+    let _ = crdt1.remove("/c").unwrap();
+    let _ = crdt2.insert("/a/d", true).unwrap();
+    let _ = crdt2.remove("/a/b/0").unwrap();
+    let _ = crdt2.replace_text("/c", 5, 0, "!").unwrap();
+
+    let delta1 = crdt1.delta_since(crdt2.summary());
+    let delta2 = crdt2.delta_since(crdt1.summary());
+    crdt1.merge_delta(delta2).unwrap();
+    crdt2.merge_delta(delta1).unwrap();
+
+    assert_eq!(crdt1.state(), crdt2.state());
+    assert_eq!(crdt1.local_value(), json!({"a":{"b":[2.0,3.0],"d":true}}));
+}
+
+#[test]
+fn test_merge_delta_wrong_type() {
+    let crdt1 = Json::from_str(r#"{"a":1.0}"#).unwrap();
+    let mut crdt2 = Json::from_state(Json::from_str("[]").unwrap().clone_state(), Some(2)).unwrap();
+    let delta = crdt1.delta_since(&Default::default());
+    assert!(crdt2.merge_delta(delta).is_err());
+}
+
+#[test]
+fn test_undo_redo() {
+    let mut crdt = Json::from_str(r#"{"a":1.0,"b":[1.0,2.0,3.0],"c":"hello"}"#).unwrap();
+    let mut undo = UndoManager::new();
+    assert!(!undo.can_undo());
+
+    let _ = undo.insert(&mut crdt, "/a", 5.0).unwrap();
+    let _ = undo.insert(&mut crdt, "/d", true).unwrap();
+    let _ = undo.remove(&mut crdt, "/b/1").unwrap();
+    let _ = undo.replace_text(&mut crdt, "/c", 5, 0, " world").unwrap();
+    assert_eq!(crdt.local_value(), json!({"a":5.0,"b":[1.0,3.0],"c":"hello world","d":true}));
+
+    let (local_op, _) = undo.undo(&mut crdt).unwrap();
+    match local_op {
+        LocalOp::ReplaceText{pointer, ..} => assert_eq!(pointer, vec![LocalUid::Object("c".into())]),
+        _ => panic!("expected a text change"),
+    }
+    let (local_op, _) = undo.undo(&mut crdt).unwrap();
+    assert_eq!(local_op, LocalOp::Insert{pointer: vec![LocalUid::Object("b".into()), LocalUid::Array(1)], value: json!(2.0)});
+    let (local_op, _) = undo.undo(&mut crdt).unwrap();
+    assert_eq!(local_op, LocalOp::Remove{pointer: vec![LocalUid::Object("d".into())]});
+    let _ = undo.undo(&mut crdt).unwrap();
+    assert!(undo.undo(&mut crdt).is_none());
+    assert_eq!(crdt.local_value(), json!({"a":1.0,"b":[1.0,2.0,3.0],"c":"hello"}));
+
+    while undo.redo(&mut crdt).is_some() {}
+    assert!(!undo.can_redo());
+    assert_eq!(crdt.local_value(), json!({"a":5.0,"b":[1.0,3.0],"c":"hello world","d":true}));
+}
+
+#[test]
+fn test_undo_ops() {
+    let mut crdt1 = Json::from_str(r#"{"a":{"x":"abc"},"b":[1.0,2.0]}"#).unwrap();
+    let mut crdt2 = Json::from_state(crdt1.clone_state(), Some(2)).unwrap();
+    let mut undo = UndoManager::new();
+
+    let op1 = undo.remove(&mut crdt1, "/a").unwrap();
+    let op2 = undo.remove(&mut crdt1, "/b/0").unwrap();
+    let (_, op3) = undo.undo(&mut crdt1).unwrap();
+    let (_, op4) = undo.undo(&mut crdt1).unwrap();
+
+    for op in [op1, op2, op3.unwrap(), op4.unwrap()] {
+        let _ = crdt2.execute_op(op);
+    }
+    assert_eq!(crdt1.local_value(), json!({"a":{"x":"abc"},"b":[1.0,2.0]}));
+    assert_eq!(crdt1.state(), crdt2.state());
+}
+
+#[test]
+fn test_undo_keeps_remote_edits() {
+    let mut crdt1 = Json::from_str(r#"{"a":1.0,"b":[1.0,2.0,3.0]}"#).unwrap();
+    let mut crdt2 = Json::from_state(crdt1.clone_state(), Some(2)).unwrap();
+    let mut undo = UndoManager::new();
+
+    let op1 = undo.insert(&mut crdt1, "/a", 2.0).unwrap();
+    let op2 = undo.remove(&mut crdt1, "/b/1").unwrap();
+    let op3 = crdt2.insert("/b/3", 4.0).unwrap();
+    let op4 = crdt2.insert("/c", "hi").unwrap();
+
+    let _ = crdt1.execute_op(op3);
+    let _ = crdt1.execute_op(op4);
+    let _ = crdt2.execute_op(op1);
+    let _ = crdt2.execute_op(op2);
+
+    let (_, op5) = undo.undo(&mut crdt1).unwrap();
+    let (_, op6) = undo.undo(&mut crdt1).unwrap();
+    let _ = crdt2.execute_op(op5.unwrap());
+    let _ = crdt2.execute_op(op6.unwrap());
+
+    assert_eq!(crdt1.local_value(), json!({"a":1.0,"b":[1.0,2.0,3.0,4.0],"c":"hi"}));
+    assert_eq!(crdt1.state(), crdt2.state());
+}
+
+#[test]
+fn test_undo_skips_overwritten_value() {
+    let mut crdt1 = Json::from_str(r#"{"a":1.0}"#).unwrap();
+    let mut crdt2 = Json::from_state(crdt1.clone_state(), Some(2)).unwrap();
+    let mut undo = UndoManager::new();
+
+    let op1 = undo.insert(&mut crdt1, "/a", 2.0).unwrap();
+    let _ = crdt2.execute_op(op1);
+    let op2 = crdt2.insert("/a", 3.0).unwrap();
+    let _ = crdt1.execute_op(op2);
+
+    assert!(undo.undo(&mut crdt1).is_none());
+    assert!(!undo.can_redo());
+    assert_eq!(crdt1.local_value(), json!({"a":3.0}));
+}
+
+#[test]
+fn test_undo_moved_array_element() {
+    let mut crdt1 = Json::from_str(r#"{"b":[1.0,2.0,3.0]}"#).unwrap();
+    let mut crdt2 = Json::from_state(crdt1.clone_state(), Some(2)).unwrap();
+    let mut undo = UndoManager::new();
+
+    let op1 = crdt1.move_item("/b/0", 2).unwrap();
+    let op2 = undo.remove(&mut crdt1, "/b/2").unwrap();
+    assert_eq!(crdt1.local_value(), json!({"b":[2.0,3.0]}));
+
+    let (local_op, op3) = undo.undo(&mut crdt1).unwrap();
+    assert_eq!(local_op, LocalOp::Insert{pointer: vec![LocalUid::Object("b".into()), LocalUid::Array(2)], value: json!(1.0)});
+    assert_eq!(crdt1.local_value(), json!({"b":[2.0,3.0,1.0]}));
+
+    for op in [op1, op2, op3.unwrap()] {
+        let _ = crdt2.execute_op(op);
+    }
+    assert_eq!(crdt1.state(), crdt2.state());
+}
+
+#[test]
+fn test_undo_restores_counter() {
+    let mut crdt1 = Json::from_str(r#"{}"#).unwrap();
+    let mut crdt2 = Json::from_state(crdt1.clone_state(), Some(2)).unwrap();
+    let mut undo = UndoManager::new();
+
+    let op1 = undo.insert(&mut crdt1, "/likes", Counter::new(5)).unwrap();
+    let op2 = crdt1.increment("/likes", 2).unwrap();
+    let op3 = undo.remove(&mut crdt1, "/likes").unwrap();
+    let (_, op4) = undo.undo(&mut crdt1).unwrap();
+    let op5 = crdt1.increment("/likes", 1).unwrap();
+    assert_eq!(crdt1.local_value(), json!({"likes":8.0}));
+
+    for op in [op1, op2, op3, op4.unwrap(), op5] {
+        let _ = crdt2.validate_and_execute_op(op, 1).unwrap();
+    }
+    assert_eq!(crdt1.state(), crdt2.state());
+}
+
+#[test]
+fn test_set_value() {
+    let mut crdt1 = Json::from_str(r#"{"a":[1,2,3,4],"b":"hello world","c":{"d":true},"e":null}"#).unwrap();
+    let mut crdt2 = Json::from_state(crdt1.clone_state(), Some(2)).unwrap();
+    let target = json!({"a":[1.0,3.0,4.0,5.0],"b":"hello, world","c":{"d":false,"f":[]},"g":"new"});
+
+    let ops = crdt1.set_value(target.clone()).unwrap();
+    assert_eq!(ops.len(), 7);
+    assert_eq!(crdt1.local_value(), target);
+    assert!(crdt1.set_value(target.clone()).unwrap().is_empty());
+
+    for op in ops { let _ = crdt2.execute_op(op); }
+    assert_eq!(crdt1.state(), crdt2.state());
+}
+
+#[test]
+fn test_set_value_concurrent_edits() {
+    let mut crdt1 = Json::from_str(r#"{"list":[{"x":1},{"x":2}],"text":"hello"}"#).unwrap();
+    let mut crdt2 = Json::from_state(crdt1.clone_state(), Some(2)).unwrap();
+
+    let ops1 = crdt1.set_value(json!({"list":[{"x":1},{"x":2,"y":3}],"text":"hello world"})).unwrap();
+    let op2 = crdt2.insert("/list/0/z", 4.0).unwrap();
+    let op3 = crdt2.replace_text("/text", 0, 0, "oh, ").unwrap();
+
+    let _ = crdt1.execute_op(op2);
+    let _ = crdt1.execute_op(op3);
+    for op in ops1 { let _ = crdt2.execute_op(op); }
+
+    assert_eq!(crdt1.state(), crdt2.state());
+    assert_eq!(crdt1.local_value(), json!({"list":[{"x":1.0,"z":4.0},{"x":2.0,"y":3.0}],"text":"oh, hello world"}));
+}
+
+#[test]
+fn test_set_value_replaces_nested_type() {
+    let mut crdt = Json::from_str(r#"{"a":[1,"x",[2]],"b":"text"}"#).unwrap();
+    let target = json!({"a":["x",[2.0,3.0],true],"b":{"c":1.0}});
+    let _ = crdt.set_value(target.clone()).unwrap();
+    assert_eq!(crdt.local_value(), target);
+}
+
+#[test]
+fn test_set_value_wrong_root_type() {
+    let mut crdt = Json::from_str(r#"{"a":1}"#).unwrap();
+    assert_eq!(crdt.set_value(json!([1])), Err(ditto::Error::WrongJsonType));
+    assert_eq!(crdt.local_value(), json!({"a":1.0}));
+
+    let mut crdt = Json::from_str("1").unwrap();
+    assert!(crdt.set_value(json!(1)).unwrap().is_empty());
+    assert_eq!(crdt.set_value(json!(2)), Err(ditto::Error::WrongJsonType));
+}
+
+#[test]
+fn test_set_value_awaiting_site() {
+    let crdt1 = Json::from_str(r#"{"a":"hi"}"#).unwrap();
+    let mut crdt2 = Json::from_state(crdt1.clone_state(), None).unwrap();
+    assert_eq!(crdt2.set_value(json!({"a":"hi!","b":1})), Err(ditto::Error::AwaitingSiteId));
+    assert_eq!(crdt2.local_value(), json!({"a":"hi!","b":1.0}));
+    assert_eq!(crdt2.add_site_id(2).unwrap().len(), 2);
+}
+
+#[test]
+fn test_apply_patch() {
+    let mut crdt1 = Json::from_str(r#"{"a":[1,2],"b":{"c":"x"},"d/e":true}"#).unwrap();
+    let mut crdt2 = Json::from_state(crdt1.clone_state(), Some(2)).unwrap();
+    let patch: Vec<PatchOp> = serde_json::from_str(r#"[
+        {"op":"test","path":"/a/1","value":2},
+        {"op":"add","path":"/a/-","value":3},
+        {"op":"add","path":"/a/0","value":0},
+        {"op":"remove","path":"/d~1e"},
+        {"op":"replace","path":"/b/c","value":"y"},
+        {"op":"move","from":"/b","path":"/f"},
+        {"op":"copy","from":"/f","path":"/a/1"}
+    ]"#).unwrap();
+
+    let ops = crdt1.apply_patch(&patch).unwrap();
+    assert_eq!(crdt1.local_value(), json!({"a":[0.0,{"c":"y"},1.0,2.0,3.0],"f":{"c":"y"}}));
+    for op in ops { let _ = crdt2.execute_op(op); }
+    assert_eq!(crdt1.state(), crdt2.state());
+}
+
+#[test]
+fn test_apply_patch_is_atomic() {
+    let mut crdt = Json::from_str(r#"{"a":[1,2]}"#).unwrap();
+    let patch = |json: &str| -> Vec<PatchOp> { serde_json::from_str(json).unwrap() };
+
+    let failed_test = patch(r#"[{"op":"add","path":"/b","value":1},{"op":"test","path":"/a/0","value":2}]"#);
+    assert_eq!(crdt.apply_patch(&failed_test), Err(ditto::Error::TestFailed));
+    assert_eq!(crdt.apply_patch(&patch(r#"[{"op":"remove","path":"/a/2"}]"#)), Err(ditto::Error::OutOfBounds));
+    assert_eq!(crdt.apply_patch(&patch(r#"[{"op":"add","path":"/c/d","value":1}]"#)), Err(ditto::Error::DoesNotExist));
+    assert_eq!(crdt.apply_patch(&patch(r#"[{"op":"move","from":"/a","path":"/a/0"}]"#)), Err(ditto::Error::InvalidPointer));
+    assert_eq!(crdt.apply_patch(&patch(r#"[{"op":"remove","path":""}]"#)), Err(ditto::Error::InvalidPointer));
+    assert_eq!(crdt.local_value(), json!({"a":[1.0,2.0]}));
+
+    let replace_root = patch(r#"[{"op":"replace","path":"","value":{"a":[2]}}]"#);
+    assert_eq!(crdt.apply_patch(&replace_root).unwrap().len(), 1);
+    assert_eq!(crdt.local_value(), json!({"a":[2.0]}));
+}
+
+#[test]
+fn test_patch_writer() {
+    let mut crdt1 = Json::from_str(r#"{"a":[1,2],"b":"hello","c~":null}"#).unwrap();
+    let mut crdt2 = Json::from_state(crdt1.clone_state(), Some(2)).unwrap();
+    let mut plain = Json::from_state(crdt1.clone_state(), Some(3)).unwrap();
+
+    let op1 = crdt1.insert("/a/1", "x").unwrap();
+    let op2 = crdt1.remove("/a/0").unwrap();
+    let op3 = crdt1.replace_text("/b", 0, 1, "j").unwrap();
+    let op4 = crdt1.insert("/c~0", true).unwrap();
+
+    let mut writer = PatchWriter::new(&crdt2);
+    let patch: Vec<PatchOp> = vec![op1, op2, op3, op4].into_iter()
+        .flat_map(|op| writer.write(&crdt2.execute_op(op).unwrap()))
+        .collect();
+
+    assert_eq!(patch[0], PatchOp::Add{path: "/a/1".into(), value: json!("x")});
+    assert_eq!(patch[1], PatchOp::Remove{path: "/a/0".into()});
+    assert_eq!(patch[2], PatchOp::Replace{path: "/b".into(), value: json!("jello")});
+    assert_eq!(patch[3], PatchOp::Add{path: "/c~0".into(), value: json!(true)});
+
+    let _ = plain.apply_patch(&patch).unwrap();
+    assert_eq!(plain.local_value(), crdt1.local_value());
+}
+
+#[test]
+fn test_apply_merge_patch() {
+    let mut crdt1 = Json::from_str(r#"{"title":"Goodbye!","author":{"givenName":"John","familyName":"Doe"},"tags":["example","sample"],"content":"This will be unchanged"}"#).unwrap();
+    let mut crdt2 = Json::from_state(crdt1.clone_state(), Some(2)).unwrap();
+    let patch = json!({"title":"Hello!","phoneNumber":"+01-123-456-7890","author":{"familyName":null},"tags":["example"],"extra":{"a":null,"b":1}});
+
+    let ops = crdt1.apply_merge_patch(&patch).unwrap();
+    assert_eq!(ops.len(), 5);
+    assert_eq!(crdt1.local_value(), json!({
+        "title":"Hello!",
+        "author":{"givenName":"John"},
+        "tags":["example"],
+        "content":"This will be unchanged",
+        "phoneNumber":"+01-123-456-7890",
+        "extra":{"b":1.0}
+    }));
+    assert!(crdt1.apply_merge_patch(&patch).unwrap().is_empty());
+
+    for op in ops { let _ = crdt2.execute_op(op); }
+    assert_eq!(crdt1.state(), crdt2.state());
+}
+
+#[test]
+fn test_apply_merge_patch_root() {
+    let mut crdt = Json::from_str(r#"{"a":{"b":1}}"#).unwrap();
+    assert_eq!(crdt.apply_merge_patch(&json!(["x"])), Err(ditto::Error::WrongJsonType));
+    assert_eq!(crdt.apply_merge_patch(&json!({"a":"x"})).unwrap().len(), 1);
+    assert_eq!(crdt.local_value(), json!({"a":"x"}));
+
+    let mut crdt = Json::from_str("[1]").unwrap();
+    assert_eq!(crdt.apply_merge_patch(&json!({"a":1})), Err(ditto::Error::WrongJsonType));
+    assert!(crdt.apply_merge_patch(&json!([1])).unwrap().is_empty());
+}
+
+#[test]
+fn test_transaction() {
+    let mut crdt1 = Json::from_str(r#"{"status":"open","notes":[]}"#).unwrap();
+    let mut crdt2 = Json::from_state(crdt1.clone_state(), Some(2)).unwrap();
+
+    let op = crdt1.transaction(|transaction| {
+        transaction.insert("/status", "done")?;
+        transaction.insert("/notes/0", "fixed")?;
+        transaction.replace_text("/notes/0", 5, 0, "!")?;
+        assert_eq!(transaction.json().local_value()["notes"][0], "fixed!");
+        transaction.remove("/status")
+    }).unwrap();
+
+    assert_eq!(crdt1.local_value(), json!({"notes":["fixed!"]}));
+    let local_op = crdt2.execute_op(op).unwrap();
+    match local_op {
+        LocalOp::Transaction{ref changes} => assert_eq!(changes.len(), 4),
+        _ => panic!("expected a transaction"),
+    }
+    assert_eq!(crdt1.state(), crdt2.state());
+
+    let mut writer = PatchWriter::new(&Json::from_str(r#"{"status":"open","notes":[]}"#).unwrap());
+    assert_eq!(writer.write(&local_op).len(), 4);
+}
+
+#[test]
+fn test_transaction_rollback() {
+    let mut crdt = Json::from_str(r#"{"a":1}"#).unwrap();
+    let result = crdt.transaction(|transaction| {
+        transaction.insert("/b", true)?;
+        transaction.remove("/c/d")
+    });
+
+    assert_eq!(result, Err(ditto::Error::DoesNotExist));
+    assert_eq!(crdt.local_value(), json!({"a":1.0}));
+    assert_eq!(crdt.transaction(|_| Ok(())), Err(ditto::Error::Noop));
+
+    let op = crdt.insert("/b", false).unwrap();
+    assert_eq!(op.inserted_dots(), [ditto::dot::Dot::new(1, 2)]);
+}
+
+#[test]
+fn test_transaction_rollback_all_edits() {
+    let mut crdt = Json::from_str(r#"{"a":1,"list":[1,2,3],"text":"abc"}"#).unwrap();
+    let _ = crdt.insert("/likes", Counter::new(1)).unwrap();
+    let _ = crdt.insert("/owner", Register::new(json!("x"))).unwrap();
+    let _ = crdt.move_item("/list/0", 2).unwrap();
+    let _ = crdt.replace_text("/text", 3, 0, "d").unwrap();
+    let state = crdt.clone_state();
+    let value = crdt.local_value();
+
+    let result = crdt.transaction(|transaction| {
+        transaction.insert("/a", 2.0)?;
+        transaction.insert("/b", json!({"c":[]}))?;
+        transaction.insert("/b/c/0", "d")?;
+        transaction.remove("/list/0")?;
+        transaction.move_item("/list/1", 0)?;
+        transaction.replace_text("/text", 1, 2, "xyz")?;
+        transaction.replace_text("/text", 5, 0, "!")?;
+        transaction.increment("/likes", 4)?;
+        transaction.update_register("/owner", json!("y"))?;
+        transaction.remove("/a")?;
+        transaction.remove("/nope/x")
+    });
+
+    assert_eq!(result, Err(ditto::Error::DoesNotExist));
+    assert_eq!(crdt.local_value(), value);
+    assert_eq!(crdt.clone_state(), state);
+
+    let _ = crdt.replace_text("/text", 4, 0, "e").unwrap();
+    assert_eq!(crdt.local_value()["text"], "abcde");
+}
+
+#[test]
+fn test_transaction_validate() {
+    let crdt1 = Json::from_str(r#"{"a":1}"#).unwrap();
+    let mut crdt2 = Json::from_state(crdt1.clone_state(), Some(2)).unwrap();
+    let mut crdt3 = Json::from_state(crdt1.clone_state(), Some(3)).unwrap();
+
+    let op = crdt2.transaction(|transaction| {
+        transaction.insert("/b", 2.0)?;
+        transaction.insert("/c", 3.0)
+    }).unwrap();
+
+    assert!(crdt3.validate_and_execute_op(op.clone(), 3).is_err());
+    assert_eq!(crdt3.local_value(), json!({"a":1.0}));
+    assert!(crdt3.validate_and_execute_op(op.clone(), 2).is_ok());
+    assert_eq!(crdt3.state(), crdt2.state());
+}
+
+#[test]
+fn test_transaction_awaiting_site() {
+    let crdt1 = Json::from_str(r#"{"a":"x"}"#).unwrap();
+    let mut crdt2 = Json::from_state(crdt1.clone_state(), None).unwrap();
+    let mut crdt3 = Json::from_state(crdt1.clone_state(), Some(3)).unwrap();
+
+    let result = crdt2.transaction(|transaction| {
+        transaction.insert("/b", "y")?;
+        transaction.replace_text("/b", 1, 0, "z")
+    });
+    assert_eq!(result, Err(ditto::Error::AwaitingSiteId));
+
+    let ops = crdt2.add_site_id(2).unwrap();
+    assert_eq!(ops.len(), 1);
+    assert!(ops[0].inserted_dots().iter().all(|dot| dot.site_id == 2));
+    let _ = crdt3.validate_and_execute_op(ops[0].clone(), 2).unwrap();
+    assert_eq!(crdt3.state(), crdt2.state());
+    assert_eq!(crdt3.local_value(), json!({"a":"x","b":"yz"}));
+}
+
+#[test]
+fn test_move_item() {
+    let mut crdt1 = Json::from_str(r#"{"cards":[{"title":"a"},{"title":"b"},{"title":"c"}]}"#).unwrap();
+    let mut crdt2 = Json::from_state(crdt1.clone_state(), Some(2)).unwrap();
+    let op1 = crdt1.move_item("/cards/0", 2).unwrap();
+    let op2 = crdt2.insert("/cards/0/done", true).unwrap();
+    let op3 = crdt2.replace_text("/cards/0/title", 1, 0, "!").unwrap();
+
+    let local_op = crdt2.execute_op(op1).unwrap();
+    assert_eq!(local_op, LocalOp::Move{pointer: vec![LocalUid::Object("cards".into()), LocalUid::Array(0)], to: 2});
+    let _ = crdt1.execute_op(op2).unwrap();
+    let _ = crdt1.execute_op(op3).unwrap();
+
+    assert_eq!(crdt1.state(), crdt2.state());
+    assert_eq!(crdt1.local_value(), json!({"cards":[{"title":"b"},{"title":"c"},{"title":"a!","done":true}]}));
+}
+
+#[test]
+fn test_move_item_concurrent() {
+    let mut crdt1 = Json::from_str(r#"[1,2,3]"#).unwrap();
+    let mut crdt2 = Json::from_state(crdt1.clone_state(), Some(2)).unwrap();
+    let op1 = crdt1.move_item("/0", 2).unwrap();
+    let op2 = crdt2.move_item("/0", 1).unwrap();
+
+    let _ = crdt1.execute_op(op2);
+    let _ = crdt2.execute_op(op1);
+
+    assert_eq!(crdt1.state(), crdt2.state());
+    let value = crdt1.local_value();
+    assert!(value == json!([2.0,3.0,1.0]) || value == json!([2.0,1.0,3.0]));
+}
+
+#[test]
+fn test_move_item_merge() {
+    let mut crdt1 = Json::from_str(r#"[{"x":1},{"x":2},{"x":3}]"#).unwrap();
+    let mut crdt2 = Json::from_state(crdt1.clone_state(), Some(2)).unwrap();
+    let mut crdt3 = Json::from_state(crdt1.clone_state(), Some(3)).unwrap();
+    let mut crdt4 = Json::from_state(crdt1.clone_state(), Some(4)).unwrap();
+    let _ = crdt1.move_item("/2", 0).unwrap();
+    let _ = crdt2.insert("/2/y", true).unwrap();
+    let _ = crdt2.remove("/0").unwrap();
+
+    let crdt1_state = crdt1.clone_state();
+    let local_ops = crdt1.merge(crdt2.clone_state()).unwrap();
+    let _ = crdt2.merge(crdt1_state).unwrap();
+    assert_eq!(local_ops.len(), 2);
+    assert_eq!(crdt1.state(), crdt2.state());
+    assert_eq!(crdt1.local_value(), json!([{"x":3.0,"y":true},{"x":2.0}]));
+
+    let delta = crdt1.delta_since(crdt3.summary());
+    crdt3.merge_delta(delta).unwrap();
+    let _ = crdt4.merge(crdt1.clone_state()).unwrap();
+    assert_eq!(crdt3.state(), crdt1.state());
+    assert_eq!(crdt4.state(), crdt1.state());
+}
+
+#[test]
+fn test_move_item_invalid() {
+    let mut crdt = Json::from_str(r#"{"a":[1,2],"b":{"c":1}}"#).unwrap();
+    assert_eq!(crdt.move_item("/a/2", 0), Err(ditto::Error::OutOfBounds));
+    assert_eq!(crdt.move_item("/a/0", 2), Err(ditto::Error::OutOfBounds));
+    assert_eq!(crdt.move_item("/b/c", 0), Err(ditto::Error::WrongJsonType));
+    assert_eq!(crdt.local_value(), json!({"a":[1.0,2.0],"b":{"c":1.0}}));
+}
+
+#[test]
+fn test_patch_move() {
+    let mut crdt1 = Json::from_str(r#"{"a":[1,2,3]}"#).unwrap();
+    let mut crdt2 = Json::from_state(crdt1.clone_state(), Some(2)).unwrap();
+    let patch: Vec<PatchOp> = serde_json::from_str(r#"[{"op":"move","from":"/a/0","path":"/a/-"}]"#).unwrap();
+
+    let ops = crdt1.apply_patch(&patch).unwrap();
+    assert_eq!(ops.len(), 1);
+    assert_eq!(crdt1.local_value(), json!({"a":[2.0,3.0,1.0]}));
+
+    let mut writer = PatchWriter::new(&crdt2);
+    let local_op = crdt2.execute_op(ops[0].clone()).unwrap();
+    assert_eq!(writer.write(&local_op), [PatchOp::Move{from: "/a/0".into(), path: "/a/2".into()}]);
+    assert_eq!(crdt1.state(), crdt2.state());
+}
+
+#[test]
+fn test_counter_increment() {
+    let mut crdt1 = Json::from_str(r#"{"post":{}}"#).unwrap();
+    let _ = crdt1.insert("/post/likes", Counter::new(0)).unwrap();
+    let mut crdt2 = Json::from_state(crdt1.clone_state(), Some(2)).unwrap();
+    let op1 = crdt1.increment("/post/likes", 1).unwrap();
+    let op2 = crdt2.increment("/post/likes", 2).unwrap();
+
+    let local_op = crdt1.execute_op(op2).unwrap();
+    let pointer = vec![LocalUid::Object("post".into()), LocalUid::Object("likes".into())];
+    assert_eq!(local_op, LocalOp::Update{pointer, value: json!(3.0)});
+    let _ = crdt2.execute_op(op1).unwrap();
+
+    assert_eq!(crdt1.state(), crdt2.state());
+    assert_eq!(crdt1.local_value(), json!({"post":{"likes":3.0}}));
+}
+
+#[test]
+fn test_counter_merge() {
+    let mut crdt1 = Json::from_str(r#"{"a":[]}"#).unwrap();
+    let _ = crdt1.insert("/a/0", Counter::new(10)).unwrap();
+    let mut crdt2 = Json::from_state(crdt1.clone_state(), Some(2)).unwrap();
+    let mut crdt3 = Json::from_state(crdt1.clone_state(), Some(3)).unwrap();
+    let _ = crdt1.increment("/a/0", -3).unwrap();
+    let _ = crdt2.increment("/a/0", 5).unwrap();
+    let _ = crdt2.increment("/a/0", 5).unwrap();
+
+    let delta = crdt2.delta_since(crdt3.summary());
+    crdt3.merge_delta(delta).unwrap();
+    let local_ops = crdt1.merge(crdt2.clone_state()).unwrap();
+    assert_eq!(local_ops, [LocalOp::Update{pointer: vec![LocalUid::Object("a".into()), LocalUid::Array(0)], value: json!(17.0)}]);
+    let _ = crdt3.merge(crdt1.clone_state()).unwrap();
+
+    assert_eq!(crdt1.local_value(), json!({"a":[17.0]}));
+    assert_eq!(crdt3.state(), crdt1.state());
+}
+
+#[test]
+fn test_counter_awaiting_site() {
+    let crdt1 = Json::new(json!({})).unwrap();
+    let mut crdt2 = Json::from_state(crdt1.clone_state(), None).unwrap();
+    let mut crdt3 = Json::from_state(crdt1.clone_state(), Some(3)).unwrap();
+    assert_eq!(crdt2.insert("/n", Counter::new(1)), Err(ditto::Error::AwaitingSiteId));
+    assert_eq!(crdt2.increment("/n", 2), Err(ditto::Error::AwaitingSiteId));
+
+    let ops = crdt2.add_site_id(2).unwrap();
+    assert_eq!(ops.len(), 2);
+    for op in ops {
+        assert!(op.inserted_dots().iter().all(|dot| dot.site_id == 2));
+        let _ = crdt3.validate_and_execute_op(op, 2).unwrap();
+    }
+    assert_eq!(crdt3.state(), crdt2.state());
+    assert_eq!(crdt3.local_value(), json!({"n":3.0}));
+}
+
+#[test]
+fn test_counter_invalid() {
+    let mut crdt = Json::from_str(r#"{"a":1,"b":"x"}"#).unwrap();
+    let _ = crdt.insert("/c", Counter::new(1)).unwrap();
+    assert_eq!(crdt.increment("/a", 1), Err(ditto::Error::WrongJsonType));
+    assert_eq!(crdt.increment("/b", 1), Err(ditto::Error::WrongJsonType));
+    assert_eq!(crdt.increment("/d", 1), Err(ditto::Error::DoesNotExist));
+    assert_eq!(crdt.update_register("/c", json!(1)), Err(ditto::Error::WrongJsonType));
+}
+
+#[test]
+fn test_register_update() {
+    let mut crdt1 = Json::from_str(r#"{}"#).unwrap();
+    let _ = crdt1.insert("/status", Register::new(json!("open"))).unwrap();
+    let mut crdt2 = Json::from_state(crdt1.clone_state(), Some(2)).unwrap();
+    let op1 = crdt1.update_register("/status", json!({"closed": 1})).unwrap();
+    let op2 = crdt2.update_register("/status", json!("done")).unwrap();
+
+    let _ = crdt1.execute_op(op2);
+    let _ = crdt2.execute_op(op1);
+    assert_eq!(crdt1.state(), crdt2.state());
+    assert_eq!(crdt1.local_value(), json!({"status":{"closed":1.0}}));
+
+    let op3 = crdt2.update_register("/status", json!(null)).unwrap();
+    let local_op = crdt1.execute_op(op3).unwrap();
+    assert_eq!(local_op, LocalOp::Update{pointer: vec![LocalUid::Object("status".into())], value: json!(null)});
+    assert_eq!(crdt1.local_value(), json!({"status":null}));
+}
+
+#[test]
+fn test_register_merge_delta() {
+    let mut crdt1 = Json::from_str(r#"{}"#).unwrap();
+    let _ = crdt1.insert("/r", Register::new(json!(1))).unwrap();
+    let mut crdt2 = Json::from_state(crdt1.clone_state(), Some(2)).unwrap();
+    let mut crdt3 = Json::from_state(crdt1.clone_state(), Some(3)).unwrap();
+    let _ = crdt2.update_register("/r", json!([2])).unwrap();
+
+    let delta = crdt2.delta_since(crdt1.summary());
+    crdt1.merge_delta(delta).unwrap();
+    let local_ops = crdt3.merge(crdt2.clone_state()).unwrap();
+    assert_eq!(local_ops.len(), 1);
+    assert_eq!(crdt1.state(), crdt2.state());
+    assert_eq!(crdt3.state(), crdt2.state());
+    assert_eq!(crdt1.local_value(), json!({"r":[2.0]}));
+}
+
+#[test]
+fn test_set_value_counter_and_register() {
+    let mut crdt1 = Json::from_str(r#"{}"#).unwrap();
+    let _ = crdt1.insert("/n", Counter::new(5)).unwrap();
+    let _ = crdt1.insert("/r", Register::new(json!("a"))).unwrap();
+    let mut crdt2 = Json::from_state(crdt1.clone_state(), Some(2)).unwrap();
+    let op = crdt2.increment("/n", 1).unwrap();
+
+    let ops = crdt1.set_value(json!({"n":8,"r":["b"]})).unwrap();
+    assert_eq!(ops.len(), 2);
+    let _ = crdt1.execute_op(op);
+    assert_eq!(crdt1.local_value(), json!({"n":9.0,"r":["b"]}));
+    assert!(crdt1.set_value(json!({"n":9.5,"r":["b"]})).is_ok());
+    assert_eq!(crdt1.local_value(), json!({"n":9.5,"r":["b"]}));
+}
+
+#[test]
+fn test_patch_writer_update() {
+    let mut crdt1 = Json::from_str(r#"{"a":{}}"#).unwrap();
+    let _ = crdt1.insert("/a/n", Counter::new(0)).unwrap();
+    let mut crdt2 = Json::from_state(crdt1.clone_state(), Some(2)).unwrap();
+    let op = crdt1.increment("/a/n", 4).unwrap();
+
+    let mut writer = PatchWriter::new(&crdt2);
+    let local_op = crdt2.execute_op(op).unwrap();
+    assert_eq!(writer.write(&local_op), [PatchOp::Replace{path: "/a/n".into(), value: json!(4.0)}]);
+}
+
 #[test]
 fn test_serialize() {
     let crdt = Json::from_str(r#"{"foo":[1.0,true,"hello"],"bar":null}"#).unwrap();
@@ -23,6 +684,22 @@ fn test_serialize() {
     common::test_serde(state);
 }
 
+#[test]
+fn test_serialize_counter_and_register() {
+    let mut crdt = Json::from_str(r#"{}"#).unwrap();
+    let _ = crdt.insert("/n", Counter::new(2)).unwrap();
+    let _ = crdt.insert("/r", Register::new(json!("x"))).unwrap();
+    let op1 = crdt.increment("/n", 3).unwrap();
+    let op2 = crdt.update_register("/r", json!([1.0])).unwrap();
+    let delta = crdt.delta_since(&Default::default());
+
+    common::test_serde(crdt.clone_state());
+    common::test_serde(op1);
+    common::test_serde(op2);
+    common::test_serde(delta);
+    common::test_serde(LocalOp::Update{pointer: vec![LocalUid::Object("n".into())], value: json!(5.0)});
+}
+
 #[test]
 fn test_serialize_op() {
     let mut crdt = Json::from_str(r#"{"foo":[1.0,true,"hello"],"bar":null}"#).unwrap();
@@ -1,8 +1,10 @@
 extern crate ditto;
+extern crate serde_json;
 
 mod common;
 use ditto::Error;
 use ditto::list::*;
+use ditto::{text, Counter, Text};
 
 #[test]
 fn test_new() {
@@ -184,6 +186,81 @@ fn test_execute_op_remove_dupe() {
     assert_eq!(list2.len(), 0);
 }
 
+#[test]
+fn test_move_item() {
+    let mut list1 = List::from(vec![1, 2, 3, 4]);
+    let mut list2 = List::from_state(list1.clone_state(), Some(2)).unwrap();
+    let op = list1.move_item(0, 2).unwrap();
+    assert_eq!(list1.local_value(), [2, 3, 1, 4]);
+
+    assert_eq!(list2.execute_op(op.clone()), Some(LocalOp::Move{from: 0, to: 2}));
+    assert_eq!(list2.execute_op(op), None);
+    assert_eq!(list1.state(), list2.state());
+}
+
+#[test]
+fn test_move_item_out_of_bounds() {
+    let mut list = List::from(vec![1, 2, 3]);
+    let state = list.clone_state();
+    assert_eq!(list.move_item(0, 3), Err(Error::OutOfBounds));
+    assert_eq!(list.move_item(3, 0), Err(Error::OutOfBounds));
+    assert_eq!(list.state(), state);
+    assert_eq!(list.local_value(), vec![1, 2, 3]);
+}
+
+#[test]
+fn test_move_item_concurrent() {
+    let mut list1 = List::from(vec![1, 2, 3]);
+    let mut list2 = List::from_state(list1.clone_state(), Some(2)).unwrap();
+    let op1 = list1.move_item(0, 2).unwrap();
+    let op2 = list2.move_item(0, 1).unwrap();
+
+    let _ = list1.execute_op(op2);
+    let _ = list2.execute_op(op1);
+
+    assert_eq!(list1.state(), list2.state());
+    assert!(list1.local_value() == [2, 3, 1] || list1.local_value() == [2, 1, 3]);
+}
+
+#[test]
+fn test_move_item_twice() {
+    let mut list1 = List::from(vec![1, 2, 3]);
+    let mut list2 = List::from_state(list1.clone_state(), Some(2)).unwrap();
+    let op1 = list1.move_item(2, 0).unwrap();
+    let _ = list2.execute_op(op1.clone());
+    let op2 = list2.move_item(0, 1).unwrap();
+
+    // a move that has seen an earlier move wins over it
+    let mut list3 = List::from_state(list1.clone_state(), Some(3)).unwrap();
+    let _ = list3.execute_op(op2.clone());
+    assert_eq!(list3.execute_op(op1), None);
+    assert_eq!(list1.execute_op(op2), Some(LocalOp::Move{from: 0, to: 1}));
+    assert_eq!(list1.local_value(), [1, 3, 2]);
+    assert_eq!(list3.local_value(), [1, 3, 2]);
+}
+
+#[test]
+fn test_move_item_concurrent_remove() {
+    let mut list1 = List::from(vec![1, 2, 3]);
+    let mut list2 = List::from_state(list1.clone_state(), Some(2)).unwrap();
+    let op1 = list1.move_item(0, 2).unwrap();
+    let op2 = list2.remove(0).1.unwrap();
+
+    assert_eq!(list1.execute_op(op2), Some(LocalOp::Remove{idx: 2}));
+    assert_eq!(list2.execute_op(op1), None);
+    assert_eq!(list1.local_value(), [2, 3]);
+    assert_eq!(list1.local_value(), list2.local_value());
+}
+
+#[test]
+fn test_move_item_awaiting_site() {
+    let mut list: List<i64> = List::from_state(List::from(vec![1, 2]).state(), None).unwrap();
+    assert_eq!(list.move_item(1, 0), Err(Error::AwaitingSiteId));
+    let ops = list.add_site_id(3).unwrap();
+    assert_eq!(ops.len(), 1);
+    assert_eq!(list.local_value(), [2, 1]);
+}
+
 #[test]
 fn test_merge() {
     let mut list1 = List::new();
@@ -208,6 +285,150 @@ fn test_merge() {
     assert!(list1.summary().contains_pair(1,3));
 }
 
+#[test]
+fn test_merge_local_ops() {
+    let mut list1 = List::from(vec![3, 6, 9]);
+    let mut list2 = List::from_state(list1.clone_state(), Some(2)).unwrap();
+    let _ = list2.remove(0);
+    let _ = list2.push(12);
+
+    let local_ops = list1.merge(list2.clone_state()).unwrap();
+    assert_eq!(local_ops, [LocalOp::Remove{idx: 0}, LocalOp::Insert{idx: 2, value: 12}]);
+    assert_eq!(list1.local_value(), [6, 9, 12]);
+    assert!(list1.merge(list2.clone_state()).unwrap().is_empty());
+}
+
+#[test]
+fn test_merge_delta() {
+    let mut list1 = List::from(vec![3, 6, 9]);
+    let mut list2 = List::from_state(list1.clone_state(), Some(2)).unwrap();
+    let _ = list1.remove(1);
+    let _ = list1.push(12);
+    let _ = list2.insert(0, 15);
+    let _ = list2.remove(3);
+
+    let delta1 = list1.delta_since(list2.summary());
+    let delta2 = list2.delta_since(list1.summary());
+    list1.merge_delta(delta2).unwrap();
+    list2.merge_delta(delta1).unwrap();
+
+    assert_eq!(list1.state(), list2.state());
+    assert_eq!(list1.local_value(), [15, 3, 12]);
+}
+
+#[test]
+fn test_merge_moves() {
+    let mut list1 = List::from(vec![1, 2, 3]);
+    let mut list2 = List::from_state(list1.clone_state(), Some(2)).unwrap();
+    let _ = list1.move_item(0, 2).unwrap();
+    let _ = list1.remove(0);
+    let _ = list2.move_item(0, 1).unwrap();
+    let _ = list2.move_item(2, 0).unwrap();
+
+    let list1_state = list1.clone_state();
+    let local_ops = list1.merge(list2.clone_state()).unwrap();
+    let _ = list2.merge(list1_state).unwrap();
+
+    assert!(local_ops.iter().any(|op| matches!(*op, LocalOp::Move{..})));
+    assert_eq!(list1.state(), list2.state());
+    assert_eq!(list1.local_value()[0], 3);
+    assert_eq!(list1.len(), 2);
+}
+
+#[test]
+fn test_merge_delta_moves() {
+    let mut list1 = List::from(vec![1, 2, 3]);
+    let mut list2 = List::from_state(list1.clone_state(), Some(2)).unwrap();
+    let _ = list1.move_item(0, 2).unwrap();
+    let _ = list2.move_item(2, 0).unwrap();
+    let _ = list2.remove(1);
+
+    let delta1 = list1.delta_since(list2.summary());
+    let delta2 = list2.delta_since(list1.summary());
+    list1.merge_delta(delta2).unwrap();
+    list2.merge_delta(delta1).unwrap();
+
+    assert_eq!(list1.state(), list2.state());
+    assert_eq!(list1.local_value(), [3, 2]);
+}
+
+#[test]
+fn test_merge_delta_unknown_summary() {
+    let list1 = List::from(vec![3, 6, 9]);
+    let mut list2: List<i32> = List::from_state(List::new().state(), Some(2)).unwrap();
+    let delta = list1.delta_since(list1.summary());
+    assert_eq!(list2.merge_delta(delta), Err(Error::InvalidDelta));
+    assert!(list2.is_empty());
+}
+
+#[test]
+fn test_edit_nested() {
+    let mut list1: List<Counter> = List::from(vec![Counter::new(1), Counter::new(2)]);
+    let mut list2 = List::from_state(list1.clone_state(), Some(2)).unwrap();
+
+    let op = list1.edit(1, |counter| counter.increment(5)).unwrap();
+    assert_eq!(list1.get(1).unwrap().get(), 7);
+
+    assert_eq!(list2.execute_op(op.clone()), Some(LocalOp::Edit{idx: 1, ops: vec![5]}));
+    assert_eq!(list1.state(), list2.state());
+
+    let _ = list2.remove(1);
+    assert_eq!(list2.execute_op(op), None);
+}
+
+#[test]
+#[should_panic]
+fn test_edit_out_of_bounds() {
+    let mut list: List<Counter> = List::new();
+    let _ = list.edit(0, |counter| counter.increment(1));
+}
+
+#[test]
+fn test_edit_nested_awaiting_site() {
+    let mut list: List<Counter> = List::from_state(List::new().state(), None).unwrap();
+    let _ = list.push(Counter::new(0));
+    assert_eq!(list.edit(0, |counter| counter.increment(3)), Err(Error::AwaitingSiteId));
+
+    let ops = list.add_site_id(4).unwrap();
+    let (uid, op) = ops[1].edit().unwrap();
+    assert_eq!(uid.site_id, 4);
+    assert_eq!(op.site_id(), 4);
+}
+
+#[test]
+fn test_merge_nested() {
+    let mut list1: List<Text> = List::from(vec![Text::from("ab")]);
+    let mut list2 = List::from_state(list1.clone_state(), Some(2)).unwrap();
+
+    let _ = list1.edit(0, |text| text.replace(0, 0, "1").unwrap());
+    let _ = list2.edit(0, |text| text.replace(2, 0, "2").unwrap());
+
+    let list1_state = list1.clone_state();
+    let local_ops = list1.merge(list2.clone_state()).unwrap();
+    assert_eq!(local_ops, [LocalOp::Edit{idx: 0, ops: vec![text::LocalOp{idx: 3, len: 0, text: "2".into()}]}]);
+    let _ = list2.merge(list1_state).unwrap();
+
+    assert_eq!(list1.state(), list2.state());
+    assert_eq!(list1.get(0).unwrap().local_value(), "1ab2");
+}
+
+#[test]
+fn test_merge_delta_nested() {
+    let mut list1: List<Counter> = List::from(vec![Counter::new(0), Counter::new(0)]);
+    let mut list2 = List::from_state(list1.clone_state(), Some(2)).unwrap();
+
+    let _ = list1.edit(0, |counter| counter.increment(1));
+    let _ = list2.edit(0, |counter| counter.increment(2));
+
+    let delta1 = list1.delta_since(list2.summary());
+    let delta2 = list2.delta_since(list1.summary());
+    list1.merge_delta(delta2).unwrap();
+    list2.merge_delta(delta1).unwrap();
+
+    assert_eq!(list1.state(), list2.state());
+    assert_eq!(list1.get(0).unwrap().get(), 3);
+}
+
 #[test]
 fn test_add_site_id() {
     let mut list: List<u32> = List::from_state(List::new().state(), None).unwrap();
@@ -258,19 +479,64 @@ fn test_serialize_state() {
     common::test_serde(list.state());
 }
 
+#[test]
+fn test_serialize_state_format() {
+    let mut list: List<u32> = List::from(vec![1, 2, 3]);
+    let _ = list.move_item(2, 0).unwrap();
+
+    let value = serde_json::to_value(list.state()).unwrap();
+    let elements = value["inner"].as_array().unwrap();
+    assert_eq!(elements.len(), 3);
+    assert_eq!(elements[0]["value"], 3);
+    assert!(elements[0]["moved"].is_object());
+    assert!(elements[1].get("moved").is_none());
+    assert!(elements[2].get("moved").is_none());
+
+    let json = serde_json::to_string(&list.state()).unwrap();
+    let list2 = List::from_state(serde_json::from_str(&json).unwrap(), Some(2)).unwrap();
+    assert_eq!(list2.local_value(), [3, 1, 2]);
+    assert_eq!(list2.state(), list.state());
+}
+
+#[test]
+fn test_serialize_delta() {
+    let mut list1 = List::from(vec![1, 2, 3]);
+    let list2 = List::from_state(list1.clone_state(), Some(2)).unwrap();
+    let _ = list1.push(4);
+    common::test_serde(list1.delta_since(list2.summary()));
+}
+
 #[test]
 fn test_serialize_op() {
     let mut list: List<i8> = List::new();
     let op1 = list.push(123).unwrap();
+    let _   = list.push(45).unwrap();
     let op2 = list.pop().unwrap().1.unwrap();
+    let op3 = list.move_item(0, 0).unwrap();
     common::test_serde(op1);
     common::test_serde(op2);
+    common::test_serde(op3);
+}
+
+#[test]
+fn test_serialize_op_nested() {
+    let mut list: List<Text> = List::from(vec![Text::from("abc")]);
+    let op = list.edit(0, |text| text.replace(1, 1, "x").unwrap()).unwrap();
+    common::test_serde(op);
 }
 
 #[test]
 fn test_serialize_local_op() {
     let op1 = LocalOp::Insert{idx: 123, value: "abc".to_owned()};
     let op2: LocalOp<String> = LocalOp::Remove{idx: 123};
+    let op3: LocalOp<String> = LocalOp::Move{from: 123, to: 4};
     common::test_serde(op1);
     common::test_serde(op2);
+    common::test_serde(op3);
+}
+
+#[test]
+fn test_serialize_local_op_nested() {
+    let op: LocalOp<Counter> = LocalOp::Edit{idx: 3, ops: vec![-2]};
+    common::test_serde(op);
 }
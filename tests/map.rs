@@ -4,6 +4,8 @@ mod common;
 use ditto::Error;
 use ditto::dot::Dot;
 use ditto::map::*;
+use ditto::resolver::{MaxValue, MinValue};
+use ditto::{list, set, text, Counter, List, Set, Text};
 
 #[test]
 fn test_new() {
@@ -199,6 +201,238 @@ fn test_merge() {
     assert!(map1.summary().contains_pair(2, 2));
 }
 
+#[test]
+fn test_merge_local_ops() {
+    let mut map1: Map<u32, u32> = Map::new();
+    let _ = map1.insert(1, 10);
+    let _ = map1.insert(2, 20);
+    let mut map2 = Map::from_state(map1.clone_state(), Some(2)).unwrap();
+    let _ = map2.remove(&1);
+    let _ = map2.insert(3, 30);
+
+    let local_ops = map1.merge(map2.clone_state()).unwrap();
+    assert_eq!(local_ops.len(), 2);
+    assert!(local_ops.contains(&LocalOp::Remove{key: 1}));
+    assert!(local_ops.contains(&LocalOp::Insert{key: 3, value: 30}));
+    assert!(map1.merge(map2.clone_state()).unwrap().is_empty());
+
+    let _ = map2.insert(2, 21);
+    let local_ops = map1.merge(map2.clone_state()).unwrap();
+    assert_eq!(local_ops, [LocalOp::Insert{key: 2, value: 21}]);
+}
+
+#[test]
+fn test_merge_delta() {
+    let mut map1: Map<i32, bool> = Map::new();
+    let _ = map1.insert(1, true);
+    let _ = map1.insert(2, true);
+    let mut map2 = Map::from_state(map1.clone_state(), Some(2)).unwrap();
+
+    let _ = map1.remove(&1);
+    let _ = map1.insert(3, false);
+    let _ = map2.insert(2, false);
+    let _ = map2.insert(4, true);
+
+    let delta1 = map1.delta_since(map2.summary());
+    let delta2 = map2.delta_since(map1.summary());
+    map1.merge_delta(delta2).unwrap();
+    map2.merge_delta(delta1).unwrap();
+
+    assert_eq!(map1.state(), map2.state());
+    assert!(!map1.contains_key(&1));
+    assert_eq!(map1.get(&2), Some(&false));
+    assert_eq!(map1.get(&3), Some(&false));
+    assert_eq!(map1.get(&4), Some(&true));
+}
+
+#[test]
+fn test_edit_nested_text() {
+    let mut map1: Map<String, Text> = Map::new();
+    let op1 = map1.insert("a".into(), Text::from("hello")).unwrap();
+    let mut map2 = Map::from_state(Map::new().state(), Some(2)).unwrap();
+    let _ = map2.execute_op(op1);
+
+    let op2 = map1.edit(&"a".into(), |text| text.replace(5, 0, " world").unwrap()).unwrap().unwrap();
+    assert_eq!(op2.key(), "a");
+    assert_eq!(op2.edit().unwrap().0, Dot::new(1,1));
+    assert_eq!(map1.get(&"a".into()).unwrap().local_value(), "hello world");
+    assert!(map1.summary().contains_pair(1, 2));
+
+    let edit = text::LocalOp{idx: 5, len: 0, text: " world".into()};
+    assert_eq!(map2.execute_op(op2), LocalOp::Edit{key: "a".into(), ops: vec![edit]});
+    assert_eq!(map1.state(), map2.state());
+    assert!(map1.edit(&"b".into(), |text| text.replace(0, 0, "x").unwrap()).is_none());
+}
+
+#[test]
+fn test_edit_nested_awaiting_site() {
+    let mut map1: Map<u32, Counter> = Map::from_state(Map::new().state(), None).unwrap();
+    let _ = map1.insert(1, Counter::new(10));
+    assert_eq!(map1.edit(&1, |counter| counter.increment(5)), Some(Err(Error::AwaitingSiteId)));
+    assert_eq!(map1.get(&1).unwrap().get(), 15);
+
+    let ops = map1.add_site_id(3).unwrap();
+    assert_eq!(ops[1].edit().unwrap().0, Dot::new(3,1));
+    assert_eq!(ops[1].edit().unwrap().1.site_id(), 3);
+
+    let mut map2: Map<u32, Counter> = Map::new();
+    for op in ops {
+        let _ = map2.validate_and_execute_op(op, 3).unwrap();
+    }
+    assert_eq!(map2.get(&1).unwrap().get(), 15);
+}
+
+#[test]
+fn test_execute_op_nested_concurrent() {
+    let mut map1: Map<u32, Set<u32>> = Map::new();
+    let _ = map1.insert(1, Set::new()).unwrap();
+    let mut map2 = Map::from_state(map1.clone_state(), Some(2)).unwrap();
+
+    let op2 = map1.edit(&1, |set| set.insert(10)).unwrap().unwrap();
+    let op3 = map2.edit(&1, |set| set.insert(20)).unwrap().unwrap();
+    let op4 = map2.insert(1, Set::new()).unwrap();
+
+    assert_eq!(map1.execute_op(op3), LocalOp::Edit{key: 1, ops: vec![set::LocalOp::Insert(20)]});
+    assert_eq!(map2.execute_op(op2), LocalOp::Edit{key: 1, ops: vec![]});
+    assert_eq!(map1.execute_op(op4), LocalOp::Insert{key: 1, value: map2.get(&1).unwrap().clone()});
+    assert_eq!(map1.state(), map2.state());
+    assert!(!map1.get(&1).unwrap().contains(&10));
+}
+
+#[test]
+fn test_merge_nested() {
+    let mut map1: Map<String, Text> = Map::new();
+    let _ = map1.insert("a".into(), Text::from("abc"));
+    let mut map2 = Map::from_state(map1.clone_state(), Some(2)).unwrap();
+
+    let _ = map1.edit(&"a".into(), |text| text.replace(0, 0, "1").unwrap());
+    let _ = map2.edit(&"a".into(), |text| text.replace(3, 0, "2").unwrap());
+
+    let map1_state = map1.clone_state();
+    let local_ops = map1.merge(map2.clone_state()).unwrap();
+    assert_eq!(local_ops, [LocalOp::Edit{key: "a".into(), ops: vec![text::LocalOp{idx: 4, len: 0, text: "2".into()}]}]);
+    let _ = map2.merge(map1_state).unwrap();
+
+    assert_eq!(map1.state(), map2.state());
+    assert_eq!(map1.get(&"a".into()).unwrap().local_value(), "1abc2");
+    assert!(map1.merge(map2.clone_state()).unwrap().is_empty());
+}
+
+#[test]
+fn test_merge_delta_nested() {
+    let mut map1: Map<u32, Counter> = Map::new();
+    let _ = map1.insert(1, Counter::new(0));
+    let _ = map1.insert(2, Counter::new(0));
+    let mut map2 = Map::from_state(map1.clone_state(), Some(2)).unwrap();
+
+    let _ = map1.edit(&1, |counter| counter.increment(3));
+    let _ = map2.edit(&1, |counter| counter.increment(4));
+
+    let delta1 = map1.delta_since(map2.summary());
+    let delta2 = map2.delta_since(map1.summary());
+    map1.merge_delta(delta2).unwrap();
+    map2.merge_delta(delta1).unwrap();
+
+    assert_eq!(map1.state(), map2.state());
+    assert_eq!(map1.get(&1).unwrap().get(), 7);
+    assert_eq!(map1.get(&2).unwrap().get(), 0);
+}
+
+#[test]
+fn test_delta_since_nested_text() {
+    let mut map1: Map<String, Text> = Map::new();
+    let _ = map1.insert("a".into(), Text::from("hello world"));
+    let _ = map1.insert("b".into(), Text::from("abc"));
+    let mut map2 = Map::from_state(map1.clone_state(), Some(2)).unwrap();
+
+    let delta = map1.delta_since(map2.summary());
+    map2.merge_delta(delta).unwrap();
+    assert_eq!(map1.state(), map2.state());
+
+    let _ = map1.edit(&"a".into(), |text| text.replace(5, 6, "").unwrap());
+    let delta = map1.delta_since(map2.summary());
+    common::test_serde(delta.clone());
+    map2.merge_delta(delta).unwrap();
+
+    assert_eq!(map1.state(), map2.state());
+    assert_eq!(map2.get(&"a".into()).unwrap().local_value(), "hello");
+    assert_eq!(map2.get(&"b".into()).unwrap().local_value(), "abc");
+}
+
+#[test]
+fn test_nested_path() {
+    let mut map1: Map<String, List<Text>> = Map::new();
+    let _ = map1.insert("a".into(), List::from(vec![Text::from("x")]));
+    let mut map2 = Map::from_state(map1.clone_state(), Some(2)).unwrap();
+
+    let op = map1.edit(&"a".into(), |list| list.edit(0, |text| text.replace(1, 0, "y").unwrap())).unwrap().unwrap();
+    let edit = text::LocalOp{idx: 1, len: 0, text: "y".into()};
+    let list_op = list::LocalOp::Edit{idx: 0, ops: vec![edit]};
+    assert_eq!(map2.execute_op(op), LocalOp::Edit{key: "a".into(), ops: vec![list_op]});
+    assert_eq!(map2.get(&"a".into()).unwrap().get(0).unwrap().local_value(), "xy");
+}
+
+#[test]
+fn test_resolver_execute_op() {
+    let mut map1: Map<u32, u32, MaxValue> = Map::with_resolver::<MaxValue>();
+    let mut map2 = Map::from_state(map1.clone_state(), Some(2)).unwrap();
+
+    let op1 = map1.insert(1, 10).unwrap();
+    let op2 = map2.insert(1, 3).unwrap();
+
+    assert_eq!(map1.execute_op(op2), LocalOp::Insert{key: 1, value: 10});
+    assert_eq!(map2.execute_op(op1), LocalOp::Insert{key: 1, value: 10});
+    assert_eq!(map1.get(&1), Some(&10));
+    assert_eq!(map2.local_value().get(&1), Some(&10));
+    assert_eq!(map1.state(), map2.state());
+}
+
+#[test]
+fn test_resolver_merge() {
+    let mut map1 = Map::with_resolver::<MinValue>();
+    let mut map2 = Map::from_state(map1.clone_state(), Some(2)).unwrap();
+
+    let _ = map1.insert("a".to_owned(), 10).unwrap();
+    let _ = map2.insert("a".to_owned(), 3).unwrap();
+    let _ = map2.insert("b".to_owned(), 4).unwrap();
+
+    let local_ops = map1.merge(map2.clone_state()).unwrap();
+    assert_eq!(local_ops.len(), 2);
+    assert!(local_ops.contains(&LocalOp::Insert{key: "a".to_owned(), value: 3}));
+    assert!(local_ops.contains(&LocalOp::Insert{key: "b".to_owned(), value: 4}));
+    assert_eq!(map1.get(&"a".to_owned()), Some(&3));
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct LargestCounter;
+
+impl ditto::Resolver<Counter> for LargestCounter {
+    fn compare(a: (Dot, &Counter), b: (Dot, &Counter)) -> ::std::cmp::Ordering {
+        a.1.get().cmp(&b.1.get())
+    }
+}
+
+#[test]
+fn test_resolver_edit_changes_visible_value() {
+    let mut map1 = Map::with_resolver::<LargestCounter>();
+    let mut map2 = Map::from_state(map1.clone_state(), Some(2)).unwrap();
+    let mut map3 = Map::from_state(map1.clone_state(), Some(3)).unwrap();
+
+    let op1 = map1.insert(1, Counter::new(0)).unwrap();
+    let op2 = map2.insert(1, Counter::new(5)).unwrap();
+    let op3 = map1.edit(&1, |counter| counter.increment(10)).unwrap().unwrap();
+
+    let _ = map3.execute_op(op1);
+    let _ = map3.execute_op(op2);
+    assert_eq!(map3.get(&1).unwrap().get(), 5);
+
+    match map3.execute_op(op3) {
+        LocalOp::Insert{key: 1, value} => assert_eq!(value.get(), 10),
+        local_op => panic!("unexpected local op {:?}", local_op),
+    }
+    assert_eq!(map3.get(&1).unwrap().get(), 10);
+}
+
 #[test]
 fn test_add_site_id() {
     let mut map: Map<i32, u64> = Map::from_state(Map::new().state(), None).unwrap();
@@ -256,6 +490,14 @@ fn test_serialize_op() {
     common::test_serde(op2);
 }
 
+#[test]
+fn test_serialize_op_nested() {
+    let mut map: Map<String, Set<u32>> = Map::new();
+    let _ = map.insert("abc".into(), Set::new()).unwrap();
+    let op = map.edit(&"abc".into(), |set| set.insert(3)).unwrap().unwrap();
+    common::test_serde(op);
+}
+
 #[test]
 fn test_serialize_local_op() {
     let op1 = LocalOp::Insert{key: "abc".to_owned(), value: 103};
@@ -263,3 +505,9 @@ fn test_serialize_local_op() {
     common::test_serde(op1);
     common::test_serde(op2);
 }
+
+#[test]
+fn test_serialize_local_op_nested() {
+    let op: LocalOp<String, Counter> = LocalOp::Edit{key: "abc".to_owned(), ops: vec![4, -1]};
+    common::test_serde(op);
+}
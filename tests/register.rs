@@ -2,7 +2,9 @@ extern crate ditto;
 
 mod common;
 use ditto::Error;
+use ditto::dot::Dot;
 use ditto::register::*;
+use ditto::resolver::{HighestSite, LastWriterWins, MaxValue, MinValue};
 
 #[test]
 fn test_new() {
@@ -56,6 +58,60 @@ fn test_execute_op_concurrent() {
     assert_eq!(register1.state(), register3.state());
 }
 
+#[test]
+fn test_conflicts() {
+    let mut register1 = Register::new("a");
+    let mut register2 = Register::from_state(register1.clone_state(), Some(2)).unwrap();
+    assert_eq!(register1.get_all(), [(Dot::new(1,1), &"a")]);
+    assert!(register1.conflicts().is_empty());
+
+    let op1 = register1.update("b").unwrap();
+    let op2 = register2.update("c").unwrap();
+    let _ = register1.execute_op(op2);
+    let _ = register2.execute_op(op1);
+
+    assert_eq!(register1.get_all(), [(Dot::new(1,2), &"b"), (Dot::new(2,1), &"c")]);
+    assert_eq!(register1.conflicts(), [(Dot::new(2,1), &"c")]);
+    assert_eq!(register2.get_all(), register1.get_all());
+}
+
+#[test]
+fn test_resolve() {
+    let mut register1 = Register::new("a");
+    let mut register2 = Register::from_state(register1.clone_state(), Some(2)).unwrap();
+    let mut register3 = Register::from_state(register1.clone_state(), Some(3)).unwrap();
+
+    let op1 = register1.update("b").unwrap();
+    let op2 = register2.update("c").unwrap();
+    let _ = register3.execute_op(op1.clone());
+    let _ = register3.execute_op(op2.clone());
+    let _ = register2.execute_op(op1);
+
+    let op3 = register2.resolve("c").unwrap();
+    assert_eq!(op3.removed_dots(), [Dot::new(1,2)]);
+    assert_eq!(register2.get_all(), [(Dot::new(2,2), &"c")]);
+
+    assert_eq!(register3.execute_op(op3), &"c");
+    assert!(register3.conflicts().is_empty());
+    assert_eq!(register3.state(), register2.state());
+
+    let _ = register1.execute_op(op2);
+    assert_eq!(register1.merge(register2.clone_state()), Some(&"c"));
+    assert!(register1.conflicts().is_empty());
+}
+
+#[test]
+fn test_resolve_awaiting_site() {
+    let register1 = Register::new(1);
+    let mut register2 = Register::from_state(register1.clone_state(), None).unwrap();
+    assert_eq!(register2.resolve(2), Err(Error::AwaitingSiteId));
+    assert_eq!(register2.get_all(), [(Dot::new(0,1), &2)]);
+
+    let op = register2.add_site_id(4).unwrap().unwrap();
+    assert_eq!(op.removed_dots(), [Dot::new(1,1)]);
+    assert_eq!(register2.get_all(), [(Dot::new(4,1), &2)]);
+}
+
 #[test]
 fn test_execute_remote_dupe() {
     let mut register1 = Register::new("a");
@@ -80,6 +136,134 @@ fn test_merge() {
     assert_eq!(register1.state(), register2.state());
 }
 
+#[test]
+fn test_merge_local_ops() {
+    let mut register1 = Register::new(1u32);
+    let mut register2 = Register::from_state(register1.clone_state(), Some(2)).unwrap();
+    let _ = register2.update(2).unwrap();
+
+    assert_eq!(register1.merge(register2.clone_state()), Some(&2));
+    assert_eq!(register1.merge(register2.clone_state()), None);
+}
+
+#[test]
+fn test_merge_delta() {
+    let mut register1 = Register::new("a");
+    let mut register2 = Register::from_state(register1.clone_state(), Some(2)).unwrap();
+    let mut register3 = Register::from_state(register1.clone_state(), Some(3)).unwrap();
+    let _ = register2.update("b");
+    let _ = register3.update("c");
+
+    let delta2 = register2.delta_since(register1.summary());
+    let delta3 = register3.delta_since(register1.summary());
+    register1.merge_delta(delta2).unwrap();
+    register1.merge_delta(delta3).unwrap();
+    assert_eq!(register1.get(), &"b");
+
+    let delta1 = register1.delta_since(register2.summary());
+    register2.merge_delta(delta1).unwrap();
+    assert_eq!(register1.state(), register2.state());
+}
+
+#[test]
+fn test_resolver_max_value() {
+    let mut register1 = Register::with_resolver::<MaxValue>(1);
+    let mut register2 = Register::from_state(register1.clone_state(), Some(2)).unwrap();
+    let mut register3 = Register::from_state(register1.clone_state(), Some(3)).unwrap();
+
+    let op1 = register1.update(9).unwrap();
+    let op2 = register2.update(5).unwrap();
+
+    assert_eq!(register1.execute_op(op2.clone()), &9);
+    assert_eq!(register2.execute_op(op1.clone()), &9);
+    assert_eq!(register2.get_all(), [(Dot::new(1,2), &9), (Dot::new(2,1), &5)]);
+    assert_eq!(register2.conflicts(), [(Dot::new(2,1), &5)]);
+
+    let _ = register3.execute_op(op2);
+    assert_eq!(register3.merge(register1.clone_state()), Some(&9));
+    assert_eq!(register3.get(), &9);
+}
+
+#[test]
+fn test_resolver_highest_site() {
+    let mut register1 = Register::with_resolver::<HighestSite>("a");
+    let mut register2 = Register::from_state(register1.clone_state(), Some(2)).unwrap();
+
+    let op1 = register1.update("b").unwrap();
+    let op2 = register2.update("c").unwrap();
+    assert_eq!(register1.execute_op(op2), &"c");
+    assert_eq!(register2.execute_op(op1), &"c");
+    assert_eq!(register1.conflicts(), [(Dot::new(1,2), &"b")]);
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Stamped {
+    time: u64,
+    text: &'static str,
+}
+
+impl ditto::resolver::Timestamped for Stamped {
+    fn timestamp(&self) -> u64 { self.time }
+}
+
+#[test]
+fn test_resolver_last_writer_wins() {
+    let mut register1 = Register::with_resolver::<LastWriterWins>(Stamped{time: 0, text: "a"});
+    let mut register2 = Register::from_state(register1.clone_state(), Some(2)).unwrap();
+
+    let op1 = register1.update(Stamped{time: 20, text: "b"}).unwrap();
+    let op2 = register2.update(Stamped{time: 10, text: "c"}).unwrap();
+    let _ = register1.execute_op(op2);
+    let _ = register2.execute_op(op1);
+
+    assert_eq!(register1.get().text, "b");
+    assert_eq!(register2.get().text, "b");
+}
+
+#[test]
+fn test_resolver_last_writer_wins_merge() {
+    let mut register1 = Register::with_resolver::<LastWriterWins>(Stamped{time: 0, text: "a"});
+    let mut register2 = Register::from_state(register1.clone_state(), Some(2)).unwrap();
+    let _ = register1.update(Stamped{time: 10, text: "b"}).unwrap();
+    let _ = register2.update(Stamped{time: 20, text: "c"}).unwrap();
+
+    let state1 = register1.clone_state();
+    assert_eq!(register1.merge(register2.clone_state()).map(|v| v.text), Some("c"));
+    assert_eq!(register2.merge(state1), None);
+    assert_eq!(register1.get().text, "c");
+    assert_eq!(register2.get().text, "c");
+    assert_eq!(register1.conflicts(), [(Dot::new(1,2), &Stamped{time: 10, text: "b"})]);
+}
+
+#[test]
+fn test_resolver_last_writer_wins_delta() {
+    let mut register1 = Register::with_resolver::<LastWriterWins>(Stamped{time: 0, text: "a"});
+    let mut register2 = Register::from_state(register1.clone_state(), Some(2)).unwrap();
+    let _ = register1.update(Stamped{time: 30, text: "b"}).unwrap();
+    let _ = register2.update(Stamped{time: 20, text: "c"}).unwrap();
+
+    let delta1 = register1.delta_since(register2.summary());
+    let delta2 = register2.delta_since(register1.summary());
+    register1.merge_delta(delta2).unwrap();
+    register2.merge_delta(delta1).unwrap();
+    assert_eq!(register1.get().text, "b");
+    assert_eq!(register2.get().text, "b");
+    assert_eq!(register1.state(), register2.state());
+}
+
+#[test]
+fn test_resolver_delta() {
+    let mut register1 = Register::with_resolver::<MinValue>(1);
+    let mut register2 = Register::from_state(register1.clone_state(), Some(2)).unwrap();
+    let _ = register1.update(7).unwrap();
+    let _ = register2.update(3).unwrap();
+
+    let delta = register2.delta_since(register1.summary());
+    register1.merge_delta(delta).unwrap();
+    assert_eq!(register1.get(), &3);
+    assert_eq!(register1.get_all()[0], (Dot::new(2,1), &3));
+}
+
 #[test]
 fn test_add_site_id() {
     let mut register1 = Register::new(123);
@@ -198,6 +198,44 @@ fn test_merge() {
     assert!(set1.summary().contains_pair(2, 2));
 }
 
+#[test]
+fn test_merge_local_ops() {
+    let mut set1: Set<u32> = Set::new();
+    let _ = set1.insert(1);
+    let _ = set1.insert(2);
+    let mut set2 = Set::from_state(set1.clone_state(), Some(2)).unwrap();
+    let _ = set2.remove(&1);
+    let _ = set2.insert(2);
+    let _ = set2.insert(3);
+
+    let local_ops = set1.merge(set2.clone_state()).unwrap();
+    assert_eq!(local_ops.len(), 2);
+    assert!(local_ops.contains(&LocalOp::Remove(1)));
+    assert!(local_ops.contains(&LocalOp::Insert(3)));
+    assert!(set1.merge(set2.clone_state()).unwrap().is_empty());
+}
+
+#[test]
+fn test_merge_delta() {
+    let mut set1: Set<u32> = Set::new();
+    let _ = set1.insert(1);
+    let _ = set1.insert(2);
+    let mut set2 = Set::from_state(set1.clone_state(), Some(2)).unwrap();
+
+    let _ = set1.remove(&1);
+    let _ = set1.insert(3);
+    let _ = set2.remove(&2);
+    let _ = set2.insert(4);
+
+    let delta1 = set1.delta_since(set2.summary());
+    let delta2 = set2.delta_since(set1.summary());
+    set1.merge_delta(delta2).unwrap();
+    set2.merge_delta(delta1).unwrap();
+
+    assert_eq!(set1.state(), set2.state());
+    assert_eq!(set1.local_value(), [3, 4].iter().cloned().collect());
+}
+
 #[test]
 fn test_add_site_id() {
     let mut set: Set<u64> = Set::from_state(Set::new().clone_state(), None).unwrap();
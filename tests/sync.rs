@@ -0,0 +1,150 @@
+extern crate ditto;
+extern crate serde_json;
+
+use ditto::{Crdt, Counter, Error, Json, List, Map, Register, Text};
+use ditto::sync::{Message, SyncSession};
+
+#[test]
+fn test_list() {
+    let mut list1 = List::from(vec![1, 2, 3]);
+    let mut list2 = List::from_state(list1.clone_state(), Some(2)).unwrap();
+    let _ = list1.push(4).unwrap();
+    let _ = list1.remove(0);
+    let _ = list2.insert(1, 5).unwrap();
+    let _ = list2.remove(2);
+
+    sync(&mut list1, &mut list2);
+    assert_eq!(list1.local_value(), [5, 3, 4]);
+    assert_eq!(list1.state(), list2.state());
+}
+
+#[test]
+fn test_map() {
+    let mut map1: Map<u32, bool> = Map::new();
+    let _ = map1.insert(1, true).unwrap();
+    let mut map2 = Map::from_state(map1.clone_state(), Some(2)).unwrap();
+    let _ = map1.remove(&1).unwrap();
+    let _ = map2.insert(2, false).unwrap();
+
+    sync(&mut map2, &mut map1);
+    assert!(!map1.contains_key(&1));
+    assert_eq!(map1.get(&2), Some(&false));
+    assert_eq!(map1.state(), map2.state());
+}
+
+#[test]
+fn test_text() {
+    let text = Text::from_str("hello");
+    let mut text1 = Text::from_state(text.clone_state(), None).unwrap();
+    let mut text2 = Text::from_state(text.clone_state(), Some(2)).unwrap();
+    let _ = text1.add_site_id(1);
+    let _ = text1.replace(5, 0, " world").unwrap();
+    let _ = text2.replace(0, 0, "oh, ").unwrap();
+
+    sync(&mut text1, &mut text2);
+    assert_eq!(text1.local_value(), "oh, hello world");
+    assert_eq!(text1.state(), text2.state());
+}
+
+#[test]
+fn test_json() {
+    let mut crdt1 = Json::from_str(r#"{"a":[1.0]}"#).unwrap();
+    let mut crdt2 = Json::from_state(crdt1.clone_state(), Some(2)).unwrap();
+    let _ = crdt1.insert("/b", "hi").unwrap();
+    let _ = crdt2.remove("/a/0").unwrap();
+
+    sync(&mut crdt1, &mut crdt2);
+    assert_eq!(crdt1.local_value(), crdt2.local_value());
+    assert_eq!(crdt1.state(), crdt2.state());
+}
+
+#[test]
+fn test_counter() {
+    let mut counter1 = Counter::new(1);
+    let mut counter2 = Counter::from_state(counter1.clone_state(), Some(2)).unwrap();
+    let _ = counter1.increment(2).unwrap();
+    let _ = counter2.increment(3).unwrap();
+
+    sync(&mut counter1, &mut counter2);
+    assert_eq!(counter1.get(), 6);
+    assert_eq!(counter1.state(), counter2.state());
+}
+
+#[test]
+fn test_register() {
+    let mut register1 = Register::new(1u32);
+    let mut register2 = Register::from_state(register1.clone_state(), Some(2)).unwrap();
+    let _ = register2.update(2).unwrap();
+
+    sync(&mut register1, &mut register2);
+    assert_eq!(*register1.get(), 2);
+    assert_eq!(register1.state(), register2.state());
+}
+
+#[test]
+fn test_already_synced() {
+    let mut list1 = List::from(vec![1, 2]);
+    let mut list2 = List::from_state(list1.clone_state(), Some(2)).unwrap();
+    sync(&mut list1, &mut list2);
+    sync(&mut list2, &mut list1);
+    assert_eq!(list1.local_value(), [1, 2]);
+    assert_eq!(list1.state(), list2.state());
+}
+
+#[test]
+fn test_edit_during_sync() {
+    let mut list1 = List::from(vec![1]);
+    let mut list2 = List::from_state(list1.clone_state(), Some(2)).unwrap();
+    let mut session1 = SyncSession::new();
+    let mut session2 = SyncSession::new();
+
+    let request = session1.start(&list1);
+    let reply = session2.receive(&mut list2, request).unwrap().unwrap();
+    let _ = list2.push(2).unwrap();
+    let _ = list1.remove(0);
+    let delta = session1.receive(&mut list1, reply).unwrap().unwrap();
+    let _ = list1.push(3).unwrap();
+    assert!(session2.receive(&mut list2, delta).unwrap().is_none());
+    assert_eq!(list1.local_value(), [3]);
+    assert_eq!(list2.local_value(), [2]);
+
+    sync(&mut list1, &mut list2);
+    assert_eq!(list1.local_value(), list2.local_value());
+    assert_eq!(list1.state(), list2.state());
+}
+
+#[test]
+fn test_invalid_message() {
+    let mut list1: List<u32> = List::new();
+    let mut list2 = List::from_state(list1.clone_state(), Some(2)).unwrap();
+    let mut session1 = SyncSession::new();
+    let mut session2 = SyncSession::new();
+
+    let request = session1.start(&list1);
+    let reply = session2.receive(&mut list2, request).unwrap().unwrap();
+    let delta = session1.receive(&mut list1, reply).unwrap().unwrap();
+    let delta_json = serde_json::to_string(&delta).unwrap();
+
+    let mut session3 = SyncSession::new();
+    let delta = serde_json::from_str(&delta_json).unwrap();
+    assert!(session3.receive(&mut list2, delta).err() == Some(Error::InvalidMessage));
+    let delta = serde_json::from_str(&delta_json).unwrap();
+    assert!(session1.receive(&mut list1, delta).err() == Some(Error::InvalidMessage));
+    assert!(!session3.is_done());
+}
+
+fn sync<C: Crdt>(initiator: &mut C, responder: &mut C) {
+    let mut session1 = SyncSession::new();
+    let mut session2 = SyncSession::new();
+    let request = transfer(session1.start(initiator));
+    let reply = transfer(session2.receive(responder, request).unwrap().unwrap());
+    let delta = transfer(session1.receive(initiator, reply).unwrap().unwrap());
+    assert!(session2.receive(responder, delta).unwrap().is_none());
+    assert!(session1.is_done());
+    assert!(session2.is_done());
+}
+
+fn transfer<C: Crdt>(message: Message<C>) -> Message<C> {
+    let json = serde_json::to_string(&message).unwrap();
+    serde_json::from_str(&json).unwrap()
+}
@@ -1,4 +1,6 @@
 extern crate ditto;
+extern crate rmp_serde;
+extern crate serde_json;
 
 mod common;
 use ditto::Error;
@@ -116,6 +118,125 @@ fn test_merge() {
     assert!(text1.summary().contains_pair(3, 1));
 }
 
+#[test]
+fn test_merge_local_ops() {
+    let text = Text::from_str("hello");
+    let mut text1 = Text::from_state(text.clone_state(), Some(1)).unwrap();
+    let mut text2 = Text::from_state(text.clone_state(), Some(2)).unwrap();
+    let _ = text1.replace(0, 5, "");
+    let _ = text1.replace(0, 0, "hi");
+    let _ = text2.replace(5, 0, " world");
+    let _ = text2.replace(0, 0, "oh, ");
+
+    let mut value = text2.local_value();
+    for edit in text2.merge(text1.clone_state()).unwrap() {
+        value.replace_range(edit.idx..edit.idx + edit.len, &edit.text);
+    }
+    assert_eq!(value, text2.local_value());
+    assert_eq!(value.len(), 12);
+    assert!(text2.merge(text1.clone_state()).unwrap().is_empty());
+}
+
+#[test]
+fn test_merge_delta() {
+    let text = Text::from_str("hello");
+    let mut text1 = Text::from_state(text.clone_state(), Some(1)).unwrap();
+    let mut text2 = Text::from_state(text.clone_state(), Some(2)).unwrap();
+    let _ = text1.replace(5, 0, " world");
+    let _ = text2.replace(0, 5, "Hi");
+
+    let delta1 = text1.delta_since(text2.summary());
+    let delta2 = text2.delta_since(text1.summary());
+    text1.merge_delta(delta2).unwrap();
+    text2.merge_delta(delta1).unwrap();
+
+    assert_eq!(text1.state(), text2.state());
+    assert!(text1.local_value() == "Hi world" || text1.local_value() == " worldHi");
+}
+
+#[test]
+fn test_merge_delta_unknown_summary() {
+    let text1 = Text::from_str("hello");
+    let mut text2 = Text::from_state(Text::new().state(), Some(2)).unwrap();
+    let delta = text1.delta_since(text1.summary());
+    assert_eq!(text2.merge_delta(delta), Err(Error::InvalidDelta));
+    assert_eq!(text2.local_value(), "");
+}
+
+#[test]
+fn test_delta_since_small_edit() {
+    let mut text1 = Text::new();
+    let mut text2 = Text::from_state(text1.clone_state(), Some(2)).unwrap();
+    for i in 0..100 {
+        let op1 = text1.replace(i * 2, 0, "a").unwrap().unwrap();
+        let _ = text2.execute_op(op1);
+        let op2 = text2.replace(i * 2 + 1, 0, "b").unwrap().unwrap();
+        let _ = text1.execute_op(op2);
+    }
+    let mut text3 = Text::from_state(text1.clone_state(), Some(3)).unwrap();
+    let _ = text1.replace(100, 1, "");
+    let _ = text1.replace(10, 1, "c");
+
+    let delta = text1.delta_since(text3.summary());
+    assert!(rmp_serde::to_vec(&text1.state()).unwrap().len() > 1500);
+    assert!(rmp_serde::to_vec(&delta).unwrap().len() < 100);
+
+    text3.merge_delta(delta).unwrap();
+    assert_eq!(text1.local_value(), text3.local_value());
+    assert_eq!(text1.summary(), text3.summary());
+    assert_eq!(text3.len(), 199);
+}
+
+#[test]
+fn test_merge_delta_removal_only() {
+    let mut text1 = Text::from_str("hello world");
+    let mut text2 = Text::from_state(text1.clone_state(), Some(2)).unwrap();
+    let _ = text1.replace(5, 6, "");
+
+    let delta = text1.delta_since(text2.summary());
+    text2.merge_delta(delta).unwrap();
+    assert_eq!(text2.local_value(), "hello");
+    assert_eq!(text1.state(), text2.state());
+}
+
+/// Earlier versions gave every element inserted by one edit the same
+/// dot; here "ab", "XY" and "cd" all have the dot (2,1).
+const SHARED_DOT_STATE: &str = r#"{"i":[{"u":[192,128,4,2,1],"t":"ab"},{"u":[192,128,7,2,1],"t":"XY"},{"u":[192,128,42,2,1],"t":"cd"}],"s":[[1,1],[2,1]]}"#;
+
+#[test]
+fn test_merge_delta_shared_dot() {
+    let state: TextState = serde_json::from_str(SHARED_DOT_STATE).unwrap();
+    let mut text1 = Text::from_state(state.clone(), Some(3)).unwrap();
+    let mut text2 = Text::from_state(state, Some(4)).unwrap();
+    let _ = text1.replace(2, 2, "");
+
+    let delta1 = text1.delta_since(text2.summary());
+    let delta2 = text2.delta_since(text1.summary());
+    text2.merge_delta(delta1).unwrap();
+    text1.merge_delta(delta2).unwrap();
+
+    assert_eq!(text1.local_value(), "abcd");
+    assert_eq!(text2.local_value(), "abcd");
+    assert_eq!(text1.state(), text2.state());
+}
+
+#[test]
+fn test_merge_delta_shared_dot_after_reload() {
+    let state: TextState = serde_json::from_str(SHARED_DOT_STATE).unwrap();
+    let mut text1 = Text::from_state(state.clone(), Some(3)).unwrap();
+    let mut text2 = Text::from_state(state, Some(4)).unwrap();
+    let _ = text1.replace(2, 4, "");
+
+    let encoded = rmp_serde::to_vec(&text1.state()).unwrap();
+    let text1 = Text::from_state(rmp_serde::from_slice(&encoded).unwrap(), Some(3)).unwrap();
+    assert_eq!(text1.local_value(), "ab");
+
+    let delta = text1.delta_since(text2.summary());
+    text2.merge_delta(delta).unwrap();
+    assert_eq!(text2.local_value(), "ab");
+    assert_eq!(text1.state(), text2.state());
+}
+
 #[test]
 fn test_add_site_id() {
     let mut text1 = Text::new();
@@ -162,3 +283,396 @@ fn test_serialize_op() {
 fn test_serialize_local_op() {
     common::test_serde(LocalOp{idx: 99, len: 53, text: "San Juan de Miguel".into()});
 }
+
+#[test]
+fn test_undo_redo() {
+    let mut text = Text::from_state(Text::from_str("hello").clone_state(), Some(1)).unwrap();
+    let mut remote = Text::from_state(text.clone_state(), Some(2)).unwrap();
+    let mut undo = UndoManager::new();
+    assert!(!undo.can_undo());
+
+    let op = undo.replace(&mut text, 0, 5, "howdy").unwrap().unwrap();
+    let _ = remote.execute_op(op);
+    assert_eq!(text.local_value(), "howdy");
+    assert!(undo.can_undo());
+
+    let (local_ops, op) = undo.undo(&mut text).unwrap();
+    let _ = remote.execute_op(op.unwrap());
+    assert_eq!(text.local_value(), "hello");
+    assert_eq!(local_ops, [LocalOp{idx: 0, len: 5, text: "hello".into()}]);
+    assert!(!undo.can_undo());
+    assert!(undo.can_redo());
+    assert!(undo.undo(&mut text).is_none());
+
+    let (_, op) = undo.redo(&mut text).unwrap();
+    let _ = remote.execute_op(op.unwrap());
+    assert_eq!(text.local_value(), "howdy");
+    assert!(undo.redo(&mut text).is_none());
+    assert_eq!(text.state(), remote.state());
+}
+
+#[test]
+fn test_undo_groups_merged_edits() {
+    let mut text = Text::from_state(Text::from_str("hello").clone_state(), Some(1)).unwrap();
+    let mut undo = UndoManager::new();
+    let _ = undo.replace(&mut text, 5, 0, " ");
+    let _ = undo.replace(&mut text, 6, 0, "w");
+    let _ = undo.replace(&mut text, 7, 0, "o");
+    let _ = undo.replace(&mut text, 7, 1, "");
+    let _ = undo.replace(&mut text, 0, 1, "H");
+    assert_eq!(text.local_value(), "Hello w");
+
+    let _ = undo.undo(&mut text).unwrap();
+    assert_eq!(text.local_value(), "hello w");
+    let _ = undo.undo(&mut text).unwrap();
+    assert_eq!(text.local_value(), "hello");
+    assert!(undo.undo(&mut text).is_none());
+
+    let _ = undo.redo(&mut text).unwrap();
+    assert_eq!(text.local_value(), "hello w");
+
+    let _ = undo.replace(&mut text, 7, 0, "!");
+    assert!(!undo.can_redo());
+}
+
+#[test]
+fn test_undo_keeps_remote_edits() {
+    let text = Text::from_str("abc");
+    let mut text1 = Text::from_state(text.clone_state(), Some(1)).unwrap();
+    let mut text2 = Text::from_state(text.clone_state(), Some(2)).unwrap();
+    let mut undo = UndoManager::new();
+
+    let op1 = undo.replace(&mut text1, 0, 3, "").unwrap().unwrap();
+    let op2 = text2.replace(3, 0, "d").unwrap().unwrap();
+    let op3 = text2.replace(0, 0, "z").unwrap().unwrap();
+    let _ = text1.execute_op(op2);
+    let _ = text1.execute_op(op3);
+    let _ = text2.execute_op(op1);
+    assert_eq!(text1.local_value(), "zd");
+
+    let (local_ops, op) = undo.undo(&mut text1).unwrap();
+    assert_eq!(local_ops, [LocalOp{idx: 1, len: 0, text: "abc".into()}]);
+    let _ = text2.execute_op(op.unwrap());
+    assert_eq!(text1.local_value(), "zabcd");
+    assert_eq!(text1.state(), text2.state());
+}
+
+#[test]
+fn test_anchor() {
+    let mut text1 = Text::from_state(Text::from_str("hello").clone_state(), Some(1)).unwrap();
+    let _ = text1.replace(5, 0, " world").unwrap().unwrap();
+    let mut text2 = Text::from_state(text1.clone_state(), Some(2)).unwrap();
+
+    let start  = text1.anchor(0, Gravity::Left);
+    let left   = text1.anchor(5, Gravity::Left);
+    let right  = text1.anchor(5, Gravity::Right);
+    let inside = text1.anchor(8, Gravity::Right);
+    let end    = text1.anchor(11, Gravity::Right);
+    assert_eq!(left.gravity(), Gravity::Left);
+    assert_eq!(text1.resolve_anchor(&start), 0);
+    assert_eq!(text1.resolve_anchor(&left), 5);
+    assert_eq!(text1.resolve_anchor(&right), 5);
+    assert_eq!(text1.resolve_anchor(&inside), 8);
+    assert_eq!(text1.resolve_anchor(&end), 11);
+
+    let op = text2.replace(5, 0, "!").unwrap().unwrap();
+    let _ = text1.execute_op(op);
+    assert_eq!(text1.local_value(), "hello! world");
+    assert_eq!(text1.resolve_anchor(&start), 0);
+    assert_eq!(text1.resolve_anchor(&left), 5);
+    assert_eq!(text1.resolve_anchor(&right), 6);
+    assert_eq!(text1.resolve_anchor(&inside), 9);
+    assert_eq!(text1.resolve_anchor(&end), 12);
+    common::test_serde(inside);
+}
+
+#[test]
+fn test_anchor_removed_element() {
+    let mut text1 = Text::from_state(Text::from_str("hello").clone_state(), Some(1)).unwrap();
+    let _ = text1.replace(5, 0, " world").unwrap().unwrap();
+    let mut text2 = Text::from_state(text1.clone_state(), Some(2)).unwrap();
+
+    let left   = text1.anchor(5, Gravity::Left);
+    let inside = text1.anchor(8, Gravity::Right);
+    let end    = text1.anchor(11, Gravity::Left);
+
+    let _ = text2.replace(5, 6, "").unwrap().unwrap();
+    let _ = text1.merge(text2.clone_state()).unwrap();
+    assert_eq!(text1.local_value(), "hello");
+    assert_eq!(text1.resolve_anchor(&left), 5);
+    assert_eq!(text1.resolve_anchor(&inside), 5);
+    assert_eq!(text1.resolve_anchor(&end), 5);
+}
+
+#[test]
+fn test_anchor_split_element() {
+    let mut text1 = Text::from_state(Text::new().clone_state(), Some(1)).unwrap();
+    let _ = text1.replace(0, 0, "hello world").unwrap().unwrap();
+    let mut text2 = Text::from_state(text1.clone_state(), Some(2)).unwrap();
+
+    let left   = text1.anchor(2, Gravity::Left);
+    let right  = text1.anchor(2, Gravity::Right);
+    let inside = text1.anchor(8, Gravity::Right);
+
+    let op = text2.replace(2, 0, "X").unwrap().unwrap();
+    let _ = text1.execute_op(op);
+    assert_eq!(text1.local_value(), "heXllo world");
+    assert_eq!(text1.resolve_anchor(&left), 2);
+    assert_eq!(text1.resolve_anchor(&right), 3);
+    assert_eq!(text1.resolve_anchor(&inside), 9);
+
+    let _ = text1.replace(0, 1, "H").unwrap().unwrap();
+    assert_eq!(text1.local_value(), "HeXllo world");
+    assert_eq!(text1.resolve_anchor(&left), 2);
+    assert_eq!(text1.resolve_anchor(&inside), 9);
+
+    let _ = text1.replace(7, 5, "").unwrap().unwrap();
+    assert_eq!(text1.local_value(), "HeXllo ");
+    assert_eq!(text1.resolve_anchor(&inside), 7);
+
+    let text3 = Text::from_state(text1.clone_state(), Some(3)).unwrap();
+    assert_eq!(text3.resolve_anchor(&left), 2);
+    assert_eq!(text3.resolve_anchor(&right), 3);
+    common::test_serde(text1.clone_state());
+}
+
+#[test]
+fn test_anchor_merged_edits() {
+    let mut text1 = Text::from_state(Text::new().clone_state(), Some(1)).unwrap();
+    let _ = text1.replace(0, 0, "abcd").unwrap().unwrap();
+    let mut text2 = Text::from_state(text1.clone_state(), Some(2)).unwrap();
+
+    let left  = text1.anchor(2, Gravity::Left);
+    let right = text1.anchor(2, Gravity::Right);
+    let end   = text1.anchor(4, Gravity::Left);
+
+    let op1 = text1.replace(4, 0, "e").unwrap().unwrap();
+    let op2 = text1.replace(5, 0, "f").unwrap().unwrap();
+    assert_eq!(text1.local_value(), "abcdef");
+    assert_eq!(text1.resolve_anchor(&left), 2);
+    assert_eq!(text1.resolve_anchor(&right), 2);
+    assert_eq!(text1.resolve_anchor(&end), 4);
+
+    let typed = text1.anchor(5, Gravity::Left);
+    let op3 = text1.replace(1, 1, "").unwrap().unwrap();
+    assert_eq!(text1.local_value(), "acdef");
+    assert_eq!(text1.resolve_anchor(&left), 1);
+    assert_eq!(text1.resolve_anchor(&right), 1);
+    assert_eq!(text1.resolve_anchor(&typed), 4);
+
+    for op in [op1, op2, op3] {
+        let _ = text2.execute_op(op);
+    }
+    assert_eq!(text2.local_value(), "acdef");
+    assert_eq!(text2.resolve_anchor(&left), 1);
+    assert_eq!(text2.resolve_anchor(&right), 1);
+    assert_eq!(text2.resolve_anchor(&end), 3);
+    assert_eq!(text2.resolve_anchor(&typed), 4);
+}
+
+#[test]
+#[should_panic]
+fn test_anchor_outofbounds() {
+    let text = Text::from_str("hello");
+    let _ = text.anchor(6, Gravity::Right);
+}
+
+#[test]
+fn test_index_units() {
+    let mut text = Text::from_str("a😀b∆");
+    assert_eq!(text.index_unit(), IndexUnit::Byte);
+    assert_eq!(text.len(), 9);
+
+    text.set_index_unit(IndexUnit::Char);
+    assert_eq!(text.len(), 4);
+    let _ = text.replace(2, 1, "c").unwrap().unwrap();
+    assert_eq!(text.local_value(), "a😀c∆");
+
+    text.set_index_unit(IndexUnit::Utf16);
+    assert_eq!(text.len(), 5);
+    let _ = text.replace(3, 0, "x").unwrap().unwrap();
+    assert_eq!(text.local_value(), "a😀xc∆");
+
+    let anchor = text.anchor(3, Gravity::Right);
+    text.set_index_unit(IndexUnit::Char);
+    assert_eq!(text.resolve_anchor(&anchor), 2);
+}
+
+#[test]
+fn test_index_units_element_offsets() {
+    let mut text = Text::from_str("∆a∆b∆c∆");
+    text.set_index_unit(IndexUnit::Char);
+    let _ = text.replace(1, 1, "x").unwrap().unwrap();
+    let _ = text.replace(5, 1, "y").unwrap().unwrap();
+    assert_eq!(text.local_value(), "∆x∆b∆y∆");
+
+    text.set_index_unit(IndexUnit::Utf16);
+    let _ = text.replace(7, 0, "z").unwrap().unwrap();
+    assert_eq!(text.local_value(), "∆x∆b∆y∆z");
+    assert_eq!(text.char_at(6), Some('∆'));
+}
+
+#[test]
+#[should_panic]
+fn test_index_units_notoncharboundary() {
+    let mut text = Text::from_str("a😀b");
+    text.set_index_unit(IndexUnit::Utf16);
+    let _ = text.replace(2, 0, "x");
+}
+
+#[test]
+fn test_index_units_local_ops() {
+    let mut text1 = Text::from_state(Text::from_str("😀😀").clone_state(), Some(1)).unwrap();
+    let mut text2 = Text::from_state(text1.clone_state(), Some(2)).unwrap();
+    let mut text3 = Text::from_state(text1.clone_state(), Some(3)).unwrap();
+    text2.set_index_unit(IndexUnit::Utf16);
+    text3.set_index_unit(IndexUnit::Utf16);
+
+    let op1 = text1.replace(8, 0, "∆").unwrap().unwrap();
+    let local_ops = text2.execute_op(op1);
+    assert_eq!(local_ops, [LocalOp{idx: 4, len: 0, text: "∆".into()}]);
+
+    let old_value = text2.local_value();
+    let op2 = text1.replace(4, 4, "b").unwrap().unwrap();
+    let local_ops = text2.execute_op(op2);
+    assert_eq!(text2.local_value(), "😀b∆");
+    assert_eq!(apply_utf16(&old_value, &local_ops), "😀b∆");
+
+    let local_ops = text3.merge(text1.clone_state()).unwrap();
+    assert_eq!(apply_utf16("😀😀", &local_ops), "😀b∆");
+}
+
+fn apply_utf16(value: &str, local_ops: &[LocalOp]) -> String {
+    let mut units: Vec<u16> = value.encode_utf16().collect();
+    for op in local_ops {
+        let _ = units.splice(op.idx..op.idx + op.len, op.text.encode_utf16());
+    }
+    String::from_utf16(&units).unwrap()
+}
+
+#[test]
+fn test_lines() {
+    let mut text = Text::from_str("ab\n😀c\n\nd");
+    assert_eq!(text.line_count(), 4);
+    assert_eq!(Text::new().line_count(), 1);
+
+    text.set_index_unit(IndexUnit::Utf16);
+    assert_eq!(text.position_to_index(0, 2), Some(2));
+    assert_eq!(text.position_to_index(0, 3), None);
+    assert_eq!(text.position_to_index(1, 3), Some(6));
+    assert_eq!(text.position_to_index(2, 0), Some(7));
+    assert_eq!(text.position_to_index(3, 1), Some(9));
+    assert_eq!(text.position_to_index(4, 0), None);
+    assert_eq!(text.index_to_position(0), Position{line: 0, col: 0});
+    assert_eq!(text.index_to_position(5), Position{line: 1, col: 2});
+    assert_eq!(text.index_to_position(7), Position{line: 2, col: 0});
+    assert_eq!(text.index_to_position(9), Position{line: 3, col: 1});
+}
+
+#[test]
+fn test_line_edits() {
+    let mut text1 = Text::from_state(Text::from_str("one\ntwo\n").clone_state(), Some(1)).unwrap();
+    let mut text2 = Text::from_state(text1.clone_state(), Some(2)).unwrap();
+    let mut text3 = Text::from_state(text1.clone_state(), Some(3)).unwrap();
+
+    let op = text1.replace(4, 3, "2\n3").unwrap().unwrap();
+    let line_edits = text2.execute_op_lines(op);
+    assert_eq!(text2.local_value(), "one\n2\n3\n");
+    assert_eq!(apply_line_edits("one\ntwo\n", &line_edits), "one\n2\n3\n");
+
+    let line_edits = text3.merge_lines(text1.clone_state()).unwrap();
+    assert_eq!(text3.local_value(), "one\n2\n3\n");
+    assert_eq!(apply_line_edits("one\ntwo\n", &line_edits), "one\n2\n3\n");
+    common::test_serde(line_edits);
+}
+
+fn apply_line_edits(value: &str, line_edits: &[LineEdit]) -> String {
+    let mut value = value.to_owned();
+    for edit in line_edits {
+        let start = byte_idx(&value, edit.start);
+        let end = byte_idx(&value, edit.end);
+        value.replace_range(start..end, &edit.text);
+    }
+    value
+}
+
+fn byte_idx(value: &str, position: Position) -> usize {
+    let line_start: usize = value.split('\n').take(position.line).map(|line| line.len() + 1).sum();
+    line_start + position.col
+}
+
+#[test]
+fn test_read_access() {
+    let mut text = Text::from_state(Text::from_str("hello").clone_state(), Some(1)).unwrap();
+    let _ = text.replace(5, 0, " wörld").unwrap().unwrap();
+    let _ = text.replace(0, 0, "¡").unwrap().unwrap();
+    let value = "¡hello wörld";
+
+    assert_eq!(text.chunks().collect::<String>(), value);
+    assert_eq!(text.chunks().count(), 3);
+    assert_eq!(text.to_string(), value);
+    assert_eq!(format!("<{}>", text), format!("<{}>", value));
+
+    assert_eq!(text.slice(0..text.len()), value);
+    assert_eq!(text.slice(2..12), &value[2..12]);
+    assert_eq!(text.slice(5..5), "");
+    assert_eq!(text.char_at(0), Some('¡'));
+    assert_eq!(text.char_at(9), Some('ö'));
+    assert_eq!(text.char_at(text.len()), None);
+
+    text.set_index_unit(IndexUnit::Char);
+    assert_eq!(text.slice(1..9), "hello wö");
+    assert_eq!(text.char_at(8), Some('ö'));
+}
+
+#[test]
+#[should_panic]
+fn test_slice_outofbounds() {
+    let text = Text::from_str("hello");
+    let _ = text.slice(2..6);
+}
+
+#[test]
+fn test_set_value() {
+    let mut text1 = Text::from_str("the quick fox");
+    for &(idx, string) in &[(13, " jumps over"), (24, " the dog")] {
+        text1 = Text::from_state(text1.clone_state(), Some(1)).unwrap();
+        let _ = text1.replace(idx, 0, string).unwrap().unwrap();
+    }
+    let mut text2 = Text::from_state(text1.clone_state(), Some(2)).unwrap();
+
+    let ops = text1.set_value("the slow cat jumps over the dog").unwrap();
+    assert_eq!(ops.len(), 2);
+    assert_eq!(text1.local_value(), "the slow cat jumps over the dog");
+    assert!(text1.set_value("the slow cat jumps over the dog").unwrap().is_empty());
+
+    let op = text2.replace(28, 0, " lazy").unwrap().unwrap();
+    for op in ops { let _ = text2.execute_op(op); }
+    let _ = text1.execute_op(op);
+    assert_eq!(text1.state(), text2.state());
+    assert_eq!(text1.local_value(), "the slow cat jumps over the lazy dog");
+}
+
+#[test]
+fn test_set_value_index_unit() {
+    let mut text1 = Text::from_str("añb😀c");
+    text1.set_index_unit(IndexUnit::Utf16);
+    let mut text2 = Text::from_state(text1.clone_state(), Some(2)).unwrap();
+
+    let ops = text1.set_value("aéb😀cd").unwrap();
+    assert_eq!(text1.local_value(), "aéb😀cd");
+    for op in ops { let _ = text2.execute_op(op); }
+    assert_eq!(text2.local_value(), "aéb😀cd");
+
+    assert_eq!(text1.set_value("").unwrap().len(), 1);
+    assert!(text1.is_empty());
+}
+
+#[test]
+fn test_set_value_awaiting_site() {
+    let text1 = Text::from_str("hello");
+    let mut text2 = Text::from_state(text1.clone_state(), None).unwrap();
+    assert_eq!(text2.set_value("jello!"), Err(Error::AwaitingSiteId));
+    assert_eq!(text2.local_value(), "jello!");
+    assert_eq!(text2.add_site_id(2).unwrap().len(), 2);
+}